@@ -1,5 +1,13 @@
+use std::path::Path;
+
+use anyhow::Result;
 use instant::{Duration, Instant};
-use nes_emulator::nes::Nes;
+use nes_emulator::{
+    cartridge::Cartridge,
+    mappers::AnyMapper,
+    nes::{Nes, ProgressStatus, ProgressTarget},
+    ppu::FastRendererStats,
+};
 
 pub struct BenchmarkState {
     nes_cpu_clock_hz: u64,
@@ -25,6 +33,8 @@ pub struct BenchmarkState {
 
     /// emulated frames (not drawn frames)
     pub frame_count: u32,
+
+    last_stats_update_fast_renderer_stats: FastRendererStats,
 }
 
 impl BenchmarkState {
@@ -49,6 +59,8 @@ impl BenchmarkState {
             profiled_aggregate_fps: 0.0,
 
             frame_count: 0,
+
+            last_stats_update_fast_renderer_stats: nes.ppu().fast_renderer_stats(),
         }
     }
 
@@ -111,6 +123,21 @@ impl BenchmarkState {
             let latest_speed = (self.real_time_emulation_speed() * 100.0) as u32;
             log::debug!("Raw Emulator Stats:       Clocks/s: {last_cps:8}, Update FPS: {last_fps:4.2}, Real-time Speed: {latest_speed:3}%");
 
+            let fast_renderer_stats = nes.ppu().fast_renderer_stats();
+            if nes.ppu().fast_renderer_enabled() {
+                let last = self.last_stats_update_fast_renderer_stats;
+                let batched = fast_renderer_stats
+                    .batched_lines
+                    .saturating_sub(last.batched_lines);
+                let interrupted = fast_renderer_stats
+                    .interrupted_lines
+                    .saturating_sub(last.interrupted_lines);
+                let batched_percent = visible_lines_percent(batched, n_frames);
+                let interrupted_percent = visible_lines_percent(interrupted, n_frames);
+                log::debug!("Fast Renderer Stats:      Batched Lines: {batched_percent:3}%, Interrupted Lines: {interrupted_percent:3}%");
+            }
+            self.last_stats_update_fast_renderer_stats = fast_renderer_stats;
+
             self.last_stats_update_timestamp = now;
             self.last_stats_update_frame_no = self.frame_count;
             self.last_stats_update_cpu_clock = cpu_clock;
@@ -127,3 +154,153 @@ impl BenchmarkState {
         self.frame_count += 1;
     }
 }
+
+/// The percentage of the visible lines in `n_frames` frames that `lines` represents
+fn visible_lines_percent(lines: u64, n_frames: u32) -> u64 {
+    let visible_lines = (n_frames as u64 * 240).max(1);
+    (lines * 100 / visible_lines).min(100)
+}
+
+/// Measures how long it takes to emulate `n_frames` with the given cartridge
+///
+/// Also returns the fast renderer's stats for the run (which will be zero
+/// unless `fast_renderer` is enabled)
+fn time_frames(
+    cartridge: Cartridge,
+    audio_sample_rate: u32,
+    n_frames: u32,
+    fast_renderer: bool,
+) -> Result<(Duration, FastRendererStats)> {
+    let start = Instant::now();
+    let mut nes = Nes::new_with_cartridge(cartridge, audio_sample_rate, start)?;
+    nes.ppu_mut().set_fast_renderer_enabled(fast_renderer);
+    nes.power_cycle(start);
+    for _ in 0..n_frames {
+        while !matches!(
+            nes.progress(ProgressTarget::FrameReady),
+            ProgressStatus::FrameReady
+        ) {}
+    }
+    Ok((Instant::now() - start, nes.ppu().fast_renderer_stats()))
+}
+
+/// Compares emulating each ROM with the built-in, enum dispatched, mappers
+/// against the same mappers being dispatched through a `Box<dyn Mapper>`
+///
+/// Each ROM is run a few times in both modes, taking the fastest time for each,
+/// and the results are printed as a table.
+pub fn benchmark_mapper_dispatch(
+    rom_paths: &[impl AsRef<Path>],
+    audio_sample_rate: u32,
+    n_frames: u32,
+) -> Result<()> {
+    const ROUNDS: usize = 3;
+
+    println!(
+        "{:<40} {:>12} {:>12} {:>9}",
+        "ROM", "enum (ms)", "dyn (ms)", "speed-up"
+    );
+    let mut total_enum = Duration::ZERO;
+    let mut total_dyn = Duration::ZERO;
+    for path in rom_paths {
+        let path = path.as_ref();
+        let rom = std::fs::read(path)?;
+        let cartridge = Cartridge::from_binary(&rom)?;
+
+        let mut enum_time = Duration::MAX;
+        let mut dyn_time = Duration::MAX;
+        for _ in 0..ROUNDS {
+            let (time, _) = time_frames(cartridge.clone(), audio_sample_rate, n_frames, false)?;
+            enum_time = enum_time.min(time);
+
+            let external = Cartridge::with_external_mapper(
+                cartridge.config.clone(),
+                cartridge.mapper.clone().into_dyn(),
+            );
+            debug_assert!(matches!(external.mapper, AnyMapper::External(_)));
+            let (time, _) = time_frames(external, audio_sample_rate, n_frames, false)?;
+            dyn_time = dyn_time.min(time);
+        }
+        total_enum += enum_time;
+        total_dyn += dyn_time;
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        println!(
+            "{:<40} {:>12.1} {:>12.1} {:>8.3}x",
+            name,
+            enum_time.as_secs_f64() * 1000.0,
+            dyn_time.as_secs_f64() * 1000.0,
+            dyn_time.as_secs_f64() / enum_time.as_secs_f64()
+        );
+    }
+    println!(
+        "{:<40} {:>12.1} {:>12.1} {:>8.3}x",
+        "Total",
+        total_enum.as_secs_f64() * 1000.0,
+        total_dyn.as_secs_f64() * 1000.0,
+        total_dyn.as_secs_f64() / total_enum.as_secs_f64()
+    );
+
+    Ok(())
+}
+
+/// Compares emulating each ROM by stepping the PPU one dot at a time against
+/// the PPU's fast renderer, which renders whole spans of a line at once
+///
+/// Each ROM is run a few times in both modes, taking the fastest time for each,
+/// and the results are printed as a table along with the percentage of
+/// visible lines that the fast renderer batched (or had to interrupt).
+pub fn benchmark_fast_renderer(
+    rom_paths: &[impl AsRef<Path>],
+    audio_sample_rate: u32,
+    n_frames: u32,
+) -> Result<()> {
+    const ROUNDS: usize = 3;
+
+    println!(
+        "{:<40} {:>12} {:>12} {:>9} {:>8} {:>12}",
+        "ROM", "dots (ms)", "fast (ms)", "speed-up", "batched", "interrupted"
+    );
+    let mut total_dots = Duration::ZERO;
+    let mut total_fast = Duration::ZERO;
+    for path in rom_paths {
+        let path = path.as_ref();
+        let rom = std::fs::read(path)?;
+        let cartridge = Cartridge::from_binary(&rom)?;
+
+        let mut dots_time = Duration::MAX;
+        let mut fast_time = Duration::MAX;
+        let mut stats = FastRendererStats::default();
+        for _ in 0..ROUNDS {
+            let (time, _) = time_frames(cartridge.clone(), audio_sample_rate, n_frames, false)?;
+            dots_time = dots_time.min(time);
+
+            let (time, fast_stats) =
+                time_frames(cartridge.clone(), audio_sample_rate, n_frames, true)?;
+            fast_time = fast_time.min(time);
+            stats = fast_stats;
+        }
+        total_dots += dots_time;
+        total_fast += fast_time;
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        println!(
+            "{:<40} {:>12.1} {:>12.1} {:>8.3}x {:>7}% {:>11}%",
+            name,
+            dots_time.as_secs_f64() * 1000.0,
+            fast_time.as_secs_f64() * 1000.0,
+            dots_time.as_secs_f64() / fast_time.as_secs_f64(),
+            visible_lines_percent(stats.batched_lines, n_frames),
+            visible_lines_percent(stats.interrupted_lines, n_frames),
+        );
+    }
+    println!(
+        "{:<40} {:>12.1} {:>12.1} {:>8.3}x",
+        "Total",
+        total_dots.as_secs_f64() * 1000.0,
+        total_fast.as_secs_f64() * 1000.0,
+        total_dots.as_secs_f64() / total_fast.as_secs_f64()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,357 @@
+//! Support for the status protocol used by blargg's test ROMs
+//!
+//! Test ROMs that follow this protocol write their status to $6000 once the
+//! signature bytes `DE B0 61` have been written to $6001-$6003:
+//!
+//! - `$80`: the test is still running
+//! - `$81`: the reset button needs to be pressed, no sooner than 100ms later
+//! - `$00-$7F`: the test finished with this result code, where zero means it passed
+//!
+//! Along with the status, a NUL-terminated text description of the results is
+//! written from $6004.
+
+use instant::Duration;
+use nes_emulator::{nes::Nes, system::WriteBreakpointHandle};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::macros::{self, Macro, MacroCommand};
+
+const STATUS_ADDR: u16 = 0x6000;
+const SIGNATURE_ADDR: u16 = 0x6001;
+const SIGNATURE: [u8; 3] = [0xde, 0xb0, 0x61];
+const TEXT_ADDR: u16 = 0x6004;
+const TEXT_MAX_LEN: u16 = 0x1ffb; // up to the end of PRG RAM
+
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_RESET_REQUIRED: u8 = 0x81;
+
+/// The minimum time a test ROM expects before the reset button is pressed
+const RESET_DELAY: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlarggStatus {
+    /// The signature hasn't been written yet (the ROM may not use the protocol)
+    NotStarted,
+    Running,
+    /// The test finished with the given result code (zero if it passed) and text
+    Finished {
+        code: u8,
+        text: String,
+    },
+}
+
+/// Watches a test ROM's status, pressing reset whenever the ROM asks for it
+///
+/// Status changes are seen via a write breakpoint on $6000, so the emulator
+/// will stop whenever the ROM writes its status (see [`Self::status_written`]).
+#[derive(Debug, Default)]
+pub struct BlarggStatusMonitor {
+    /// Set once the monitor has started watching for writes to the status
+    status_write: Option<WriteBreakpointHandle>,
+
+    /// The last status written after the signature
+    status: Option<u8>,
+
+    /// The CPU clock at which the reset button should be pressed, after the
+    /// ROM requested a reset
+    reset_clock: Option<u64>,
+}
+
+impl BlarggStatusMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if the ROM has written its status since it was last polled
+    pub fn status_written(&self, nes: &mut Nes) -> bool {
+        self.status_write
+            .is_some_and(|handle| nes.system_mut().write_breakpoint_hit(handle))
+    }
+
+    /// Reads the status if the ROM has written it since the last poll, and
+    /// presses reset if the ROM has asked for a reset and the required delay
+    /// has passed
+    ///
+    /// This should be polled regularly (such as once per frame) while the ROM
+    /// runs, as well as whenever the status is written. Call [`Self::stop`]
+    /// once the monitor is no longer needed.
+    pub fn poll(&mut self, nes: &mut Nes) -> BlarggStatus {
+        match self.status_write {
+            Some(handle) if !nes.system_mut().write_breakpoint_hit(handle) => {}
+            _ => {
+                // (Re)start watching before reading the status so the first
+                // poll also sees a status written before the monitor started
+                self.stop(nes);
+                self.status_write = Some(nes.system_mut().add_write_breakpoint(STATUS_ADDR, None));
+                self.read_status(nes);
+            }
+        }
+
+        if let Some(reset_clock) = self.reset_clock {
+            if nes.cpu_clock() >= reset_clock {
+                log::debug!("Test ROM requested a reset");
+                nes.reset();
+                self.reset_clock = None;
+            }
+        }
+
+        match self.status {
+            None => BlarggStatus::NotStarted,
+            Some(code) if code < 0x80 => BlarggStatus::Finished {
+                code,
+                text: read_text(nes),
+            },
+            Some(_) => BlarggStatus::Running,
+        }
+    }
+
+    /// Stops watching for writes to the status
+    pub fn stop(&mut self, nes: &mut Nes) {
+        if let Some(handle) = self.status_write.take() {
+            nes.system_mut().remove_write_breakpoint(handle);
+        }
+    }
+
+    fn read_status(&mut self, nes: &mut Nes) {
+        let signature = [0, 1, 2].map(|i| nes.peek_system_bus(SIGNATURE_ADDR + i));
+        if signature != SIGNATURE {
+            self.status = None;
+            return;
+        }
+
+        let status = nes.peek_system_bus(STATUS_ADDR);
+        match status {
+            STATUS_RUNNING => {}
+            STATUS_RESET_REQUIRED => {
+                if self.reset_clock.is_none() {
+                    self.reset_clock =
+                        Some(nes.cpu_clock() + nes.cpu_clocks_for_duration(RESET_DELAY));
+                }
+            }
+            code if code < 0x80 => {}
+            _ => log::warn!("Unknown test ROM status 0x{status:02x}"),
+        }
+        self.status = Some(status);
+    }
+}
+
+fn read_text(nes: &mut Nes) -> String {
+    let mut bytes = vec![];
+    for i in 0..TEXT_MAX_LEN {
+        let byte = nes.peek_system_bus(TEXT_ADDR + i);
+        if byte == 0 {
+            break;
+        }
+        bytes.push(byte);
+    }
+    String::from_utf8_lossy(&bytes).trim_end().to_string()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn find_roms(dir: &std::path::Path, roms: &mut Vec<std::path::PathBuf>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_roms(&path, roms)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("nes"))
+        {
+            roms.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Creates a macro for each test ROM found under `dir` that waits for the ROM
+/// to report its status and checks that it passed
+///
+/// ROM paths are relative to `dir`, so the library should be played with `dir`
+/// passed as a ROM directory.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn generate_macros(dir: &std::path::Path) -> anyhow::Result<Vec<Macro>> {
+    let mut roms = vec![];
+    find_roms(dir, &mut roms)?;
+    roms.sort();
+
+    Ok(roms
+        .iter()
+        .map(|path| {
+            let relative = path.strip_prefix(dir).unwrap_or(path);
+            let stem = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            Macro {
+                name: macros::name_from_rom_path(relative, stem),
+                rom: relative.to_string_lossy().to_string(),
+                tags: ["blargg".to_string()].into_iter().collect(),
+                commands: vec![MacroCommand::CheckBlarggStatus {
+                    timeout_frames: macros::DEFAULT_BLARGG_TIMEOUT_FRAMES,
+                }],
+                ..Default::default()
+            }
+        })
+        .collect())
+}
+
+/// A test ROM program that follows the status protocol in stages:
+///
+/// - writes a status before the signature, then waits for $00 to be non-zero
+/// - writes the signature and a running status, then waits for $01 to be non-zero
+/// - requests a reset the first time through (counted in $02, which survives the reset)
+/// - after the reset, writes "ok" and finishes with the result code from $03
+#[cfg(test)]
+const TEST_PROGRAM: [u8; 70] = [
+    0xa9, 0x80, // LDA #$80
+    0x8d, 0x00, 0x60, // STA $6000
+    0xa5, 0x00, // wait1: LDA $00
+    0xf0, 0xfc, // BEQ wait1
+    0xa9, 0xde, // LDA #$DE
+    0x8d, 0x01, 0x60, // STA $6001
+    0xa9, 0xb0, // LDA #$B0
+    0x8d, 0x02, 0x60, // STA $6002
+    0xa9, 0x61, // LDA #$61
+    0x8d, 0x03, 0x60, // STA $6003
+    0xa9, 0x80, // LDA #$80
+    0x8d, 0x00, 0x60, // STA $6000
+    0xa5, 0x01, // wait2: LDA $01
+    0xf0, 0xfc, // BEQ wait2
+    0xa5, 0x02, // LDA $02
+    0xd0, 0x0a, // BNE done
+    0xe6, 0x02, // INC $02
+    0xa9, 0x81, // LDA #$81
+    0x8d, 0x00, 0x60, // STA $6000
+    0x4c, 0x2c, 0x80, // JMP $802C
+    0xa9, b'o', // done: LDA #'o'
+    0x8d, 0x04, 0x60, // STA $6004
+    0xa9, b'k', // LDA #'k'
+    0x8d, 0x05, 0x60, // STA $6005
+    0xa9, 0x00, // LDA #0
+    0x8d, 0x06, 0x60, // STA $6006
+    0xa5, 0x03, // LDA $03
+    0x8d, 0x00, 0x60, // STA $6000
+    0x4c, 0x43, 0x80, // JMP $8043
+];
+
+/// Creates a Nes running [`TEST_PROGRAM`] with the given values for $00-$03
+#[cfg(test)]
+fn test_program_nes(zero_page: [u8; 4]) -> Nes {
+    let start = instant::Instant::now();
+    let mut nes = Nes::new(nes_emulator::system::Model::Ntsc, 48000, start);
+    nes.open_binary(&crate::utils::test_nrom_binary(&TEST_PROGRAM))
+        .unwrap();
+    nes.power_cycle(start);
+    nes.system_mut().wram[..4].copy_from_slice(&zero_page);
+    nes
+}
+
+#[test]
+fn test_blargg_status_monitor() {
+    use nes_emulator::nes::{ProgressStatus, ProgressTarget};
+
+    // Runs a frame, polling the monitor whenever the status is written (like
+    // the macro player) and at the end of the frame
+    let run_frame = |nes: &mut Nes, monitor: &mut BlarggStatusMonitor| loop {
+        match nes.progress(ProgressTarget::FrameReady) {
+            ProgressStatus::FrameReady => return monitor.poll(nes),
+            ProgressStatus::Breakpoint => {
+                assert!(monitor.status_written(nes));
+                monitor.poll(nes);
+            }
+            ProgressStatus::ReachedTarget => unreachable!(),
+        }
+    };
+
+    let mut nes = test_program_nes([0, 0, 0, 0]);
+    let mut monitor = BlarggStatusMonitor::new();
+    assert_eq!(monitor.poll(&mut nes), BlarggStatus::NotStarted);
+    assert!(!monitor.status_written(&mut nes));
+
+    // A status written without the signature is ignored
+    assert_eq!(run_frame(&mut nes, &mut monitor), BlarggStatus::NotStarted);
+
+    nes.system_mut().wram[0] = 1;
+    assert_eq!(run_frame(&mut nes, &mut monitor), BlarggStatus::Running);
+    assert_eq!(run_frame(&mut nes, &mut monitor), BlarggStatus::Running);
+
+    // The reset isn't pressed until at least 100ms after it's requested
+    nes.system_mut().wram[1] = 1;
+    assert_eq!(run_frame(&mut nes, &mut monitor), BlarggStatus::Running);
+    assert_eq!(nes.system_mut().wram[2], 1);
+    let requested_clock = nes.cpu_clock();
+    let mut frames = 0;
+    let status = loop {
+        let status = run_frame(&mut nes, &mut monitor);
+        if status != BlarggStatus::Running {
+            break status;
+        }
+        frames += 1;
+        assert!(frames < 60, "Test ROM wasn't reset");
+    };
+    assert!(
+        nes.cpu_clock() - requested_clock >= nes.cpu_clocks_for_duration(RESET_DELAY),
+        "Reset pressed too early"
+    );
+    assert_eq!(
+        status,
+        BlarggStatus::Finished {
+            code: 0,
+            text: "ok".to_string()
+        }
+    );
+
+    monitor.stop(&mut nes);
+    assert!(!monitor.status_written(&mut nes));
+}
+
+#[test]
+fn test_check_blargg_status() {
+    use crate::macros::{FailedCheck, FrameCrcs, MacroPlayer};
+    use nes_emulator::nes::{ProgressStatus, ProgressTarget};
+    use std::{cell::RefCell, rc::Rc};
+
+    // Plays a macro that checks the status of TEST_PROGRAM, returning any failed check
+    let play = |zero_page: [u8; 4], timeout_frames: u32| {
+        let mut nes = test_program_nes(zero_page);
+        let recording = Macro {
+            name: "blargg".to_string(),
+            commands: vec![MacroCommand::CheckBlarggStatus { timeout_frames }],
+            ..Default::default()
+        };
+        let crcs = Rc::new(RefCell::new(FrameCrcs::default()));
+        let mut player = MacroPlayer::new(recording, &mut nes, crcs);
+        for _ in 0..600 {
+            if !player.playing() {
+                break;
+            }
+            if let ProgressStatus::Breakpoint = nes.progress(ProgressTarget::FrameReady) {
+                assert!(player.check_breakpoint(&mut nes));
+            }
+            player.update(&mut nes);
+        }
+        assert!(!player.playing());
+        assert_eq!(
+            player.all_checks_passed(),
+            player.check_failures().is_empty()
+        );
+        player
+            .check_failures()
+            .first()
+            .map(|failure| failure.check.clone())
+    };
+
+    assert_eq!(play([1, 1, 0, 0], 600), None);
+    assert_eq!(
+        play([1, 1, 0, 3], 600),
+        Some(FailedCheck::BlarggStatus {
+            code: 3,
+            text: "ok".to_string()
+        })
+    );
+    // Never gets past the first stage
+    assert_eq!(
+        play([0, 0, 0, 0], 20),
+        Some(FailedCheck::BlarggTimeout { frames: 20 })
+    );
+}
@@ -0,0 +1,131 @@
+//! Tolerant comparisons between the framebuffer and reference images, for
+//! checks that shouldn't be affected by small palette or filtering changes
+
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use image::{Rgb, RgbImage};
+use nes_emulator::{framebuffer::FramebufferInfo, nes::Nes};
+
+/// Copies the PPU's current framebuffer into an RGB image
+pub fn framebuffer_image(nes: &mut Nes) -> RgbImage {
+    let front = &nes.ppu_mut().framebuffer;
+    let fb_width = front.width();
+    let fb_height = front.height();
+
+    let fb_buf = &front.data;
+    let stride = fb_width * 4;
+    let mut imgbuf = RgbImage::new(fb_width as u32, fb_height as u32);
+    for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
+        let x = x as usize;
+        let y = y as usize;
+        let r = fb_buf[stride * y + x * 4];
+        let g = fb_buf[stride * y + x * 4 + 1];
+        let b = fb_buf[stride * y + x * 4 + 2];
+        *pixel = Rgb([r, g, b]);
+    }
+    imgbuf
+}
+
+pub fn load_reference_image(path: &Path) -> Result<RgbImage> {
+    let image =
+        image::open(path).map_err(|err| anyhow!("Failed to load {}: {err}", path.display()))?;
+    Ok(image.to_rgb8())
+}
+
+pub struct FrameDifference {
+    /// The number of pixels where any channel differs by more than the tolerance
+    pub differing_pixels: u32,
+
+    /// The largest difference for any channel of any pixel
+    pub max_difference: u8,
+
+    /// Differing pixels are highlighted in red over a dimmed, grayscale copy
+    /// of the frame
+    pub diff: RgbImage,
+}
+
+/// Compares two images, where pixels only count as different if a color
+/// channel differs by more than `tolerance`
+pub fn compare_images(
+    frame: &RgbImage,
+    reference: &RgbImage,
+    tolerance: u8,
+) -> Result<FrameDifference> {
+    if frame.dimensions() != reference.dimensions() {
+        return Err(anyhow!(
+            "Reference image is {:?} but the frame is {:?}",
+            reference.dimensions(),
+            frame.dimensions()
+        ));
+    }
+
+    let mut differing_pixels = 0;
+    let mut max_difference = 0;
+    let mut diff = RgbImage::new(frame.width(), frame.height());
+    for ((actual, expected), out) in frame
+        .pixels()
+        .zip(reference.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let difference = (0..3)
+            .map(|i| actual[i].abs_diff(expected[i]))
+            .max()
+            .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+        if difference > tolerance {
+            differing_pixels += 1;
+            *out = Rgb([0xff, 0, 0]);
+        } else {
+            let luma = (actual[0] as u16 * 3 + actual[1] as u16 * 6 + actual[2] as u16) / 10;
+            let dimmed = (luma / 3) as u8;
+            *out = Rgb([dimmed, dimmed, dimmed]);
+        }
+    }
+
+    Ok(FrameDifference {
+        differing_pixels,
+        max_difference,
+        diff,
+    })
+}
+
+#[test]
+fn test_compare_images() {
+    let frame = RgbImage::from_fn(16, 8, |x, y| Rgb([x as u8 * 16, y as u8 * 32, 0x80]));
+
+    // Identical
+    let result = compare_images(&frame, &frame.clone(), 0).unwrap();
+    assert_eq!(result.differing_pixels, 0);
+    assert_eq!(result.max_difference, 0);
+    assert_eq!(result.diff.dimensions(), frame.dimensions());
+    assert!(result.diff.pixels().all(|p| p[0] == p[1] && p[1] == p[2]));
+
+    // Every pixel differs slightly (e.g. after a palette tweak), within the tolerance
+    let mut reference = frame.clone();
+    for pixel in reference.pixels_mut() {
+        pixel[2] += 4;
+    }
+    let result = compare_images(&frame, &reference, 4).unwrap();
+    assert_eq!(result.differing_pixels, 0);
+    assert_eq!(result.max_difference, 4);
+    let result = compare_images(&frame, &reference, 3).unwrap();
+    assert_eq!(result.differing_pixels, 16 * 8);
+
+    // A few pixels differ by more than the tolerance, in either direction
+    let mut reference = frame.clone();
+    reference.put_pixel(0, 0, Rgb([0xff, 0, 0x80]));
+    reference.put_pixel(15, 7, Rgb([0, 0, 0x80]));
+    reference.put_pixel(3, 3, Rgb([3 * 16, 3 * 32, 0x80 + 2]));
+    let result = compare_images(&frame, &reference, 8).unwrap();
+    assert_eq!(result.differing_pixels, 2);
+    assert_eq!(result.max_difference, 0xff);
+    assert_eq!(*result.diff.get_pixel(0, 0), Rgb([0xff, 0, 0]));
+    assert_eq!(*result.diff.get_pixel(15, 7), Rgb([0xff, 0, 0]));
+    assert_ne!(*result.diff.get_pixel(3, 3), Rgb([0xff, 0, 0]));
+
+    // Mismatched dimensions
+    let smaller = RgbImage::new(16, 7);
+    assert!(compare_images(&frame, &smaller, 0xff).is_err());
+    assert!(compare_images(&smaller, &frame, 0xff).is_err());
+}
@@ -1,26 +1,34 @@
 use std::{
     cell::RefCell,
+    collections::VecDeque,
     fs::File,
     io::{BufWriter, Write},
+    panic::AssertUnwindSafe,
     path::{Path, PathBuf},
     rc::Rc,
+    sync::{mpsc, Mutex},
 };
 
 use instant::{Duration, Instant};
 
 use anyhow::Result;
 use nes_emulator::{
-    framebuffer::FramebufferInfo,
     nes::{Nes, ProgressTarget},
     system::Model,
 };
 
 use crate::{
-    benchmark::BenchmarkState,
-    macros::{self, MacroPlayer},
+    benchmark::{self, BenchmarkState},
+    blargg, frame_image,
+    macros::{self, CheckFailure, FrameCrcs, Macro, MacroPlayer},
+    trace_diff::{TraceDiff, TraceDiffOptions, TraceDiffStatus},
     utils,
 };
 
+mod results;
+pub use results::ResultsFormat;
+use results::{MacroOutcome, MacroResult};
+
 const DUMMY_AUDIO_SAMPLE_RATE: u32 = 48000;
 
 fn progress_nes_emulation(nes: &mut Nes, stats: &mut BenchmarkState) -> bool {
@@ -42,23 +50,22 @@ fn progress_nes_emulation(nes: &mut Nes, stats: &mut BenchmarkState) -> bool {
     breakpoint
 }
 
-fn save_check_failed_image(nes: &mut Nes, name: &String, expected_failure: bool) {
-    let front = &nes.ppu_mut().framebuffer;
-    let fb_width = front.width();
-    let fb_height = front.height();
-
-    let fb_buf = &front.data;
-    let stride = fb_width * 4;
-    let mut imgbuf = image::ImageBuffer::new(fb_width as u32, fb_height as u32);
-    for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-        let x = x as usize;
-        let y = y as usize;
-        let r = fb_buf[stride * y + x * 4];
-        let g = fb_buf[stride * y + x * 4 + 1];
-        let b = fb_buf[stride * y + x * 4 + 2];
-        let _a = fb_buf[stride * y + x * 4 + 3];
-        *pixel = image::Rgb([r, g, b]);
-    }
+/// The images saved for a check that failed
+struct FailureImages {
+    screenshot: String,
+    /// For image checks, highlights the pixels that differ from the reference
+    diff: Option<String>,
+}
+
+/// Saves the current frame as a PNG (along with any diff image for an image
+/// check), returning the filenames
+fn save_check_failed_image(
+    nes: &mut Nes,
+    name: &String,
+    expected_failure: bool,
+    diff: Option<&image::RgbImage>,
+) -> FailureImages {
+    let imgbuf = frame_image::framebuffer_image(nes);
     let status = if expected_failure {
         "changed"
     } else {
@@ -73,24 +80,42 @@ fn save_check_failed_image(nes: &mut Nes, name: &String, expected_failure: bool)
     );
 
     log::warn!("{} {}: Saving debug image: {}", name, status, filename);
-    imgbuf.save(filename).unwrap();
+    imgbuf.save(&filename).unwrap();
+
+    let diff = diff.map(|diff| {
+        let diff_filename = format!("{}-diff.png", filename.trim_end_matches(".png"));
+        log::warn!("{name} {status}: Saving diff image: {diff_filename}");
+        diff.save(&diff_filename).unwrap();
+        diff_filename
+    });
+
+    FailureImages {
+        screenshot: filename,
+        diff,
+    }
 }
 
 fn setup_new_nes(
     rom_path: impl AsRef<Path>,
+    model: Option<Model>,
     rom_dirs: &[PathBuf],
     audio_sample_rate: u32,
     trace_file: Option<&String>,
+    fast_renderer: bool,
 ) -> Result<Nes> {
     let rom_path = match utils::search_rom_dirs(&rom_path, rom_dirs) {
         Some(rom) => rom,
         None => {
-            eprintln!("Failed to find ROM {}", rom_path.as_ref().to_string_lossy());
-            std::process::exit(1);
+            return Err(anyhow::anyhow!(
+                "Failed to find ROM {}",
+                rom_path.as_ref().to_string_lossy()
+            ));
         }
     };
 
-    let mut nes = utils::create_nes_from_binary_path(rom_path, audio_sample_rate, Instant::now())?;
+    let mut nes =
+        utils::create_nes_from_binary_path(rom_path, model, audio_sample_rate, Instant::now())?;
+    nes.ppu_mut().set_fast_renderer_enabled(fast_renderer);
 
     if let Some(trace) = trace_file {
         if trace == "-" {
@@ -111,110 +136,219 @@ fn setup_new_nes(
     Ok(nes)
 }
 
-#[derive(serde::Serialize)]
-struct MacroResult {
-    name: String,
+/// Options that apply to every macro played by [`run_macros`]
+struct MacroRunOptions<'a> {
+    rom_dirs: &'a [PathBuf],
+    /// Where to find reference images for `CheckFrameImage` checks
+    image_dir: Option<PathBuf>,
+    trace: Option<&'a String>,
+    fast_renderer: bool,
+    /// The number of frames a macro can run for before it's reported as a timeout
+    timeout_frames: u32,
+    /// An optional (non-deterministic) wall-clock limit for each macro
+    timeout: Option<Duration>,
 }
 
-pub fn run_macros(args: &crate::Args, rom_dirs: &[PathBuf], library: &String) -> Result<()> {
-    let shared_crc32 = Rc::new(RefCell::new(0u32));
+impl MacroRunOptions<'_> {
+    fn timeout_message(&self) -> String {
+        match self.timeout {
+            Some(timeout) => format!(
+                "Didn't finish within {} frames or {timeout:?}",
+                self.timeout_frames
+            ),
+            None => format!("Didn't finish within {} frames", self.timeout_frames),
+        }
+    }
+}
 
-    let mut nes = Nes::new(Model::Ntsc, DUMMY_AUDIO_SAMPLE_RATE, Instant::now());
+/// Plays a single macro to completion on a new [`Nes`]
+///
+/// Returns the outcome, along with the first check that failed and the
+/// images that were saved for it
+fn play_macro(
+    recording: Macro,
+    options: &MacroRunOptions,
+) -> Result<(MacroOutcome, Option<CheckFailure>, Option<FailureImages>)> {
+    log::debug!("Starting macro {}", recording.name);
+
+    let shared_crcs = Rc::new(RefCell::new(FrameCrcs::default()));
+    let mut nes = setup_new_nes(
+        &recording.rom,
+        recording.model.map(Model::from),
+        options.rom_dirs,
+        DUMMY_AUDIO_SAMPLE_RATE,
+        options.trace,
+        options.fast_renderer,
+    )?;
+    // To handle any CRC32 checks in the macro we register a hook that continuously tracks the CRC32 for every frame
+    let _crc_hook_handle = macros::register_frame_crc_hasher(&mut nes, shared_crcs.clone());
     let mut stats = BenchmarkState::new(&nes, Duration::from_secs(3));
 
-    let mut macro_player = None;
-
-    let mut macro_queue = macros::read_macro_library_from_file(library, &args.play_macros)?;
-    macro_queue.reverse(); // We'll be playing by popping off the end
-
-    let mut results = vec![];
-
-    loop {
-        if macro_player.is_none() {
-            if let Some(next_macro) = macro_queue.pop() {
-                log::debug!("Starting macro {}", next_macro.name);
-
-                nes = setup_new_nes(
-                    &next_macro.rom,
-                    rom_dirs,
-                    DUMMY_AUDIO_SAMPLE_RATE,
-                    args.trace.as_ref(),
-                )?;
-                // To handle any CRC32 checks in the macro we register a hook that continuously tracks the CRC32 for every frame
-                let _crc_hook_handle =
-                    macros::register_frame_crc_hasher(&mut nes, shared_crc32.clone());
-                stats = BenchmarkState::new(&nes, Duration::from_secs(3));
-
-                // macros run in headless mode are treated like tests and check failures are considered fatal
-                let mut player = MacroPlayer::new(next_macro, &mut nes, shared_crc32.clone());
-                player.set_check_failure_callback(Box::new(|nes, name, tags, _err| {
-                    let expected_failure = tags.contains("test_failure");
-                    save_check_failed_image(nes, name, expected_failure);
-                    //panic!("{}", err);
-                }));
-                macro_player = Some(player);
-            } else {
-                log::debug!("Macro queue empty");
-                break;
-            }
+    // macros run in headless mode are treated like tests and check failures are considered fatal
+    let mut player = MacroPlayer::new(recording, &mut nes, shared_crcs);
+    player.set_image_dir(options.image_dir.clone());
+    let images = Rc::new(RefCell::new(None));
+    let first_images = images.clone();
+    player.set_check_failure_callback(Box::new(move |nes, name, tags, _err, diff| {
+        let expected_failure = tags.contains("test_failure");
+        let saved = save_check_failed_image(nes, name, expected_failure, diff);
+        first_images.borrow_mut().get_or_insert(saved);
+        //panic!("{}", err);
+    }));
+
+    // The frame budget is counted here, since a macro may reset or power
+    // cycle the Nes
+    let mut frames = 0;
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    while player.playing() {
+        if frames >= options.timeout_frames
+            || deadline.is_some_and(|deadline| Instant::now() > deadline)
+        {
+            return Ok((MacroOutcome::Timeout, None, None));
         }
 
         let hit_breakpoint = progress_nes_emulation(&mut nes, &mut stats);
+        if hit_breakpoint {
+            player.check_breakpoint(&mut nes);
+        } else {
+            frames += 1;
+        }
+        player.update(&mut nes);
+    }
 
-        if let Some(player) = &mut macro_player {
-            if hit_breakpoint {
-                player.check_breakpoint(&mut nes);
-            }
+    let outcome = match (player.all_checks_passed(), player.checks_for_failure()) {
+        (true, false) => MacroOutcome::Passed,
+        (true, true) => MacroOutcome::ExpectedFailure,
+        (false, true) => MacroOutcome::Unknown,
+        (false, false) => MacroOutcome::Failed,
+    };
+    let check_failure = player.check_failures().first().cloned();
+    let images = images.borrow_mut().take();
+    Ok((outcome, check_failure, images))
+}
 
-            player.update(&mut nes);
-        }
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
 
-        if let Some(player) = &mut macro_player {
-            if !player.playing() {
-                #[allow(clippy::collapsible_else_if)]
-                if player.all_checks_passed() {
-                    if player.checks_for_failure() {
-                        results.push(serde_json::json!({
-                            "name": player.name(),
-                            "result": "EXPECTED_FAILURE"
-                        }));
-                        log::warn!("FAILED (as expected): {}", player.name());
-                        println!("FAILED (as expected): {}", player.name());
-                    } else {
-                        results.push(serde_json::json!({
-                            "name": player.name(),
-                            "result": "PASSED"
-                        }));
-                        log::debug!("PASSED: {}", player.name());
-                        println!("PASSED: {}", player.name());
-                    }
-                } else {
-                    if player.checks_for_failure() {
-                        results.push(serde_json::json!({
-                            "name": player.name(),
-                            "result": "UNKNOWN"
-                        }));
-                        log::warn!("UNKNOWN (didn't hit expected failure): {}", player.name());
-                        println!("UNKNOWN (didn't hit expected failure): {}", player.name());
-                    } else {
-                        results.push(serde_json::json!({
-                            "name": player.name(),
-                            "result": "FAILED"
-                        }));
-                        log::error!("FAILED: {}", player.name());
-                        println!("FAILED: {}", player.name());
+/// Plays all the macros selected from the given library, as tests
+///
+/// Since every macro is played on a new [`Nes`], the macros are shared out
+/// between a pool of worker threads (see `--jobs`). Results are always
+/// reported in the same order as the library, regardless of which macros
+/// finish first. A macro that panics is reported as crashed and one that
+/// runs for longer than `--macro-timeout-frames` (or `--macro-timeout`) is
+/// reported as a timeout, without affecting any other macros.
+pub fn run_macros(args: &crate::Args, rom_dirs: &[PathBuf], library: &String) -> Result<()> {
+    let macro_queue = macros::read_macro_library_from_file(library, &args.play_macros)?;
+    let n_macros = macro_queue.len();
+
+    let mut jobs = args
+        .jobs
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+        .clamp(1, n_macros.max(1));
+    if args.trace.is_some() && jobs > 1 {
+        log::warn!("Only running one macro at a time while tracing");
+        jobs = 1;
+    }
+
+    let options = MacroRunOptions {
+        rom_dirs,
+        image_dir: Path::new(library).parent().map(Path::to_path_buf),
+        trace: args.trace.as_ref(),
+        fast_renderer: args.fast_renderer,
+        timeout_frames: args.macro_timeout_frames,
+        timeout: args.macro_timeout.map(Duration::from_secs),
+    };
+    let macro_queue = Mutex::new(macro_queue.into_iter().enumerate().collect::<VecDeque<_>>());
+    let mut results: Vec<Option<MacroResult>> = (0..n_macros).map(|_| None).collect();
+
+    std::thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..jobs {
+            let tx = tx.clone();
+            let macro_queue = &macro_queue;
+            let options = &options;
+            scope.spawn(move || loop {
+                let Some((index, recording)) = macro_queue.lock().unwrap().pop_front() else {
+                    break;
+                };
+                let name = recording.name.clone();
+                let mut tags: Vec<String> = recording.tags.iter().cloned().collect();
+                tags.sort();
+                let start = Instant::now();
+                let outcome =
+                    std::panic::catch_unwind(AssertUnwindSafe(|| play_macro(recording, options)));
+                let duration = Instant::now() - start;
+                let (result, check_failure, images, message) = match outcome {
+                    Ok(Ok((result, check_failure, images))) => {
+                        (result, check_failure, images, None)
                     }
+                    Ok(Err(err)) => (MacroOutcome::Crashed, None, None, Some(format!("{err:#}"))),
+                    Err(payload) => (
+                        MacroOutcome::Crashed,
+                        None,
+                        None,
+                        Some(panic_message(&*payload)),
+                    ),
+                };
+                let message = message.or_else(|| {
+                    (result == MacroOutcome::Timeout).then(|| options.timeout_message())
+                });
+                let (screenshot, diff_image) = match images {
+                    Some(images) => (Some(images.screenshot), images.diff),
+                    None => (None, None),
+                };
+                let result = MacroResult {
+                    name,
+                    result,
+                    message,
+                    tags,
+                    duration,
+                    check_failure,
+                    screenshot,
+                    diff_image,
+                };
+                if tx.send((index, result)).is_err() {
+                    break;
                 }
-                macro_player = None;
+            });
+        }
+        drop(tx);
+
+        // Report results in library order as soon as all earlier macros have finished
+        let mut next_report = 0;
+        for (index, result) in rx {
+            results[index] = Some(result);
+            while let Some(Some(result)) = results.get(next_report) {
+                result.report();
+                next_report += 1;
             }
         }
+    });
+
+    let results: Vec<MacroResult> = results.into_iter().flatten().collect();
+    if let Some(results_path) = &args.results_json {
+        let suite_name = Path::new(library)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "macros".to_string());
+        results::write_results(results_path, args.results_format, &suite_name, &results)?;
     }
 
-    if let Some(results_json) = &args.results_json {
-        std::fs::write(
-            results_json,
-            serde_json::to_string_pretty(&results).unwrap(),
-        )?;
+    if let Some(baseline) = &args.baseline {
+        let regressions = results::compare_with_baseline(baseline, &results)?;
+        if regressions > 0 {
+            return Err(anyhow::anyhow!(
+                "{regressions} test(s) regressed compared to {baseline}"
+            ));
+        }
     }
 
     Ok(())
@@ -235,9 +369,11 @@ pub fn run_single_rom(args: &crate::Args, rom_dirs: &[PathBuf]) -> Result<()> {
 
     let mut nes = setup_new_nes(
         rom_path,
+        None,
         rom_dirs,
         DUMMY_AUDIO_SAMPLE_RATE,
         args.trace.as_ref(),
+        args.fast_renderer,
     )?;
     let mut stats = BenchmarkState::new(&nes, Duration::from_secs(3));
 
@@ -246,10 +382,113 @@ pub fn run_single_rom(args: &crate::Args, rom_dirs: &[PathBuf]) -> Result<()> {
     }
 }
 
+/// Runs until the CPU trace diverges from the `reference` log, or the
+/// reference ends, failing if that takes more than `max_frames` frames
+fn diff_trace(
+    nes: &mut Nes,
+    reference: &Path,
+    options: TraceDiffOptions,
+    max_frames: u32,
+) -> Result<TraceDiffStatus> {
+    let diff = Rc::new(RefCell::new(TraceDiff::new(
+        reference,
+        nes.model(),
+        options,
+    )?));
+    let hook_diff = diff.clone();
+    nes.add_cpu_instruction_trace_hook(Box::new(move |nes, trace_state| {
+        hook_diff.borrow_mut().compare(nes, trace_state);
+    }));
+
+    let mut stats = BenchmarkState::new(nes, Duration::from_secs(3));
+    let start_frame = nes.ppu().frame;
+    while matches!(diff.borrow().status(), TraceDiffStatus::Running) {
+        if nes.ppu().frame.wrapping_sub(start_frame) >= max_frames {
+            return Err(anyhow::anyhow!(
+                "Reached the limit of {max_frames} frames after {} matching instructions, before the end of {}",
+                diff.borrow().instructions(),
+                reference.display()
+            ));
+        }
+        progress_nes_emulation(nes, &mut stats);
+    }
+
+    let status = diff.borrow().status().clone();
+    Ok(status)
+}
+
+/// Runs a ROM until its CPU trace diverges from the `--trace-diff` reference
+/// log, or the reference ends, failing if that takes more than
+/// `--trace-diff-max-frames` frames
+pub fn run_trace_diff(args: &crate::Args, rom_dirs: &[PathBuf], reference: &str) -> Result<()> {
+    let Some(rom) = &args.rom else {
+        return Err(anyhow::anyhow!("A ROM must be specified for --trace-diff"));
+    };
+    let mut nes = setup_new_nes(
+        rom,
+        None,
+        rom_dirs,
+        DUMMY_AUDIO_SAMPLE_RATE,
+        args.trace.as_ref(),
+        args.fast_renderer,
+    )?;
+    if let Some(pc) = args.trace_diff_start_pc {
+        // The reset sequence has already run, so this only replaces the reset
+        // vector as the first instruction, leaving SP, P and the clock as-is
+        nes.cpu_mut().pc = pc;
+    }
+
+    let options = TraceDiffOptions {
+        ignore_cycles: args.trace_diff_ignore_cycles,
+        ignore_ppu: args.trace_diff_ignore_ppu,
+        cycle_offset: args.trace_diff_cycle_offset,
+        dot_offset: args.trace_diff_dot_offset,
+        context: args.trace_diff_context,
+    };
+    let status = diff_trace(
+        &mut nes,
+        Path::new(reference),
+        options,
+        args.trace_diff_max_frames,
+    )?;
+    match status {
+        TraceDiffStatus::Matched { instructions } => {
+            println!("Matched all {instructions} instructions in {reference}");
+            Ok(())
+        }
+        TraceDiffStatus::Diverged(divergence) => {
+            divergence.report();
+            Err(anyhow::anyhow!("Trace diverged from {reference}"))
+        }
+        TraceDiffStatus::Failed(err) => Err(anyhow::anyhow!(err)),
+        TraceDiffStatus::Running => unreachable!(),
+    }
+}
+
 pub fn headless_main(args: crate::Args) -> Result<()> {
     let rom_dirs = utils::canonicalize_rom_dirs(&args.rom_dir);
 
-    if let Some(library) = &args.macros {
+    let find_roms = |roms: &[String]| {
+        roms.iter()
+            .map(|rom| match utils::search_rom_dirs(rom, &rom_dirs) {
+                Some(path) => Ok(path),
+                None => Err(anyhow::anyhow!("Failed to find ROM {rom}")),
+            })
+            .collect::<Result<Vec<_>>>()
+    };
+
+    if !args.benchmark_mapper_dispatch.is_empty() {
+        let rom_paths = find_roms(&args.benchmark_mapper_dispatch)?;
+        benchmark::benchmark_mapper_dispatch(&rom_paths, DUMMY_AUDIO_SAMPLE_RATE, 600)?;
+    } else if !args.benchmark_fast_renderer.is_empty() {
+        let rom_paths = find_roms(&args.benchmark_fast_renderer)?;
+        benchmark::benchmark_fast_renderer(&rom_paths, DUMMY_AUDIO_SAMPLE_RATE, 600)?;
+    } else if let Some(dir) = &args.generate_blargg_macros {
+        let library = blargg::generate_macros(Path::new(dir))?;
+        println!("{}", serde_json::to_string_pretty(&library)?);
+    } else if let Some(reference) = &args.trace_diff {
+        run_trace_diff(&args, &rom_dirs, reference)?;
+    } else if let Some(library) = &args.macros {
         run_macros(&args, &rom_dirs, library)?;
     } else {
         run_single_rom(&args, &rom_dirs)?;
@@ -257,3 +496,71 @@ pub fn headless_main(args: crate::Args) -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_diff_trace_start_pc() {
+    // Like nestest.nes, the code under test doesn't run from the reset vector
+    // and has to be started with --trace-diff-start-pc
+    #[rustfmt::skip]
+    let program = [
+        0x4c, 0x00, 0x80, // $8000: JMP $8000
+        0xea, 0xea, 0xea, 0xea, 0xea, 0xea, 0xea, 0xea, 0xea, 0xea, 0xea, 0xea, 0xea,
+        0xa9, 0x42,       // $8010: LDA #$42
+        0xaa,             // $8012: TAX
+        0xe8,             // $8013: INX
+        0x4c, 0x10, 0x80, // $8014: JMP $8010
+    ];
+    // In the format of nestest.log
+    let reference = "\
+8010  A9 42     LDA #$42                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+8012  AA        TAX                             A:42 X:00 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9
+8013  E8        INX                             A:42 X:42 Y:00 P:24 SP:FD PPU:  0, 33 CYC:11
+8014  4C 10 80  JMP $8010                       A:42 X:43 Y:00 P:24 SP:FD PPU:  0, 39 CYC:13
+8010  A9 42     LDA #$42                        A:42 X:43 Y:00 P:24 SP:FD PPU:  0, 48 CYC:16
+8012  AA        TAX                             A:42 X:43 Y:00 P:24 SP:FD PPU:  0, 54 CYC:18
+";
+    let reference_path =
+        std::env::temp_dir().join(format!("trace-diff-test-{}.log", std::process::id()));
+    std::fs::write(&reference_path, reference).unwrap();
+
+    let diff = |start_pc: Option<u16>| {
+        let mut nes = utils::create_nes_from_binary(
+            &utils::test_nrom_binary(&program),
+            None,
+            DUMMY_AUDIO_SAMPLE_RATE,
+            Instant::now(),
+        )
+        .unwrap();
+        if let Some(pc) = start_pc {
+            nes.cpu_mut().pc = pc;
+        }
+        // Our reset sequence takes one more cycle than nestest.log assumes
+        let options = TraceDiffOptions {
+            cycle_offset: 1,
+            dot_offset: 3,
+            ..Default::default()
+        };
+        diff_trace(&mut nes, &reference_path, options, 10).unwrap()
+    };
+    let started = diff(Some(0x8010));
+    let from_reset = diff(None);
+    std::fs::remove_file(&reference_path).unwrap();
+
+    assert!(
+        matches!(started, TraceDiffStatus::Matched { instructions: 6 }),
+        "{started:?}"
+    );
+    assert!(
+        matches!(from_reset, TraceDiffStatus::Diverged(_)),
+        "{from_reset:?}"
+    );
+}
+
+#[test]
+fn test_parse_address() {
+    assert_eq!(crate::parse_address("C000"), Ok(0xc000));
+    assert_eq!(crate::parse_address("$c000"), Ok(0xc000));
+    assert_eq!(crate::parse_address("0x8010"), Ok(0x8010));
+    assert!(crate::parse_address("10000").is_err());
+    assert!(crate::parse_address("xyz").is_err());
+}
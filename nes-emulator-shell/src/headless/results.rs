@@ -0,0 +1,761 @@
+use std::{collections::HashMap, fmt::Write as _, path::Path, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use instant::Duration;
+
+use crate::macros::{CheckFailure, FailedCheck};
+
+/// The outcome of playing a macro in headless mode, where macros are treated
+/// like tests
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub(super) enum MacroOutcome {
+    Passed,
+    Failed,
+    /// All checks passed for a macro tagged with "test_failure" (i.e. the
+    /// test still fails in the same way)
+    ExpectedFailure,
+    /// A check failed for a macro tagged with "test_failure", so its behaviour
+    /// has changed
+    Unknown,
+    /// The emulator panicked, or the macro couldn't be started
+    Crashed,
+    /// The macro didn't finish within the timeout
+    Timeout,
+}
+
+impl MacroOutcome {
+    /// The status as printed to stdout, and as found in `tests/results.txt`
+    fn label(&self) -> &'static str {
+        match self {
+            MacroOutcome::Passed => "PASSED",
+            MacroOutcome::Failed => "FAILED",
+            MacroOutcome::ExpectedFailure => "FAILED (as expected)",
+            MacroOutcome::Unknown => "UNKNOWN (didn't hit expected failure)",
+            MacroOutcome::Crashed => "CRASHED",
+            MacroOutcome::Timeout => "TIMEOUT",
+        }
+    }
+
+    /// Whether the result matches what's expected for the macro
+    ///
+    /// Macros tagged with "test_failure" record how a test currently fails, so
+    /// an expected failure is still good.
+    fn is_good(&self) -> bool {
+        matches!(self, MacroOutcome::Passed | MacroOutcome::ExpectedFailure)
+    }
+
+    const ALL: [MacroOutcome; 6] = [
+        MacroOutcome::Passed,
+        MacroOutcome::Failed,
+        MacroOutcome::ExpectedFailure,
+        MacroOutcome::Unknown,
+        MacroOutcome::Crashed,
+        MacroOutcome::Timeout,
+    ];
+}
+
+fn serialize_duration_secs<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+#[derive(serde::Serialize)]
+pub(super) struct MacroResult {
+    pub name: String,
+    pub result: MacroOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+
+    /// Sorted tags from the macro
+    pub tags: Vec<String>,
+    #[serde(rename = "duration_secs", serialize_with = "serialize_duration_secs")]
+    pub duration: Duration,
+
+    /// The first check that failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub check_failure: Option<CheckFailure>,
+    /// The image saved for the first check that failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub screenshot: Option<String>,
+    /// For a failed image check, highlights the pixels that differ
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff_image: Option<String>,
+}
+
+impl MacroResult {
+    pub fn report(&self) {
+        let name = &self.name;
+        let label = self.result.label();
+        match self.result {
+            MacroOutcome::Passed => log::debug!("{label}: {name}"),
+            MacroOutcome::ExpectedFailure | MacroOutcome::Unknown => log::warn!("{label}: {name}"),
+            _ => log::error!("{label}: {name}"),
+        }
+        println!("{label}: {name}");
+        if let Some(message) = &self.message {
+            log::error!("{name}: {message}");
+            println!("    {message}");
+        }
+    }
+
+    /// A summary of why the macro didn't pass
+    fn details(&self) -> String {
+        let mut details = String::new();
+        if let Some(message) = &self.message {
+            let _ = writeln!(details, "{message}");
+        }
+        if let Some(failure) = &self.check_failure {
+            let (frame, line, dot) = (failure.frame, failure.line, failure.dot);
+            match &failure.check {
+                FailedCheck::FrameCRC32 {
+                    raw,
+                    expected,
+                    actual,
+                } => {
+                    let kind = if *raw { "Raw frame" } else { "Frame" };
+                    let _ = writeln!(
+                        details,
+                        "{kind} CRC32 check failed at frame {frame}, line {line}, dot {dot}: expected {expected:08x}, actual {actual:08x}",
+                    );
+                }
+                FailedCheck::BlarggStatus { code, text } => {
+                    let _ = writeln!(
+                        details,
+                        "Test ROM failed with code {code} at frame {frame}, line {line}, dot {dot}:\n{text}"
+                    );
+                }
+                FailedCheck::FrameImage {
+                    path,
+                    tolerance,
+                    max_differing_pixels,
+                    differing_pixels,
+                    max_difference,
+                } => {
+                    let _ = writeln!(
+                        details,
+                        "Image check failed at frame {frame}, line {line}, dot {dot}: {differing_pixels} pixels differ from {path} by more than {tolerance} (allowed {max_differing_pixels}, max difference = {max_difference})"
+                    );
+                }
+                FailedCheck::FrameImageError { path, error } => {
+                    let _ = writeln!(
+                        details,
+                        "Image check against {path} failed at frame {frame}, line {line}, dot {dot}: {error}"
+                    );
+                }
+                FailedCheck::Timeout { condition, frames } => {
+                    let _ = writeln!(
+                        details,
+                        "Timed out at frame {frame}, line {line}, dot {dot} after waiting {frames} frames until {condition}"
+                    );
+                }
+                FailedCheck::BlarggTimeout { frames } => {
+                    let _ = writeln!(
+                        details,
+                        "Timed out at frame {frame}, line {line}, dot {dot} after waiting {frames} frames for the test ROM status"
+                    );
+                }
+                FailedCheck::Memory {
+                    addr,
+                    mask,
+                    expected,
+                    actual,
+                } => {
+                    let _ = writeln!(
+                        details,
+                        "Memory check failed at frame {frame}, line {line}, dot {dot}: [{addr:04x}] = {actual:02x}, expected {expected:02x} (mask = {mask:02x})"
+                    );
+                }
+                FailedCheck::CpuRegisters { expected, actual } => {
+                    let _ = writeln!(
+                        details,
+                        "CPU registers check failed at frame {frame}, line {line}, dot {dot}: {actual}, expected {expected}"
+                    );
+                }
+            }
+        }
+        if let Some(screenshot) = &self.screenshot {
+            let _ = writeln!(details, "Screenshot: {screenshot}");
+        }
+        if let Some(diff_image) = &self.diff_image {
+            let _ = writeln!(details, "Diff image: {diff_image}");
+        }
+        details
+    }
+}
+
+/// The file format for macro results (see `--results-format`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResultsFormat {
+    #[default]
+    Json,
+    JUnit,
+    Tap,
+}
+
+impl FromStr for ResultsFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(ResultsFormat::Json),
+            "junit" => Ok(ResultsFormat::JUnit),
+            "tap" => Ok(ResultsFormat::Tap),
+            _ => Err(anyhow!(
+                "Unknown results format \"{s}\" (expected json, junit or tap)"
+            )),
+        }
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Key, value pairs describing a failed check, for JUnit properties and TAP
+/// diagnostics
+fn check_failure_fields(failure: &CheckFailure) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("frame", failure.frame.to_string()),
+        ("line", failure.line.to_string()),
+        ("dot", failure.dot.to_string()),
+    ];
+    match &failure.check {
+        FailedCheck::FrameCRC32 {
+            raw,
+            expected,
+            actual,
+        } => {
+            fields.push(("raw", raw.to_string()));
+            fields.push(("expected_crc32", format!("{expected:08x}")));
+            fields.push(("actual_crc32", format!("{actual:08x}")));
+        }
+        FailedCheck::BlarggStatus { code, text } => {
+            fields.push(("result_code", code.to_string()));
+            fields.push(("result_text", text.clone()));
+        }
+        FailedCheck::FrameImage {
+            path,
+            tolerance,
+            max_differing_pixels,
+            differing_pixels,
+            max_difference,
+        } => {
+            fields.push(("reference_image", path.clone()));
+            fields.push(("tolerance", tolerance.to_string()));
+            fields.push(("max_differing_pixels", max_differing_pixels.to_string()));
+            fields.push(("differing_pixels", differing_pixels.to_string()));
+            fields.push(("max_difference", max_difference.to_string()));
+        }
+        FailedCheck::FrameImageError { path, error } => {
+            fields.push(("reference_image", path.clone()));
+            fields.push(("error", error.clone()));
+        }
+        FailedCheck::Timeout { condition, frames } => {
+            fields.push(("condition", condition.to_string()));
+            fields.push(("timeout_frames", frames.to_string()));
+        }
+        FailedCheck::BlarggTimeout { frames } => {
+            fields.push(("timeout_frames", frames.to_string()));
+        }
+        FailedCheck::Memory {
+            addr,
+            mask,
+            expected,
+            actual,
+        } => {
+            fields.push(("address", format!("{addr:04x}")));
+            fields.push(("mask", format!("{mask:02x}")));
+            fields.push(("expected", format!("{expected:02x}")));
+            fields.push(("actual", format!("{actual:02x}")));
+        }
+        FailedCheck::CpuRegisters { expected, actual } => {
+            fields.push(("expected", expected.to_string()));
+            fields.push(("actual", actual.to_string()));
+        }
+    }
+    fields
+}
+
+fn format_junit(suite_name: &str, results: &[MacroResult]) -> String {
+    let count = |outcomes: &[MacroOutcome]| {
+        results
+            .iter()
+            .filter(|r| outcomes.contains(&r.result))
+            .count()
+    };
+    let failures = count(&[MacroOutcome::Failed, MacroOutcome::Unknown]);
+    let errors = count(&[MacroOutcome::Crashed, MacroOutcome::Timeout]);
+    let skipped = count(&[MacroOutcome::ExpectedFailure]);
+    let time: f64 = results.iter().map(|r| r.duration.as_secs_f64()).sum();
+    let suite_name = xml_escape(suite_name);
+
+    let mut xml = String::new();
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        xml,
+        r#"<testsuites name="{suite_name}" tests="{}" failures="{failures}" errors="{errors}" skipped="{skipped}" time="{time:.3}">"#,
+        results.len()
+    );
+    let _ = writeln!(
+        xml,
+        r#"  <testsuite name="{suite_name}" tests="{}" failures="{failures}" errors="{errors}" skipped="{skipped}" time="{time:.3}">"#,
+        results.len()
+    );
+    for result in results {
+        // Group tests by their first tag, like the HTML report
+        let classname = result.tags.first().map(String::as_str).unwrap_or("misc");
+        let _ = writeln!(
+            xml,
+            r#"    <testcase name="{}" classname="{}" time="{:.3}">"#,
+            xml_escape(&result.name),
+            xml_escape(classname),
+            result.duration.as_secs_f64()
+        );
+        let _ = writeln!(xml, "      <properties>");
+        let _ = writeln!(
+            xml,
+            r#"        <property name="tags" value="{}"/>"#,
+            xml_escape(&result.tags.join(","))
+        );
+        let _ = writeln!(
+            xml,
+            r#"        <property name="result" value="{}"/>"#,
+            xml_escape(result.result.label())
+        );
+        if let Some(failure) = &result.check_failure {
+            for (name, value) in check_failure_fields(failure) {
+                let _ = writeln!(
+                    xml,
+                    r#"        <property name="{name}" value="{}"/>"#,
+                    xml_escape(&value)
+                );
+            }
+        }
+        if let Some(screenshot) = &result.screenshot {
+            let _ = writeln!(
+                xml,
+                r#"        <property name="screenshot" value="{}"/>"#,
+                xml_escape(screenshot)
+            );
+        }
+        if let Some(diff_image) = &result.diff_image {
+            let _ = writeln!(
+                xml,
+                r#"        <property name="diff_image" value="{}"/>"#,
+                xml_escape(diff_image)
+            );
+        }
+        let _ = writeln!(xml, "      </properties>");
+
+        let details = xml_escape(&result.details());
+        match result.result {
+            MacroOutcome::Passed => {}
+            MacroOutcome::ExpectedFailure => {
+                let _ = writeln!(xml, r#"      <skipped message="Expected failure"/>"#);
+            }
+            MacroOutcome::Failed | MacroOutcome::Unknown => {
+                let _ = writeln!(
+                    xml,
+                    r#"      <failure message="{}">{details}</failure>"#,
+                    xml_escape(result.result.label())
+                );
+            }
+            MacroOutcome::Crashed | MacroOutcome::Timeout => {
+                let _ = writeln!(
+                    xml,
+                    r#"      <error message="{}">{details}</error>"#,
+                    xml_escape(result.result.label())
+                );
+            }
+        }
+        if let Some(screenshot) = &result.screenshot {
+            // Recognised by some CI systems for attaching files to a test case
+            let mut attachments = format!("[[ATTACHMENT|{}]]", xml_escape(screenshot));
+            if let Some(diff_image) = &result.diff_image {
+                let _ = write!(attachments, "\n[[ATTACHMENT|{}]]", xml_escape(diff_image));
+            }
+            let _ = writeln!(xml, "      <system-out>{attachments}</system-out>");
+        }
+        let _ = writeln!(xml, "    </testcase>");
+    }
+    let _ = writeln!(xml, "  </testsuite>");
+    let _ = writeln!(xml, "</testsuites>");
+    xml
+}
+
+fn format_tap(results: &[MacroResult]) -> String {
+    let mut tap = String::new();
+    let _ = writeln!(tap, "TAP version 13");
+    let _ = writeln!(tap, "1..{}", results.len());
+    for (i, result) in results.iter().enumerate() {
+        let n = i + 1;
+        let name = &result.name;
+        match result.result {
+            MacroOutcome::Passed => {
+                let _ = writeln!(tap, "ok {n} - {name}");
+            }
+            MacroOutcome::ExpectedFailure => {
+                let _ = writeln!(tap, "not ok {n} - {name} # TODO expected failure");
+            }
+            _ => {
+                let _ = writeln!(tap, "not ok {n} - {name}");
+            }
+        }
+
+        let _ = writeln!(tap, "  ---");
+        let _ = writeln!(tap, "  result: \"{}\"", result.result.label());
+        let _ = writeln!(tap, "  duration_ms: {}", result.duration.as_millis());
+        let _ = writeln!(tap, "  tags: [{}]", result.tags.join(", "));
+        if let Some(message) = &result.message {
+            let _ = writeln!(tap, "  message: {message:?}");
+        }
+        if let Some(failure) = &result.check_failure {
+            for (name, value) in check_failure_fields(failure) {
+                let _ = writeln!(tap, "  {name}: {value:?}");
+            }
+        }
+        if let Some(screenshot) = &result.screenshot {
+            let _ = writeln!(tap, "  screenshot: {screenshot:?}");
+        }
+        if let Some(diff_image) = &result.diff_image {
+            let _ = writeln!(tap, "  diff_image: {diff_image:?}");
+        }
+        let _ = writeln!(tap, "  ...");
+    }
+    tap
+}
+
+pub(super) fn write_results(
+    path: impl AsRef<Path>,
+    format: ResultsFormat,
+    suite_name: &str,
+    results: &[MacroResult],
+) -> Result<()> {
+    let contents = match format {
+        ResultsFormat::Json => serde_json::to_string_pretty(results)?,
+        ResultsFormat::JUnit => format_junit(suite_name, results),
+        ResultsFormat::Tap => format_tap(results),
+    };
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Reads the outcome of each macro from previous results
+///
+/// This accepts either the JSON written by `--results` or the text printed to
+/// stdout while playing macros (such as `tests/results.txt`).
+fn read_baseline(path: impl AsRef<Path>) -> Result<HashMap<String, MacroOutcome>> {
+    #[derive(serde::Deserialize)]
+    struct JsonResult {
+        name: String,
+        result: MacroOutcome,
+    }
+
+    let contents = std::fs::read_to_string(path)?;
+    if let Ok(json) = serde_json::from_str::<Vec<JsonResult>>(&contents) {
+        return Ok(json.into_iter().map(|r| (r.name, r.result)).collect());
+    }
+    Ok(parse_baseline_text(&contents))
+}
+
+/// Parses the outcome of each macro from the text printed to stdout while
+/// playing macros, ignoring any other lines
+fn parse_baseline_text(contents: &str) -> HashMap<String, MacroOutcome> {
+    let mut baseline = HashMap::new();
+    for line in contents.lines() {
+        // Note: the longest labels need to be checked first since "FAILED" is
+        // a prefix of "FAILED (as expected)"
+        let mut outcomes = MacroOutcome::ALL;
+        outcomes.sort_by_key(|outcome| std::cmp::Reverse(outcome.label().len()));
+        for outcome in outcomes {
+            if let Some(name) = line
+                .strip_prefix(outcome.label())
+                .and_then(|rest| rest.strip_prefix(": "))
+            {
+                baseline.insert(name.to_string(), outcome);
+                break;
+            }
+        }
+    }
+    baseline
+}
+
+/// Compares results against a baseline, printing any tests whose result
+/// changed
+///
+/// Returns the number of tests that regressed (i.e. that had a good result in
+/// the baseline but not any more)
+pub(super) fn compare_with_baseline(
+    baseline_path: impl AsRef<Path>,
+    results: &[MacroResult],
+) -> Result<usize> {
+    let baseline = read_baseline(baseline_path)?;
+    Ok(compare_results(&baseline, results))
+}
+
+fn compare_results(baseline: &HashMap<String, MacroOutcome>, results: &[MacroResult]) -> usize {
+    let mut newly_passing = vec![];
+    let mut newly_failing = vec![];
+    let mut changed = vec![];
+    let mut added = vec![];
+    for result in results {
+        match baseline.get(&result.name) {
+            Some(&before) if before != result.result => {
+                if !before.is_good() && result.result.is_good() {
+                    newly_passing.push((result, before));
+                } else if before.is_good() && !result.result.is_good() {
+                    newly_failing.push((result, before));
+                } else {
+                    changed.push((result, before));
+                }
+            }
+            Some(_) => {}
+            None => added.push(result),
+        }
+    }
+    let mut missing: Vec<&String> = baseline
+        .keys()
+        .filter(|name| !results.iter().any(|r| &r.name == *name))
+        .collect();
+    missing.sort();
+
+    let print_changes = |heading: &str, changes: &[(&MacroResult, MacroOutcome)]| {
+        if !changes.is_empty() {
+            println!("{heading}:");
+            for (result, before) in changes {
+                println!(
+                    "    {}: {} -> {}",
+                    result.name,
+                    before.label(),
+                    result.result.label()
+                );
+            }
+        }
+    };
+    print_changes("Newly passing", &newly_passing);
+    print_changes("Newly failing", &newly_failing);
+    print_changes("Changed", &changed);
+    if !added.is_empty() {
+        println!("Not in baseline:");
+        for result in added {
+            println!("    {}: {}", result.name, result.result.label());
+        }
+    }
+    if !missing.is_empty() {
+        println!("Missing from results:");
+        for name in missing {
+            println!("    {name}");
+        }
+    }
+    println!(
+        "Compared with baseline: {} newly passing, {} newly failing",
+        newly_passing.len(),
+        newly_failing.len()
+    );
+
+    newly_failing.len()
+}
+
+#[cfg(test)]
+fn test_result(name: &str, result: MacroOutcome) -> MacroResult {
+    MacroResult {
+        name: name.to_string(),
+        result,
+        message: None,
+        tags: vec![],
+        duration: Duration::from_millis(1500),
+        check_failure: None,
+        screenshot: None,
+        diff_image: None,
+    }
+}
+
+#[test]
+fn test_parse_baseline_text() {
+    let baseline = parse_baseline_text(
+        "PASSED: instr_test-v5:01-basics\n\
+         FAILED: sprite_hit_tests_2005.10.05:09.timing_basics\n\
+         FAILED (as expected): ppu_vbl_nmi\n\
+         UNKNOWN (didn't hit expected failure): scrolltest:scroll\n\
+         CRASHED: mmc3_test:1-clocking\n\
+         TIMEOUT: cpu_interrupts_v2\n\
+         \x20   Didn't finish within 18000 frames\n\
+         Compared with baseline: 0 newly passing, 0 newly failing\n\
+         PASSED:missing_space\n",
+    );
+
+    let expected = [
+        ("instr_test-v5:01-basics", MacroOutcome::Passed),
+        (
+            "sprite_hit_tests_2005.10.05:09.timing_basics",
+            MacroOutcome::Failed,
+        ),
+        // Must not be read as "FAILED" with the name "(as expected): ppu_vbl_nmi"
+        ("ppu_vbl_nmi", MacroOutcome::ExpectedFailure),
+        ("scrolltest:scroll", MacroOutcome::Unknown),
+        ("mmc3_test:1-clocking", MacroOutcome::Crashed),
+        ("cpu_interrupts_v2", MacroOutcome::Timeout),
+    ];
+    assert_eq!(baseline.len(), expected.len());
+    for (name, outcome) in expected {
+        assert_eq!(baseline.get(name), Some(&outcome), "{name}");
+    }
+}
+
+#[test]
+fn test_compare_results() {
+    let baseline: HashMap<String, MacroOutcome> = [
+        ("still_passing", MacroOutcome::Passed),
+        ("now_failing", MacroOutcome::Passed),
+        ("now_crashing", MacroOutcome::ExpectedFailure),
+        ("now_passing", MacroOutcome::Failed),
+        ("still_failing", MacroOutcome::Failed),
+        ("timeout_to_crash", MacroOutcome::Timeout),
+        ("removed", MacroOutcome::Passed),
+    ]
+    .into_iter()
+    .map(|(name, outcome)| (name.to_string(), outcome))
+    .collect();
+    let results = [
+        test_result("still_passing", MacroOutcome::Passed),
+        test_result("now_failing", MacroOutcome::Failed),
+        test_result("now_crashing", MacroOutcome::Crashed),
+        test_result("now_passing", MacroOutcome::Passed),
+        test_result("still_failing", MacroOutcome::Failed),
+        test_result("timeout_to_crash", MacroOutcome::Crashed),
+        test_result("added", MacroOutcome::Failed),
+    ];
+    // Only tests that had a good result in the baseline can regress
+    assert_eq!(compare_results(&baseline, &results), 2);
+
+    let results = [
+        test_result("still_passing", MacroOutcome::Passed),
+        test_result("now_passing", MacroOutcome::ExpectedFailure),
+    ];
+    assert_eq!(compare_results(&baseline, &results), 0);
+}
+
+#[test]
+fn test_compare_with_json_baseline() {
+    let results = [
+        test_result("a", MacroOutcome::Passed),
+        test_result("b", MacroOutcome::ExpectedFailure),
+    ];
+    let path = std::env::temp_dir().join(format!("baseline-{}.json", std::process::id()));
+    write_results(&path, ResultsFormat::Json, "tests", &results).unwrap();
+    let baseline = read_baseline(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    let baseline = baseline.unwrap();
+    assert_eq!(baseline.get("a"), Some(&MacroOutcome::Passed));
+    assert_eq!(baseline.get("b"), Some(&MacroOutcome::ExpectedFailure));
+    let results = [
+        test_result("a", MacroOutcome::Timeout),
+        test_result("b", MacroOutcome::Unknown),
+    ];
+    assert_eq!(compare_results(&baseline, &results), 2);
+}
+
+#[test]
+fn test_xml_escape() {
+    assert_eq!(
+        xml_escape(r#"<a href="x">Tom & Jerry's</a>"#),
+        "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;"
+    );
+    assert_eq!(xml_escape("plain: text"), "plain: text");
+}
+
+#[test]
+fn test_format_junit() {
+    let mut failed = test_result("blargg <apu> & \"dmc\"", MacroOutcome::Failed);
+    failed.tags = vec!["apu".to_string(), "blargg".to_string()];
+    failed.check_failure = Some(CheckFailure {
+        frame: 10,
+        line: 20,
+        dot: 30,
+        check: FailedCheck::BlarggStatus {
+            code: 2,
+            text: "Failed <here>".to_string(),
+        },
+    });
+    failed.screenshot = Some("fail&1.png".to_string());
+    let mut timeout = test_result("timeout", MacroOutcome::Timeout);
+    timeout.message = Some("Didn't finish within 10 frames".to_string());
+    let results = [
+        test_result("passed", MacroOutcome::Passed),
+        failed,
+        test_result("expected", MacroOutcome::ExpectedFailure),
+        timeout,
+    ];
+
+    let xml = format_junit("tests & more", &results);
+    assert!(xml.contains(
+        r#"<testsuites name="tests &amp; more" tests="4" failures="1" errors="1" skipped="1" time="6.000">"#
+    ));
+    assert!(xml.contains(
+        r#"<testcase name="blargg &lt;apu&gt; &amp; &quot;dmc&quot;" classname="apu" time="1.500">"#
+    ));
+    assert!(xml.contains(r#"<testcase name="passed" classname="misc" time="1.500">"#));
+    assert!(xml.contains(r#"<property name="tags" value="apu,blargg"/>"#));
+    assert!(xml.contains(r#"<property name="result_text" value="Failed &lt;here&gt;"/>"#));
+    assert!(xml.contains(
+        r#"<failure message="FAILED">Test ROM failed with code 2 at frame 10, line 20, dot 30:"#
+    ));
+    assert!(xml.contains(r#"<skipped message="Expected failure"/>"#));
+    assert!(xml.contains(r#"<error message="TIMEOUT">Didn&apos;t finish within 10 frames"#));
+    assert!(xml.contains("<system-out>[[ATTACHMENT|fail&amp;1.png]]</system-out>"));
+    // Nothing should be left unescaped inside attributes or text
+    assert!(!xml.contains("<apu>"));
+    assert!(!xml.contains("fail&1"));
+}
+
+#[test]
+fn test_format_tap() {
+    let mut failed = test_result("failed", MacroOutcome::Failed);
+    failed.tags = vec!["cpu".to_string(), "ppu".to_string()];
+    failed.check_failure = Some(CheckFailure {
+        frame: 1,
+        line: 2,
+        dot: 3,
+        check: FailedCheck::FrameCRC32 {
+            raw: false,
+            expected: 0x1234,
+            actual: 0xabcd,
+        },
+    });
+    let results = [
+        test_result("passed", MacroOutcome::Passed),
+        failed,
+        test_result("expected", MacroOutcome::ExpectedFailure),
+    ];
+
+    let tap = format_tap(&results);
+    let lines: Vec<&str> = tap.lines().collect();
+    assert_eq!(lines[0], "TAP version 13");
+    assert_eq!(lines[1], "1..3");
+    assert_eq!(lines[2], "ok 1 - passed");
+    assert!(lines.contains(&"not ok 2 - failed"));
+    assert!(lines.contains(&"not ok 3 - expected # TODO expected failure"));
+    assert!(lines.contains(&"  result: \"FAILED\""));
+    assert!(lines.contains(&"  duration_ms: 1500"));
+    assert!(lines.contains(&"  tags: [cpu, ppu]"));
+    assert!(lines.contains(&"  expected_crc32: \"00001234\""));
+    assert!(lines.contains(&"  actual_crc32: \"0000abcd\""));
+    // Every test has a YAML block
+    assert_eq!(lines.iter().filter(|line| **line == "  ---").count(), 3);
+    assert_eq!(lines.iter().filter(|line| **line == "  ...").count(), 3);
+}
@@ -6,8 +6,11 @@
 use clap::Parser;
 
 mod benchmark;
+mod blargg;
+mod frame_image;
 pub mod headless;
 mod macros;
+mod trace_diff;
 pub mod ui;
 mod utils;
 
@@ -56,12 +59,45 @@ pub struct Args {
     )]
     pub play_macros: Vec<String>,
 
+    #[clap(
+        short = 'j',
+        long = "jobs",
+        help = "The number of macros to play in parallel in headless mode (defaults to the number of CPUs)"
+    )]
+    pub jobs: Option<usize>,
+
+    #[clap(
+        long = "macro-timeout-frames",
+        default_value = "18000",
+        help = "Report a macro played in headless mode as a timeout if it runs for more than this many frames"
+    )]
+    pub macro_timeout_frames: u32,
+
+    #[clap(
+        long = "macro-timeout",
+        help = "Also report a macro played in headless mode as a timeout if it takes longer than this many seconds (not deterministic)"
+    )]
+    pub macro_timeout: Option<u64>,
+
     #[clap(
         long = "results",
-        help = "Write the results of running macros to the given file in JSON format"
+        help = "Write the results of running macros to the given file (see --results-format)"
     )]
     pub results_json: Option<String>,
 
+    #[clap(
+        long = "results-format",
+        default_value = "json",
+        help = "The format for --results: json, junit (XML) or tap"
+    )]
+    pub results_format: headless::ResultsFormat,
+
+    #[clap(
+        long = "baseline",
+        help = "Compare the results of running macros against a previous JSON results file or the output from a previous run (such as tests/results.txt), and fail if any tests regressed"
+    )]
+    pub baseline: Option<String>,
+
     #[clap(
         short = 'd',
         long = "rom-dir",
@@ -71,6 +107,94 @@ pub struct Args {
 
     #[clap(short = 'g', long = "genie", help = "Game Genie Code")]
     pub genie_codes: Vec<String>,
+
+    #[clap(
+        long = "fast-renderer",
+        help = "Let the PPU render whole spans of a line at once when nothing can observe the difference"
+    )]
+    pub fast_renderer: bool,
+
+    #[clap(
+        long = "benchmark-mapper-dispatch",
+        help = "Compare the speed of the built-in mapper dispatch against dynamic dispatch for the given ROMs (headless only)"
+    )]
+    pub benchmark_mapper_dispatch: Vec<String>,
+
+    #[clap(
+        long = "benchmark-fast-renderer",
+        help = "Compare the speed of stepping the PPU one dot at a time against the PPU's fast renderer for the given ROMs (headless only)"
+    )]
+    pub benchmark_fast_renderer: Vec<String>,
+
+    #[clap(
+        long = "generate-blargg-macros",
+        help = "Print a macro library (JSON) with a test for every ROM under the given directory that reports its status via $6000, like blargg's test ROMs (headless only)"
+    )]
+    pub generate_blargg_macros: Option<String>,
+
+    #[clap(
+        long = "trace-diff",
+        help = "Run the ROM, comparing the CPU trace against a reference trace log from another emulator (such as Mesen, FCEUX or nestest.log) and stop at the first divergence (headless only)"
+    )]
+    pub trace_diff: Option<String>,
+
+    #[clap(
+        long = "trace-diff-ignore-cycles",
+        help = "Don't compare CPU cycle counts with --trace-diff"
+    )]
+    pub trace_diff_ignore_cycles: bool,
+
+    #[clap(
+        long = "trace-diff-ignore-ppu",
+        help = "Don't compare PPU lines and dots with --trace-diff"
+    )]
+    pub trace_diff_ignore_ppu: bool,
+
+    #[clap(
+        long = "trace-diff-cycle-offset",
+        default_value = "0",
+        allow_hyphen_values = true,
+        help = "Add this to the reference CPU cycle counts with --trace-diff, for emulators that count from a different start"
+    )]
+    pub trace_diff_cycle_offset: i64,
+
+    #[clap(
+        long = "trace-diff-dot-offset",
+        default_value = "0",
+        allow_hyphen_values = true,
+        help = "Add this many dots to the reference PPU positions with --trace-diff, for emulators that sample the PPU at a different point of each instruction"
+    )]
+    pub trace_diff_dot_offset: i64,
+
+    #[clap(
+        long = "trace-diff-context",
+        default_value = "10",
+        help = "The number of matching instructions to show before a divergence with --trace-diff"
+    )]
+    pub trace_diff_context: usize,
+
+    #[clap(
+        long = "trace-diff-max-frames",
+        default_value = "3600",
+        help = "Fail --trace-diff if the reference hasn't ended or diverged after this many frames"
+    )]
+    pub trace_diff_max_frames: u32,
+
+    #[clap(
+        long = "trace-diff-start-pc",
+        parse(try_from_str = parse_address),
+        help = "Start executing from this address (hex) after reset with --trace-diff, such as C000 for the automated mode of nestest.nes (which also needs --trace-diff-cycle-offset 1 --trace-diff-dot-offset 3 to compare against nestest.log)"
+    )]
+    pub trace_diff_start_pc: Option<u16>,
+}
+
+/// Parses a hex address, with an optional `$` or `0x` prefix
+fn parse_address(addr: &str) -> Result<u16, std::num::ParseIntError> {
+    let digits = addr
+        .strip_prefix('$')
+        .or_else(|| addr.strip_prefix("0x"))
+        .unwrap_or(addr);
+    u16::from_str_radix(digits, 16)
 }
 
 /*
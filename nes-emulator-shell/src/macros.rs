@@ -1,23 +1,30 @@
 use anyhow::Result;
 use instant::{Duration, Instant};
 use nes_emulator::{
+    cpu::core::BreakpointHandle,
     genie::GameGenieCode,
     hook::HookHandle,
     nes::Nes,
     port::ControllerButton,
-    ppu::{DotBreakpointCallbackAction, DotBreakpointHandle},
+    ppu::{DotBreakpointCallbackAction, DotBreakpointHandle, FnMuxHook},
+    system::{Model, WriteBreakpointHandle},
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::path::PathBuf;
+#[cfg(not(target_arch = "wasm32"))]
+use std::str::FromStr;
 use std::{
     cell::{Cell, RefCell},
+    fmt,
     path::Path,
     rc::Rc,
 };
-#[cfg(not(target_arch = "wasm32"))]
-use std::{path::PathBuf, str::FromStr};
 
-use crate::RomIdentifier;
+use crate::{
+    blargg::{BlarggStatus, BlarggStatusMonitor},
+    frame_image, RomIdentifier,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MacroWait {
@@ -64,6 +71,162 @@ pub enum InputEvent {
     },
 }
 
+/// CPU register values for a [`MacroCommand::CheckCpuRegisters`] check, where
+/// any registers that aren't given aren't checked
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuRegisters {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub a: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sp: Option<u8>,
+    /// The status flags
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pc: Option<u16>,
+}
+
+impl CpuRegisters {
+    /// All of the registers from the current CPU state
+    pub fn current(nes: &mut Nes) -> Self {
+        let cpu = nes.cpu_mut();
+        Self {
+            a: Some(cpu.a),
+            x: Some(cpu.x),
+            y: Some(cpu.y),
+            sp: Some(cpu.sp),
+            p: Some(cpu.p.bits()),
+            pc: Some(cpu.pc),
+        }
+    }
+
+    /// Returns `true` if all of the given registers match `actual`
+    pub fn matches(&self, actual: &CpuRegisters) -> bool {
+        fn check<T: PartialEq>(expected: Option<T>, actual: Option<T>) -> bool {
+            expected.is_none() || expected == actual
+        }
+        check(self.a, actual.a)
+            && check(self.x, actual.x)
+            && check(self.y, actual.y)
+            && check(self.sp, actual.sp)
+            && check(self.p, actual.p)
+            && check(self.pc, actual.pc)
+    }
+}
+
+impl fmt::Display for CpuRegisters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut registers = vec![];
+        for (name, value) in [
+            ("A", self.a),
+            ("X", self.x),
+            ("Y", self.y),
+            ("SP", self.sp),
+            ("P", self.p),
+        ] {
+            if let Some(value) = value {
+                registers.push(format!("{name}={value:02x}"));
+            }
+        }
+        if let Some(pc) = self.pc {
+            registers.push(format!("PC={pc:04x}"));
+        }
+        write!(f, "{}", registers.join(" "))
+    }
+}
+
+fn default_memory_mask() -> u8 {
+    0xff
+}
+
+/// The default timeout for a [`MacroCommand::WaitUntil`] command (10 seconds for NTSC)
+pub const DEFAULT_TIMEOUT_FRAMES: u32 = 600;
+
+fn default_timeout_frames() -> u32 {
+    DEFAULT_TIMEOUT_FRAMES
+}
+
+/// The default timeout for a [`MacroCommand::CheckBlarggStatus`] command (one
+/// minute for NTSC), since some test ROMs run for a long time
+pub const DEFAULT_BLARGG_TIMEOUT_FRAMES: u32 = 3600;
+
+fn default_blargg_timeout_frames() -> u32 {
+    DEFAULT_BLARGG_TIMEOUT_FRAMES
+}
+
+/// A condition for a [`MacroCommand::WaitUntil`] command
+///
+/// There's no condition for hitting an arbitrary debugger breakpoint, but
+/// `PC` and `Write` are detected via breakpoints (stopping the emulator as
+/// soon as they hold) and cover execution and write breakpoints.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum MacroCondition {
+    /// The CRC32 of the last complete frame (see [`MacroCommand::CheckFrameCRC32`])
+    FrameCRC32(u32),
+    RawFrameCRC32(u32),
+
+    /// The value at an address on the system bus, only comparing the bits in `mask`
+    Memory {
+        addr: u16,
+        expected: u8,
+        #[serde(default = "default_memory_mask")]
+        mask: u8,
+    },
+    MemoryRange {
+        addr: u16,
+        bytes: Vec<u8>,
+    },
+
+    /// The CPU is about to execute the instruction at this address
+    PC(u16),
+
+    /// The CPU writes to an address, or writes a specific value
+    Write {
+        addr: u16,
+        #[serde(default)]
+        value: Option<u8>,
+    },
+}
+
+impl MacroCondition {
+    /// Whether the condition is detected via a breakpoint, instead of being polled
+    fn uses_breakpoint(&self) -> bool {
+        matches!(self, MacroCondition::PC(_) | MacroCondition::Write { .. })
+    }
+}
+
+impl fmt::Display for MacroCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MacroCondition::FrameCRC32(crc) => write!(f, "frame CRC32 == {crc:08x}"),
+            MacroCondition::RawFrameCRC32(crc) => write!(f, "raw frame CRC32 == {crc:08x}"),
+            MacroCondition::Memory {
+                addr,
+                expected,
+                mask: 0xff,
+            } => write!(f, "[{addr:04x}] == {expected:02x}"),
+            MacroCondition::Memory {
+                addr,
+                expected,
+                mask,
+            } => write!(f, "[{addr:04x}] & {mask:02x} == {:02x}", expected & mask),
+            MacroCondition::MemoryRange { addr, bytes } => {
+                write!(f, "{} bytes from [{addr:04x}] match", bytes.len())
+            }
+            MacroCondition::PC(addr) => write!(f, "PC == {addr:04x}"),
+            MacroCondition::Write { addr, value: None } => write!(f, "write to [{addr:04x}]"),
+            MacroCondition::Write {
+                addr,
+                value: Some(value),
+            } => write!(f, "write of {value:02x} to [{addr:04x}]"),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum MacroCommand {
     Reset,
@@ -74,6 +237,92 @@ pub enum MacroCommand {
     /// at the end of each frame on line 239, dot 255 when the last pixel is written
     /// which can be read at any time until the next frame starts at line 0, dot 0.
     CheckFrameCRC32(u32),
+
+    /// Like `CheckFrameCRC32` except the CRC32 is calculated from the frame in the
+    /// `RAW9` pixel format (palette value + emphasis bits) so it's not affected
+    /// by any changes to how the PPU's output is mapped to RGB colors
+    CheckRawFrameCRC32(u32),
+
+    /// Compare the framebuffer with a reference PNG, where a pixel only counts
+    /// as different if a color channel differs by more than `tolerance`, and
+    /// the check only fails if more than `max_differing_pixels` pixels differ
+    ///
+    /// Relative paths are found relative to the directory of the macro library.
+    CheckFrameImage {
+        path: String,
+        #[serde(default)]
+        tolerance: u8,
+        #[serde(default)]
+        max_differing_pixels: u32,
+    },
+
+    /// Wait until a test ROM reports a result via the $6000 status protocol
+    /// used by blargg's tests (pressing reset whenever the ROM asks) and check
+    /// that it passed, failing if there's no result within `timeout_frames`
+    CheckBlarggStatus {
+        #[serde(default = "default_blargg_timeout_frames")]
+        timeout_frames: u32,
+    },
+
+    /// Check the value at an address on the system bus (read without side
+    /// effects), only comparing the bits in `mask`
+    CheckMemory {
+        addr: u16,
+        expected: u8,
+        #[serde(default = "default_memory_mask")]
+        mask: u8,
+    },
+
+    /// Check a range of values on the system bus, starting from `addr`
+    CheckMemoryRange {
+        addr: u16,
+        bytes: Vec<u8>,
+    },
+
+    /// Check the CPU registers (between instructions)
+    CheckCpuRegisters(CpuRegisters),
+
+    /// Write a value to the system bus, with the same side effects as a CPU write
+    WriteMemory {
+        addr: u16,
+        value: u8,
+    },
+
+    /// Wait until the CPU writes to an address, or writes a specific value
+    WaitForWrite {
+        addr: u16,
+        #[serde(default)]
+        value: Option<u8>,
+    },
+
+    /// Wait until a condition holds, failing if it doesn't within `timeout_frames`
+    ///
+    /// Unlike `WaitForDot` this doesn't depend on the exact timing of the
+    /// emulator. Conditions other than `PC` and `Write` are polled between
+    /// updates (typically once per frame).
+    WaitUntil {
+        condition: MacroCondition,
+        #[serde(default = "default_timeout_frames")]
+        timeout_frames: u32,
+    },
+}
+
+/// The model of Nes that a macro is played on
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacroModel {
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl From<MacroModel> for Model {
+    fn from(model: MacroModel) -> Self {
+        match model {
+            MacroModel::Ntsc => Model::Ntsc,
+            MacroModel::Pal => Model::Pal,
+            MacroModel::Dendy => Model::Dendy,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
@@ -82,6 +331,11 @@ pub struct Macro {
     pub rom: String,
     pub notes: String,
 
+    /// Overrides the model that would otherwise be picked based on the ROM's
+    /// header (e.g. to run a test ROM that has no NES 2.0 header on PAL)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<MacroModel>,
+
     #[serde(default)]
     pub genie_codes: Vec<String>,
 
@@ -142,23 +396,52 @@ pub fn read_macro_library_from_file<P: AsRef<std::path::Path>>(
     }
 }
 
-pub fn register_frame_crc_hasher(nes: &mut Nes, shared_crc32: Rc<RefCell<u32>>) -> HookHandle {
+/// The CRC32s for the last complete frame
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameCrcs {
+    /// CRC32 of the palette value for each pixel
+    pub crc32: u32,
+
+    /// CRC32 of the frame in the `RAW9` pixel format
+    pub raw_crc32: u32,
+}
+
+impl FrameCrcs {
+    pub fn select(&self, raw: bool) -> u32 {
+        if raw {
+            self.raw_crc32
+        } else {
+            self.crc32
+        }
+    }
+}
+
+/// Creates a PPU mux hook that calculates the CRC32s for each frame
+pub fn frame_crc_hasher(shared_crcs: Rc<RefCell<FrameCrcs>>) -> Box<FnMuxHook> {
     let mut hasher = crc32fast::Hasher::new();
+    let mut raw_hasher = crc32fast::Hasher::new();
 
-    nes.ppu_mut()
-        .add_mux_hook(Box::new(move |_ppu, _cartridge, state| {
-            if state.screen_x == 0 && state.screen_y == 0 {
-                hasher.reset();
-            }
+    Box::new(move |_ppu, _cartridge, state| {
+        if state.screen_x == 0 && state.screen_y == 0 {
+            hasher.reset();
+            raw_hasher.reset();
+        }
 
-            hasher.update(&[state.palette_value]);
+        hasher.update(&[state.palette_value]);
+        raw_hasher.update(&state.raw_pixel().to_le_bytes());
 
-            if state.screen_x == 255 && state.screen_y == 239 {
-                let crc = hasher.clone().finalize();
-                //println!("Frame {}, CRC = {:08x}", ppu.frame, crc);
-                *shared_crc32.borrow_mut() = crc;
-            }
-        }))
+        if state.screen_x == 255 && state.screen_y == 239 {
+            //println!("Frame {}, CRC = {:08x}", ppu.frame, crc);
+            *shared_crcs.borrow_mut() = FrameCrcs {
+                crc32: hasher.clone().finalize(),
+                raw_crc32: raw_hasher.clone().finalize(),
+            };
+        }
+    })
+}
+
+pub fn register_frame_crc_hasher(nes: &mut Nes, shared_crcs: Rc<RefCell<FrameCrcs>>) -> HookHandle {
+    nes.ppu_mut().add_mux_hook(frame_crc_hasher(shared_crcs))
 }
 
 pub fn name_from_rom_path(path: &Path, default_name: String) -> String {
@@ -195,20 +478,89 @@ pub fn name_from_rom_path(path: &Path, default_name: String) -> String {
     }
 }
 
-type MacroCheckFailureCallback = Box<dyn FnMut(&mut Nes, &String, &HashSet<String>, String)>;
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub enum FailedCheck {
+    FrameCRC32 {
+        /// `true` if the CRC was calculated from the `RAW9` frame
+        raw: bool,
+        expected: u32,
+        actual: u32,
+    },
+    FrameImage {
+        path: String,
+        tolerance: u8,
+        max_differing_pixels: u32,
+        differing_pixels: u32,
+        max_difference: u8,
+    },
+    /// The reference image couldn't be loaded or compared with the frame
+    FrameImageError { path: String, error: String },
+    /// A test ROM reported a non-zero result code via the $6000 protocol
+    BlarggStatus { code: u8, text: String },
+    /// A `WaitUntil` condition didn't hold within its timeout
+    Timeout {
+        condition: MacroCondition,
+        frames: u32,
+    },
+    /// A test ROM didn't report a result within the `CheckBlarggStatus` timeout
+    BlarggTimeout { frames: u32 },
+    /// The first value that didn't match for a `CheckMemory` or `CheckMemoryRange`
+    Memory {
+        addr: u16,
+        mask: u8,
+        expected: u8,
+        actual: u8,
+    },
+    CpuRegisters {
+        expected: CpuRegisters,
+        actual: CpuRegisters,
+    },
+}
+
+/// Details of a check that failed while playing a macro
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CheckFailure {
+    pub frame: u32,
+    pub line: u16,
+    pub dot: u16,
+    pub check: FailedCheck,
+}
+
+/// The state for a `WaitUntil` command that's in progress
+struct ConditionWait {
+    start_frame: u32,
+    cpu_breakpoint: Option<BreakpointHandle>,
+    write_breakpoint: Option<WriteBreakpointHandle>,
+}
+
+/// The state for a `CheckBlarggStatus` command that's in progress
+struct BlarggWait {
+    start_frame: u32,
+    monitor: BlarggStatusMonitor,
+}
+
+/// Called with the Nes, macro name, tags, error message and, for image
+/// checks, an image highlighting the pixels that differ
+type MacroCheckFailureCallback =
+    Box<dyn FnMut(&mut Nes, &String, &HashSet<String>, String, Option<&image::RgbImage>)>;
 
 pub struct MacroPlayer {
     recording: Macro,
     all_checks_passed: bool,
     command: Cell<usize>,
-    shared_crc32: Rc<RefCell<u32>>,
+    shared_crcs: Rc<RefCell<FrameCrcs>>,
     //waiting_for_dot: bool,
     wait_breakpoint: Option<DotBreakpointHandle>,
+    wait_write: Option<WriteBreakpointHandle>,
+    wait_condition: Option<ConditionWait>,
+    image_dir: Option<PathBuf>,
     wait_update_timestamp: Instant,
     check_failure_callback: Option<MacroCheckFailureCallback>,
+    check_failures: Vec<CheckFailure>,
+    blargg_wait: Option<BlarggWait>,
 }
 impl MacroPlayer {
-    pub fn new(recording: Macro, nes: &mut Nes, shared_crc32: Rc<RefCell<u32>>) -> Self {
+    pub fn new(recording: Macro, nes: &mut Nes, shared_crcs: Rc<RefCell<FrameCrcs>>) -> Self {
         let genie_codes: Vec<GameGenieCode> = recording
             .genie_codes
             .iter()
@@ -230,10 +582,15 @@ impl MacroPlayer {
             recording,
             all_checks_passed: true,
             command: Cell::new(0),
-            shared_crc32,
+            shared_crcs,
             wait_update_timestamp: Instant::now(),
             wait_breakpoint: None,
+            wait_write: None,
+            wait_condition: None,
+            image_dir: None,
             check_failure_callback: None,
+            check_failures: vec![],
+            blargg_wait: None,
         }
     }
 
@@ -258,10 +615,21 @@ impl MacroPlayer {
         self.check_failure_callback = Some(callback);
     }
 
+    /// Sets the directory that relative `CheckFrameImage` paths are found in
+    /// (otherwise they are relative to the current directory)
+    pub fn set_image_dir(&mut self, dir: Option<PathBuf>) {
+        self.image_dir = dir;
+    }
+
     pub fn all_checks_passed(&self) -> bool {
         self.all_checks_passed
     }
 
+    /// All the checks that have failed so far, in order
+    pub fn check_failures(&self) -> &[CheckFailure] {
+        &self.check_failures
+    }
+
     pub fn checks_for_failure(&self) -> bool {
         self.recording.tags.contains("test_failure")
     }
@@ -307,10 +675,289 @@ impl MacroPlayer {
             }
         }
 
+        if let Some(MacroCommand::WaitUntil { condition, .. }) = self.current_cmd() {
+            if condition.uses_breakpoint() && self.condition_met(nes, &condition.clone()) {
+                log::debug!("Macro: Condition met");
+                self.finish_condition_wait(nes);
+                self.next();
+                return true;
+            }
+        }
+
+        if let Some(MacroCommand::CheckBlarggStatus { timeout_frames }) = self.current_cmd() {
+            let timeout_frames = *timeout_frames;
+            if let Some(wait) = &self.blargg_wait {
+                if wait.monitor.status_written(nes) {
+                    log::debug!("Macro: Test ROM status written");
+                    if self.check_blargg_status(nes, timeout_frames) {
+                        self.next();
+                    }
+                    return true;
+                }
+            }
+        }
+
+        if let Some(handle) = self.wait_write {
+            if nes.system_mut().write_breakpoint_hit(handle) {
+                log::debug!("Macro: Finished waiting for write");
+                nes.system_mut().remove_write_breakpoint(handle);
+                self.wait_write = None;
+                self.next();
+                return true;
+            }
+        }
+
         false
     }
 
+    fn check_failed(&mut self, nes: &mut Nes, check: FailedCheck, err: String) {
+        self.check_failed_with_diff(nes, check, err, None);
+    }
+
+    fn check_failed_with_diff(
+        &mut self,
+        nes: &mut Nes,
+        check: FailedCheck,
+        err: String,
+        diff: Option<&image::RgbImage>,
+    ) {
+        self.all_checks_passed = false;
+        let ppu = nes.ppu_mut();
+        self.check_failures.push(CheckFailure {
+            frame: ppu.frame,
+            line: ppu.line,
+            dot: ppu.dot,
+            check,
+        });
+        log::error!("{err}");
+        if let Some(callback) = self.check_failure_callback.as_mut() {
+            callback(nes, &self.recording.name, &self.recording.tags, err, diff);
+        }
+    }
+
+    fn check_frame_crc(&mut self, nes: &mut Nes, raw: bool, crc: u32) {
+        let current_crc = self.shared_crcs.borrow().select(raw);
+        if current_crc != crc {
+            let kind = if raw { "Raw CRC" } else { "CRC" };
+            let err = format!("Macro: {kind} check failed!: CRC32 = {current_crc:08x}, expected CRC32 was {crc:08x}");
+            let check = FailedCheck::FrameCRC32 {
+                raw,
+                expected: crc,
+                actual: current_crc,
+            };
+            self.check_failed(nes, check, err);
+        }
+    }
+
+    fn check_frame_image(
+        &mut self,
+        nes: &mut Nes,
+        path: &str,
+        tolerance: u8,
+        max_differing_pixels: u32,
+    ) {
+        let full_path = match &self.image_dir {
+            Some(dir) => dir.join(path),
+            None => PathBuf::from(path),
+        };
+        let frame = frame_image::framebuffer_image(nes);
+        let difference = frame_image::load_reference_image(&full_path)
+            .and_then(|reference| frame_image::compare_images(&frame, &reference, tolerance));
+        match difference {
+            Ok(difference) => {
+                if difference.differing_pixels > max_differing_pixels {
+                    let err = format!(
+                        "Macro: image check failed!: {} pixels differ from {path} by more than {tolerance} (max difference = {})",
+                        difference.differing_pixels, difference.max_difference
+                    );
+                    let check = FailedCheck::FrameImage {
+                        path: path.to_string(),
+                        tolerance,
+                        max_differing_pixels,
+                        differing_pixels: difference.differing_pixels,
+                        max_difference: difference.max_difference,
+                    };
+                    self.check_failed_with_diff(nes, check, err, Some(&difference.diff));
+                }
+            }
+            Err(err) => {
+                let error = format!("{err:#}");
+                let check = FailedCheck::FrameImageError {
+                    path: path.to_string(),
+                    error: error.clone(),
+                };
+                self.check_failed(nes, check, format!("Macro: image check failed!: {error}"));
+            }
+        }
+    }
+
+    fn check_memory(&mut self, nes: &mut Nes, addr: u16, mask: u8, expected: u8) {
+        let actual = nes.peek_system_bus(addr);
+        if actual & mask != expected & mask {
+            let err = format!("Macro: memory check failed!: [{addr:04x}] = {actual:02x}, expected {expected:02x} (mask = {mask:02x})");
+            let check = FailedCheck::Memory {
+                addr,
+                mask,
+                expected,
+                actual,
+            };
+            self.check_failed(nes, check, err);
+        }
+    }
+
+    fn check_memory_range(&mut self, nes: &mut Nes, start: u16, bytes: &[u8]) {
+        for (i, expected) in bytes.iter().copied().enumerate() {
+            let addr = start.wrapping_add(i as u16);
+            if nes.peek_system_bus(addr) != expected {
+                // Only report the first byte that doesn't match
+                self.check_memory(nes, addr, 0xff, expected);
+                break;
+            }
+        }
+    }
+
+    fn check_cpu_registers(&mut self, nes: &mut Nes, expected: CpuRegisters) {
+        let actual = CpuRegisters::current(nes);
+        if !expected.matches(&actual) {
+            let err = format!("Macro: CPU registers check failed!: {actual}, expected {expected}");
+            self.check_failed(nes, FailedCheck::CpuRegisters { expected, actual }, err);
+        }
+    }
+
+    fn condition_met(&self, nes: &mut Nes, condition: &MacroCondition) -> bool {
+        match condition {
+            MacroCondition::FrameCRC32(crc) => self.shared_crcs.borrow().select(false) == *crc,
+            MacroCondition::RawFrameCRC32(crc) => self.shared_crcs.borrow().select(true) == *crc,
+            MacroCondition::Memory {
+                addr,
+                expected,
+                mask,
+            } => nes.peek_system_bus(*addr) & mask == expected & mask,
+            MacroCondition::MemoryRange { addr, bytes } => bytes
+                .iter()
+                .enumerate()
+                .all(|(i, value)| nes.peek_system_bus(addr.wrapping_add(i as u16)) == *value),
+            MacroCondition::PC(addr) => nes.cpu_mut().pc == *addr,
+            MacroCondition::Write { .. } => match &self.wait_condition {
+                Some(ConditionWait {
+                    write_breakpoint: Some(handle),
+                    ..
+                }) => nes.system_mut().write_breakpoint_hit(*handle),
+                _ => false,
+            },
+        }
+    }
+
+    fn finish_condition_wait(&mut self, nes: &mut Nes) {
+        if let Some(wait) = self.wait_condition.take() {
+            if let Some(handle) = wait.cpu_breakpoint {
+                nes.cpu_mut().remove_breakpoint(handle);
+            }
+            if let Some(handle) = wait.write_breakpoint {
+                nes.system_mut().remove_write_breakpoint(handle);
+            }
+        }
+    }
+
+    /// Checks the condition for a `WaitUntil` command (starting the wait if
+    /// necessary), returning `true` once the condition holds or the wait timed out
+    fn wait_until(
+        &mut self,
+        nes: &mut Nes,
+        condition: &MacroCondition,
+        timeout_frames: u32,
+    ) -> bool {
+        let frame = nes.ppu().frame;
+        if self.wait_condition.is_none() {
+            let mut wait = ConditionWait {
+                start_frame: frame,
+                cpu_breakpoint: None,
+                write_breakpoint: None,
+            };
+            match *condition {
+                MacroCondition::PC(addr) => {
+                    wait.cpu_breakpoint = Some(nes.cpu_mut().add_tmp_break(addr));
+                }
+                MacroCondition::Write { addr, value } => {
+                    wait.write_breakpoint =
+                        Some(nes.system_mut().add_write_breakpoint(addr, value));
+                }
+                _ => {}
+            }
+            log::debug!(
+                "Macro: Started to wait until {condition} (timeout = {timeout_frames} frames)"
+            );
+            self.wait_condition = Some(wait);
+        }
+
+        if self.condition_met(nes, condition) {
+            log::debug!("Macro: Condition met");
+            self.finish_condition_wait(nes);
+            return true;
+        }
+
+        let frames = match &self.wait_condition {
+            Some(wait) => frame.wrapping_sub(wait.start_frame),
+            None => 0,
+        };
+        if frames > timeout_frames {
+            self.finish_condition_wait(nes);
+            let err =
+                format!("Macro: timed out after {timeout_frames} frames waiting until {condition}");
+            let check = FailedCheck::Timeout {
+                condition: condition.clone(),
+                frames: timeout_frames,
+            };
+            self.check_failed(nes, check, err);
+            return true;
+        }
+
+        false
+    }
+
+    /// Polls the test ROM status (starting to monitor it if necessary),
+    /// returning `true` once the ROM has finished or the wait timed out
+    fn check_blargg_status(&mut self, nes: &mut Nes, timeout_frames: u32) -> bool {
+        let frame = nes.ppu().frame;
+        let wait = self.blargg_wait.get_or_insert_with(|| BlarggWait {
+            start_frame: frame,
+            monitor: BlarggStatusMonitor::new(),
+        });
+        match wait.monitor.poll(nes) {
+            BlarggStatus::NotStarted | BlarggStatus::Running => {
+                if frame.wrapping_sub(wait.start_frame) > timeout_frames {
+                    wait.monitor.stop(nes);
+                    self.blargg_wait = None;
+                    let err = format!(
+                        "Macro: timed out after {timeout_frames} frames waiting for test ROM status"
+                    );
+                    let check = FailedCheck::BlarggTimeout {
+                        frames: timeout_frames,
+                    };
+                    self.check_failed(nes, check, err);
+                    return true;
+                }
+                false
+            }
+            BlarggStatus::Finished { code, text } => {
+                wait.monitor.stop(nes);
+                self.blargg_wait = None;
+                if code == 0 {
+                    log::debug!("Macro: test ROM passed: {text}");
+                } else {
+                    let err = format!("Macro: test ROM failed with code {code}: {text}");
+                    self.check_failed(nes, FailedCheck::BlarggStatus { code, text }, err);
+                }
+                true
+            }
+        }
+    }
+
     pub fn update(&mut self, nes: &mut Nes) {
+        if self.wait_write.is_some() {
+            // Waiting for check_breakpoint() to see the write
+            return;
+        }
         if self.wait_breakpoint.is_some() {
             //println!("Macro: Continuing to wait for dot");
             let duration = Instant::now() - self.wait_update_timestamp;
@@ -385,18 +1032,286 @@ impl MacroPlayer {
                 }
                 MacroCommand::CheckFrameCRC32(crc) => {
                     //println!("Macro: checking for framebuffer CRC32 = {crc:08x}");
-                    let current_crc = *self.shared_crc32.borrow();
-                    if current_crc != crc {
-                        self.all_checks_passed = false;
-                        let err = format!("Macro: CRC check failed!: CRC32 = {current_crc:08x}, expected CRC32 was {crc:08x}");
-                        log::error!("{err}");
-                        if let Some(callback) = self.check_failure_callback.as_mut() {
-                            callback(nes, &self.recording.name, &self.recording.tags, err);
-                        }
+                    self.check_frame_crc(nes, false, crc);
+                    self.next();
+                }
+                MacroCommand::CheckRawFrameCRC32(crc) => {
+                    self.check_frame_crc(nes, true, crc);
+                    self.next();
+                }
+                MacroCommand::CheckFrameImage {
+                    ref path,
+                    tolerance,
+                    max_differing_pixels,
+                } => {
+                    let path = path.clone();
+                    self.check_frame_image(nes, &path, tolerance, max_differing_pixels);
+                    self.next();
+                }
+                MacroCommand::CheckBlarggStatus { timeout_frames } => {
+                    if !self.check_blargg_status(nes, timeout_frames) {
+                        // Poll again on the next update
+                        break;
                     }
                     self.next();
                 }
+                MacroCommand::CheckMemory {
+                    addr,
+                    expected,
+                    mask,
+                } => {
+                    self.check_memory(nes, addr, mask, expected);
+                    self.next();
+                }
+                MacroCommand::CheckMemoryRange { addr, ref bytes } => {
+                    let bytes = bytes.clone();
+                    self.check_memory_range(nes, addr, &bytes);
+                    self.next();
+                }
+                MacroCommand::CheckCpuRegisters(expected) => {
+                    self.check_cpu_registers(nes, expected);
+                    self.next();
+                }
+                MacroCommand::WriteMemory { addr, value } => {
+                    log::debug!("Macro: write {value:02x} to {addr:04x}");
+                    nes.write_system_bus(addr, value);
+                    self.next();
+                }
+                MacroCommand::WaitUntil {
+                    ref condition,
+                    timeout_frames,
+                } => {
+                    let condition = condition.clone();
+                    if !self.wait_until(nes, &condition, timeout_frames) {
+                        // Check again on the next update
+                        break;
+                    }
+                    self.next();
+                }
+                MacroCommand::WaitForWrite { addr, value } => {
+                    self.wait_write = Some(nes.system_mut().add_write_breakpoint(addr, value));
+                    log::debug!(
+                        "Macro: Started to wait for write to {addr:04x} (value = {value:?})"
+                    );
+                    break;
+                }
             }
         }
     }
 }
+
+/// Plays a macro against a synthetic NROM running `program` until the
+/// macro finishes, returning any checks that failed
+#[cfg(test)]
+fn play_test_macro(program: &[u8], commands: Vec<MacroCommand>) -> Vec<CheckFailure> {
+    use nes_emulator::nes::{ProgressStatus, ProgressTarget};
+
+    let start = Instant::now();
+    let mut nes = Nes::new(Model::Ntsc, 48000, start);
+    nes.open_binary(&crate::utils::test_nrom_binary(program))
+        .unwrap();
+    nes.power_cycle(start);
+
+    let recording = Macro {
+        name: "test".to_string(),
+        commands,
+        ..Default::default()
+    };
+    let crcs = Rc::new(RefCell::new(FrameCrcs::default()));
+    let _crc_hook_handle = register_frame_crc_hasher(&mut nes, crcs.clone());
+    let mut player = MacroPlayer::new(recording, &mut nes, crcs);
+    for _ in 0..600 {
+        if !player.playing() {
+            break;
+        }
+        if let ProgressStatus::Breakpoint = nes.progress(ProgressTarget::FrameReady) {
+            assert!(player.check_breakpoint(&mut nes), "Unexpected breakpoint");
+        }
+        player.update(&mut nes);
+    }
+    assert!(!player.playing(), "Macro didn't finish");
+    player.check_failures().to_vec()
+}
+
+/// Counts up in $10 every 256 iterations of an inner loop
+#[cfg(test)]
+const COUNTER_PROGRAM: [u8; 10] = [
+    0xa2, 0x00, // LDX #0
+    0xe8, // loop: INX
+    0xd0, 0xfd, // BNE loop
+    0xe6, 0x10, // INC $10
+    0x4c, 0x02, 0x80, // JMP loop
+];
+
+#[test]
+fn test_wait_until() {
+    // Memory conditions are polled once per frame, so only check the bit
+    // that was waited for
+    let failures = play_test_macro(
+        &COUNTER_PROGRAM,
+        vec![
+            MacroCommand::WaitUntil {
+                condition: MacroCondition::Memory {
+                    addr: 0x10,
+                    expected: 0x80,
+                    mask: 0x80,
+                },
+                timeout_frames: 60,
+            },
+            MacroCommand::CheckMemory {
+                addr: 0x10,
+                expected: 0x80,
+                mask: 0x80,
+            },
+        ],
+    );
+    assert_eq!(failures, vec![]);
+
+    // PC and Write conditions stop the emulator as soon as they're met
+    let failures = play_test_macro(
+        &COUNTER_PROGRAM,
+        vec![
+            MacroCommand::WaitUntil {
+                condition: MacroCondition::Write {
+                    addr: 0x10,
+                    value: Some(3),
+                },
+                timeout_frames: 60,
+            },
+            MacroCommand::CheckMemory {
+                addr: 0x10,
+                expected: 3,
+                mask: 0xff,
+            },
+            MacroCommand::WaitUntil {
+                condition: MacroCondition::PC(0x8005),
+                timeout_frames: 60,
+            },
+            MacroCommand::CheckCpuRegisters(CpuRegisters {
+                x: Some(0),
+                pc: Some(0x8005),
+                ..Default::default()
+            }),
+            MacroCommand::CheckMemory {
+                addr: 0x10,
+                expected: 3,
+                mask: 0xff,
+            },
+        ],
+    );
+    assert_eq!(failures, vec![]);
+}
+
+#[test]
+fn test_wait_until_timeout() {
+    let condition = MacroCondition::PC(0x9000);
+    let failures = play_test_macro(
+        &COUNTER_PROGRAM,
+        vec![
+            MacroCommand::WaitUntil {
+                condition: condition.clone(),
+                timeout_frames: 10,
+            },
+            // Commands after a timeout still run
+            MacroCommand::CheckMemory {
+                addr: 0x10,
+                expected: 0,
+                mask: 0xff,
+            },
+        ],
+    );
+    assert_eq!(failures.len(), 2);
+    assert_eq!(
+        failures[0].check,
+        FailedCheck::Timeout {
+            condition,
+            frames: 10
+        }
+    );
+    assert!(failures[0].frame >= 10);
+    assert!(matches!(
+        failures[1].check,
+        FailedCheck::Memory { addr: 0x10, .. }
+    ));
+}
+
+#[test]
+fn test_check_frame_image() {
+    use nes_emulator::nes::{ProgressStatus, ProgressTarget};
+
+    // With rendering disabled every frame is the same, so any complete frame
+    // can be used as the reference
+    let start = Instant::now();
+    let mut nes = Nes::new(Model::Ntsc, 48000, start);
+    nes.open_binary(&crate::utils::test_nrom_binary(&COUNTER_PROGRAM))
+        .unwrap();
+    nes.power_cycle(start);
+    for _ in 0..3 {
+        while !matches!(
+            nes.progress(ProgressTarget::FrameReady),
+            ProgressStatus::FrameReady
+        ) {}
+    }
+    let frame = frame_image::framebuffer_image(&mut nes);
+
+    let dir = std::env::temp_dir().join(format!("frame-image-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let save_reference = |name: &str, n_differing: u32| {
+        let mut reference = frame.clone();
+        for x in 0..n_differing {
+            let pixel = reference.get_pixel_mut(x, 0);
+            pixel[0] = pixel[0].wrapping_add(0x80);
+        }
+        let path = dir.join(name);
+        reference.save(&path).unwrap();
+        path.to_string_lossy().to_string()
+    };
+    let play_check = |path: String| {
+        play_test_macro(
+            &COUNTER_PROGRAM,
+            vec![
+                MacroCommand::WaitUntil {
+                    condition: MacroCondition::Memory {
+                        addr: 0x10,
+                        expected: 0x80,
+                        mask: 0x80,
+                    },
+                    timeout_frames: 60,
+                },
+                MacroCommand::CheckFrameImage {
+                    path,
+                    tolerance: 8,
+                    max_differing_pixels: 2,
+                },
+            ],
+        )
+    };
+
+    let at_threshold = save_reference("at_threshold.png", 2);
+    let over_threshold = save_reference("over_threshold.png", 3);
+    let wrong_size = dir.join("wrong_size.png");
+    image::RgbImage::new(16, 16).save(&wrong_size).unwrap();
+
+    let passed = play_check(at_threshold);
+    let over = play_check(over_threshold.clone());
+    let error = play_check(wrong_size.to_string_lossy().to_string());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(passed, vec![]);
+    assert_eq!(over.len(), 1);
+    assert_eq!(
+        over[0].check,
+        FailedCheck::FrameImage {
+            path: over_threshold,
+            tolerance: 8,
+            max_differing_pixels: 2,
+            differing_pixels: 3,
+            max_difference: 0x80,
+        }
+    );
+    assert_eq!(error.len(), 1);
+    assert!(matches!(
+        error[0].check,
+        FailedCheck::FrameImageError { .. }
+    ));
+}
@@ -0,0 +1,501 @@
+//! Compares our CPU trace against a reference trace log from another emulator
+//!
+//! The reference is parsed leniently so that logs from Mesen, FCEUX and
+//! Nintendulator (`nestest.log`) can all be compared: only the fields that
+//! both traces have are compared and lines that don't look like an
+//! instruction (such as interrupt annotations) are skipped.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, Lines},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use nes_emulator::{cpu::core::TraceState, nes::Nes, system::Model};
+
+/// The status flag bits that are actually stored in the P register, since
+/// emulators disagree about how to show the B flag and unused bit
+const REAL_FLAGS_MASK: u8 = 0b1100_1111;
+
+const DOTS_PER_LINE: i64 = 341;
+
+/// How fields that are formatted (or counted) differently by other emulators
+/// should be normalised before comparing
+#[derive(Debug, Clone, Default)]
+pub struct TraceDiffOptions {
+    pub ignore_cycles: bool,
+    pub ignore_ppu: bool,
+    /// Added to the reference CPU cycle count before comparing
+    pub cycle_offset: i64,
+    /// Added to the reference PPU position (in dots, wrapping across lines
+    /// and frames) before comparing
+    pub dot_offset: i64,
+    /// The number of matching instructions to show before a divergence
+    pub context: usize,
+}
+
+/// The state for a single instruction, where the fields that a reference log
+/// doesn't include are `None`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct TraceRecord {
+    pc: u16,
+    a: u8,
+    x: u8,
+    y: u8,
+    sp: Option<u8>,
+    p: Option<u8>,
+    cpu_cycle: Option<u64>,
+    /// The PPU line and dot, where the pre-render line may be -1
+    ppu_position: Option<(i64, i64)>,
+}
+
+impl TraceRecord {
+    fn from_trace_state(trace: &TraceState) -> Self {
+        Self {
+            pc: trace.instruction_pc,
+            a: trace.saved_a,
+            x: trace.saved_x,
+            y: trace.saved_y,
+            sp: Some(trace.saved_sp),
+            p: Some(trace.saved_p.bits()),
+            cpu_cycle: Some(trace.cpu_clock),
+            ppu_position: Some((trace.ppu_line as i64, trace.ppu_dot as i64)),
+        }
+    }
+
+    /// Parses a line from a reference trace, or returns `None` if the line
+    /// doesn't describe an instruction
+    fn parse(line: &str) -> Option<Self> {
+        let pc = parse_pc(line)?;
+        let a = u8::from_str_radix(field(line, "A:")?, 16).ok()?;
+        let x = u8::from_str_radix(field(line, "X:")?, 16).ok()?;
+        let y = u8::from_str_radix(field(line, "Y:")?, 16).ok()?;
+        let sp = field(line, "SP:")
+            .or_else(|| field(line, "S:"))
+            .and_then(|sp| u8::from_str_radix(sp, 16).ok());
+        let p = field(line, "P:").and_then(parse_flags);
+
+        // Mesen (like our own trace) uses CYC: for the PPU dot, alongside SL:
+        // for the line, while nestest.log uses CYC: for the CPU cycle count
+        let (cpu_cycle, ppu_position) = if let Some(line_no) = field(line, "SL:") {
+            let position = match (line_no.parse(), field(line, "CYC:").map(str::parse)) {
+                (Ok(line_no), Some(Ok(dot))) => Some((line_no, dot)),
+                _ => None,
+            };
+            let cycle = field(line, "CPU Cycle:").and_then(|cycle| cycle.parse().ok());
+            (cycle, position)
+        } else {
+            let cycle = field(line, "CYC:").and_then(|cycle| cycle.parse().ok());
+            (cycle, parse_nintendulator_ppu(line))
+        };
+
+        Some(Self {
+            pc,
+            a,
+            x,
+            y,
+            sp,
+            p,
+            cpu_cycle,
+            ppu_position,
+        })
+    }
+}
+
+/// Finds the value following `key`, where the key must start a word
+fn field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let mut search = line;
+    while let Some(pos) = search.find(key) {
+        let start = line.len() - search.len() + pos;
+        let after = &line[start + key.len()..];
+        if start == 0 || line[..start].ends_with(char::is_whitespace) {
+            let value = after.trim_start();
+            let end = value.find(char::is_whitespace).unwrap_or(value.len());
+            return Some(&value[..end]);
+        }
+        search = after;
+    }
+    None
+}
+
+/// The PC is either given as the first word of the line (Mesen, nestest.log
+/// and our own trace) or as `$XXXX:` before the instruction bytes (FCEUX)
+fn parse_pc(line: &str) -> Option<u16> {
+    let is_address = |word: &str| word.len() == 4 && word.chars().all(|c| c.is_ascii_hexdigit());
+    if let Some(word) = line
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix('$'))
+        .filter_map(|word| word.split_once(':'))
+        .map(|(addr, _)| addr)
+        .find(|addr| is_address(addr))
+    {
+        return u16::from_str_radix(word, 16).ok();
+    }
+    let first = line.split_whitespace().next()?;
+    is_address(first)
+        .then(|| u16::from_str_radix(first, 16).ok())
+        .flatten()
+}
+
+/// Parses the status flags as either a hex byte or as letters in `NV-BDIZC`
+/// order, where upper case letters are set
+fn parse_flags(flags: &str) -> Option<u8> {
+    if flags.len() == 2 {
+        return u8::from_str_radix(flags, 16).ok();
+    }
+    if flags.len() != 8 {
+        return None;
+    }
+    Some(
+        flags
+            .chars()
+            .fold(0, |p, c| p << 1 | c.is_ascii_uppercase() as u8),
+    )
+}
+
+/// Parses nestest.log's `PPU:line,dot` (which may be padded after the colon)
+fn parse_nintendulator_ppu(line: &str) -> Option<(i64, i64)> {
+    let start = line.find("PPU:")? + "PPU:".len();
+    let (line_no, rest) = line[start..].split_once(',')?;
+    let dot = rest.split_whitespace().next()?;
+    Some((line_no.trim().parse().ok()?, dot.parse().ok()?))
+}
+
+/// The context for the first instruction where our trace didn't match
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    pub instruction: u64,
+    pub reference_line_no: usize,
+    pub reference_line: String,
+    pub line: String,
+    pub mismatches: Vec<String>,
+    /// The preceding instructions that matched, as (reference line number,
+    /// reference line, our line)
+    pub context: Vec<(usize, String, String)>,
+    /// The CPU and PPU state after running the diverging instruction
+    pub state: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum TraceDiffStatus {
+    Running,
+    /// Reached the end of the reference log without diverging
+    Matched {
+        instructions: u64,
+    },
+    Diverged(Box<Divergence>),
+    Failed(String),
+}
+
+/// Streams our trace against a reference log, one instruction at a time
+pub struct TraceDiff {
+    options: TraceDiffOptions,
+    reference: Lines<BufReader<File>>,
+    reference_line_no: usize,
+    lines_per_frame: i64,
+    instructions: u64,
+    context: VecDeque<(usize, String, String)>,
+    status: TraceDiffStatus,
+}
+
+impl TraceDiff {
+    pub fn new(reference: &Path, model: Model, options: TraceDiffOptions) -> Result<Self> {
+        let file = File::open(reference)
+            .map_err(|err| anyhow!("Failed to open {}: {err}", reference.display()))?;
+        let lines_per_frame = match model {
+            Model::Ntsc => 262,
+            Model::Pal | Model::Dendy => 312,
+        };
+        Ok(Self {
+            options,
+            reference: BufReader::new(file).lines(),
+            reference_line_no: 0,
+            lines_per_frame,
+            instructions: 0,
+            context: VecDeque::new(),
+            status: TraceDiffStatus::Running,
+        })
+    }
+
+    pub fn status(&self) -> &TraceDiffStatus {
+        &self.status
+    }
+
+    /// The number of instructions that have matched so far
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Reads the next reference line that describes an instruction
+    fn next_reference(&mut self) -> Result<Option<(String, TraceRecord)>> {
+        for line in self.reference.by_ref() {
+            let line = line?;
+            self.reference_line_no += 1;
+            if let Some(record) = TraceRecord::parse(&line) {
+                return Ok(Some((line, record)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Position within the frame, in dots, treating line -1 as the pre-render line
+    fn frame_position(&self, (line, dot): (i64, i64)) -> i64 {
+        line.rem_euclid(self.lines_per_frame) * DOTS_PER_LINE + dot
+    }
+
+    fn mismatches(&self, ours: &TraceRecord, reference: &TraceRecord) -> Vec<String> {
+        let mut mismatches = vec![];
+        let mut check = |name: &str, ours: String, reference: String| {
+            if ours != reference {
+                mismatches.push(format!("{name}: {ours}, expected {reference}"));
+            }
+        };
+        check(
+            "PC",
+            format!("{:04X}", ours.pc),
+            format!("{:04X}", reference.pc),
+        );
+        check(
+            "A",
+            format!("{:02X}", ours.a),
+            format!("{:02X}", reference.a),
+        );
+        check(
+            "X",
+            format!("{:02X}", ours.x),
+            format!("{:02X}", reference.x),
+        );
+        check(
+            "Y",
+            format!("{:02X}", ours.y),
+            format!("{:02X}", reference.y),
+        );
+        if let (Some(ours), Some(reference)) = (ours.sp, reference.sp) {
+            check("SP", format!("{ours:02X}"), format!("{reference:02X}"));
+        }
+        if let (Some(ours), Some(reference)) = (ours.p, reference.p) {
+            check(
+                "P",
+                format!("{:02X}", ours & REAL_FLAGS_MASK),
+                format!("{:02X}", reference & REAL_FLAGS_MASK),
+            );
+        }
+        if !self.options.ignore_cycles {
+            if let (Some(ours), Some(reference)) = (ours.cpu_cycle, reference.cpu_cycle) {
+                let reference = reference as i64 + self.options.cycle_offset;
+                check("CPU cycle", ours.to_string(), reference.to_string());
+            }
+        }
+        if !self.options.ignore_ppu {
+            if let (Some(ours), Some(reference)) = (ours.ppu_position, reference.ppu_position) {
+                let frame_dots = self.lines_per_frame * DOTS_PER_LINE;
+                let ours = self.frame_position(ours);
+                let reference = (self.frame_position(reference) + self.options.dot_offset)
+                    .rem_euclid(frame_dots);
+                let format = |position: i64| {
+                    format!(
+                        "line {} dot {}",
+                        position / DOTS_PER_LINE,
+                        position % DOTS_PER_LINE
+                    )
+                };
+                check("PPU", format(ours), format(reference));
+            }
+        }
+        mismatches
+    }
+
+    /// Compares the next instruction from our trace against the reference
+    ///
+    /// This should be called from a CPU instruction trace hook and does
+    /// nothing once the traces have diverged or the reference has ended.
+    pub fn compare(&mut self, nes: &mut Nes, trace: &TraceState) {
+        if !matches!(self.status, TraceDiffStatus::Running) {
+            return;
+        }
+
+        let (reference_line, reference) = match self.next_reference() {
+            Ok(Some(next)) => next,
+            Ok(None) => {
+                self.status = TraceDiffStatus::Matched {
+                    instructions: self.instructions,
+                };
+                return;
+            }
+            Err(err) => {
+                self.status = TraceDiffStatus::Failed(format!(
+                    "Failed to read line {} of the reference: {err}",
+                    self.reference_line_no + 1
+                ));
+                return;
+            }
+        };
+
+        let line = trace.to_string();
+        let mismatches = self.mismatches(&TraceRecord::from_trace_state(trace), &reference);
+        if mismatches.is_empty() {
+            self.instructions += 1;
+            if self.options.context > 0 {
+                if self.context.len() == self.options.context {
+                    self.context.pop_front();
+                }
+                self.context
+                    .push_back((self.reference_line_no, reference_line, line));
+            }
+            return;
+        }
+
+        self.status = TraceDiffStatus::Diverged(Box::new(Divergence {
+            instruction: self.instructions,
+            reference_line_no: self.reference_line_no,
+            reference_line,
+            line,
+            mismatches,
+            context: self.context.drain(..).collect(),
+            state: describe_state(nes),
+        }));
+    }
+}
+
+fn describe_state(nes: &mut Nes) -> String {
+    let cpu = nes.cpu_mut();
+    let cpu_state = format!(
+        "CPU: PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{} SP:{:02X} CPU Cycle:{}",
+        cpu.pc,
+        cpu.a,
+        cpu.x,
+        cpu.y,
+        cpu.p.to_flags_string(),
+        cpu.sp,
+        cpu.clock
+    );
+    let ppu = nes.ppu();
+    let ppu_state = format!(
+        "PPU: frame:{} SL:{} CYC:{} CTRL:{:02X} MASK:{:02X} STATUS:{:02X} V:{:04X} T:{:04X}",
+        ppu.frame,
+        ppu.line,
+        ppu.dot,
+        ppu.control1.bits(),
+        ppu.control2.bits(),
+        ppu.status.bits(),
+        ppu.shared_v_register,
+        ppu.shared_t_register
+    );
+    format!("{cpu_state}\n{ppu_state}")
+}
+
+impl Divergence {
+    pub fn report(&self) {
+        println!(
+            "Trace diverged after {} matching instructions, at line {} of the reference:",
+            self.instruction, self.reference_line_no
+        );
+        println!();
+        for (line_no, reference, ours) in self.context.iter() {
+            println!("  {line_no:>8} ref: {reference}");
+            println!("  {:>8} our: {ours}", "");
+        }
+        println!(
+            "> {:>8} ref: {}",
+            self.reference_line_no, self.reference_line
+        );
+        println!("> {:>8} our: {}", "", self.line);
+        println!();
+        for mismatch in self.mismatches.iter() {
+            println!("  {mismatch}");
+        }
+        println!();
+        println!("After running the instruction:");
+        println!("{}", self.state);
+    }
+}
+
+#[test]
+fn test_parse_reference_lines() {
+    let mesen = TraceRecord::parse(
+        "C72D  D0 E0     BNE $C70F                       A:00 X:12 Y:34 P:27 SP:FB CYC:115 SL:-1  CPU Cycle:1337",
+    );
+    assert_eq!(
+        mesen,
+        Some(TraceRecord {
+            pc: 0xc72d,
+            a: 0x00,
+            x: 0x12,
+            y: 0x34,
+            sp: Some(0xfb),
+            p: Some(0x27),
+            cpu_cycle: Some(1337),
+            ppu_position: Some((-1, 115)),
+        })
+    );
+
+    let fceux =
+        TraceRecord::parse("f1       A:05 X:FF Y:00 S:FD P:NvUbdIzC  $C004:A2 FF     LDX #$FF");
+    assert_eq!(
+        fceux,
+        Some(TraceRecord {
+            pc: 0xc004,
+            a: 0x05,
+            x: 0xff,
+            y: 0x00,
+            sp: Some(0xfd),
+            p: Some(0xa5),
+            cpu_cycle: None,
+            ppu_position: None,
+        })
+    );
+
+    let nestest = TraceRecord::parse(
+        "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+    );
+    assert_eq!(
+        nestest,
+        Some(TraceRecord {
+            pc: 0xc000,
+            a: 0x00,
+            x: 0x00,
+            y: 0x00,
+            sp: Some(0xfd),
+            p: Some(0x24),
+            cpu_cycle: Some(7),
+            ppu_position: Some((0, 21)),
+        })
+    );
+
+    // Annotations and truncated lines are skipped
+    assert_eq!(TraceRecord::parse("[NMI - Cycle: 29658]"), None);
+    assert_eq!(TraceRecord::parse(""), None);
+    assert_eq!(
+        TraceRecord::parse("C000  4C F5 C5  JMP $C5F5  A:00 X:00"),
+        None
+    );
+}
+
+#[test]
+fn test_trace_field() {
+    let line = "C000  EA  NOP  A:01 X:02 Y:03 P:24 SP:FD CYC:  7";
+
+    assert_eq!(field(line, "A:"), Some("01"));
+    // Keys have to start a word, so P: doesn't match the end of SP:
+    assert_eq!(field(line, "P:"), Some("24"));
+    assert_eq!(field("SP:FD P:25", "P:"), Some("25"));
+    assert_eq!(field("SP:FD", "P:"), None);
+    // Padding after the key is skipped
+    assert_eq!(field(line, "CYC:"), Some("7"));
+    assert_eq!(field(line, "Z:"), None);
+}
+
+#[test]
+fn test_trace_parse_pc() {
+    assert_eq!(parse_pc("C000  4C F5 C5  JMP $C5F5"), Some(0xc000));
+    assert_eq!(
+        parse_pc("A:00 X:00  $8005:8D 00 20  STA $2000"),
+        Some(0x8005)
+    );
+    // Zero page operands aren't mistaken for the PC
+    assert_eq!(parse_pc("$12:34 IGNORED"), None);
+    assert_eq!(parse_pc("NMI"), None);
+    assert_eq!(parse_pc(""), None);
+}
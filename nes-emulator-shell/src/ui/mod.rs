@@ -24,6 +24,8 @@ use ring_channel::{ring_channel, RingReceiver, RingSender, TryRecvError};
 
 use nes_emulator::framebuffer::*;
 use nes_emulator::genie::GameGenieCode;
+use nes_emulator::ntsc_filter::{NtscFilter, NTSC_FILTER_HEIGHT, NTSC_FILTER_WIDTH};
+use nes_emulator::ppu::Overscan;
 use nes_emulator::{
     cpu::core::BreakpointHandle, hook::HookHandle, nes::*, port::ControllerButton, system::Model,
 };
@@ -31,10 +33,11 @@ use nes_emulator::{
 use crate::RomIdentifier;
 use crate::{
     benchmark::BenchmarkState,
-    macros::{self, Macro, MacroPlayer},
+    macros::{self, FrameCrcs, Macro, MacroPlayer},
     ui::view::{
         apu::ApuView, debugger::DebuggerView, macro_builder::MacroBuilderView, memory::MemView,
-        nametable::NametablesView, sprites::SpritesView, trace_events::TraceEventsView,
+        nametable::NametablesView, palette::PaletteView, sprites::SpritesView,
+        trace_events::TraceEventsView,
     },
     utils, Args,
 };
@@ -47,11 +50,26 @@ use ::std::path::Path;
 
 const BENCHMARK_STATS_PERIOD_SECS: u8 = 3;
 
+const MAX_RUN_AHEAD_FRAMES: u8 = 4;
+
 pub enum Status {
     Ok,
     Quit,
 }
 
+/// Button labels for the input display overlay, in the order the buttons are shifted out
+/// of a standard controller
+const INPUT_DISPLAY_BUTTONS: [(ControllerButton, char); 8] = [
+    (ControllerButton::A, 'A'),
+    (ControllerButton::B, 'B'),
+    (ControllerButton::Select, 's'),
+    (ControllerButton::Start, 'S'),
+    (ControllerButton::Up, 'U'),
+    (ControllerButton::Down, 'D'),
+    (ControllerButton::Left, 'L'),
+    (ControllerButton::Right, 'R'),
+];
+
 const NOTICE_TIMEOUT_SECS: u8 = 7;
 struct Notice {
     level: log::Level,
@@ -72,6 +90,7 @@ pub enum ViewRequest {
     InstructionStepOver,
     InstructionStepIn,
     InstructionStepOut,
+    RunToAddress(u16),
 }
 
 #[derive(Clone)]
@@ -90,6 +109,7 @@ impl ViewRequestSender {
 #[cfg(not(target_arch = "wasm32"))]
 fn load_nes_from_path(
     path: Option<impl AsRef<Path>>,
+    model: Option<Model>,
     rom_dirs: &[PathBuf],
     audio_sample_rate: u32,
     start_timestamp: Instant,
@@ -97,7 +117,12 @@ fn load_nes_from_path(
 ) -> (Nes, Option<RomIdentifier>) {
     if let Some(path) = path {
         if let Some(ref path) = utils::search_rom_dirs(path, rom_dirs) {
-            match utils::create_nes_from_binary_path(path, audio_sample_rate, start_timestamp) {
+            match utils::create_nes_from_binary_path(
+                path,
+                model,
+                audio_sample_rate,
+                start_timestamp,
+            ) {
                 Ok(nes) => return (nes, Some(path.clone())),
                 Err(err) => {
                     notices.push_back(Notice {
@@ -122,7 +147,7 @@ fn load_nes_from_rom(
     notices: &mut VecDeque<Notice>,
 ) -> (Nes, Option<RomIdentifier>) {
     if let Some((rom, rom_name)) = rom {
-        match utils::create_nes_from_binary(rom, audio_sample_rate, start_timestamp) {
+        match utils::create_nes_from_binary(rom, None, audio_sample_rate, start_timestamp) {
             Ok(nes) => {
                 return (nes, Some(rom_name));
             }
@@ -141,6 +166,60 @@ fn load_nes_from_rom(
     )
 }
 
+/// Copies the contents of an RGBA8888 framebuffer into a texture
+fn upload_framebuffer_texture(
+    ctx: &egui::Context,
+    texture: &TextureHandle,
+    framebuffer: &Framebuffer,
+) {
+    let rental = framebuffer.rent_data().unwrap();
+
+    // hmmm, redundant copy, grumble grumble...
+    let copy = ImageDelta::full(
+        ImageData::Color(ColorImage {
+            size: [framebuffer.width() as _, framebuffer.height() as _],
+            pixels: rental
+                .data
+                .chunks_exact(4)
+                .map(|p| Color32::from_rgba_premultiplied(p[0], p[1], p[2], 255))
+                .collect(),
+        }),
+        TextureOptions::NEAREST,
+    );
+
+    ctx.tex_manager().write().set(texture.id(), copy);
+}
+
+/// The state for filtering the PPU's raw output with an [`NtscFilter`], which
+/// is presented instead of the PPU's own framebuffer
+struct NtscFilterState {
+    filter: NtscFilter,
+    framebuffer: Framebuffer,
+    texture: TextureHandle,
+}
+
+impl NtscFilterState {
+    fn new(ctx: &egui::Context) -> Self {
+        let framebuffer =
+            Framebuffer::new(NTSC_FILTER_WIDTH, NTSC_FILTER_HEIGHT, PixelFormat::RGBA8888);
+        let texture = blank_texture_for_framebuffer(ctx, &framebuffer, "ntsc_framebuffer");
+        Self {
+            filter: NtscFilter::default(),
+            framebuffer,
+            texture,
+        }
+    }
+
+    fn filter_frame(&mut self, nes: &mut Nes) {
+        if let Some(raw_frame) = nes.ppu_mut().raw_frame() {
+            let mut rental = self.framebuffer.rent_data().unwrap();
+            if let Err(err) = self.filter.filter_frame(raw_frame, &mut rental) {
+                error!("Failed to apply NTSC filter: {err:?}");
+            }
+        }
+    }
+}
+
 pub fn blank_texture_for_framebuffer(
     ctx: &egui::Context,
     info: &impl FramebufferInfo,
@@ -154,7 +233,15 @@ pub fn blank_texture_for_framebuffer(
     ctx.load_texture(name, blank, TextureOptions::NEAREST)
 }
 
-pub fn full_framebuffer_image_delta(fb: &FramebufferDataRental) -> ImageDelta {
+/// Copies a whole framebuffer into an [`ImageDelta`] for uploading to a texture
+///
+/// `raw_palette` is the emphasis palette used to convert `RAW9` pixels and is
+/// expected to be cached alongside the texture, so that it's not regenerated
+/// for every upload
+pub fn full_framebuffer_image_delta(
+    fb: &FramebufferDataRental,
+    raw_palette: &[nes_emulator::color::Color32],
+) -> ImageDelta {
     let owner = &fb.owner();
     let width = owner.width();
     let height = owner.height();
@@ -193,6 +280,21 @@ pub fn full_framebuffer_image_delta(fb: &FramebufferDataRental) -> ImageDelta {
             }),
             TextureOptions::NEAREST,
         ),
+        PixelFormat::RAW9 => ImageDelta::full(
+            ImageData::Color(ColorImage {
+                size: [width, height],
+                pixels: fb
+                    .data
+                    .chunks_exact(2)
+                    .map(|p| {
+                        let raw = u16::from_le_bytes([p[0], p[1]]) & 0x1ff;
+                        let color = raw_palette[raw as usize];
+                        Color32::from_rgb(color.r(), color.g(), color.b())
+                    })
+                    .collect(),
+            }),
+            TextureOptions::NEAREST,
+        ),
     }
 }
 
@@ -322,7 +424,10 @@ pub struct EmulatorUi {
 
     macro_queue: Vec<Macro>,
     macro_player: Option<MacroPlayer>,
-    shared_crc32: Rc<RefCell<u32>>,
+    /// Where to find reference images for macro image checks (the directory
+    /// of the macro library)
+    macro_image_dir: Option<PathBuf>,
+    shared_crcs: Rc<RefCell<FrameCrcs>>,
     crc_hook_handle: Option<HookHandle>,
 
     rom_dirs: Vec<PathBuf>,
@@ -348,6 +453,21 @@ pub struct EmulatorUi {
     queue_framebuffer_upload: bool,
     last_frame_time: Instant,
 
+    /// Applies an NTSC composite video filter to each frame when enabled
+    ntsc_filter: Option<NtscFilterState>,
+
+    /// Display options for the PPU that are re-applied to each new Nes
+    sprite_limit_disabled: bool,
+    overscan: Overscan,
+    fast_renderer: bool,
+
+    /// Overlays the frame / lag frame counters and controller input on top of the framebuffer
+    show_frame_counters: bool,
+
+    /// The number of frames to speculatively emulate ahead (with a clone of the Nes) for
+    /// each frame that's presented, to hide any input latency inherent to the game
+    run_ahead_frames: u8,
+
     #[cfg(feature = "cpu-debugger")]
     debugger_view: DebuggerView,
 
@@ -358,6 +478,8 @@ pub struct EmulatorUi {
 
     apu_view: ApuView,
 
+    palette_view: PaletteView,
+
     #[cfg(feature = "sprite-view")]
     sprites_view: SpritesView,
 
@@ -418,6 +540,7 @@ impl EmulatorUi {
             };
             let (nes, loaded_rom) = load_nes_from_path(
                 rom_path.as_ref(),
+                None,
                 &rom_dirs,
                 audio_sample_rate,
                 Instant::now(),
@@ -496,8 +619,12 @@ impl EmulatorUi {
 
             macro_queue,
             macro_player: None,
+            macro_image_dir: args
+                .macros
+                .as_ref()
+                .and_then(|library| Path::new(library).parent().map(Path::to_path_buf)),
             crc_hook_handle: None,
-            shared_crc32: Rc::new(RefCell::new(0)),
+            shared_crcs: Rc::new(RefCell::new(FrameCrcs::default())),
 
             tracing: false,
             trace_writer: None,
@@ -515,6 +642,13 @@ impl EmulatorUi {
             queue_framebuffer_upload: false,
             last_frame_time: now,
 
+            ntsc_filter: None,
+            sprite_limit_disabled: false,
+            overscan: Overscan::default(),
+            fast_renderer: args.fast_renderer,
+            show_frame_counters: false,
+            run_ahead_frames: 0,
+
             #[cfg(feature = "cpu-debugger")]
             debugger_view: DebuggerView::new(view_request_sender.clone(), paused),
 
@@ -531,6 +665,8 @@ impl EmulatorUi {
 
             apu_view: ApuView::new(),
 
+            palette_view: PaletteView::new(view_request_sender.clone()),
+
             #[cfg(feature = "sprite-view")]
             sprites_view: SpritesView::new(ctx),
 
@@ -586,6 +722,18 @@ impl EmulatorUi {
             }
         }
 
+        self.palette_view.power_on_new_nes_hook(&mut self.nes);
+        if self.ntsc_filter.is_some() {
+            self.nes.ppu_mut().set_raw_output_enabled(true);
+        }
+        self.nes
+            .ppu_mut()
+            .set_sprite_limit_enabled(!self.sprite_limit_disabled);
+        self.nes.ppu_mut().set_overscan(self.overscan);
+        self.nes
+            .ppu_mut()
+            .set_fast_renderer_enabled(self.fast_renderer);
+
         let start_timestamp = Instant::now();
         self.nes.power_cycle(start_timestamp);
         if let Err(err) = self.audio_stream.play() {
@@ -623,10 +771,13 @@ impl EmulatorUi {
     }
 
     #[cfg(not(target_arch = "wasm32"))]
-    pub fn poweron_nes_from_path(&mut self, path: impl AsRef<Path>) {
+    /// Powers on a new Nes for the given ROM, optionally overriding the model
+    /// that would otherwise be picked based on the ROM's header
+    pub fn poweron_nes_from_path(&mut self, path: impl AsRef<Path>, model: Option<Model>) {
         self.disconnect_nes();
         let (nes, loaded_rom) = load_nes_from_path(
             Some(path),
+            model,
             &self.rom_dirs,
             self.audio_sample_rate,
             Instant::now(),
@@ -663,7 +814,7 @@ impl EmulatorUi {
     #[cfg(not(any(target_os = "android", target_arch = "wasm32")))]
     fn open_dialog(&mut self, _ctx: &egui::Context) {
         if let Some(path) = EmulatorUi::pick_rom_dialog() {
-            self.poweron_nes_from_path(path);
+            self.poweron_nes_from_path(path, None);
         }
     }
 
@@ -774,7 +925,7 @@ impl EmulatorUi {
                     #[cfg(not(target_arch = "wasm32"))]
                     let success = {
                         if let Some(rom) = utils::search_rom_dirs(id, &self.rom_dirs) {
-                            self.poweron_nes_from_path(rom);
+                            self.poweron_nes_from_path(rom, None);
                             self.set_paused(false);
                             true
                         } else {
@@ -803,6 +954,7 @@ impl EmulatorUi {
                 ViewRequest::InstructionStepIn => self.step_instruction_in(),
                 ViewRequest::InstructionStepOut => self.step_instruction_out(),
                 ViewRequest::InstructionStepOver => self.step_instruction_over(),
+                ViewRequest::RunToAddress(addr) => self.run_to_address(addr),
             }
         }
 
@@ -810,19 +962,18 @@ impl EmulatorUi {
         if self.macro_player.is_none() {
             if let Some(next_macro) = self.macro_queue.pop() {
                 if let Some(rom) = utils::search_rom_dirs(&next_macro.rom, &self.rom_dirs) {
-                    self.poweron_nes_from_path(rom);
+                    self.poweron_nes_from_path(rom, next_macro.model.map(Model::from));
 
                     if self.crc_hook_handle.is_none() {
                         self.crc_hook_handle = Some(macros::register_frame_crc_hasher(
                             &mut self.nes,
-                            self.shared_crc32.clone(),
+                            self.shared_crcs.clone(),
                         ));
                     }
-                    self.macro_player = Some(MacroPlayer::new(
-                        next_macro,
-                        &mut self.nes,
-                        self.shared_crc32.clone(),
-                    ));
+                    let mut player =
+                        MacroPlayer::new(next_macro, &mut self.nes, self.shared_crcs.clone());
+                    player.set_image_dir(self.macro_image_dir.clone());
+                    self.macro_player = Some(player);
 
                     self.set_paused(false);
                     if let Some(macro_player) = &mut self.macro_player {
@@ -901,6 +1052,12 @@ impl EmulatorUi {
                             .swap_framebuffer(self.front_framebuffer.clone())
                             .expect("Failed to swap in new framebuffer for PPU");
 
+                        if self.run_ahead_enabled() {
+                            self.run_ahead();
+                        } else if let Some(ntsc_filter) = &mut self.ntsc_filter {
+                            ntsc_filter.filter_frame(&mut self.nes);
+                        }
+
                         self.queue_framebuffer_upload = true;
                         if self.nametables_view.visible {
                             self.nametables_view.update(&mut self.nes);
@@ -1017,10 +1174,21 @@ impl EmulatorUi {
                                 #[cfg(feature = "macro-builder")]
                                 self.macro_builder_view.save();
                             }
+                            Key::Num5 | Key::Num6 => {
+                                if let Some(vs_system) = &mut self.nes.system_mut().vs_system {
+                                    vs_system.insert_coin(if *key == Key::Num5 { 0 } else { 1 });
+                                }
+                            }
                             _ => {}
                         }
                     }
 
+                    if *key == Key::Num9 {
+                        if let Some(vs_system) = &mut self.nes.system_mut().vs_system {
+                            vs_system.service_button = *pressed;
+                        }
+                    }
+
                     let button = match key {
                         Key::Enter => Some(ControllerButton::Start),
                         Key::Space => Some(ControllerButton::Select),
@@ -1066,24 +1234,10 @@ impl EmulatorUi {
         if self.queue_framebuffer_upload {
             //println!("Uploading frame for render");
             //let rental = self.framebuffers[self.front_framebuffer].rent_data().unwrap();
-            let rental = self.front_framebuffer.rent_data().unwrap();
-
-            // hmmm, redundant copy, grumble grumble...
-            let copy = ImageDelta::full(
-                ImageData::Color(ColorImage {
-                    size: [front.width() as _, front.height() as _],
-                    pixels: rental
-                        .data
-                        .chunks_exact(4)
-                        .map(|p| Color32::from_rgba_premultiplied(p[0], p[1], p[2], 255))
-                        .collect(),
-                }),
-                TextureOptions::NEAREST,
-            );
-
-            ctx.tex_manager()
-                .write()
-                .set(self.framebuffer_texture.id(), copy);
+            upload_framebuffer_texture(ctx, &self.framebuffer_texture, &self.front_framebuffer);
+            if let Some(ntsc_filter) = &self.ntsc_filter {
+                upload_framebuffer_texture(ctx, &ntsc_filter.texture, &ntsc_filter.framebuffer);
+            }
 
             /*
             // DEBUG clear to red, to be able to see if any framebuffer pixels aren't rendered in the next frame
@@ -1153,6 +1307,111 @@ impl EmulatorUi {
                         }
                         ui.label("Ctrl-T");
                         ui.end_row();
+
+                        ui.checkbox(&mut self.show_frame_counters, "Show Frame Counters");
+                        ui.end_row();
+
+                        let mut ntsc_filter = self.ntsc_filter.is_some();
+                        if ui.checkbox(&mut ntsc_filter, "NTSC Filter").changed() {
+                            self.ntsc_filter = ntsc_filter.then(|| NtscFilterState::new(ctx));
+                            self.nes.ppu_mut().set_raw_output_enabled(ntsc_filter);
+                        }
+                        ui.end_row();
+
+                        if ui
+                            .checkbox(&mut self.sprite_limit_disabled, "Remove Sprite Limit")
+                            .on_hover_text(
+                                "Show more than eight sprites per line, to reduce flickering",
+                            )
+                            .changed()
+                        {
+                            self.nes
+                                .ppu_mut()
+                                .set_sprite_limit_enabled(!self.sprite_limit_disabled);
+                        }
+                        ui.end_row();
+
+                        ui.label("Overscan");
+                        let overscan_changed = ui
+                            .horizontal(|ui| {
+                                let mut changed = false;
+                                for (edge, pixels) in [
+                                    ("Top", &mut self.overscan.top),
+                                    ("Bottom", &mut self.overscan.bottom),
+                                    ("Left", &mut self.overscan.left),
+                                    ("Right", &mut self.overscan.right),
+                                ] {
+                                    changed |= ui
+                                        .add(egui::DragValue::new(pixels).clamp_range(0..=64))
+                                        .on_hover_text(format!("{edge} pixels to crop"))
+                                        .changed();
+                                }
+                                changed
+                            })
+                            .inner;
+                        if overscan_changed {
+                            self.nes.ppu_mut().set_overscan(self.overscan);
+                        }
+                        ui.end_row();
+
+                        if ui
+                            .checkbox(&mut self.fast_renderer, "Fast Renderer")
+                            .on_hover_text("Render whole spans of a line at once when possible (the output is identical)")
+                            .changed()
+                        {
+                            self.nes
+                                .ppu_mut()
+                                .set_fast_renderer_enabled(self.fast_renderer);
+                        }
+                        ui.end_row();
+
+                        let run_ahead_supported = !self.debugging();
+                        ui.add_enabled_ui(run_ahead_supported, |ui| {
+                            ui.add(
+                                egui::Slider::new(
+                                    &mut self.run_ahead_frames,
+                                    0..=MAX_RUN_AHEAD_FRAMES,
+                                )
+                                .text("Run Ahead"),
+                            )
+                            .on_disabled_hover_text(
+                                "Run ahead is disabled while debugging or playing/recording macros",
+                            );
+                        });
+                        ui.end_row();
+
+                        if let Some(vs_system) = &mut self.nes.system_mut().vs_system {
+                            ui.separator();
+                            ui.end_row();
+
+                            if ui.button("Insert Coin").clicked() {
+                                vs_system.insert_coin(0);
+                            }
+                            ui.label("5");
+                            ui.end_row();
+
+                            if ui.button("Insert Coin (Slot 2)").clicked() {
+                                vs_system.insert_coin(1);
+                            }
+                            ui.label("6");
+                            ui.end_row();
+
+                            ui.label("DIP Switches");
+                            ui.horizontal(|ui| {
+                                for switch in 0..8 {
+                                    let mask = 1u8 << switch;
+                                    let mut on = vs_system.dip_switches & mask != 0;
+                                    if ui
+                                        .checkbox(&mut on, "")
+                                        .on_hover_text(format!("DIP switch {}", switch + 1))
+                                        .changed()
+                                    {
+                                        vs_system.dip_switches ^= mask;
+                                    }
+                                }
+                            });
+                            ui.end_row();
+                        }
                     });
                 });
             });
@@ -1168,6 +1427,7 @@ impl EmulatorUi {
                     ui.toggle_value(&mut self.nametables_view.visible, "Nametables");
 
                     ui.toggle_value(&mut self.apu_view.visible, "APU");
+                    ui.toggle_value(&mut self.palette_view.visible, "Palette");
 
                     ui.add_enabled_ui(cfg!(feature = "sprite-view"), |ui| {
                         let resp = ui
@@ -1213,10 +1473,18 @@ impl EmulatorUi {
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.add(egui::Image::new(
-                self.framebuffer_texture.id(),
+            // The filtered frame is wider but it's displayed at the same size
+            let texture = match &self.ntsc_filter {
+                Some(ntsc_filter) => ntsc_filter.texture.id(),
+                None => self.framebuffer_texture.id(),
+            };
+            let resp = ui.add(egui::Image::new(
+                texture,
                 egui::Vec2::new((front.width() * 2) as f32, (front.height() * 2) as f32),
             ));
+            if self.show_frame_counters {
+                self.draw_frame_counters_overlay(ui, resp.rect);
+            }
         });
 
         #[cfg(feature = "cpu-debugger")]
@@ -1234,6 +1502,10 @@ impl EmulatorUi {
             self.apu_view.draw(&mut self.nes, ctx);
         }
 
+        if self.palette_view.visible {
+            self.palette_view.draw(&mut self.nes, ctx);
+        }
+
         #[cfg(feature = "sprite-view")]
         if self.sprites_view.visible {
             self.sprites_view.draw(&mut self.nes, ctx);
@@ -1249,12 +1521,102 @@ impl EmulatorUi {
             self.trace_events_view.draw(&mut self.nes, ctx);
         }
         if self.mem_view.visible {
+            #[cfg(feature = "macro-builder")]
+            {
+                self.mem_view.macro_actions_enabled = self.macro_builder_view.recording();
+            }
             self.mem_view.draw(&mut self.nes, ctx);
+            #[cfg(feature = "macro-builder")]
+            if let Some(action) = self.mem_view.take_action() {
+                self.macro_builder_view.memory_action(&mut self.nes, action);
+            }
         }
 
         status
     }
 
+    /// Returns `true` if any debugging tools are active that need to observe the Nes that's
+    /// being presented, which would be confused by run ahead emulation.
+    fn debugging(&self) -> bool {
+        #[cfg(feature = "cpu-debugger")]
+        if self.debugger_view.visible {
+            return true;
+        }
+        #[cfg(feature = "macro-builder")]
+        if self.macro_builder_view.visible {
+            return true;
+        }
+
+        self.macro_player.is_some()
+            || self.tracing
+            || self.trace_events_view.visible
+            || self.mem_view.visible
+    }
+
+    fn run_ahead_enabled(&self) -> bool {
+        self.run_ahead_frames > 0 && !self.debugging()
+    }
+
+    /// Emulates `run_ahead_frames` into the future, using a clone of the Nes, and
+    /// presents the last frame instead of the frame that was just completed.
+    ///
+    /// The clone is discarded afterwards (along with any audio it generated) so
+    /// the real Nes only ever progresses by one frame at a time.
+    fn run_ahead(&mut self) {
+        let mut ahead = self.nes.clone();
+        for _ in 0..self.run_ahead_frames {
+            // Debug state (such as breakpoints) isn't cloned so we can only
+            // expect to stop at the end of each frame
+            while !matches!(
+                ahead.progress(ProgressTarget::FrameReady),
+                ProgressStatus::FrameReady
+            ) {}
+        }
+
+        // Recycle the frame we were going to present as the new render target for the clone,
+        // which will be discarded before rendering to it.
+        match ahead.swap_framebuffer(self.front_framebuffer.clone()) {
+            Ok(framebuffer) => self.front_framebuffer = framebuffer,
+            Err(err) => error!("Failed to swap framebuffer for run ahead: {err:?}"),
+        }
+        if let Some(ntsc_filter) = &mut self.ntsc_filter {
+            ntsc_filter.filter_frame(&mut ahead);
+        }
+    }
+
+    fn draw_frame_counters_overlay(&mut self, ui: &mut Ui, rect: egui::Rect) {
+        let frame = self.nes.ppu_mut().frame;
+        let lag_frames = self.nes.lag_frame_count();
+        let lag = if self.nes.frame_was_lag() { " LAG" } else { "" };
+
+        let port = &self.nes.system_mut().port1;
+        let input: String = INPUT_DISPLAY_BUTTONS
+            .iter()
+            .map(|(button, label)| {
+                if port.peek_button(*button) {
+                    *label
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+
+        let painter = ui.painter_at(rect);
+        let font = egui::FontId::monospace(14.0);
+        let pos = rect.left_top() + egui::vec2(4.0, 4.0);
+        let text = format!("{frame}/{lag_frames}{lag}\n{input}");
+
+        // Draw a drop shadow so the text is readable over any background
+        painter.text(
+            pos + egui::vec2(1.0, 1.0),
+            egui::Align2::LEFT_TOP,
+            &text,
+            font.clone(),
+            Color32::BLACK,
+        );
+        painter.text(pos, egui::Align2::LEFT_TOP, &text, font, Color32::WHITE);
+    }
+
     pub fn set_paused(&mut self, paused: bool) {
         self.paused = paused;
 
@@ -1303,4 +1665,10 @@ impl EmulatorUi {
         self.temp_debug_breakpoint = self.nes.add_tmp_step_out_breakpoint();
         self.set_paused(false);
     }
+
+    #[cfg(feature = "cpu-debugger")]
+    pub fn run_to_address(&mut self, addr: u16) {
+        self.temp_debug_breakpoint = Some(self.nes.add_tmp_run_to_breakpoint(addr));
+        self.set_paused(false);
+    }
 }
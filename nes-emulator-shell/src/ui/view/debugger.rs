@@ -1,17 +1,117 @@
-use egui::vec2;
+use egui::{vec2, Color32, RichText};
 use egui_extras::{Size, StripBuilder};
+use nes_emulator::cpu::condition::Condition;
+use nes_emulator::cpu::core::{BreakpointCallbackAction, BreakpointHandle};
 use nes_emulator::nes::Nes;
 
 use crate::ui::{ViewRequest, ViewRequestSender};
 
+/// The longest 6502 instruction is three bytes (opcode + 16 bit operand)
+const MAX_INSTRUCTION_LEN: u16 = 3;
+
 pub struct InstructionLine {
+    addr: u16,
+    len: u16,
+    /// Where the instruction is in PRG ROM, if it's in ROM
+    rom_offset: Option<usize>,
+    bytes: String,
     disassembly: String,
 }
 
+impl InstructionLine {
+    fn peek(nes: &mut Nes, addr: u16) -> Self {
+        let (instruction, operand) = nes.peek_instruction(addr);
+        let len = instruction.len() as u16;
+        let bytes = (0..len)
+            .map(|i| format!("{:02x}", nes.peek_system_bus(addr.wrapping_add(i))))
+            .collect::<Vec<_>>()
+            .join(" ");
+        Self {
+            addr,
+            len,
+            rom_offset: nes.prg_rom_offset(addr),
+            bytes,
+            disassembly: instruction.disassemble(operand.raw_operand, operand.operand),
+        }
+    }
+
+    fn next_addr(&self) -> u16 {
+        self.addr.wrapping_add(self.len)
+    }
+
+    fn peek_lines(nes: &mut Nes, mut addr: u16, n_lines: usize) -> Vec<Self> {
+        let mut lines = Vec::with_capacity(n_lines);
+        for _ in 0..n_lines {
+            let line = Self::peek(nes, addr);
+            addr = line.next_addr();
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+/// Finds the address of the instruction that's `n_lines` before `addr`
+///
+/// Instructions can't be decoded backwards so this instead decodes forwards
+/// from progressively earlier addresses until it finds a start address whose
+/// instructions line up with `addr` again. If there's no way to go back by
+/// `n_lines` then the earliest in-sync start address that was found is
+/// returned.
+fn addr_lines_before(nes: &mut Nes, addr: u16, n_lines: usize) -> u16 {
+    let mut best = (addr, 0);
+    for back in 1..=(n_lines as u16 * MAX_INSTRUCTION_LEN) {
+        let start = addr.wrapping_sub(back);
+        let mut pc = start;
+        let mut count = 0;
+        while addr.wrapping_sub(pc) != 0 && addr.wrapping_sub(pc) <= back {
+            let (instruction, _) = nes.peek_instruction(pc);
+            pc = pc.wrapping_add(instruction.len() as u16);
+            count += 1;
+        }
+        if pc == addr && count <= n_lines && count > best.1 {
+            best = (start, count);
+            if count == n_lines {
+                break;
+            }
+        }
+    }
+    best.0
+}
+
+fn parse_address(text: &str) -> Option<u16> {
+    let text = text.trim();
+    let text = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .unwrap_or(text);
+    u16::from_str_radix(text, 16).ok()
+}
+
 pub struct DebuggerView {
     pub visible: bool,
     paused: bool,
     view_request_sender: ViewRequestSender,
+
+    /// Keep the PC in view as it changes
+    follow_pc: bool,
+
+    /// The address of the first instruction shown in the disassembly view
+    view_addr: u16,
+
+    /// The last instruction the user clicked on
+    cursor: Option<u16>,
+
+    /// Scrolling that's been accumulated but doesn't yet add up to a whole
+    /// line, so that smooth scrolling still moves the view
+    scroll_remainder: f32,
+
+    goto_address: String,
+
+    new_breakpoint_address: String,
+    new_breakpoint_condition: String,
+
+    /// The breakpoint whose condition is being edited, and the edited text
+    editing_condition: Option<(BreakpointHandle, String)>,
 }
 
 impl DebuggerView {
@@ -20,6 +120,14 @@ impl DebuggerView {
             visible: false,
             paused,
             view_request_sender,
+            follow_pc: true,
+            view_addr: 0,
+            cursor: None,
+            scroll_remainder: 0.0,
+            goto_address: String::new(),
+            new_breakpoint_address: String::new(),
+            new_breakpoint_condition: String::new(),
+            editing_condition: None,
         }
     }
 
@@ -27,7 +135,329 @@ impl DebuggerView {
         self.paused = paused;
     }
 
-    pub fn draw_disassembly(&mut self, _nes: &mut Nes, _ctx: &egui::Context) {}
+    /// Removes any enabled breakpoint at the given address, or else adds one
+    /// (or re-enables a disabled breakpoint)
+    ///
+    /// Conditional breakpoints are disabled instead of being removed, so that
+    /// their condition, hit count and break-after count aren't lost.
+    fn toggle_breakpoint(&mut self, nes: &mut Nes, addr: u16) {
+        let existing = nes
+            .cpu_mut()
+            .breakpoints()
+            .find(|bp| !bp.temporary() && bp.address() == Some(addr))
+            .map(|bp| (bp.handle(), bp.enabled(), bp.condition().is_some()));
+        match existing {
+            Some((handle, true, false)) => nes.cpu_mut().remove_breakpoint(handle),
+            Some((handle, enabled, _)) => {
+                if let Some(bp) = nes.cpu_mut().breakpoint_mut(handle) {
+                    bp.set_enabled(!enabled);
+                }
+            }
+            None => {
+                nes.cpu_mut()
+                    .add_break(addr, Box::new(|_, _| BreakpointCallbackAction::Keep));
+            }
+        }
+    }
+
+    fn parse_condition(&self, text: &str) -> Option<Condition> {
+        match Condition::try_from(text) {
+            Ok(condition) => Some(condition),
+            Err(err) => {
+                self.view_request_sender.send(ViewRequest::ShowUserNotice(
+                    log::Level::Warn,
+                    format!("Invalid condition: {err}"),
+                ));
+                None
+            }
+        }
+    }
+
+    fn add_breakpoint(&mut self, nes: &mut Nes) {
+        let addr = if self.new_breakpoint_address.trim().is_empty() {
+            None
+        } else if let Some(addr) = parse_address(&self.new_breakpoint_address) {
+            Some(addr)
+        } else {
+            self.view_request_sender.send(ViewRequest::ShowUserNotice(
+                log::Level::Warn,
+                format!("Invalid address \"{}\"", self.new_breakpoint_address),
+            ));
+            return;
+        };
+
+        if self.new_breakpoint_condition.trim().is_empty() {
+            match addr {
+                Some(addr) => {
+                    nes.cpu_mut()
+                        .add_break(addr, Box::new(|_, _| BreakpointCallbackAction::Keep));
+                }
+                None => {
+                    self.view_request_sender.send(ViewRequest::ShowUserNotice(
+                        log::Level::Warn,
+                        "A breakpoint needs an address or a condition".to_string(),
+                    ));
+                    return;
+                }
+            }
+        } else if let Some(condition) = self.parse_condition(&self.new_breakpoint_condition) {
+            nes.cpu_mut().add_conditional_break(addr, condition);
+        } else {
+            return;
+        }
+        self.new_breakpoint_address.clear();
+        self.new_breakpoint_condition.clear();
+    }
+
+    /// Lists breakpoints with their conditions and hit counts, which can all be
+    /// edited, along with a form for adding new (conditional) breakpoints
+    fn draw_breakpoints(&mut self, nes: &mut Nes, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.new_breakpoint_address)
+                    .hint_text("Address")
+                    .desired_width(60.0),
+            );
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.new_breakpoint_condition)
+                    .hint_text("Condition, e.g. A == $20 && scanline >= 200"),
+            );
+            let entered = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if entered || ui.button("Add").clicked() {
+                self.add_breakpoint(nes);
+            }
+        });
+
+        let breakpoints: Vec<_> = nes
+            .cpu_mut()
+            .breakpoints()
+            .filter(|bp| !bp.temporary())
+            .map(|bp| {
+                (
+                    bp.handle(),
+                    bp.address(),
+                    bp.enabled(),
+                    bp.condition().map(|c| c.source().to_string()),
+                    bp.hits(),
+                    bp.break_after(),
+                )
+            })
+            .collect();
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.set_min_size(ui.available_size());
+            for (handle, addr, mut enabled, condition, hits, mut break_after) in breakpoints {
+                ui.horizontal(|ui| {
+                    let mut remove = false;
+                    let mut reset_hits = false;
+                    let mut new_condition = None;
+
+                    let enabled_changed = ui.checkbox(&mut enabled, "").changed();
+                    match addr {
+                        Some(addr) => ui.monospace(format!("{addr:04x}")),
+                        None => ui.monospace("any "),
+                    };
+
+                    match &mut self.editing_condition {
+                        Some((editing, text)) if *editing == handle => {
+                            let response = ui.text_edit_singleline(text);
+                            if response.lost_focus() {
+                                if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                                    new_condition = Some(text.clone());
+                                }
+                                self.editing_condition = None;
+                            } else {
+                                response.request_focus();
+                            }
+                        }
+                        _ => {
+                            let text = condition.as_deref().unwrap_or("(no condition)");
+                            if ui
+                                .add(egui::Label::new(text).sense(egui::Sense::click()))
+                                .on_hover_text("Click to edit the condition")
+                                .clicked()
+                            {
+                                self.editing_condition =
+                                    Some((handle, condition.clone().unwrap_or_default()));
+                            }
+                        }
+                    }
+
+                    ui.label(format!("{hits} hits"));
+                    if ui.small_button("Reset").clicked() {
+                        reset_hits = true;
+                    }
+                    let break_after_changed = ui
+                        .add(egui::DragValue::new(&mut break_after).prefix("break after: "))
+                        .changed();
+                    if ui.small_button("Remove").clicked() {
+                        remove = true;
+                    }
+
+                    if remove {
+                        nes.cpu_mut().remove_breakpoint(handle);
+                    } else if let Some(bp) = nes.cpu_mut().breakpoint_mut(handle) {
+                        if enabled_changed {
+                            bp.set_enabled(enabled);
+                        }
+                        if reset_hits {
+                            bp.reset_hits();
+                        }
+                        if break_after_changed {
+                            bp.set_break_after(break_after);
+                        }
+                        // An empty condition removes the condition
+                        if let Some(text) = new_condition {
+                            if text.trim().is_empty() {
+                                bp.set_condition(None);
+                            } else if let Some(condition) = self.parse_condition(&text) {
+                                bp.set_condition(Some(condition));
+                            }
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    fn goto(&mut self, nes: &mut Nes, addr: u16) {
+        self.follow_pc = false;
+        self.cursor = Some(addr);
+        self.view_addr = addr_lines_before(nes, addr, 4);
+    }
+
+    /// Draws a disassembly of the instructions at `view_addr`, with the PC,
+    /// breakpoints and cursor highlighted
+    ///
+    /// Clicking on the gutter of a line toggles a breakpoint and the context
+    /// menu for each line can be used to run to that line.
+    pub fn draw_disassembly(&mut self, nes: &mut Nes, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.goto_address)
+                    .hint_text("Address")
+                    .desired_width(60.0),
+            );
+            let entered = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if entered || ui.button("Go").clicked() {
+                match parse_address(&self.goto_address) {
+                    Some(addr) => self.goto(nes, addr),
+                    None => self.view_request_sender.send(ViewRequest::ShowUserNotice(
+                        log::Level::Warn,
+                        format!("Invalid address \"{}\"", self.goto_address),
+                    )),
+                }
+            }
+            ui.checkbox(&mut self.follow_pc, "Follow PC");
+        });
+        ui.separator();
+
+        let pc = nes.cpu_mut().pc;
+        let breakpoints: Vec<u16> = nes
+            .cpu_mut()
+            .breakpoints()
+            .filter(|bp| bp.enabled() && !bp.temporary())
+            .filter_map(|bp| bp.address())
+            .collect();
+
+        let row_height =
+            ui.text_style_height(&egui::TextStyle::Monospace) + ui.spacing().item_spacing.y;
+        let n_rows = ((ui.available_height() / row_height) as usize).max(1);
+
+        let list_rect = ui.available_rect_before_wrap();
+        if ui.rect_contains_pointer(list_rect) {
+            self.scroll_remainder += ui.input(|i| i.scroll_delta.y);
+            let n_lines = (self.scroll_remainder.abs() / row_height) as usize;
+            if n_lines > 0 {
+                let scroll = self.scroll_remainder;
+                self.scroll_remainder -= scroll.signum() * n_lines as f32 * row_height;
+                self.follow_pc = false;
+                if scroll > 0.0 {
+                    self.view_addr = addr_lines_before(nes, self.view_addr, n_lines);
+                } else {
+                    for _ in 0..n_lines {
+                        self.view_addr = InstructionLine::peek(nes, self.view_addr).next_addr();
+                    }
+                }
+            }
+        }
+
+        let mut lines = InstructionLine::peek_lines(nes, self.view_addr, n_rows);
+
+        // Re-centre the view whenever the PC moves out of view, or out of
+        // sync with the instructions that are shown
+        if self.follow_pc
+            && !lines[..lines.len().saturating_sub(2)]
+                .iter()
+                .any(|line| line.addr == pc)
+        {
+            self.view_addr = addr_lines_before(nes, pc, n_rows / 4);
+            lines = InstructionLine::peek_lines(nes, self.view_addr, n_rows);
+        }
+
+        for line in lines {
+            let is_breakpoint = breakpoints.contains(&line.addr);
+            ui.horizontal(|ui| {
+                let gutter = if is_breakpoint {
+                    RichText::new("●").color(Color32::RED)
+                } else {
+                    RichText::new("○").color(Color32::DARK_GRAY)
+                };
+                let gutter = ui
+                    .add(egui::Label::new(gutter.monospace()).sense(egui::Sense::click()))
+                    .on_hover_text("Toggle breakpoint");
+                if gutter.clicked() {
+                    self.toggle_breakpoint(nes, line.addr);
+                }
+
+                let marker = if line.addr == pc { "▶" } else { " " };
+                let rom = match line.rom_offset {
+                    Some(offset) => format!("{offset:05x}"),
+                    None => "-----".to_string(),
+                };
+                let text = format!(
+                    "{marker} {:04x} {rom}  {:<8}  {}",
+                    line.addr, line.bytes, line.disassembly
+                );
+                let mut text = RichText::new(text).monospace();
+                if line.addr == pc {
+                    text = text
+                        .background_color(Color32::from_rgb(0x50, 0x50, 0x10))
+                        .color(Color32::WHITE);
+                } else if self.cursor == Some(line.addr) {
+                    text = text.background_color(ui.visuals().selection.bg_fill);
+                }
+                if is_breakpoint {
+                    text = text.color(Color32::LIGHT_RED);
+                }
+
+                let response = ui.add(egui::Label::new(text).sense(egui::Sense::click()));
+                if response.clicked() {
+                    self.cursor = Some(line.addr);
+                }
+                response.context_menu(|ui| {
+                    self.cursor = Some(line.addr);
+                    if ui.button("Toggle Breakpoint").clicked() {
+                        self.toggle_breakpoint(nes, line.addr);
+                        ui.close_menu();
+                    }
+                    if ui
+                        .add_enabled(self.paused, egui::Button::new("Run to Cursor"))
+                        .clicked()
+                    {
+                        self.view_request_sender
+                            .send(ViewRequest::RunToAddress(line.addr));
+                        ui.close_menu();
+                    }
+                    if ui.button("Copy Address").clicked() {
+                        ui.output_mut(|o| o.copied_text = format!("{:04x}", line.addr));
+                        ui.close_menu();
+                    }
+                });
+            });
+        }
+    }
+
     pub fn draw(&mut self, nes: &mut Nes, ctx: &egui::Context) {
         egui::Window::new("Debugger")
             .default_size(vec2(1024.0, 1024.0))
@@ -48,14 +478,20 @@ impl DebuggerView {
                                 self.view_request_sender
                                     .send(ViewRequest::InstructionStepOut);
                             }
+                            if let Some(cursor) = self.cursor {
+                                if ui.button("Run to Cursor").clicked() {
+                                    self.view_request_sender
+                                        .send(ViewRequest::RunToAddress(cursor));
+                                }
+                            }
                         }
                     });
                 });
 
                 egui::CentralPanel::default().show_inside(ui, |ui| {
                     StripBuilder::new(ui)
-                        .size(Size::relative(0.85))
-                        .size(Size::relative(0.15))
+                        .size(Size::relative(0.7))
+                        .size(Size::relative(0.3))
                         .vertical(|mut strip| {
                             strip.strip(|builder| {
                                 builder
@@ -65,16 +501,8 @@ impl DebuggerView {
                                     .horizontal(|mut strip| {
                                         // Disassembly
                                         strip.cell(|ui| {
-                                            egui::ScrollArea::vertical().show(ui, |ui| {
-                                                // Add a lot of widgets here.
-                                                let pc = nes.cpu_mut().pc;
-                                                let (instruction, operand) =
-                                                    nes.peek_instruction(pc);
-
-                                                ui.label(instruction.disassemble(
-                                                    operand.raw_operand,
-                                                    operand.operand,
-                                                ));
+                                            ui.push_id("debugger_disassembly", |ui| {
+                                                self.draw_disassembly(nes, ui);
                                             });
                                         });
 
@@ -121,13 +549,7 @@ impl DebuggerView {
                                             ui.heading("Breakpoints");
                                             ui.push_id("debugger_breakpoints", |ui| {
                                                 ui.group(|ui| {
-                                                    egui::ScrollArea::vertical().show(ui, |ui| {
-                                                        ui.set_min_size(ui.available_size());
-                                                        for bp in nes.cpu_mut().breakpoints() {
-                                                            let addr = bp.address();
-                                                            ui.label(format!("{addr:04x}"));
-                                                        }
-                                                    });
+                                                    self.draw_breakpoints(nes, ui);
                                                 });
                                             });
                                         });
@@ -7,8 +7,11 @@ use egui_extras::{Column, TableBuilder};
 use nes_emulator::{genie::GameGenieCode, hook::HookHandle, nes::Nes, port::ControllerButton};
 
 use crate::{
-    macros::{self, InputEvent, Macro, MacroCommand, MacroPlayer, MacroWait},
-    ui::{ViewRequest, ViewRequestSender},
+    macros::{
+        self, CpuRegisters, FrameCrcs, InputEvent, Macro, MacroCommand, MacroCondition,
+        MacroPlayer, MacroWait,
+    },
+    ui::{view::memory::MemoryAction, ViewRequest, ViewRequestSender},
     Args, RomIdentifier,
 };
 
@@ -17,10 +20,6 @@ use crate::ui::EmulatorUi;
 #[cfg(not(target_arch = "wasm32"))]
 use crate::utils;
 
-struct MacroBuilderHookState {
-    crc32: u32,
-}
-
 pub struct MacroBuilderView {
     pub visible: bool,
 
@@ -46,7 +45,11 @@ pub struct MacroBuilderView {
     last_wait: MacroWait,
 
     hook_handle: Option<HookHandle>,
-    hook_state: Rc<RefCell<MacroBuilderHookState>>,
+    hook_state: Rc<RefCell<FrameCrcs>>,
+
+    /// Record CRC checks against the raw PPU output, instead of palette values, so
+    /// they're also sensitive to color emphasis but not to any palette changes
+    raw_crc_checks: bool,
 
     /// Pending button state changes, relative to the current Nes state
     /// Flushed when another command progresses the Nes CPU clock
@@ -85,7 +88,8 @@ impl MacroBuilderView {
             },
 
             hook_handle: None,
-            hook_state: Rc::new(RefCell::new(MacroBuilderHookState { crc32: 0 })),
+            hook_state: Rc::new(RefCell::new(FrameCrcs::default())),
+            raw_crc_checks: false,
 
             pending_button_input: HashMap::new(),
         };
@@ -114,22 +118,10 @@ impl MacroBuilderView {
             return;
         }
 
-        let shared = self.hook_state.clone();
-        let mut hasher = crc32fast::Hasher::new();
-
-        self.hook_handle = Some(nes.ppu_mut().add_mux_hook(Box::new(
-            move |_ppu, _cartridge, state| {
-                if state.screen_x == 0 && state.screen_y == 0 {
-                    hasher.reset();
-                }
-
-                hasher.update(&[state.palette_value]);
-
-                if state.screen_x == 255 && state.screen_y == 239 {
-                    shared.borrow_mut().crc32 = hasher.clone().finalize();
-                }
-            },
-        )));
+        self.hook_handle = Some(macros::register_frame_crc_hasher(
+            nes,
+            self.hook_state.clone(),
+        ));
     }
 
     pub fn disconnect_nes(&mut self, nes: &mut Nes) {
@@ -343,6 +335,65 @@ impl MacroBuilderView {
         }));
     }
 
+    /// Saves the current frame as a reference image next to the macro library,
+    /// returning the path relative to the library's directory
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_reference_image(
+        library_path: Option<&std::path::Path>,
+        macro_name: &str,
+        nes: &mut Nes,
+    ) -> Option<String> {
+        let library_dir = library_path?.parent()?;
+        let name: String = macro_name
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect();
+        let relative = format!("images/{name}-frame-{}.png", nes.ppu_mut().frame);
+        let path = library_dir.join(&relative);
+        let saved = std::fs::create_dir_all(path.parent()?)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| Ok(crate::frame_image::framebuffer_image(nes).save(&path)?));
+        match saved {
+            Ok(()) => Some(relative),
+            Err(err) => {
+                log::error!("Failed to save reference image {}: {err:?}", path.display());
+                None
+            }
+        }
+    }
+
+    pub fn recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Records a command that was picked from the context menu for a value in
+    /// the memory view
+    pub fn memory_action(&mut self, nes: &mut Nes, action: MemoryAction) {
+        if !self.recording {
+            return;
+        }
+        debug_assert!(self.current_macro < self.library.len());
+        let test = &mut self.library[self.current_macro];
+
+        let wait = MacroWait {
+            frame: Some(nes.ppu_mut().frame),
+            line: Some(nes.ppu_mut().line),
+            dot: nes.ppu_mut().dot,
+        };
+        if self.last_wait.less_than(&wait) {
+            test.commands.push(MacroCommand::WaitForDot(wait));
+            self.last_wait = wait;
+        }
+        test.commands.push(match action {
+            MemoryAction::CheckValue(addr) => MacroCommand::CheckMemory {
+                addr,
+                expected: nes.peek_system_bus(addr),
+                mask: 0xff,
+            },
+            MemoryAction::WaitForWrite(addr) => MacroCommand::WaitForWrite { addr, value: None },
+        });
+    }
+
     // Input changes are buffered while paused so we don't end up recording lots of redundant input changes
     // within a single cycle
     pub fn set_paused(&mut self, paused: bool, nes: &mut Nes) {
@@ -489,7 +540,7 @@ impl MacroBuilderView {
 
                         ui.allocate_ui(Vec2::new(labels_width, height), |ui| {
                             ui.set_min_width(labels_width);
-                            let crc = self.hook_state.borrow().crc32;
+                            let crc = self.hook_state.borrow().select(self.raw_crc_checks);
                             let crc_text = format!("{crc:08x}");
                             let label = egui::Label::new(format!("Frame CRC: {crc_text}")).sense(egui::Sense::click());
                             ui.add(label).context_menu(|ui| {
@@ -629,8 +680,62 @@ impl MacroBuilderView {
                                                 current_macro.commands.push(MacroCommand::WaitForDot(wait));
                                                 self.last_wait = wait;
                                             }
-                                            current_macro.commands.push(MacroCommand::CheckFrameCRC32(self.hook_state.borrow().crc32));
+                                            let crc = self.hook_state.borrow().select(self.raw_crc_checks);
+                                            current_macro.commands.push(if self.raw_crc_checks {
+                                                MacroCommand::CheckRawFrameCRC32(crc)
+                                            } else {
+                                                MacroCommand::CheckFrameCRC32(crc)
+                                            });
+                                        }
+                                        if ui.button("Add Wait Until Frame CRC32").on_hover_text("Wait until the frame matches, instead of waiting for the current frame, line and dot").clicked() {
+                                            let crc = self.hook_state.borrow().select(self.raw_crc_checks);
+                                            let condition = if self.raw_crc_checks {
+                                                MacroCondition::RawFrameCRC32(crc)
+                                            } else {
+                                                MacroCondition::FrameCRC32(crc)
+                                            };
+                                            current_macro.commands.push(MacroCommand::WaitUntil { condition, timeout_frames: macros::DEFAULT_TIMEOUT_FRAMES });
+                                        }
+                                        ui.checkbox(&mut self.raw_crc_checks, "Raw CRC32")
+                                            .on_hover_text("Check a CRC32 of the raw PPU output (including emphasis) that's not affected by palette changes");
+                                        #[cfg(not(target_arch = "wasm32"))]
+                                        if ui.add_enabled(self.library_path.is_some(), egui::Button::new("Add Frame Image Check"))
+                                            .on_hover_text("Save the current frame as a reference image next to the macro library and check against it, with some tolerance")
+                                            .clicked()
+                                        {
+                                            let wait = MacroWait {
+                                                frame: Some(nes.ppu_mut().frame),
+                                                line: Some(nes.ppu_mut().line),
+                                                dot: nes.ppu_mut().dot
+                                            };
+                                            if let Some(path) = Self::save_reference_image(self.library_path.as_deref(), &current_macro.name, nes) {
+                                                if self.last_wait.less_than(&wait) {
+                                                    current_macro.commands.push(MacroCommand::WaitForDot(wait));
+                                                    self.last_wait = wait;
+                                                }
+                                                current_macro.commands.push(MacroCommand::CheckFrameImage {
+                                                    path,
+                                                    tolerance: 8,
+                                                    max_differing_pixels: 0,
+                                                });
+                                            }
+                                        }
+                                        if ui.button("Add Test ROM Status Check").clicked() {
+                                            current_macro.commands.push(MacroCommand::CheckBlarggStatus { timeout_frames: macros::DEFAULT_BLARGG_TIMEOUT_FRAMES });
+                                        }
+                                        if ui.button("Add CPU Registers Check").clicked() {
+                                            let wait = MacroWait {
+                                                frame: Some(nes.ppu_mut().frame),
+                                                line: Some(nes.ppu_mut().line),
+                                                dot: nes.ppu_mut().dot
+                                            };
+                                            if self.last_wait.less_than(&wait) {
+                                                current_macro.commands.push(MacroCommand::WaitForDot(wait));
+                                                self.last_wait = wait;
+                                            }
+                                            current_macro.commands.push(MacroCommand::CheckCpuRegisters(CpuRegisters::current(nes)));
                                         }
+                                        ui.label("Memory checks can be added from the context menu for a value in the Memory View");
                                         if ui.button("Add Reset").clicked() {
                                             let wait = MacroWait {
                                                 frame: Some(nes.ppu_mut().frame),
@@ -801,6 +906,41 @@ impl MacroBuilderView {
                                                 MacroCommand::CheckFrameCRC32(crc) => {
                                                     ui.label(format!("Check framebuffer CRC32 == {crc:08x}"));
                                                 }
+                                                MacroCommand::CheckRawFrameCRC32(crc) => {
+                                                    ui.label(format!("Check raw framebuffer CRC32 == {crc:08x}"));
+                                                }
+                                                MacroCommand::CheckFrameImage { path, tolerance, max_differing_pixels } => {
+                                                    ui.label(format!("Check frame matches {path} (tolerance = {tolerance}, max differing pixels = {max_differing_pixels})"));
+                                                }
+                                                MacroCommand::CheckBlarggStatus { timeout_frames } => {
+                                                    ui.label(format!("Wait for test ROM status ($6000) and check it passed (timeout = {timeout_frames} frames)"));
+                                                }
+                                                MacroCommand::CheckMemory { addr, expected, mask } => {
+                                                    if *mask == 0xff {
+                                                        ui.label(format!("Check [{addr:04x}] == {expected:02x}"));
+                                                    } else {
+                                                        ui.label(format!("Check [{addr:04x}] & {mask:02x} == {:02x}", expected & mask));
+                                                    }
+                                                }
+                                                MacroCommand::CheckMemoryRange { addr, bytes } => {
+                                                    ui.label(format!("Check {} bytes from [{addr:04x}]", bytes.len()));
+                                                }
+                                                MacroCommand::CheckCpuRegisters(registers) => {
+                                                    ui.label(format!("Check CPU registers: {registers}"));
+                                                }
+                                                MacroCommand::WriteMemory { addr, value } => {
+                                                    ui.label(format!("Write {value:02x} to [{addr:04x}]"));
+                                                }
+                                                MacroCommand::WaitUntil { condition, timeout_frames } => {
+                                                    ui.label(format!("Wait until {condition} (timeout = {timeout_frames} frames)"));
+                                                }
+                                                MacroCommand::WaitForWrite { addr, value } => {
+                                                    if let Some(value) = value {
+                                                        ui.label(format!("Wait for write of {value:02x} to [{addr:04x}]"));
+                                                    } else {
+                                                        ui.label(format!("Wait for write to [{addr:04x}]"));
+                                                    }
+                                                }
                                             }
                                         });
 
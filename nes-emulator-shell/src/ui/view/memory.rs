@@ -19,10 +19,22 @@ impl Debug for AddressSpace {
     }
 }
 
+/// A request to add a macro command for a system bus address, from the context
+/// menu for a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAction {
+    CheckValue(u16),
+    WaitForWrite(u16),
+}
+
 pub struct MemView {
     pub visible: bool,
     selected_space: AddressSpace,
     tmp_row_values: Vec<u8>,
+
+    /// Set to let the user add macro commands by clicking on values
+    pub macro_actions_enabled: bool,
+    action: Option<MemoryAction>,
 }
 impl MemView {
     pub fn new() -> Self {
@@ -30,9 +42,16 @@ impl MemView {
             visible: false,
             selected_space: AddressSpace::System,
             tmp_row_values: vec![],
+            macro_actions_enabled: false,
+            action: None,
         }
     }
 
+    /// Takes the last action that was picked from a value's context menu
+    pub fn take_action(&mut self) -> Option<MemoryAction> {
+        self.action.take()
+    }
+
     pub fn draw(&mut self, nes: &mut Nes, ctx: &egui::Context) {
         egui::Window::new("Memory View")
             .resizable(true)
@@ -117,8 +136,26 @@ impl MemView {
                                 AddressSpace::Oam => nes.ppu_mut().peek_oam_data(addr as u8),
                             };
                             self.tmp_row_values.push(val);
+                            let actions_enabled = self.macro_actions_enabled
+                                && self.selected_space == AddressSpace::System;
+                            let action = &mut self.action;
                             row.col(|ui| {
-                                ui.label(format!("{:02x}", val));
+                                let label = egui::Label::new(format!("{:02x}", val))
+                                    .sense(egui::Sense::click());
+                                let response = ui.add(label);
+                                if actions_enabled {
+                                    let addr = addr as u16;
+                                    response.context_menu(|ui| {
+                                        if ui.button("Add Macro Memory Check").clicked() {
+                                            *action = Some(MemoryAction::CheckValue(addr));
+                                            ui.close_menu();
+                                        }
+                                        if ui.button("Add Macro Wait For Write").clicked() {
+                                            *action = Some(MemoryAction::WaitForWrite(addr));
+                                            ui.close_menu();
+                                        }
+                                    });
+                                }
                             });
                         }
                         row.col(|ui| {
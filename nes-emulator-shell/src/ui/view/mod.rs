@@ -2,6 +2,7 @@ pub mod apu;
 pub mod macro_builder;
 pub mod memory;
 pub mod nametable;
+pub mod palette;
 pub mod sprites;
 pub mod trace_events;
 //pub mod trace_apu;
@@ -1,6 +1,9 @@
 use egui::{epaint::ImageDelta, pos2, ColorImage, ImageData, TextureHandle, TextureOptions, Ui};
 use nes_emulator::{constants::*, nes::Nes};
 
+#[cfg(feature = "cpu-debugger")]
+use nes_emulator::ppu::RegisterWrite;
+
 pub struct NametablesView {
     pub visible: bool,
     fb_width: usize,
@@ -11,6 +14,13 @@ pub struct NametablesView {
     hover_pos: [usize; 2],
     show_scroll: bool,
     queue_fb_upload: bool,
+
+    /// Overlay the $2000/$2001/$2005/$2006 writes from the last frame, to see
+    /// where raster effects land
+    #[cfg(feature = "cpu-debugger")]
+    show_register_writes: bool,
+    #[cfg(feature = "cpu-debugger")]
+    register_writes: Vec<RegisterWrite>,
 }
 
 impl NametablesView {
@@ -40,6 +50,11 @@ impl NametablesView {
 
             show_scroll: true,
             hover_pos: [0, 0],
+
+            #[cfg(feature = "cpu-debugger")]
+            show_register_writes: false,
+            #[cfg(feature = "cpu-debugger")]
+            register_writes: vec![],
         }
     }
 
@@ -58,6 +73,17 @@ impl NametablesView {
         }
 
         self.queue_fb_upload = true;
+
+        #[cfg(feature = "cpu-debugger")]
+        {
+            let ppu = nes.ppu_mut();
+            if ppu.register_write_log_enabled() != self.show_register_writes {
+                ppu.set_register_write_log_enabled(self.show_register_writes);
+            }
+            self.register_writes.clear();
+            self.register_writes
+                .extend_from_slice(ppu.register_writes());
+        }
     }
 
     // Really klunky :/
@@ -99,13 +125,39 @@ impl NametablesView {
                     .min_width(panels_width)
                     .show_inside(ui, |ui| {
                         ui.checkbox(&mut self.show_scroll, "Show Scroll Position");
+                        #[cfg(feature = "cpu-debugger")]
+                        ui.checkbox(&mut self.show_register_writes, "Show Register Writes")
+                            .on_hover_text(
+                                "Mark where writes to $2000/$2001/$2005/$2006 land (glitches in red)",
+                            );
                     });
                 egui::SidePanel::right("nametables_properties_panel")
                     .resizable(false)
                     .min_width(panels_width)
-                    .show_inside(ui, |_ui| {
+                    .show_inside(ui, |ui| {
                         //ui.label(format!("Scroll X: {}", self.nes.system_ppu().scroll_x()));
                         //ui.label(format!("Scroll Y: {}", self.nes.system_ppu().scroll_y()));
+
+                        #[cfg(feature = "cpu-debugger")]
+                        if self.show_register_writes {
+                            egui::ScrollArea::vertical().show(ui, |ui| {
+                                for write in self.register_writes.iter() {
+                                    let text = format!(
+                                        "{:3},{:3}: ${:04X} = ${:02X}",
+                                        write.line, write.dot, write.addr, write.value
+                                    );
+                                    match write.glitch {
+                                        Some(glitch) => {
+                                            ui.colored_label(egui::Color32::RED, text)
+                                                .on_hover_text(glitch.description());
+                                        }
+                                        None => {
+                                            ui.monospace(text);
+                                        }
+                                    }
+                                }
+                            });
+                        }
                     });
 
                 egui::TopBottomPanel::bottom("nametables_footer").show_inside(ui, |ui| {
@@ -140,7 +192,50 @@ impl NametablesView {
                         let img_width = response.rect.width();
                         let _img_height = response.rect.height();
                         let img_to_nes_px = self.fb_width as f32 / img_width;
-                        let _nes_px_to_img = 1.0 / img_to_nes_px;
+
+                        #[cfg(feature = "cpu-debugger")]
+                        if self.show_register_writes {
+                            let nes_px_to_img = 1.0 / img_to_nes_px;
+                            let mut hovered = vec![];
+                            for write in self.register_writes.iter() {
+                                if let Some((x, y)) = write.nametable_position() {
+                                    let center = img_pos
+                                        + egui::vec2(x as f32, y as f32) * nes_px_to_img;
+                                    let color = if write.glitch.is_some() {
+                                        egui::Color32::RED
+                                    } else {
+                                        egui::Color32::YELLOW
+                                    };
+                                    painter.circle_filled(center, 2.0, color);
+                                    if response
+                                        .hover_pos()
+                                        .is_some_and(|pos| pos.distance(center) < 4.0)
+                                    {
+                                        hovered.push(write);
+                                    }
+                                }
+                            }
+                            if !hovered.is_empty() {
+                                egui::show_tooltip_at_pointer(
+                                    ctx,
+                                    egui::Id::new("nametables_register_write_tooltip"),
+                                    |ui| {
+                                        for write in hovered {
+                                            ui.label(format!(
+                                                "Line {}, dot {}: ${:04X} = ${:02X}",
+                                                write.line, write.dot, write.addr, write.value
+                                            ));
+                                            if let Some(glitch) = write.glitch {
+                                                ui.colored_label(
+                                                    egui::Color32::RED,
+                                                    glitch.description(),
+                                                );
+                                            }
+                                        }
+                                    },
+                                );
+                            }
+                        }
 
                         if let Some(hover_pos) = response.hover_pos() {
                             let x = ((hover_pos.x - img_pos.x) * img_to_nes_px) as usize;
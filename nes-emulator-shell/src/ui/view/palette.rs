@@ -0,0 +1,234 @@
+#[cfg(not(any(target_os = "android", target_arch = "wasm32")))]
+use std::path::PathBuf;
+
+use nes_emulator::{
+    color::Color32,
+    nes::Nes,
+    ppu_palette::{self, PaletteGeneratorParams},
+};
+
+use crate::ui::{ViewRequest, ViewRequestSender};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PaletteSource {
+    Default,
+    Generated,
+    File,
+}
+
+pub struct PaletteView {
+    pub visible: bool,
+
+    view_request_sender: ViewRequestSender,
+
+    source: PaletteSource,
+    params: PaletteGeneratorParams,
+    file_palette: Option<(String, Vec<Color32>)>,
+
+    /// The palette currently being used, including all emphasis combinations
+    palette: Vec<Color32>,
+}
+
+impl PaletteView {
+    pub fn new(view_request_sender: ViewRequestSender) -> Self {
+        Self {
+            visible: false,
+            view_request_sender,
+            source: PaletteSource::Default,
+            params: PaletteGeneratorParams::default(),
+            file_palette: None,
+            palette: ppu_palette::default_emphasis_palette(),
+        }
+    }
+
+    /// A new Nes is created each time a ROM is loaded so we need to re-apply
+    /// any custom palette
+    pub fn power_on_new_nes_hook(&mut self, nes: &mut Nes) {
+        if self.source != PaletteSource::Default {
+            self.apply(nes);
+        }
+    }
+
+    fn update_palette(&mut self, nes: &mut Nes) {
+        self.palette = match self.source {
+            PaletteSource::Default => ppu_palette::default_emphasis_palette(),
+            PaletteSource::Generated => ppu_palette::generate_ntsc_palette(&self.params),
+            PaletteSource::File => match &self.file_palette {
+                Some((_, palette)) => palette.clone(),
+                None => ppu_palette::default_emphasis_palette(),
+            },
+        };
+        self.apply(nes);
+    }
+
+    fn apply(&self, nes: &mut Nes) {
+        if let Err(err) = nes.ppu_mut().set_rgb_palette(self.palette.clone()) {
+            self.view_request_sender.send(ViewRequest::ShowUserNotice(
+                log::Level::Error,
+                format!("{err}"),
+            ));
+        }
+    }
+
+    #[cfg(not(any(target_os = "android", target_arch = "wasm32")))]
+    fn open_pal_file(&mut self, nes: &mut Nes, path: PathBuf) {
+        let palette = std::fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| ppu_palette::parse_pal_file(&data));
+        match palette {
+            Ok(palette) => {
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                self.file_palette = Some((name, palette));
+                self.source = PaletteSource::File;
+                self.update_palette(nes);
+            }
+            Err(err) => self.view_request_sender.send(ViewRequest::ShowUserNotice(
+                log::Level::Error,
+                format!("Failed to load palette: {err}"),
+            )),
+        }
+    }
+
+    #[cfg(not(any(target_os = "android", target_arch = "wasm32")))]
+    fn open_pal_file_dialog(&mut self, nes: &mut Nes) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("pal", &["pal"])
+            .pick_file()
+        {
+            self.open_pal_file(nes, path);
+        }
+    }
+
+    #[cfg(not(any(target_os = "android", target_arch = "wasm32")))]
+    fn export_pal_file_dialog(&self) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("pal", &["pal"])
+            .save_file()
+        {
+            let data = ppu_palette::pal_file_from_palette(&self.palette);
+            if let Err(err) = std::fs::write(path, data) {
+                self.view_request_sender.send(ViewRequest::ShowUserNotice(
+                    log::Level::Error,
+                    format!("Failed to export palette: {err}"),
+                ));
+            }
+        }
+    }
+
+    fn draw_swatches(&self, ui: &mut egui::Ui, emphasis: usize) {
+        let swatch_size = 20.0;
+        let (response, painter) = ui.allocate_painter(
+            egui::vec2(swatch_size * 16.0, swatch_size * 4.0),
+            egui::Sense::hover(),
+        );
+        for (i, color) in self.palette[(emphasis << 6)..((emphasis + 1) << 6)]
+            .iter()
+            .enumerate()
+        {
+            let x = (i % 16) as f32 * swatch_size;
+            let y = (i / 16) as f32 * swatch_size;
+            painter.rect_filled(
+                egui::Rect::from_min_size(
+                    response.rect.min + egui::vec2(x, y),
+                    egui::vec2(swatch_size, swatch_size),
+                ),
+                egui::Rounding::none(),
+                egui::Color32::from_rgb(color.r(), color.g(), color.b()),
+            );
+        }
+        if let Some(hover_pos) = response.hover_pos() {
+            let offset = hover_pos - response.rect.min;
+            let index = (offset.y / swatch_size) as usize * 16 + (offset.x / swatch_size) as usize;
+            if index < 64 {
+                let color = self.palette[(emphasis << 6) | index];
+                response.on_hover_text(format!(
+                    "${index:02X}: #{:02x}{:02x}{:02x}",
+                    color.r(),
+                    color.g(),
+                    color.b()
+                ));
+            }
+        }
+    }
+
+    pub fn draw(&mut self, nes: &mut Nes, ctx: &egui::Context) {
+        let mut changed = false;
+        egui::Window::new("Palette")
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::ComboBox::from_label("Palette")
+                    .selected_text(format!("{:?}", self.source))
+                    .show_ui(ui, |ui| {
+                        changed |= ui
+                            .selectable_value(&mut self.source, PaletteSource::Default, "Default")
+                            .changed();
+                        changed |= ui
+                            .selectable_value(
+                                &mut self.source,
+                                PaletteSource::Generated,
+                                "Generated",
+                            )
+                            .changed();
+                        ui.add_enabled_ui(self.file_palette.is_some(), |ui| {
+                            changed |= ui
+                                .selectable_value(&mut self.source, PaletteSource::File, "File")
+                                .changed();
+                        });
+                    });
+
+                ui.horizontal(|ui| {
+                    #[cfg(not(any(target_os = "android", target_arch = "wasm32")))]
+                    {
+                        if ui.button("Open .pal").clicked() {
+                            self.open_pal_file_dialog(nes);
+                        }
+                        if ui.button("Export .pal").clicked() {
+                            self.export_pal_file_dialog();
+                        }
+                    }
+                    if let Some((name, _)) = &self.file_palette {
+                        ui.label(name);
+                    }
+                });
+
+                if self.source == PaletteSource::Generated {
+                    ui.separator();
+                    let params = &mut self.params;
+                    changed |= ui
+                        .add(egui::Slider::new(&mut params.hue, -180.0..=180.0).text("Hue"))
+                        .changed();
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(&mut params.saturation, 0.0..=2.0).text("Saturation"),
+                        )
+                        .changed();
+                    changed |= ui
+                        .add(egui::Slider::new(&mut params.contrast, 0.5..=1.5).text("Contrast"))
+                        .changed();
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(&mut params.brightness, -0.5..=0.5)
+                                .text("Brightness"),
+                        )
+                        .changed();
+                    changed |= ui
+                        .add(egui::Slider::new(&mut params.gamma, 1.0..=3.0).text("Gamma"))
+                        .changed();
+                    if ui.button("Reset").clicked() {
+                        *params = PaletteGeneratorParams::default();
+                        changed = true;
+                    }
+                }
+
+                ui.separator();
+                self.draw_swatches(ui, 0);
+            });
+
+        if changed {
+            self.update_palette(nes);
+        }
+    }
+}
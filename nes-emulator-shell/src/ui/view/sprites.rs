@@ -17,6 +17,7 @@ struct SpritesHookState {
 pub struct SpritesView {
     pub visible: bool,
     screen_texture: TextureHandle,
+    screen_raw_palette: Vec<nes_emulator::color::Color32>,
     queue_screen_fb_upload: bool,
 
     mux_hook_handle: Option<HookHandle>,
@@ -41,6 +42,7 @@ impl SpritesView {
             visible: false,
 
             screen_texture,
+            screen_raw_palette: nes_emulator::ppu_palette::default_emphasis_palette(),
             queue_screen_fb_upload: false,
 
             mux_hook_handle: None,
@@ -131,8 +133,10 @@ impl SpritesView {
     pub fn draw(&mut self, _nes: &mut Nes, ctx: &egui::Context) {
         if self.queue_screen_fb_upload {
             let _hook_state = self.hook_state.borrow();
-            let copy =
-                full_framebuffer_image_delta(&self.hook_state.borrow().screen_framebuffer_front);
+            let copy = full_framebuffer_image_delta(
+                &self.hook_state.borrow().screen_framebuffer_front,
+                &self.screen_raw_palette,
+            );
             ctx.tex_manager()
                 .write()
                 .set(self.screen_texture.id(), copy);
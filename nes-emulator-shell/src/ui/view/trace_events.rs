@@ -97,6 +97,7 @@ pub struct TraceEventsView {
     show_apu_output: ApuOutput,
 
     screen_texture: TextureHandle,
+    screen_raw_palette: Vec<nes_emulator::color::Color32>,
     queue_screen_fb_upload: bool,
 
     mux_hook_handle: Option<HookHandle>,
@@ -149,6 +150,7 @@ impl TraceEventsView {
             line_gap_height: 0.5f32,
 
             screen_texture,
+            screen_raw_palette: nes_emulator::ppu_palette::default_emphasis_palette(),
             queue_screen_fb_upload: false,
 
             mux_hook_handle: None,
@@ -1174,8 +1176,10 @@ impl TraceEventsView {
     pub fn draw(&mut self, nes: &mut Nes, ctx: &egui::Context) {
         if self.queue_screen_fb_upload {
             let _hook_state = self.hook_state.borrow();
-            let copy =
-                full_framebuffer_image_delta(&self.hook_state.borrow().screen_framebuffer_front);
+            let copy = full_framebuffer_image_delta(
+                &self.hook_state.borrow().screen_framebuffer_front,
+                &self.screen_raw_palette,
+            );
             ctx.tex_manager()
                 .write()
                 .set(self.screen_texture.id(), copy);
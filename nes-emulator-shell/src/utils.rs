@@ -26,29 +26,35 @@ pub fn epoch_timestamp() -> u64 {
     }
 }
 
+/// Creates a powered-on [`Nes`] for the given ROM
+///
+/// The model is picked based on the ROM's header, unless overridden by `model`
 pub fn create_nes_from_binary(
     rom: &[u8],
+    model: Option<Model>,
     audio_sample_rate: u32,
     start_timestamp: Instant,
 ) -> Result<Nes> {
     let cartridge = Cartridge::from_binary(rom)?;
-    let model = match cartridge.tv_system() {
-        nes_emulator::cartridge::TVSystemCompatibility::Pal => Model::Pal,
-        _ => Model::Ntsc,
-    };
-    let mut nes = Nes::new(model, audio_sample_rate, start_timestamp);
-    nes.insert_cartridge(Some(cartridge))?;
-    nes.power_cycle(start_timestamp);
-    Ok(nes)
+    match model {
+        Some(model) => {
+            let mut nes = Nes::new(model, audio_sample_rate, start_timestamp);
+            nes.insert_cartridge(Some(cartridge))?;
+            nes.power_cycle(start_timestamp);
+            Ok(nes)
+        }
+        None => Nes::new_with_cartridge(cartridge, audio_sample_rate, start_timestamp),
+    }
 }
 
 pub fn create_nes_from_binary_path(
     path: impl AsRef<Path>,
+    model: Option<Model>,
     audio_sample_rate: u32,
     start_timestamp: Instant,
 ) -> Result<Nes> {
     let rom = std::fs::read(path)?;
-    create_nes_from_binary(&rom, audio_sample_rate, start_timestamp)
+    create_nes_from_binary(&rom, model, audio_sample_rate, start_timestamp)
 }
 
 pub fn canonicalize_rom_dirs(rom_dirs: &[String]) -> Vec<PathBuf> {
@@ -108,3 +114,44 @@ pub fn search_rom_dirs<P: AsRef<std::path::Path>>(
 
     None
 }
+
+/// Builds a minimal NROM binary (like the emulator's own tests) where
+/// `program` runs from the reset vector at $8000 and IRQs/NMIs just return
+#[cfg(test)]
+pub(crate) fn test_nrom_binary(program: &[u8]) -> Vec<u8> {
+    use nes_emulator::constants::{PAGE_SIZE_16K, PAGE_SIZE_8K};
+    const RTI: u16 = 0xfff9;
+
+    let mut prg_rom = vec![0xea; PAGE_SIZE_16K]; // NOP
+    prg_rom[..program.len()].copy_from_slice(program);
+    prg_rom[(RTI & 0x3fff) as usize] = 0x40;
+    prg_rom[0x3ffa..0x4000].copy_from_slice(&[
+        RTI as u8,
+        (RTI >> 8) as u8,
+        0x00, // reset = $8000
+        0x80,
+        RTI as u8,
+        (RTI >> 8) as u8,
+    ]);
+
+    let mut binary = vec![b'N', b'E', b'S', 0x1a, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    binary.extend_from_slice(&prg_rom);
+    binary.extend_from_slice(&[0; PAGE_SIZE_8K]);
+    binary
+}
+
+#[test]
+fn test_create_nes_model_override() {
+    let rom = include_bytes!("../../roms/other/hello.nes");
+
+    let nes = create_nes_from_binary(rom, None, 48000, Instant::now()).unwrap();
+    assert_eq!(nes.model(), Model::Ntsc);
+    let nes = create_nes_from_binary(rom, Some(Model::Pal), 48000, Instant::now()).unwrap();
+    assert_eq!(nes.model(), Model::Pal);
+
+    let recording: crate::macros::Macro = serde_json::from_str(
+        r#"{ "name": "pal", "rom": "hello.nes", "notes": "", "model": "Pal", "commands": [] }"#,
+    )
+    .unwrap();
+    assert_eq!(recording.model.map(Model::from), Some(Model::Pal));
+}
@@ -62,7 +62,7 @@ pub struct DmcChannel {
 impl DmcChannel {
     pub fn new(nes_model: Model) -> Self {
         let periods_table = match nes_model {
-            Model::Ntsc => PeriodsTable(&DMC_PERIODS_TABLE_NTSC),
+            Model::Ntsc | Model::Dendy => PeriodsTable(&DMC_PERIODS_TABLE_NTSC),
             Model::Pal => PeriodsTable(&DMC_PERIODS_TABLE_PAL),
         };
 
@@ -2,6 +2,7 @@ use bitflags::bitflags;
 
 //use crate::emulation::CPU_CLOCK_HZ;
 
+use crate::system::Model;
 use crate::trace::{TraceBuffer, TraceEvent};
 
 /*
@@ -27,8 +28,40 @@ pub enum FrameSequencerMode {
     FiveStep,
 }
 
+/// The CPU clock cycles for the steps of the frame sequencer
+///
+/// The fourth step is only used in four step mode and the fifth step is only
+/// used in five step mode.
+///
+/// These come from https://www.nesdev.org/wiki/APU_Frame_Counter except doubled
+/// because we step the frame sequencer by CPU clock cycles
+struct StepClocks {
+    step1: u16,
+    step2: u16,
+    step3: u16,
+    step4: u16,
+    step5: u16,
+}
+
+const NTSC_STEP_CLOCKS: StepClocks = StepClocks {
+    step1: 7457,
+    step2: 14913,
+    step3: 22371,
+    step4: 29829,
+    step5: 37281,
+};
+
+const PAL_STEP_CLOCKS: StepClocks = StepClocks {
+    step1: 8313,
+    step2: 16627,
+    step3: 24939,
+    step4: 33253,
+    step5: 41565,
+};
+
 #[derive(Clone, Default)]
 pub struct FrameSequencer {
+    nes_model: Model,
     clock: u16,
     queue_clock_reset: bool, // Can only reset clock on an even cycle
     pub mode: FrameSequencerMode,
@@ -42,7 +75,7 @@ pub struct FrameSequencer {
 }
 
 impl FrameSequencer {
-    pub fn new(start_apu_clock: u64) -> Self {
+    pub fn new(nes_model: Model, start_apu_clock: u64) -> Self {
         // blargg apu 2005: 09.reset_timing:
         // ; After reset or power-up, APU acts as if $4017 were written with
         // ; $00 from 9 to 12 clocks before first instruction begins.
@@ -53,6 +86,7 @@ impl FrameSequencer {
         // NB: make sure polarity matches start_apu_clock
         let clock = if start_apu_clock % 2 == 0 { 6 } else { 7 };
         Self {
+            nes_model,
             interrupt_enable: true,
             clock,
 
@@ -61,7 +95,7 @@ impl FrameSequencer {
     }
 
     pub fn power_cycle(&mut self, start_apu_clock: u64) {
-        *self = Self::new(start_apu_clock);
+        *self = Self::new(self.nes_model, start_apu_clock);
     }
 
     pub fn reset(&mut self) {
@@ -158,8 +192,10 @@ impl FrameSequencer {
         // see the half/quarter frame clocks)
         debug_assert!((apu_clock % 2 == 1) == (self.clock % 2 == 1));
 
-        // APU sequencer constants come from https://www.nesdev.org/wiki/APU_Frame_Counter
-        // except doubled because we step the frame sequencer by CPU clock cycles
+        let steps = match self.nes_model {
+            Model::Ntsc | Model::Dendy => &NTSC_STEP_CLOCKS,
+            Model::Pal => &PAL_STEP_CLOCKS,
+        };
 
         // Note: we only ever return a Half/QuarterFrame status on an odd/APU clock cycle
         // Note: we only ever reset the clock to zero on an even clock cycle
@@ -167,20 +203,22 @@ impl FrameSequencer {
         let mut status = match self.mode {
             FrameSequencerMode::FourStep => {
                 match self.clock {
-                    7457 => FrameSequencerStatus::QUARTER_FRAME,
-                    14913 => FrameSequencerStatus::QUARTER_FRAME | FrameSequencerStatus::HALF_FRAME,
-                    22371 => FrameSequencerStatus::QUARTER_FRAME,
-                    29828 => {
+                    c if c == steps.step1 => FrameSequencerStatus::QUARTER_FRAME,
+                    c if c == steps.step2 => {
+                        FrameSequencerStatus::QUARTER_FRAME | FrameSequencerStatus::HALF_FRAME
+                    }
+                    c if c == steps.step3 => FrameSequencerStatus::QUARTER_FRAME,
+                    c if c == steps.step4 - 1 => {
                         //println!("Frame Sequencer: set_irq, clock = {}", self.clock);
                         self.set_irq();
                         FrameSequencerStatus::default()
                     }
-                    29829 => {
+                    c if c == steps.step4 => {
                         //println!("Frame Sequencer: set_irq, clock = {}", self.clock);
                         self.set_irq();
                         FrameSequencerStatus::QUARTER_FRAME | FrameSequencerStatus::HALF_FRAME
                     }
-                    29830 => {
+                    c if c == steps.step4 + 1 => {
                         self.clock = 0;
                         //println!("Frame Sequencer: set_irq, clock = {}", self.clock);
                         self.set_irq();
@@ -190,11 +228,15 @@ impl FrameSequencer {
                 }
             }
             FrameSequencerMode::FiveStep => match self.clock {
-                7457 => FrameSequencerStatus::QUARTER_FRAME,
-                14913 => FrameSequencerStatus::QUARTER_FRAME | FrameSequencerStatus::HALF_FRAME,
-                22371 => FrameSequencerStatus::QUARTER_FRAME,
-                37281 => FrameSequencerStatus::QUARTER_FRAME | FrameSequencerStatus::HALF_FRAME,
-                37282 => {
+                c if c == steps.step1 => FrameSequencerStatus::QUARTER_FRAME,
+                c if c == steps.step2 => {
+                    FrameSequencerStatus::QUARTER_FRAME | FrameSequencerStatus::HALF_FRAME
+                }
+                c if c == steps.step3 => FrameSequencerStatus::QUARTER_FRAME,
+                c if c == steps.step5 => {
+                    FrameSequencerStatus::QUARTER_FRAME | FrameSequencerStatus::HALF_FRAME
+                }
+                c if c == steps.step5 + 1 => {
                     self.clock = 0;
                     FrameSequencerStatus::default()
                 }
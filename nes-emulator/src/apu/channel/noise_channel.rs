@@ -1,18 +1,20 @@
 use super::frame_sequencer::FrameSequencerStatus;
 use crate::apu::channel::length_counter::LengthCounter;
 use crate::apu::channel::volume_envelope::VolumeEnvelope;
+use crate::system::Model;
 
 const NTSC_TIMER_PERIODS_TABLE: [u16; 16] = [
     4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
 ];
 
-#[allow(dead_code)]
 const PAL_TIMER_PERIODS_TABLE: [u16; 16] = [
     4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
 ];
 
 #[derive(Clone, Default)]
 pub struct NoiseChannel {
+    nes_model: Model,
+
     volume_envelope: VolumeEnvelope,
     pub length_counter: LengthCounter,
 
@@ -26,9 +28,10 @@ pub struct NoiseChannel {
 }
 
 impl NoiseChannel {
-    pub fn new() -> Self {
+    pub fn new(nes_model: Model) -> Self {
         let channel_name = "Noise".to_string();
         Self {
+            nes_model,
             // "On power-up, the shift register is loaded with the value 1"
             shift_register: 1,
             volume_envelope: VolumeEnvelope::new(channel_name.clone()),
@@ -38,7 +41,7 @@ impl NoiseChannel {
     }
 
     pub fn power_cycle(&mut self) {
-        *self = Self::new();
+        *self = Self::new(self.nes_model);
     }
 
     pub fn update_output(&mut self) {
@@ -104,7 +107,11 @@ impl NoiseChannel {
             1 => {} // Sweep N/A
             2 => {
                 let period_index = value & 0xf;
-                self.timer_period = NTSC_TIMER_PERIODS_TABLE[period_index as usize];
+                let periods_table = match self.nes_model {
+                    Model::Ntsc | Model::Dendy => &NTSC_TIMER_PERIODS_TABLE,
+                    Model::Pal => &PAL_TIMER_PERIODS_TABLE,
+                };
+                self.timer_period = periods_table[period_index as usize];
 
                 self.mode_flag = (value & 0b1000_0000) != 0;
             }
@@ -53,7 +53,7 @@ impl Apu {
             clock,
             sample_rate,
             output_step,
-            frame_sequencer: FrameSequencer::new(clock),
+            frame_sequencer: FrameSequencer::new(nes_model, clock),
             square_channel1: SquareChannel::new(nes_model, "Square 1".to_string(), false),
             square_channel2: SquareChannel::new(
                 nes_model,
@@ -61,7 +61,7 @@ impl Apu {
                 true, /* two's compliment sweep negate */
             ),
             triangle_channel: TriangleChannel::new(nes_model),
-            noise_channel: NoiseChannel::new(),
+            noise_channel: NoiseChannel::new(nes_model),
             dmc_channel: DmcChannel::new(nes_model),
             mixer: Mixer::new(),
             ..Default::default() /*
@@ -5,6 +5,7 @@ use log::{debug, warn};
 
 use crate::cartridge::{NameTableMirror, TVSystemCompatibility};
 use crate::constants::*;
+use crate::vs_system::VsSystemConfig;
 
 #[derive(Debug)]
 pub enum Type {
@@ -56,6 +57,9 @@ pub struct INesConfig {
     /// Override the `nametable_mirror` mode and provide four screens of VRAM
     pub four_screen_vram: bool,
 
+    /// The Vs. System hardware, if this is a Vs. System game
+    pub vs_system: Option<VsSystemConfig>,
+
     /// The optional file offset for trainer code
     pub trainer_baseaddr: Option<usize>,
 
@@ -272,18 +276,45 @@ pub fn parse_ines_header(ines: &[u8]) -> Result<INesConfig> {
 
     let flags7 = ines[7];
     let _flags8 = ines[8];
-    let _flags9 = ines[9];
+    let flags9 = ines[9];
     let flags10 = ines[10];
-    let tv_system = match flags10 & 0b11 {
-        0 => TVSystemCompatibility::Ntsc,
-        2 => TVSystemCompatibility::Pal,
-        1 | 3 => TVSystemCompatibility::Dual,
-
-        _ => {
-            unreachable!()
-        } // Rust compiler should know this is unreachable :/
+    let tv_system = if version == 2 {
+        // NES 2.0 CPU/PPU timing
+        match ines[12] & 0b11 {
+            0 => TVSystemCompatibility::Ntsc,
+            1 => TVSystemCompatibility::Pal,
+            2 => TVSystemCompatibility::Dual,
+            _ => TVSystemCompatibility::Dendy,
+        }
+    } else if flags9 & 1 != 0 {
+        // The (rarely used) official iNES TV system bit
+        TVSystemCompatibility::Pal
+    } else {
+        match flags10 & 0b11 {
+            0 => TVSystemCompatibility::Ntsc,
+            2 => TVSystemCompatibility::Pal,
+            1 | 3 => TVSystemCompatibility::Dual,
+
+            _ => {
+                unreachable!()
+            } // Rust compiler should know this is unreachable :/
+        }
     };
     debug!("iNes: TV System {:?}", tv_system);
+
+    // 0 = NES/Famicom, 1 = Vs. System, 2 = PlayChoice-10, 3 = Extended
+    let console_type = flags7 & 0b11;
+    let vs_system = if console_type == 1 {
+        // iNES 1.0 headers can't specify which PPU a Vs. System game needs
+        Some(if version == 2 {
+            VsSystemConfig::from_nes2_system_type(ines[13])
+        } else {
+            VsSystemConfig::default()
+        })
+    } else {
+        None
+    };
+    debug!("iNes: Vs. System {:?}", vs_system);
     // 11~15 unused_padding
     debug_assert!(n_prg_rom_pages > 0);
 
@@ -318,6 +349,7 @@ pub fn parse_ines_header(ines: &[u8]) -> Result<INesConfig> {
         n_chr_ram_pages,
         nametable_mirror,
         four_screen_vram,
+        vs_system,
         has_battery,
         has_chr_ram,
         has_trainer,
@@ -335,3 +367,30 @@ pub fn parse_any_header(binary: &[u8]) -> Result<NesBinaryConfig> {
         Type::Unknown => Err(anyhow!("Unknown binary type")),
     }
 }
+
+/// Builds an NROM iNES image for tests that need to run a specific program
+///
+/// The program is loaded at $8000, where the CPU starts after a reset. NMIs
+/// jump to `nmi_handler` if given, or else to an `RTI` at $fff9.
+#[cfg(test)]
+pub(crate) fn test_nrom_binary(program: &[u8], nmi_handler: Option<u16>) -> Vec<u8> {
+    const RTI: u16 = 0xfff9;
+
+    let mut prg_rom = vec![0xea; PAGE_SIZE_16K]; // NOP
+    prg_rom[..program.len()].copy_from_slice(program);
+    let nmi = nmi_handler.unwrap_or(RTI);
+    prg_rom[(RTI & 0x3fff) as usize] = 0x40;
+    prg_rom[0x3ffa..0x4000].copy_from_slice(&[
+        nmi as u8,
+        (nmi >> 8) as u8,
+        0x00, // reset = $8000
+        0x80,
+        RTI as u8,
+        (RTI >> 8) as u8,
+    ]);
+
+    let mut binary = vec![b'N', b'E', b'S', 0x1a, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    binary.extend_from_slice(&prg_rom);
+    binary.extend_from_slice(&[0; PAGE_SIZE_8K]);
+    binary
+}
@@ -7,6 +7,7 @@ use anyhow::Result;
 use crate::binary::NesBinaryConfig;
 use crate::binary::{self, INesConfig, NsfConfig};
 use crate::mappers::*;
+use crate::vs_system::VsSystemConfig;
 
 pub const fn page_offset(page_no: usize, page_size: usize) -> usize {
     page_no * page_size
@@ -17,6 +18,7 @@ pub enum TVSystemCompatibility {
     Ntsc,
     Pal,
     Dual,
+    Dendy,
     Unknown,
 }
 impl Default for TVSystemCompatibility {
@@ -25,28 +27,6 @@ impl Default for TVSystemCompatibility {
     }
 }
 
-struct NoCartridge;
-impl Mapper for NoCartridge {
-    fn reset(&mut self) {}
-    fn clone_mapper(&self) -> Box<dyn Mapper> {
-        Box::new(NoCartridge)
-    }
-    fn system_bus_read(&mut self, _addr: u16) -> (u8, u8) {
-        (0, 0)
-    }
-    fn system_bus_peek(&mut self, _addr: u16) -> (u8, u8) {
-        (0, 0)
-    }
-    fn system_bus_write(&mut self, _addr: u16, _data: u8) {}
-    fn ppu_bus_read(&mut self, _addr: u16) -> u8 {
-        0
-    }
-    fn ppu_bus_peek(&mut self, _addr: u16) -> u8 {
-        0
-    }
-    fn ppu_bus_write(&mut self, _addr: u16, _data: u8) {}
-}
-
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum NameTableMirror {
     Unknown,
@@ -63,17 +43,10 @@ impl Default for NameTableMirror {
     }
 }
 
+#[derive(Clone)]
 pub struct Cartridge {
     pub config: NesBinaryConfig,
-    pub mapper: Box<dyn Mapper>,
-}
-impl Clone for Cartridge {
-    fn clone(&self) -> Self {
-        Self {
-            config: self.config.clone(),
-            mapper: self.mapper.clone_mapper(),
-        }
-    }
+    pub mapper: AnyMapper,
 }
 impl Default for Cartridge {
     fn default() -> Self {
@@ -93,7 +66,7 @@ impl Cartridge {
 
         println!("NSF Config = {config:#?}");
 
-        let mapper = Box::new(Mapper31::new(config, &nsf[128..(prg_len as usize)]));
+        let mapper = AnyMapper::Mapper31(Mapper31::new(config, &nsf[128..(prg_len as usize)]));
         Ok(Cartridge {
             config: NesBinaryConfig::Nsf(config.clone()),
             mapper,
@@ -138,14 +111,15 @@ impl Cartridge {
             chr_data[0..chr_rom_bytes].copy_from_slice(&ines[ines_start..ines_end]);
         }
 
-        let mut mapper: Box<dyn Mapper> = match config.mapper_number {
-            0 => Box::new(Mapper0::new(config, prg_rom, chr_data)),
-            1 => Box::new(Mapper1::new(config, prg_rom, chr_data)),
-            2 => Box::new(Mapper2::new(config, prg_rom, chr_data)),
-            3 => Box::new(Mapper3::new(config, prg_rom, chr_data)),
-            4 => Box::new(Mapper4::new(config, prg_rom, chr_data)),
-            7 => Box::new(Mapper7::new(config, prg_rom, chr_data)),
-            66 => Box::new(Mapper66::new(config, prg_rom, chr_data)),
+        let mut mapper = match config.mapper_number {
+            0 => AnyMapper::Mapper0(Mapper0::new(config, prg_rom, chr_data)),
+            1 => AnyMapper::Mapper1(Mapper1::new(config, prg_rom, chr_data)),
+            2 => AnyMapper::Mapper2(Mapper2::new(config, prg_rom, chr_data)),
+            3 => AnyMapper::Mapper3(Mapper3::new(config, prg_rom, chr_data)),
+            4 => AnyMapper::Mapper4(Mapper4::new(config, prg_rom, chr_data)),
+            7 => AnyMapper::Mapper7(Mapper7::new(config, prg_rom, chr_data)),
+            66 => AnyMapper::Mapper66(Mapper66::new(config, prg_rom, chr_data)),
+            99 => AnyMapper::Mapper99(Mapper99::new(config, prg_rom, chr_data)),
             _ => {
                 return Err(anyhow!(
                     "Unsupported mapper number {}",
@@ -186,10 +160,18 @@ impl Cartridge {
         }
     }
 
+    /// Create a cartridge with a mapper that's implemented outside of this crate
+    pub fn with_external_mapper(config: NesBinaryConfig, mapper: Box<dyn Mapper>) -> Cartridge {
+        Cartridge {
+            config,
+            mapper: AnyMapper::External(mapper),
+        }
+    }
+
     pub fn none() -> Cartridge {
         Cartridge {
             config: NesBinaryConfig::None,
-            mapper: Box::new(NoCartridge),
+            mapper: AnyMapper::NoCartridge(NoCartridge),
         }
     }
 
@@ -210,6 +192,14 @@ impl Cartridge {
         }
     }
 
+    /// The Vs. System hardware the cartridge is built for, if it's a Vs. System game
+    pub fn vs_system(&self) -> Option<VsSystemConfig> {
+        match &self.config {
+            NesBinaryConfig::INes(ines_config) => ines_config.vs_system,
+            _ => None,
+        }
+    }
+
     pub fn system_bus_read(&mut self, addr: u16) -> (u8, u8) {
         self.mapper.system_bus_read(addr)
     }
@@ -220,6 +210,11 @@ impl Cartridge {
         self.mapper.system_bus_write(addr, data);
     }
 
+    /// The offset into PRG ROM currently mapped at the given CPU address, if any
+    pub fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        self.mapper.prg_rom_offset(addr)
+    }
+
     pub fn ppu_bus_read(&mut self, addr: u16) -> u8 {
         //println!("PPU BUS: read {addr:04x}");
 
@@ -296,4 +291,14 @@ impl Cartridge {
     pub fn step_m2_phi2(&mut self, cpu_clock: u64) {
         self.mapper.step_m2_phi2(cpu_clock);
     }
+
+    /// Whether the mapper is currently raising an IRQ
+    pub fn irq(&self) -> bool {
+        self.mapper.irq()
+    }
+
+    /// Whether the mapper needs to observe PPU bus I/O as it happens
+    pub fn observes_ppu_fetches(&self) -> bool {
+        self.mapper.observes_ppu_fetches()
+    }
 }
@@ -864,6 +864,18 @@ pub fn rgb_from_hsv((h, s, v): (f32, f32, f32)) -> [f32; 3] {
     }
 }
 
+/// Y in 0-1, I and Q in (approximately) -0.6 to 0.6. Output rgb is not clamped.
+///
+/// The inverse of [`Color32::to_yiqf32`] (for normalized components)
+pub fn rgb_from_yiq([y, i, q]: [f32; 3]) -> [f32; 3] {
+    // Coefficients from Wikipedia: https://en.wikipedia.org/wiki/YIQ
+    [
+        y + 0.956 * i + 0.619 * q,
+        y - 0.272 * i - 0.647 * q,
+        y - 1.106 * i + 1.703 * q,
+    ]
+}
+
 #[test]
 #[ignore] // a bit expensive
 fn test_hsv_roundtrip() {
@@ -3,6 +3,7 @@ pub(crate) const CPU_START_CYCLE: u64 = 6;
 
 pub const NTSC_CPU_CLOCK_HZ: u32 = 1_789_166;
 pub const PAL_CPU_CLOCK_HZ: u32 = 1_662_607;
+pub const DENDY_CPU_CLOCK_HZ: u32 = 1_773_448;
 
 pub const PAGE_SIZE_1K: usize = 1024;
 pub const PAGE_SIZE_2K: usize = 2048;
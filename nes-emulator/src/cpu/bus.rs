@@ -0,0 +1,306 @@
+#[cfg(feature = "trace-events")]
+use crate::trace::TraceEvent;
+
+/// Everything the CPU can see of the outside world
+///
+/// Normally this is implemented by [`System`](crate::system::System) but
+/// abstracting the bus means the CPU can also be run against simpler buses,
+/// such as a flat 64K RAM for testing instructions in isolation.
+///
+/// Every read or write (including dummy accesses) corresponds to a single CPU
+/// clock cycle and cycles where the CPU doesn't access the bus (such as while
+/// coalescing dummy reads during DMA) are reported via `step_for_cpu_cycle`.
+pub trait CpuBus {
+    /// Whether writes to $4014 should trigger an OAM DMA
+    const OAM_DMA: bool = true;
+
+    /// Read non-instruction data and step the system for one CPU cycle
+    fn cpu_read(&mut self, addr: u16) -> u8;
+
+    /// Read part of an instruction and step the system for one CPU cycle
+    fn cpu_fetch(&mut self, addr: u16) -> u8;
+
+    /// Handle a superfluous read that the CPU does and step the system for one CPU cycle
+    fn dummy_cpu_read(&mut self, addr: u16);
+
+    /// Write data and step the system for one CPU cycle
+    fn cpu_write(&mut self, addr: u16, data: u8);
+
+    /// Handle a superfluous write that the CPU does and step the system for one CPU cycle
+    fn dummy_cpu_write(&mut self, addr: u16, data: u8);
+
+    /// Read the bus without side-effects, for debugging
+    fn peek(&mut self, addr: u16) -> u8;
+
+    /// Step the system for one CPU cycle without accessing the bus
+    fn step_for_cpu_cycle(&mut self);
+
+    fn nmi_line(&self) -> bool;
+    fn irq_line(&self) -> bool;
+
+    /// Takes the address of any DMC sample that the APU is waiting for
+    fn take_dmc_dma_request(&mut self) -> Option<u16>;
+
+    /// Delivers a DMC sample that was read by the DMA unit
+    fn completed_dmc_dma(&mut self, addr: u16, sample: u8);
+
+    /// Called during φ2 of every CPU cycle, for mappers that count M2 edges
+    fn step_m2_phi2(&mut self, cpu_clock: u64);
+
+    /// The PPU's current `(frame, line, dot)`, for evaluating breakpoint conditions
+    fn ppu_position(&self) -> (u32, u16, u16);
+
+    #[cfg(feature = "trace-events")]
+    fn trace(&mut self, event: TraceEvent);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusCycle {
+    Read { addr: u16, value: u8 },
+    Write { addr: u16, value: u8 },
+}
+
+/// A bus with 64K of RAM mapped at every address and no I/O, which records
+/// every read and write (including dummy accesses)
+///
+/// This is useful for checking instructions against per-cycle test vectors
+/// since the CPU's bus activity can't be affected by any other hardware.
+pub struct FlatRamBus {
+    pub ram: Vec<u8>,
+    pub cycles: Vec<BusCycle>,
+}
+
+impl Default for FlatRamBus {
+    fn default() -> Self {
+        Self {
+            ram: vec![0; 0x10000],
+            cycles: vec![],
+        }
+    }
+}
+
+impl CpuBus for FlatRamBus {
+    const OAM_DMA: bool = false;
+
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        let value = self.ram[addr as usize];
+        self.cycles.push(BusCycle::Read { addr, value });
+        value
+    }
+
+    fn cpu_fetch(&mut self, addr: u16) -> u8 {
+        self.cpu_read(addr)
+    }
+
+    fn dummy_cpu_read(&mut self, addr: u16) {
+        self.cpu_read(addr);
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        self.ram[addr as usize] = data;
+        self.cycles.push(BusCycle::Write { addr, value: data });
+    }
+
+    fn dummy_cpu_write(&mut self, addr: u16, data: u8) {
+        self.cpu_write(addr, data);
+    }
+
+    fn peek(&mut self, addr: u16) -> u8 {
+        self.ram[addr as usize]
+    }
+
+    fn step_for_cpu_cycle(&mut self) {}
+
+    fn nmi_line(&self) -> bool {
+        false
+    }
+
+    fn irq_line(&self) -> bool {
+        false
+    }
+
+    fn take_dmc_dma_request(&mut self) -> Option<u16> {
+        None
+    }
+
+    fn completed_dmc_dma(&mut self, _addr: u16, _sample: u8) {}
+
+    fn step_m2_phi2(&mut self, _cpu_clock: u64) {}
+
+    fn ppu_position(&self) -> (u32, u16, u16) {
+        (0, 0, 0)
+    }
+
+    #[cfg(feature = "trace-events")]
+    fn trace(&mut self, _event: TraceEvent) {}
+}
+
+#[cfg(test)]
+#[derive(serde::Deserialize)]
+struct TestState {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+/// A test vector in the format of the per-opcode JSON tests from
+/// <https://github.com/SingleStepTests/ProcessorTests/tree/main/nes6502>
+#[cfg(test)]
+#[derive(serde::Deserialize)]
+struct TestVector {
+    name: String,
+    initial: TestState,
+    #[serde(rename = "final")]
+    expected: TestState,
+    cycles: Vec<(u16, u8, String)>,
+}
+
+/// Runs a single instruction for each test and returns a description of
+/// every test that failed
+#[cfg(test)]
+fn run_test_vectors(tests: &[TestVector]) -> Vec<String> {
+    use super::core::{Cpu, Flags};
+
+    let mut failures = vec![];
+    for test in tests {
+        let mut bus = FlatRamBus::default();
+        for &(addr, value) in test.initial.ram.iter() {
+            bus.ram[addr as usize] = value;
+        }
+        let mut cpu = Cpu::default();
+        cpu.pc = test.initial.pc;
+        cpu.sp = test.initial.s;
+        cpu.a = test.initial.a;
+        cpu.x = test.initial.x;
+        cpu.y = test.initial.y;
+        cpu.p = Flags::from_bits_truncate(test.initial.p);
+
+        cpu.step_instruction(&mut bus);
+
+        let mut errors = vec![];
+        let expected = &test.expected;
+        let registers = [
+            ("pc", cpu.pc, expected.pc),
+            ("s", cpu.sp as u16, expected.s as u16),
+            ("a", cpu.a as u16, expected.a as u16),
+            ("x", cpu.x as u16, expected.x as u16),
+            ("y", cpu.y as u16, expected.y as u16),
+            // The break bits only exist when P is pushed to the stack
+            (
+                "p",
+                (cpu.p & Flags::REAL).bits() as u16,
+                (expected.p & Flags::REAL.bits()) as u16,
+            ),
+        ];
+        for (name, actual, expected) in registers {
+            if actual != expected {
+                errors.push(format!("{name} = {actual:#x}, expected {expected:#x}"));
+            }
+        }
+        for &(addr, value) in expected.ram.iter() {
+            let actual = bus.ram[addr as usize];
+            if actual != value {
+                errors.push(format!(
+                    "${addr:04x} = {actual:#04x}, expected {value:#04x}"
+                ));
+            }
+        }
+        let expected_cycles: Vec<BusCycle> = test
+            .cycles
+            .iter()
+            .map(|(addr, value, kind)| match kind.as_str() {
+                "write" => BusCycle::Write {
+                    addr: *addr,
+                    value: *value,
+                },
+                _ => BusCycle::Read {
+                    addr: *addr,
+                    value: *value,
+                },
+            })
+            .collect();
+        if bus.cycles != expected_cycles {
+            errors.push(format!(
+                "cycles = {:x?}, expected {:x?}",
+                bus.cycles, expected_cycles
+            ));
+        }
+
+        if !errors.is_empty() {
+            failures.push(format!("{}: {}", test.name, errors.join(", ")));
+        }
+    }
+    failures
+}
+
+/// Runs a few hand-written vectors for edge cases, like page crossings, the
+/// stack and taken branches
+///
+/// Full coverage of every opcode comes from the external ProcessorTests
+/// vectors, which CI runs via `test_external_cpu_test_vectors`
+#[test]
+fn test_cpu_test_vectors() {
+    let tests: Vec<TestVector> = serde_json::from_str(include_str!("test_vectors.json")).unwrap();
+    let failures = run_test_vectors(&tests);
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+/// Runs a directory of per-opcode test files, like `a9.json`, with:
+///
+/// `NES_CPU_TEST_VECTORS=path/to/ProcessorTests/nes6502/v1 cargo test -- --ignored cpu_test_vectors`
+///
+/// The opcodes that halt the CPU are skipped, along with XAA ($8b) and ATX
+/// ($ab), whose results depend on an unstable "magic" constant that doesn't
+/// match the one assumed by the tests.
+#[test]
+#[ignore = "requires NES_CPU_TEST_VECTORS to locate the tests"]
+fn test_external_cpu_test_vectors() {
+    let dir = std::env::var("NES_CPU_TEST_VECTORS")
+        .expect("NES_CPU_TEST_VECTORS should be set to a directory of JSON tests");
+    let mut paths: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter(|path| {
+            let opcode = path
+                .file_stem()
+                .and_then(|stem| u8::from_str_radix(&stem.to_string_lossy(), 16).ok());
+            match opcode {
+                Some(opcode) => {
+                    let halts =
+                        opcode & 0x1f == 0x12 || matches!(opcode, 0x02 | 0x22 | 0x42 | 0x62);
+                    !halts && !matches!(opcode, 0x8b | 0xab)
+                }
+                None => true,
+            }
+        })
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "No test files found");
+
+    let mut failed_files = vec![];
+    for path in paths {
+        let tests: Vec<TestVector> =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let failures = run_test_vectors(&tests);
+        if !failures.is_empty() {
+            println!(
+                "{}: {} of {} tests failed, first failure:\n  {}",
+                path.display(),
+                failures.len(),
+                tests.len(),
+                failures[0]
+            );
+            failed_files.push(path.display().to_string());
+        }
+    }
+    assert!(
+        failed_files.is_empty(),
+        "Failures in {}",
+        failed_files.join(", ")
+    );
+}
@@ -0,0 +1,517 @@
+//! Breakpoint conditions
+//!
+//! Conditions are C-like expressions such as `A == $20 && [$0300] > 4 && scanline >= 200`
+//! that can refer to:
+//!
+//! - CPU registers: `A`, `X`, `Y`, `SP`, `P` and `PC`
+//! - Status flags: `C`, `Z`, `I`, `D`, `V` and `N` (either 0 or 1)
+//! - Memory, read via the system bus without side effects: `[addr]` reads a byte
+//!   and `{addr}` reads a little-endian word
+//! - The PPU position: `scanline`, `dot` and `frame`
+//! - The CPU clock: `clock`
+//!
+//! Numbers can be written as decimal, hex (`$ff` or `0xff`) or binary (`%1010`).
+//!
+//! The supported operators, from lowest to highest precedence, are: `||`, `&&`,
+//! `|`, `^`, `&`, `==` `!=`, `<` `<=` `>` `>=`, `<<` `>>`, `+` `-`, `*` `/` `%`
+//! and then the unary operators `-`, `!` and `~`.
+//!
+//! Conditions are parsed once into a flat, postfix list of operations so they can
+//! be cheaply evaluated before every instruction without any allocations.
+
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+
+use super::bus::CpuBus;
+use super::core::{Cpu, Flags};
+
+/// The deepest stack that a condition can need while being evaluated
+const MAX_STACK_DEPTH: usize = 32;
+
+/// How deeply brackets and unary operators can be nested, to limit the
+/// parser's recursion
+const MAX_NESTING_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    A,
+    X,
+    Y,
+    Sp,
+    P,
+    Pc,
+    Flag(Flags),
+    Scanline,
+    Dot,
+    Frame,
+    Clock,
+}
+
+impl Variable {
+    fn from_name(name: &str) -> Option<Self> {
+        let var = match name.to_ascii_lowercase().as_str() {
+            "a" => Variable::A,
+            "x" => Variable::X,
+            "y" => Variable::Y,
+            "s" | "sp" => Variable::Sp,
+            "p" => Variable::P,
+            "pc" => Variable::Pc,
+            "c" => Variable::Flag(Flags::CARRY),
+            "z" => Variable::Flag(Flags::ZERO),
+            "i" => Variable::Flag(Flags::INTERRUPT),
+            "d" => Variable::Flag(Flags::DECIMAL),
+            "v" => Variable::Flag(Flags::OVERFLOW),
+            "n" => Variable::Flag(Flags::NEGATIVE),
+            "scanline" | "line" => Variable::Scanline,
+            "dot" => Variable::Dot,
+            "frame" => Variable::Frame,
+            "clock" => Variable::Clock,
+            _ => return None,
+        };
+        Some(var)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnaryOp {
+    Negate,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    LogicalOr,
+    LogicalAnd,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl BinaryOp {
+    /// Operators grouped by precedence, from lowest to highest
+    const PRECEDENCE: &'static [&'static [(&'static str, BinaryOp)]] = &[
+        &[("||", BinaryOp::LogicalOr)],
+        &[("&&", BinaryOp::LogicalAnd)],
+        &[("|", BinaryOp::BitOr)],
+        &[("^", BinaryOp::BitXor)],
+        &[("&", BinaryOp::BitAnd)],
+        &[("==", BinaryOp::Equal), ("!=", BinaryOp::NotEqual)],
+        &[
+            ("<=", BinaryOp::LessEqual),
+            (">=", BinaryOp::GreaterEqual),
+            ("<", BinaryOp::Less),
+            (">", BinaryOp::Greater),
+        ],
+        &[("<<", BinaryOp::ShiftLeft), (">>", BinaryOp::ShiftRight)],
+        &[("+", BinaryOp::Add), ("-", BinaryOp::Subtract)],
+        &[
+            ("*", BinaryOp::Multiply),
+            ("/", BinaryOp::Divide),
+            ("%", BinaryOp::Remainder),
+        ],
+    ];
+
+    fn apply(self, a: i64, b: i64) -> i64 {
+        match self {
+            BinaryOp::LogicalOr => (a != 0 || b != 0) as i64,
+            BinaryOp::LogicalAnd => (a != 0 && b != 0) as i64,
+            BinaryOp::BitOr => a | b,
+            BinaryOp::BitXor => a ^ b,
+            BinaryOp::BitAnd => a & b,
+            BinaryOp::Equal => (a == b) as i64,
+            BinaryOp::NotEqual => (a != b) as i64,
+            BinaryOp::Less => (a < b) as i64,
+            BinaryOp::LessEqual => (a <= b) as i64,
+            BinaryOp::Greater => (a > b) as i64,
+            BinaryOp::GreaterEqual => (a >= b) as i64,
+            BinaryOp::ShiftLeft => u32::try_from(b)
+                .ok()
+                .and_then(|b| a.checked_shl(b))
+                .unwrap_or(0),
+            BinaryOp::ShiftRight => u32::try_from(b)
+                .ok()
+                .and_then(|b| a.checked_shr(b))
+                .unwrap_or(0),
+            BinaryOp::Add => a.wrapping_add(b),
+            BinaryOp::Subtract => a.wrapping_sub(b),
+            BinaryOp::Multiply => a.wrapping_mul(b),
+            // Division by zero evaluates to zero instead of being an error
+            BinaryOp::Divide => a.checked_div(b).unwrap_or(0),
+            BinaryOp::Remainder => a.checked_rem(b).unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Constant(i64),
+    Variable(Variable),
+    /// Pops an address and pushes the byte at that address
+    PeekByte,
+    /// Pops an address and pushes the little-endian word at that address
+    PeekWord,
+    Unary(UnaryOp),
+    Binary(BinaryOp),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Symbol(&'static str),
+}
+
+/// Symbols, ordered so that longer symbols are matched before their prefixes
+const SYMBOLS: &[&str] = &[
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "|", "^", "&", "<", ">", "+", "-", "*", "/",
+    "%", "!", "~", "(", ")", "[", "]", "{", "}",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut rest = source.trim_start();
+    while let Some(c) = rest.chars().next() {
+        // '%' is either the remainder operator or a binary number prefix
+        let follows_operand = matches!(
+            tokens.last(),
+            Some(Token::Number(_) | Token::Identifier(_) | Token::Symbol(")" | "]" | "}"))
+        );
+        let (token, len) = if c == '$' || rest.starts_with("0x") || rest.starts_with("0X") {
+            let prefix = if c == '$' { 1 } else { 2 };
+            let digits = rest[prefix..]
+                .find(|c: char| !c.is_ascii_hexdigit())
+                .unwrap_or(rest.len() - prefix);
+            let value = i64::from_str_radix(&rest[prefix..prefix + digits], 16)
+                .map_err(|_| anyhow!("Invalid hex number at \"{rest}\""))?;
+            (Token::Number(value), prefix + digits)
+        } else if c == '%' && !follows_operand && rest[1..].starts_with(['0', '1']) {
+            let digits = rest[1..]
+                .find(|c: char| c != '0' && c != '1')
+                .unwrap_or(rest.len() - 1);
+            let value = i64::from_str_radix(&rest[1..1 + digits], 2)
+                .map_err(|_| anyhow!("Invalid binary number at \"{rest}\""))?;
+            (Token::Number(value), 1 + digits)
+        } else if c.is_ascii_digit() {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let value = rest[..digits]
+                .parse()
+                .map_err(|_| anyhow!("Invalid number at \"{rest}\""))?;
+            (Token::Number(value), digits)
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (Token::Identifier(rest[..len].to_string()), len)
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            (Token::Symbol(symbol), symbol.len())
+        } else {
+            return Err(anyhow!("Unexpected character '{c}' at \"{rest}\""));
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser that emits operations in postfix order
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    ops: Vec<Op>,
+    /// The number of brackets and unary operators currently being parsed
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn take_symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<()> {
+        if self.take_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(anyhow!("Expected '{symbol}'"))
+        }
+    }
+
+    /// Parses a nested expression, failing if it's nested too deeply
+    fn parse_nested(&mut self, parse: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        if self.depth == MAX_NESTING_DEPTH {
+            return Err(anyhow!("Condition is nested too deeply"));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<()> {
+        if level == BinaryOp::PRECEDENCE.len() {
+            return self.parse_unary();
+        }
+        self.parse_binary(level + 1)?;
+        'operators: loop {
+            for &(symbol, op) in BinaryOp::PRECEDENCE[level] {
+                if self.take_symbol(symbol) {
+                    self.parse_binary(level + 1)?;
+                    self.ops.push(Op::Binary(op));
+                    continue 'operators;
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<()> {
+        for (symbol, op) in [
+            ("-", UnaryOp::Negate),
+            ("!", UnaryOp::Not),
+            ("~", UnaryOp::BitNot),
+        ] {
+            if self.take_symbol(symbol) {
+                self.parse_nested(Self::parse_unary)?;
+                self.ops.push(Op::Unary(op));
+                return Ok(());
+            }
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<()> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| anyhow!("Unexpected end of condition"))?;
+        self.pos += 1;
+        match token {
+            Token::Number(value) => self.ops.push(Op::Constant(value)),
+            Token::Identifier(name) => {
+                let var = Variable::from_name(&name)
+                    .ok_or_else(|| anyhow!("Unknown variable \"{name}\""))?;
+                self.ops.push(Op::Variable(var));
+            }
+            Token::Symbol("(") => {
+                self.parse_nested(|parser| parser.parse_binary(0))?;
+                self.expect_symbol(")")?;
+            }
+            Token::Symbol("[") => {
+                self.parse_nested(|parser| parser.parse_binary(0))?;
+                self.expect_symbol("]")?;
+                self.ops.push(Op::PeekByte);
+            }
+            Token::Symbol("{") => {
+                self.parse_nested(|parser| parser.parse_binary(0))?;
+                self.expect_symbol("}")?;
+                self.ops.push(Op::PeekWord);
+            }
+            Token::Symbol(symbol) => return Err(anyhow!("Unexpected '{symbol}'")),
+        }
+        Ok(())
+    }
+}
+
+/// A parsed breakpoint condition (see the [module documentation](self) for the syntax)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    ops: Vec<Op>,
+}
+
+impl Condition {
+    /// The text that the condition was parsed from
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Evaluates the condition as a number (where non-zero is considered `true`)
+    pub fn evaluate(&self, cpu: &Cpu, bus: &mut impl CpuBus) -> i64 {
+        let mut stack = [0i64; MAX_STACK_DEPTH];
+        let mut len = 0;
+        for op in self.ops.iter() {
+            match *op {
+                Op::Constant(value) => {
+                    stack[len] = value;
+                    len += 1;
+                }
+                Op::Variable(var) => {
+                    stack[len] = match var {
+                        Variable::A => cpu.a as i64,
+                        Variable::X => cpu.x as i64,
+                        Variable::Y => cpu.y as i64,
+                        Variable::Sp => cpu.sp as i64,
+                        Variable::P => cpu.p.bits() as i64,
+                        Variable::Pc => cpu.pc as i64,
+                        Variable::Flag(flag) => cpu.p.contains(flag) as i64,
+                        Variable::Scanline => bus.ppu_position().1 as i64,
+                        Variable::Dot => bus.ppu_position().2 as i64,
+                        Variable::Frame => bus.ppu_position().0 as i64,
+                        Variable::Clock => cpu.clock as i64,
+                    };
+                    len += 1;
+                }
+                Op::PeekByte => {
+                    let addr = stack[len - 1] as u16;
+                    stack[len - 1] = bus.peek(addr) as i64;
+                }
+                Op::PeekWord => {
+                    let addr = stack[len - 1] as u16;
+                    let lo = bus.peek(addr) as i64;
+                    let hi = bus.peek(addr.wrapping_add(1)) as i64;
+                    stack[len - 1] = (hi << 8) | lo;
+                }
+                Op::Unary(op) => {
+                    let a = stack[len - 1];
+                    stack[len - 1] = match op {
+                        UnaryOp::Negate => a.wrapping_neg(),
+                        UnaryOp::Not => (a == 0) as i64,
+                        UnaryOp::BitNot => !a,
+                    };
+                }
+                Op::Binary(op) => {
+                    len -= 1;
+                    stack[len - 1] = op.apply(stack[len - 1], stack[len]);
+                }
+            }
+        }
+        stack[0]
+    }
+
+    /// Checks whether the condition is currently true
+    pub fn is_true(&self, cpu: &Cpu, bus: &mut impl CpuBus) -> bool {
+        self.evaluate(cpu, bus) != 0
+    }
+}
+
+impl TryFrom<&str> for Condition {
+    type Error = anyhow::Error;
+
+    fn try_from(source: &str) -> Result<Self, Self::Error> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+            ops: vec![],
+            depth: 0,
+        };
+        if parser.tokens.is_empty() {
+            return Err(anyhow!("Empty condition"));
+        }
+        parser.parse_binary(0)?;
+        if let Some(token) = parser.peek() {
+            return Err(anyhow!("Unexpected {token:?} after end of condition"));
+        }
+
+        // Make sure evaluation can't overflow the fixed-size stack
+        let mut depth = 0usize;
+        for op in parser.ops.iter() {
+            match op {
+                Op::Constant(_) | Op::Variable(_) => depth += 1,
+                Op::Binary(_) => depth -= 1,
+                Op::PeekByte | Op::PeekWord | Op::Unary(_) => {}
+            }
+            if depth > MAX_STACK_DEPTH {
+                return Err(anyhow!("Condition is too complex"));
+            }
+        }
+
+        Ok(Condition {
+            source: source.trim().to_string(),
+            ops: parser.ops,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+fn evaluate_str(source: &str, cpu: &Cpu, bus: &mut super::bus::FlatRamBus) -> i64 {
+    Condition::try_from(source).unwrap().evaluate(cpu, bus)
+}
+
+#[test]
+fn test_condition_evaluate() {
+    use super::bus::FlatRamBus;
+
+    let mut cpu = Cpu::default();
+    cpu.a = 0x20;
+    cpu.x = 3;
+    cpu.pc = 0xc123;
+    cpu.p = Flags::CARRY | Flags::NEGATIVE;
+    let mut bus = FlatRamBus::default();
+    bus.ram[0x0300] = 5;
+    bus.ram[0x0303] = 0x34;
+    bus.ram[0x0304] = 0x12;
+
+    assert_eq!(evaluate_str("A == $20 && [$0300] > 4", &cpu, &mut bus), 1);
+    assert_eq!(evaluate_str("a == 0x20 && [0x300] > 5", &cpu, &mut bus), 0);
+    assert_eq!(evaluate_str("{$0300 + x}", &cpu, &mut bus), 0x1234);
+    assert_eq!(evaluate_str("[$0300 + X] == $34", &cpu, &mut bus), 1);
+    assert_eq!(evaluate_str("PC >> 8", &cpu, &mut bus), 0xc1);
+    assert_eq!(evaluate_str("C && N && !Z", &cpu, &mut bus), 1);
+    assert_eq!(evaluate_str("1 + 2 * 3", &cpu, &mut bus), 7);
+    assert_eq!(evaluate_str("(1 + 2) * 3", &cpu, &mut bus), 9);
+    assert_eq!(evaluate_str("10 - 4 - 3", &cpu, &mut bus), 3);
+    assert_eq!(evaluate_str("%1010 | 1 == 11", &cpu, &mut bus), 10);
+    assert_eq!(evaluate_str("(%1010 | 1) == 11", &cpu, &mut bus), 1);
+    assert_eq!(evaluate_str("-x + ~0", &cpu, &mut bus), -4);
+    assert_eq!(evaluate_str("5 / 0 || 7 % 0", &cpu, &mut bus), 0);
+    assert_eq!(evaluate_str("17 %10", &cpu, &mut bus), 7);
+    assert_eq!(
+        evaluate_str("scanline >= 0 && frame == 0", &cpu, &mut bus),
+        1
+    );
+}
+
+#[test]
+fn test_condition_errors() {
+    for source in [
+        "", "A ==", "A == $", "(A", "[$300", "foo > 1", "A $20", "A = 1", "#1",
+    ] {
+        assert!(
+            Condition::try_from(source).is_err(),
+            "Expected \"{source}\" to fail to parse"
+        );
+    }
+
+    let deep = format!("{}1{}", "(1 + ".repeat(40), ")".repeat(40));
+    assert!(Condition::try_from(deep.as_str()).is_err());
+
+    // Deep nesting is rejected instead of overflowing the parser's stack
+    for deep in [
+        format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000)),
+        format!("{}1", "-".repeat(10_000)),
+        format!("{}1{}", "[".repeat(10_000), "]".repeat(10_000)),
+    ] {
+        assert!(Condition::try_from(deep.as_str()).is_err());
+    }
+}
@@ -2,11 +2,15 @@ use std::fmt;
 
 use bitflags::bitflags;
 
+use super::bus::CpuBus;
+use super::condition::Condition;
 use super::instruction::{AddressingMode, Instruction, OopsHandling, Opcode};
 
-use crate::{constants::CPU_START_CYCLE, trace::CpuInterruptStatus};
+#[cfg(feature = "debugger")]
+use crate::system::System;
 #[cfg(feature = "trace-events")]
-use crate::{system::System, trace::TraceEvent};
+use crate::trace::TraceEvent;
+use crate::{constants::CPU_START_CYCLE, trace::CpuInterruptStatus};
 
 pub const CPU_FREQ: u32 = 1790000;
 pub const NMI_READ_LOWER: u16 = 0xfffa;
@@ -183,13 +187,77 @@ pub struct BreakpointHandle(u32);
 
 pub struct Breakpoint {
     pub(super) handle: BreakpointHandle,
-    pub(super) addr: u16,
+    /// The PC to break at, or `None` to check the condition before every instruction
+    pub(super) addr: Option<u16>,
     pub(super) callback: Box<FnBreakpointCallback>,
+    pub(super) condition: Option<Condition>,
+    pub(super) enabled: bool,
+    pub(super) hits: u64,
+    pub(super) break_after: u64,
+    pub(super) temporary: bool,
 }
 impl Breakpoint {
-    pub fn address(&self) -> u16 {
+    pub fn address(&self) -> Option<u16> {
         self.addr
     }
+    pub fn handle(&self) -> BreakpointHandle {
+        self.handle
+    }
+
+    pub fn condition(&self) -> Option<&Condition> {
+        self.condition.as_ref()
+    }
+    pub fn set_condition(&mut self, condition: Option<Condition>) {
+        self.condition = condition;
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// How many times the breakpoint's address was reached while its condition was true
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+    pub fn reset_hits(&mut self) {
+        self.hits = 0;
+    }
+
+    /// The number of hits before the breakpoint actually stops the emulator
+    pub fn break_after(&self) -> u64 {
+        self.break_after
+    }
+
+    /// Ignore hits until the breakpoint has been hit `n` times (`0` and `1` both
+    /// break on the first hit)
+    pub fn set_break_after(&mut self, n: u64) {
+        self.break_after = n;
+    }
+
+    /// Whether this breakpoint was added with [`Cpu::add_tmp_break`], for
+    /// stepping or running to an address, instead of by the user
+    pub fn temporary(&self) -> bool {
+        self.temporary
+    }
+
+    /// Counts a hit if the breakpoint is enabled and its address and condition
+    /// match, and returns whether the emulator should stop
+    #[cfg(feature = "debugger")]
+    pub(super) fn check(&mut self, cpu: &Cpu, system: &mut impl CpuBus) -> bool {
+        if !self.enabled || self.addr.is_some_and(|addr| addr != cpu.pc) {
+            return false;
+        }
+        if let Some(condition) = &self.condition {
+            if !condition.is_true(cpu, system) {
+                return false;
+            }
+        }
+        self.hits += 1;
+        self.hits >= self.break_after
+    }
 }
 
 /// Debugger state attached to a CPU instance that won't be
@@ -200,6 +268,9 @@ pub struct NoCloneDebugState {
     pub(super) next_breakpoint_handle: u32,
     pub(super) breakpoints: Vec<Breakpoint>,
     pub breakpoint_hit: bool,
+    /// The clock when a breakpoint last stopped the CPU, before executing the
+    /// instruction, so that resuming doesn't check (and count) it again
+    pub(super) break_clock: Option<u64>,
 }
 impl Clone for NoCloneDebugState {
     fn clone(&self) -> Self {
@@ -397,19 +468,20 @@ impl<'a> Iterator for Backtrace<'a> {
 impl Cpu {
     /// Reset the state of the CPU to a power-on state, but preserving debug state such as breakpoints
     pub(crate) fn power_cycle(&mut self) {
-        let debugger = std::mem::take(&mut self.debug);
+        let mut debugger = std::mem::take(&mut self.debug);
+        debugger.break_clock = None;
         *self = Self {
             debug: debugger,
             ..Default::default()
         };
     }
 
-    pub(crate) fn reset(&mut self, system: &mut System) {
+    pub(crate) fn reset(&mut self, system: &mut impl CpuBus) {
         self.handle_interrupt(system, Interrupt::RESET);
     }
 
     #[inline(always)]
-    fn dma_read(&mut self, system: &mut System, addr: u16) -> u8 {
+    fn dma_read(&mut self, system: &mut impl CpuBus, addr: u16) -> u8 {
         let value = system.cpu_read(addr);
 
         #[cfg(feature = "trace-events")]
@@ -425,7 +497,7 @@ impl Cpu {
     }
 
     #[inline(always)]
-    fn dma_write(&mut self, system: &mut System, addr: u16, value: u8) {
+    fn dma_write(&mut self, system: &mut impl CpuBus, addr: u16, value: u8) {
         system.cpu_write(addr, value);
 
         #[cfg(feature = "trace-events")]
@@ -439,7 +511,7 @@ impl Cpu {
     }
 
     /// Handles OAM and DMC DMA requests with pedantic handling of cycle stealing
-    fn run_dma_unit(&mut self, system: &mut System, dummy_addr: u16) {
+    fn run_dma_unit(&mut self, system: &mut impl CpuBus, dummy_addr: u16) {
         debug_assert!(!self.input_ready); // Make sure we aren't recursing somehow
 
         //println!("Start running DMA unit on clock = {}", self.clock);
@@ -522,7 +594,7 @@ impl Cpu {
                     } else {
                         let sample = self.dma_read(system, dmc_dma_addr); // will call .step_for_cpu_cycle()
                         last_read_addr = dmc_dma_addr;
-                        system.apu.dmc_channel.completed_dma(dmc_dma_addr, sample);
+                        system.completed_dmc_dma(dmc_dma_addr, sample);
                         dmc_dma_state = DmcDmaState::None;
                     }
                 }
@@ -590,7 +662,7 @@ impl Cpu {
     /// will effectively be repeated for any dummy cycle needed while servicing the DMA.
     #[allow(non_snake_case)]
     #[inline]
-    fn handle_RDY_halt(&mut self, system: &mut System, addr: u16) -> u8 {
+    fn handle_RDY_halt(&mut self, system: &mut impl CpuBus, addr: u16) -> u8 {
         //println!("CPU Halt");
 
         let dummy_addr = addr;
@@ -616,9 +688,9 @@ impl Cpu {
     }
 
     #[inline(always)]
-    fn step_read_cycle<F: Fn(&mut System, u16) -> u8>(
+    fn step_read_cycle<B: CpuBus, F: Fn(&mut B, u16) -> u8>(
         &mut self,
-        system: &mut System,
+        system: &mut B,
         addr: u16,
         func: F,
     ) -> u8 {
@@ -657,7 +729,7 @@ impl Cpu {
     ///
     /// If the CPU is halted by the RDY line the address is given for performing
     /// any required dummy reads while the DMA unit is running
-    pub(super) fn dummy_read_system_bus(&mut self, system: &mut System, addr: u16) {
+    pub(super) fn dummy_read_system_bus(&mut self, system: &mut impl CpuBus, addr: u16) {
         self.step_read_cycle(system, addr, |system, addr| {
             system.dummy_cpu_read(addr);
             0
@@ -675,7 +747,7 @@ impl Cpu {
         */
     }
 
-    pub(super) fn read_system_bus(&mut self, system: &mut System, addr: u16) -> u8 {
+    pub(super) fn read_system_bus(&mut self, system: &mut impl CpuBus, addr: u16) -> u8 {
         self.step_read_cycle(system, addr, |system, addr| system.cpu_read(addr))
 
         /*
@@ -699,7 +771,7 @@ impl Cpu {
 
     /// Like `read_system_bus` but signifies that the read is to fetch part of an instruction
     /// so we can track additional statistics about memory that is executed
-    pub(super) fn fetch_system_bus(&mut self, system: &mut System, addr: u16) -> u8 {
+    pub(super) fn fetch_system_bus(&mut self, system: &mut impl CpuBus, addr: u16) -> u8 {
         self.step_read_cycle(system, addr, |system, addr| system.cpu_fetch(addr))
 
         /*
@@ -725,7 +797,7 @@ impl Cpu {
     ///
     /// As an optimization in some cases this may skip doing the actual write
     /// but still steps the system for one CPU clock cycle.
-    pub(super) fn dummy_write_system_bus(&mut self, system: &mut System, addr: u16, data: u8) {
+    pub(super) fn dummy_write_system_bus(&mut self, system: &mut impl CpuBus, addr: u16, data: u8) {
         self.start_clock_cycle_phi1(system);
         system.dummy_cpu_write(addr, data);
         self.end_clock_cycle_phi2(system);
@@ -733,7 +805,7 @@ impl Cpu {
         self.clock += 1;
     }
 
-    pub(super) fn write_system_bus(&mut self, system: &mut System, addr: u16, data: u8) {
+    pub(super) fn write_system_bus<B: CpuBus>(&mut self, system: &mut B, addr: u16, data: u8) {
         // The reads/writes by the CPU effectively correspond to clock cycles
         // so this is a convenient place to run the interrupt detection that
         // happens during phase 1/2 of each clock cycle
@@ -749,7 +821,7 @@ impl Cpu {
         // We treat the OAMDMA register as a special, internal register
         // so we can neatly control how we suspend/halt the CPU mid-instruction
         // to service DMA requests
-        if addr == 0x4014 {
+        if B::OAM_DMA && addr == 0x4014 {
             // Note that we don't stop the redundant write to the system
             // bus above via system.cpu_write() in case there are debug
             // features enabled, such as for tracing memory writes.
@@ -776,7 +848,7 @@ impl Cpu {
         self.clock += 1;
     }
 
-    pub(super) fn stack_push(&mut self, system: &mut System, data: u8, tags: StackByteTags) {
+    pub(super) fn stack_push(&mut self, system: &mut impl CpuBus, data: u8, tags: StackByteTags) {
         #[cfg(feature = "debugger")]
         {
             self.stack_tags[self.sp as usize] = tags;
@@ -785,7 +857,7 @@ impl Cpu {
         self.sp = self.sp.wrapping_sub(1);
     }
 
-    pub(super) fn stack_pop(&mut self, system: &mut System) -> u8 {
+    pub(super) fn stack_pop(&mut self, system: &mut impl CpuBus) -> u8 {
         self.sp = self.sp.wrapping_add(1);
         #[cfg(feature = "debugger")]
         {
@@ -794,11 +866,11 @@ impl Cpu {
         self.read_system_bus(system, self.sp as u16 + 0x100)
     }
 
-    pub fn stack_peek(&self, sp: u8, system: &mut System) -> u8 {
+    pub fn stack_peek(&self, sp: u8, system: &mut impl CpuBus) -> u8 {
         system.peek(sp as u16 + 0x100)
     }
 
-    pub fn handle_interrupt(&mut self, system: &mut System, interrupt: Interrupt) {
+    pub fn handle_interrupt(&mut self, system: &mut impl CpuBus, interrupt: Interrupt) {
         // "The interrupt sequences themselves do not perform interrupt polling, meaning at least one instruction
         // from the interrupt handler will execute before another interrupt is serviced."
         self.interrupt_polling_disabled = true;
@@ -934,7 +1006,7 @@ impl Cpu {
     }
 
     /// Poll the status of interrupt detection that happened during phase 1 of this cycle
-    pub(super) fn instruction_poll_interrupts(&mut self, system: &mut System) {
+    pub(super) fn instruction_poll_interrupts(&mut self, system: &mut impl CpuBus) {
         #[cfg(debug_assertions)]
         {
             self.instruction_polled_interrupts = true;
@@ -1017,7 +1089,7 @@ impl Cpu {
     /// Checks the status of the edge/level detector during φ1/phi1 (first half) of a cycle to determine if an
     /// interrupt has been detected.
     /// Note: this phase 1 state still needs to be polled before an interrupt will actually be handled
-    fn step_interrupt_detector_phi1(&mut self, system: &mut System) {
+    fn step_interrupt_detector_phi1(&mut self, system: &mut impl CpuBus) {
         if self.pending_nmi_detected {
             // Note this will then stay set until "the NMI has been handled"
             self.nmi_raised = true;
@@ -1027,9 +1099,9 @@ impl Cpu {
         self.irq_raised = self.pending_irq_detected;
         //println!("phase 1 irq_raised = {}", self.irq_raised);
 
-        if let Some(dma_req) = std::mem::take(&mut system.dmc_dma_request) {
+        if let Some(dma_addr) = system.take_dmc_dma_request() {
             debug_assert!(self.dmc_dma_pending.is_none());
-            self.dmc_dma_pending = Some(dma_req.address);
+            self.dmc_dma_pending = Some(dma_addr);
             self.input_ready = false;
         }
 
@@ -1048,12 +1120,12 @@ impl Cpu {
     }
 
     /// Handle anything specific to the first half of the clock cycle, aka φ1/phi1
-    pub(super) fn start_clock_cycle_phi1(&mut self, system: &mut System) {
+    pub(super) fn start_clock_cycle_phi1(&mut self, system: &mut impl CpuBus) {
         self.step_interrupt_detector_phi1(system);
     }
 
     /// Checks interrupt lines during φ2/phi2 (second half) of a cycle to detect NMI edges or level IRQ inputs
-    fn step_interrupt_detector_phi2(&mut self, system: &mut System) {
+    fn step_interrupt_detector_phi2(&mut self, system: &mut impl CpuBus) {
         let nmi_level = system.nmi_line();
         if nmi_level && !self.last_nmi_level {
             // Note this will then stay set until "the NMI has been handled"
@@ -1086,9 +1158,9 @@ impl Cpu {
     }
 
     /// Handle anything specific to the second half of the clock cycle, aka φ2/phi2
-    pub(super) fn end_clock_cycle_phi2(&mut self, system: &mut System) {
+    pub(super) fn end_clock_cycle_phi2(&mut self, system: &mut impl CpuBus) {
         self.step_interrupt_detector_phi2(system);
-        system.cartridge.step_m2_phi2(self.clock);
+        system.step_m2_phi2(self.clock);
     }
 
     #[cfg(feature = "debugger")]
@@ -1102,13 +1174,68 @@ impl Cpu {
 
         self.debug.breakpoints.push(Breakpoint {
             handle,
-            addr,
+            addr: Some(addr),
             callback,
+            condition: None,
+            enabled: true,
+            hits: 0,
+            break_after: 0,
+            temporary: false,
         });
 
         handle
     }
 
+    /// Adds a breakpoint that removes itself when hit, such as for running
+    /// to an address
+    ///
+    /// It's possible a different breakpoint will be hit first, so this
+    /// should still be explicitly removed when execution next stops.
+    #[cfg(feature = "debugger")]
+    pub fn add_tmp_break(&mut self, addr: u16) -> BreakpointHandle {
+        let handle = self.add_break(addr, Box::new(|_, _| BreakpointCallbackAction::Remove));
+        if let Some(bp) = self.breakpoint_mut(handle) {
+            bp.temporary = true;
+        }
+        handle
+    }
+
+    /// Adds a breakpoint that only stops when `condition` is true
+    ///
+    /// If no address is given then the condition is checked before every
+    /// instruction, which is slower but can be used to break on arbitrary
+    /// state, such as `[$0300] == 4 && scanline >= 200`.
+    #[cfg(feature = "debugger")]
+    pub fn add_conditional_break(
+        &mut self,
+        addr: Option<u16>,
+        condition: Condition,
+    ) -> BreakpointHandle {
+        let handle = BreakpointHandle(self.debug.next_breakpoint_handle);
+        self.debug.next_breakpoint_handle += 1;
+
+        self.debug.breakpoints.push(Breakpoint {
+            handle,
+            addr,
+            callback: Box::new(|_, _| BreakpointCallbackAction::Keep),
+            condition: Some(condition),
+            enabled: true,
+            hits: 0,
+            break_after: 0,
+            temporary: false,
+        });
+
+        handle
+    }
+
+    #[cfg(feature = "debugger")]
+    pub fn breakpoint_mut(&mut self, handle: BreakpointHandle) -> Option<&mut Breakpoint> {
+        self.debug
+            .breakpoints
+            .iter_mut()
+            .find(|bp| bp.handle == handle)
+    }
+
     #[cfg(feature = "debugger")]
     pub fn remove_breakpoint(&mut self, handle: BreakpointHandle) {
         if let Some(i) = self
@@ -1,5 +1,5 @@
+use super::bus::CpuBus;
 use super::core::*;
-use crate::system::System;
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum Opcode {
@@ -593,12 +593,12 @@ impl Cpu {
     /// we emulate this so that we can step the clock / system mid-instruction and
     /// since it also affects when DMC or OAM DMAs will start (they both only start
     /// when the CPU executes it's next read)
-    fn nop_pc_fetch_u8(&mut self, system: &mut System) {
+    fn nop_pc_fetch_u8(&mut self, system: &mut impl CpuBus) {
         self.dummy_read_system_bus(system, self.pc);
     }
 
     /// Fetch 1 byte from PC and after fetching, advance the PC by one
-    fn pc_fetch_u8(&mut self, system: &mut System) -> u8 {
+    fn pc_fetch_u8(&mut self, system: &mut impl CpuBus) -> u8 {
         //println!("calling system.read_u8({:x}", self.pc);
         let data = self.fetch_system_bus(system, self.pc);
         self.pc = self.pc.wrapping_add(1);
@@ -607,23 +607,44 @@ impl Cpu {
     }
 
     /// Fetch 2 bytes from PC and increment PC by two
-    fn pc_fetch_u16(&mut self, system: &mut System) -> u16 {
+    fn pc_fetch_u16(&mut self, system: &mut impl CpuBus) -> u16 {
         let lower = self.pc_fetch_u8(system);
         let upper = self.pc_fetch_u8(system);
         u16::from(lower) | (u16::from(upper) << 8)
     }
 
-    pub fn pc_peek_instruction(&self, system: &mut System) -> Instruction {
+    pub fn pc_peek_instruction(&self, system: &mut impl CpuBus) -> Instruction {
         let opcode = system.peek(self.pc);
         Instruction::from(opcode)
     }
 
+    /// Jumps to the relative address operand, returning the number of extra cycles taken
+    ///
+    /// The next opcode is read while the offset is added to PCL and if that crosses a
+    /// page then the opcode is read again, from the wrong page, while PCH is fixed up.
+    fn take_branch(&mut self, system: &mut impl CpuBus, mode: AddressingMode) -> u8 {
+        let FetchedOperand { operand: addr, .. } =
+            self.fetch_operand(system, mode, OopsHandling::Ignore);
+        self.nop_pc_fetch_u8(system);
+
+        let wrong_addr = self.pc & 0xff00 | addr & 0xff; // Address without carry for high byte
+        self.pc = addr;
+        if wrong_addr != addr {
+            // "Additionally, for taken branches that cross a page boundary, interrupts are polled before the PCH fixup cycle"
+            self.instruction_poll_interrupts(system);
+            self.dummy_read_system_bus(system, wrong_addr);
+            2
+        } else {
+            1
+        }
+    }
+
     /// Fetch the operand.
     /// Depending on the Addressing mode, the PC also advances.
     /// When implementing, Cpu::fetch when reading the operand immediately after the instruction, otherwise System::read
     fn fetch_operand(
         &mut self,
-        system: &mut System,
+        system: &mut impl CpuBus,
         mode: AddressingMode,
         oops_handling: OopsHandling,
     ) -> FetchedOperand {
@@ -846,7 +867,7 @@ impl Cpu {
         operand
     }
 
-    fn system_peek_u16(system: &mut System, addr: u16) -> u16 {
+    fn system_peek_u16(system: &mut impl CpuBus, addr: u16) -> u16 {
         let lower = system.peek(addr);
         let upper = system.peek(addr.wrapping_add(1));
         u16::from(lower) | (u16::from(upper) << 8)
@@ -854,7 +875,7 @@ impl Cpu {
 
     pub fn peek_operand(
         &mut self,
-        system: &mut System,
+        system: &mut impl CpuBus,
         addr: u16,
         mode: AddressingMode,
         oops_handling: OopsHandling,
@@ -1034,7 +1055,7 @@ impl Cpu {
     /// returns (Operand { data: immediate value or address, number of clocks), data)
     fn fetch_operand_and_value(
         &mut self,
-        system: &mut System,
+        system: &mut impl CpuBus,
         mode: AddressingMode,
         oops_handling: OopsHandling,
     ) -> (FetchedOperand, u8) {
@@ -1076,18 +1097,18 @@ impl Cpu {
     // https://play.rust-lang.org/?version=stable&mode=debug&edition=2021&gist=3cea23f5d8935a3463dcaad97212af88
     //
     /// Execute the next instruction
-    pub fn step_instruction(&mut self, system: &mut System) {
+    pub fn step_instruction(&mut self, system: &mut impl CpuBus) {
         //println!("CPU: step_instruction()");
 
         #[cfg(feature = "debugger")]
         {
-            if !self.debug.breakpoints.is_empty() {
+            if !self.debug.breakpoints.is_empty() && self.debug.break_clock != Some(self.clock) {
                 let mut tmp = std::mem::take(&mut self.debug.breakpoints);
                 let mut remove = vec![];
                 for bp in tmp.iter_mut() {
-                    if bp.addr == self.pc {
+                    if bp.check(self, system) {
                         self.debug.breakpoint_hit = true;
-                        if (bp.callback)(self, bp.addr) == BreakpointCallbackAction::Remove {
+                        if (bp.callback)(self, self.pc) == BreakpointCallbackAction::Remove {
                             remove.push(bp.handle);
                         }
                     }
@@ -1097,6 +1118,7 @@ impl Cpu {
                     self.remove_breakpoint(h);
                 }
                 if self.debug.breakpoint_hit {
+                    self.debug.break_clock = Some(self.clock);
                     return;
                 }
             }
@@ -1700,8 +1722,9 @@ impl Cpu {
                 let pc_lower = self.stack_pop(system);
                 let pc_upper = self.stack_pop(system);
 
-                self.pc = (((pc_upper as u16) << 8) | (pc_lower as u16)) + 1;
-                self.dummy_read_system_bus(system, self.pc); // dummy read while incrementing PC
+                let return_addr = ((pc_upper as u16) << 8) | (pc_lower as u16);
+                self.dummy_read_system_bus(system, return_addr); // dummy read while incrementing PC
+                self.pc = return_addr.wrapping_add(1);
 
                 expected_cyc
             }
@@ -1710,18 +1733,7 @@ impl Cpu {
             Opcode::BCC => {
                 debug_assert!(mode == AddressingMode::Relative);
                 if !self.carry_flag() {
-                    let FetchedOperand {
-                        operand: addr,
-                        oops_cyc,
-                        ..
-                    } = self.fetch_operand(system, mode, OopsHandling::Normal);
-                    self.pc = addr;
-                    if oops_cyc != 0 {
-                        // "Additionally, for taken branches that cross a page boundary, interrupts are polled before the PCH fixup cycle"
-                        self.instruction_poll_interrupts(system);
-                    }
-                    self.nop_pc_fetch_u8(system);
-                    expected_cyc + oops_cyc + 1
+                    expected_cyc + self.take_branch(system, mode)
                 } else {
                     let FetchedOperand { .. } =
                         self.fetch_operand(system, mode, OopsHandling::Ignore);
@@ -1731,18 +1743,7 @@ impl Cpu {
             Opcode::BCS => {
                 debug_assert!(mode == AddressingMode::Relative);
                 if self.carry_flag() {
-                    let FetchedOperand {
-                        operand: addr,
-                        oops_cyc,
-                        ..
-                    } = self.fetch_operand(system, mode, OopsHandling::Normal);
-                    self.pc = addr;
-                    if oops_cyc != 0 {
-                        // "Additionally, for taken branches that cross a page boundary, interrupts are polled before the PCH fixup cycle"
-                        self.instruction_poll_interrupts(system);
-                    }
-                    self.nop_pc_fetch_u8(system);
-                    expected_cyc + oops_cyc + 1
+                    expected_cyc + self.take_branch(system, mode)
                 } else {
                     let FetchedOperand { .. } =
                         self.fetch_operand(system, mode, OopsHandling::Ignore);
@@ -1752,18 +1753,7 @@ impl Cpu {
             Opcode::BEQ => {
                 debug_assert!(mode == AddressingMode::Relative);
                 if self.zero_flag() {
-                    let FetchedOperand {
-                        operand: addr,
-                        oops_cyc,
-                        ..
-                    } = self.fetch_operand(system, mode, OopsHandling::Normal);
-                    self.pc = addr;
-                    if oops_cyc != 0 {
-                        // "Additionally, for taken branches that cross a page boundary, interrupts are polled before the PCH fixup cycle"
-                        self.instruction_poll_interrupts(system);
-                    }
-                    self.nop_pc_fetch_u8(system);
-                    expected_cyc + oops_cyc + 1
+                    expected_cyc + self.take_branch(system, mode)
                 } else {
                     let FetchedOperand { .. } =
                         self.fetch_operand(system, mode, OopsHandling::Ignore);
@@ -1773,18 +1763,7 @@ impl Cpu {
             Opcode::BNE => {
                 debug_assert!(mode == AddressingMode::Relative);
                 if !self.zero_flag() {
-                    let FetchedOperand {
-                        operand: addr,
-                        oops_cyc,
-                        ..
-                    } = self.fetch_operand(system, mode, OopsHandling::Normal);
-                    self.pc = addr;
-                    if oops_cyc != 0 {
-                        // "Additionally, for taken branches that cross a page boundary, interrupts are polled before the PCH fixup cycle"
-                        self.instruction_poll_interrupts(system);
-                    }
-                    self.nop_pc_fetch_u8(system);
-                    expected_cyc + oops_cyc + 1
+                    expected_cyc + self.take_branch(system, mode)
                 } else {
                     let FetchedOperand { .. } =
                         self.fetch_operand(system, mode, OopsHandling::Ignore);
@@ -1794,18 +1773,7 @@ impl Cpu {
             Opcode::BMI => {
                 debug_assert!(mode == AddressingMode::Relative);
                 if self.negative_flag() {
-                    let FetchedOperand {
-                        operand: addr,
-                        oops_cyc,
-                        ..
-                    } = self.fetch_operand(system, mode, OopsHandling::Normal);
-                    self.pc = addr;
-                    if oops_cyc != 0 {
-                        // "Additionally, for taken branches that cross a page boundary, interrupts are polled before the PCH fixup cycle"
-                        self.instruction_poll_interrupts(system);
-                    }
-                    self.nop_pc_fetch_u8(system);
-                    expected_cyc + oops_cyc + 1
+                    expected_cyc + self.take_branch(system, mode)
                 } else {
                     let FetchedOperand { .. } =
                         self.fetch_operand(system, mode, OopsHandling::Ignore);
@@ -1815,18 +1783,7 @@ impl Cpu {
             Opcode::BPL => {
                 debug_assert!(mode == AddressingMode::Relative);
                 if !self.negative_flag() {
-                    let FetchedOperand {
-                        operand: addr,
-                        oops_cyc,
-                        ..
-                    } = self.fetch_operand(system, mode, OopsHandling::Normal);
-                    self.pc = addr;
-                    if oops_cyc != 0 {
-                        // "Additionally, for taken branches that cross a page boundary, interrupts are polled before the PCH fixup cycle"
-                        self.instruction_poll_interrupts(system);
-                    }
-                    self.nop_pc_fetch_u8(system);
-                    expected_cyc + oops_cyc + 1
+                    expected_cyc + self.take_branch(system, mode)
                 } else {
                     let FetchedOperand { .. } =
                         self.fetch_operand(system, mode, OopsHandling::Ignore);
@@ -1836,18 +1793,7 @@ impl Cpu {
             Opcode::BVC => {
                 debug_assert!(mode == AddressingMode::Relative);
                 if !self.overflow_flag() {
-                    let FetchedOperand {
-                        operand: addr,
-                        oops_cyc,
-                        ..
-                    } = self.fetch_operand(system, mode, OopsHandling::Normal);
-                    self.pc = addr;
-                    if oops_cyc != 0 {
-                        // "Additionally, for taken branches that cross a page boundary, interrupts are polled before the PCH fixup cycle"
-                        self.instruction_poll_interrupts(system);
-                    }
-                    self.nop_pc_fetch_u8(system);
-                    expected_cyc + oops_cyc + 1
+                    expected_cyc + self.take_branch(system, mode)
                 } else {
                     let FetchedOperand { .. } =
                         self.fetch_operand(system, mode, OopsHandling::Ignore);
@@ -1857,18 +1803,7 @@ impl Cpu {
             Opcode::BVS => {
                 debug_assert!(mode == AddressingMode::Relative);
                 if self.overflow_flag() {
-                    let FetchedOperand {
-                        operand: addr,
-                        oops_cyc,
-                        ..
-                    } = self.fetch_operand(system, mode, OopsHandling::Normal);
-                    self.pc = addr;
-                    if oops_cyc != 0 {
-                        // "Additionally, for taken branches that cross a page boundary, interrupts are polled before the PCH fixup cycle"
-                        self.instruction_poll_interrupts(system);
-                    }
-                    self.nop_pc_fetch_u8(system);
-                    expected_cyc + oops_cyc + 1
+                    expected_cyc + self.take_branch(system, mode)
                 } else {
                     let FetchedOperand { .. } =
                         self.fetch_operand(system, mode, OopsHandling::Ignore);
@@ -2174,9 +2109,19 @@ impl Cpu {
                 expected_cyc
             }
             Opcode::LAR => {
-                let (FetchedOperand { oops_cyc, .. }, _arg) =
+                // Sometimes called LAS
+                // AND memory with the stack pointer and store the result in A, X and S
+                let (FetchedOperand { oops_cyc, .. }, arg) =
                     self.fetch_operand_and_value(system, mode, OopsHandling::Normal);
-                // TODO
+
+                let result = arg & self.sp;
+                self.a = result;
+                self.x = result;
+                self.sp = result;
+
+                self.set_zero_flag(result == 0);
+                self.set_negative_flag((result & 0x80) == 0x80);
+
                 expected_cyc + oops_cyc
             }
             Opcode::LAX => {
@@ -2374,15 +2319,23 @@ impl Cpu {
                 //      by using the result as a replacement high byte for the address.
                 //
                 // For now the implementation uses the same logic as Mesen, since that
-                // passes existing tests
+                // passes existing tests, except the address is only modified when
+                // indexing crosses a page (the high byte is otherwise unaffected)
 
-                let FetchedOperand { operand: addr, .. } =
-                    self.fetch_operand(system, mode, OopsHandling::Always);
+                let FetchedOperand {
+                    raw_operand: base_addr,
+                    operand: addr,
+                    ..
+                } = self.fetch_operand(system, mode, OopsHandling::Always);
 
                 let high = (addr >> 8) as u8;
                 let low = (addr & 0xff) as u8;
                 let result = self.x & high.wrapping_add(1);
-                let addr = ((result as u16) << 8) | low as u16;
+                let addr = if (addr ^ base_addr) & 0xff00 != 0 {
+                    ((result as u16) << 8) | low as u16
+                } else {
+                    addr
+                };
 
                 self.write_system_bus(system, addr, result);
 
@@ -2397,15 +2350,23 @@ impl Cpu {
                 //      by using the result as a replacement high byte for the address.
                 //
                 // For now the implementation uses the same logic as Mesen, since that
-                // passes existing tests
+                // passes existing tests, except the address is only modified when
+                // indexing crosses a page (the high byte is otherwise unaffected)
 
-                let FetchedOperand { operand: addr, .. } =
-                    self.fetch_operand(system, mode, OopsHandling::Always);
+                let FetchedOperand {
+                    raw_operand: base_addr,
+                    operand: addr,
+                    ..
+                } = self.fetch_operand(system, mode, OopsHandling::Always);
 
                 let high = (addr >> 8) as u8;
                 let low = (addr & 0xff) as u8;
                 let result = self.y & high.wrapping_add(1);
-                let addr = ((result as u16) << 8) | low as u16;
+                let addr = if (addr ^ base_addr) & 0xff00 != 0 {
+                    ((result as u16) << 8) | low as u16
+                } else {
+                    addr
+                };
 
                 self.write_system_bus(system, addr, result);
 
@@ -2424,9 +2385,19 @@ impl Cpu {
                 expected_cyc + oops_cyc
             }
             Opcode::XAA => {
-                let (FetchedOperand { .. }, _arg) =
+                // Sometimes called ANE
+                // The result is A ORed with an unstable "magic" constant, ANDed with X
+                // and the immediate. For consistency with ATX we assume the constant
+                // is $ff, which makes the result X AND the immediate.
+                let (FetchedOperand { .. }, arg) =
                     self.fetch_operand_and_value(system, mode, OopsHandling::Normal);
-                // 🤷
+
+                let result = self.x & arg;
+                self.a = result;
+
+                self.set_zero_flag(result == 0);
+                self.set_negative_flag((result & 0x80) == 0x80);
+
                 expected_cyc
             }
             Opcode::XAS => {
@@ -1,3 +1,5 @@
+pub mod bus;
+pub mod condition;
 pub mod core;
 mod flags;
 pub mod instruction;
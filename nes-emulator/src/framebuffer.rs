@@ -3,11 +3,17 @@ use std::sync::Mutex;
 
 use crate::color::Color32;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum PixelFormat {
     RGBA8888,
     RGB888,
     GREY8,
+
+    /// The raw 9-bit output of the PPU, before any palette lookup, stored as a
+    /// little-endian `u16` per pixel. The 6-bit palette value is stored in
+    /// the low bits with the 3 emphasis bits from PPUMASK above (see
+    /// [`crate::ppu::RawFrame`])
+    RAW9,
     //BGRA8888,
     //ARGB8888,
 }
@@ -19,12 +25,13 @@ impl PixelFormat {
             PixelFormat::RGBA8888 => 4,
             PixelFormat::RGB888 => 3,
             PixelFormat::GREY8 => 1,
+            PixelFormat::RAW9 => 2,
         }
     }
 }
 
 pub enum FramebufferClearMode {
-    /// Clears the framebuffer to the given color. Grey framebuffers will clear to the red channel.
+    /// Clears the framebuffer to the given color. Grey and raw framebuffers will clear to the red channel.
     Solid(Color32),
     Checkerboard(u8, u8),
 }
@@ -141,6 +148,10 @@ impl FramebufferDataRental {
         self.owner.clone()
     }
 
+    /// Plots an RGB color
+    ///
+    /// There's no way to map an RGB color back to a raw PPU pixel so plotting into a
+    /// [`PixelFormat::RAW9`] framebuffer will leave the pixel unchanged (see [`Self::plot_raw`])
     pub fn plot(&mut self, x: usize, y: usize, color: Color32) {
         match self.owner.format {
             PixelFormat::RGBA8888 => {
@@ -162,9 +173,17 @@ impl FramebufferDataRental {
                 let off = self.owner.width * y + x;
                 self.data[off] = color.to_grey();
             }
+            PixelFormat::RAW9 => {}
         }
     }
 
+    /// Plots a raw 9-bit PPU pixel into a [`PixelFormat::RAW9`] framebuffer
+    pub fn plot_raw(&mut self, x: usize, y: usize, raw: u16) {
+        debug_assert!(matches!(self.owner.format, PixelFormat::RAW9));
+        let off = (self.owner.width * y + x) * 2;
+        self.data[off..off + 2].copy_from_slice(&(raw & 0x1ff).to_le_bytes());
+    }
+
     pub fn clear(&mut self, mode: FramebufferClearMode) {
         match self.owner.format {
             PixelFormat::RGBA8888 => match mode {
@@ -258,6 +277,34 @@ impl FramebufferDataRental {
                     }
                 }
             },
+            PixelFormat::RAW9 => match mode {
+                FramebufferClearMode::Solid(color) => {
+                    let raw = (color.r() as u16).to_le_bytes();
+                    for px in self.data.chunks_exact_mut(2) {
+                        px.copy_from_slice(&raw);
+                    }
+                }
+                FramebufferClearMode::Checkerboard(mut a, mut b) => {
+                    let bpp = 2;
+                    let line_stride = self.owner.width * bpp;
+                    let row_stride = line_stride * 16;
+                    let col_stride = 16 * bpp;
+
+                    for row in self.data.chunks_mut(row_stride) {
+                        for line in row.chunks_exact_mut(line_stride) {
+                            let mut la = a;
+                            let mut lb = b;
+                            for col_span in line.chunks_mut(col_stride) {
+                                for px in col_span.chunks_exact_mut(2) {
+                                    px.copy_from_slice(&(la as u16).to_le_bytes());
+                                }
+                                std::mem::swap(&mut la, &mut lb);
+                            }
+                        }
+                        std::mem::swap(&mut a, &mut b);
+                    }
+                }
+            },
         }
     }
 }
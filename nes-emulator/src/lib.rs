@@ -11,9 +11,11 @@ pub mod framebuffer;
 pub mod genie;
 pub mod mappers;
 pub mod nes;
+pub mod ntsc_filter;
 pub mod port;
 pub mod ppu;
 pub mod ppu_palette;
+pub mod rollback;
 pub mod system;
 //pub mod system_apu_reg;
 pub mod ppu_registers;
@@ -22,3 +24,4 @@ pub mod hook;
 #[cfg(feature = "ppu-sim")]
 pub mod ppusim;
 pub mod trace;
+pub mod vs_system;
@@ -85,6 +85,10 @@ impl Mapper for Mapper0 {
         Box::new(self.clone())
     }
 
+    fn observes_ppu_fetches(&self) -> bool {
+        false
+    }
+
     fn system_bus_read(&mut self, addr: u16) -> (u8, u8) {
         match addr {
             0x6000..=0x7fff => {
@@ -118,6 +122,14 @@ impl Mapper for Mapper0 {
         self.system_bus_read(addr)
     }
 
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xbfff => Some((addr - 0x8000) as usize),
+            0xc000..=0xffff => Some((addr - 0xc000) as usize + self.last_prg_page_off),
+            _ => None,
+        }
+    }
+
     fn system_bus_write(&mut self, addr: u16, data: u8) {
         match addr {
             0x6000..=0x7fff => {
@@ -194,6 +206,7 @@ fn test_mapper0_vram_mirroring() {
         has_trainer: false,
         nametable_mirror: NameTableMirror::Vertical,
         four_screen_vram: false,
+        vs_system: None,
         trainer_baseaddr: None,
         prg_rom_baseaddr: 0,
         chr_rom_baseaddr: 0,
@@ -221,3 +234,41 @@ fn test_mapper0_vram_mirroring() {
     debug_assert_eq!(mapper.vram[1025], 2);
     debug_assert_eq!(mapper.ppu_bus_read(0x2401), 2);
 }
+
+#[test]
+fn test_mapper0_prg_rom_offset() {
+    use crate::cartridge::TVSystemCompatibility;
+
+    for n_prg_rom_pages in [1, 2] {
+        let cfg = INesConfig {
+            version: 1,
+            mapper_number: 0,
+            tv_system: TVSystemCompatibility::Ntsc,
+            n_prg_rom_pages,
+            n_prg_ram_pages: 0,
+            n_chr_rom_pages: 1,
+            n_chr_ram_pages: 0,
+            has_chr_ram: false,
+            has_battery: false,
+            has_trainer: false,
+            nametable_mirror: NameTableMirror::Vertical,
+            four_screen_vram: false,
+            vs_system: None,
+            trainer_baseaddr: None,
+            prg_rom_baseaddr: 0,
+            chr_rom_baseaddr: 0,
+        };
+        let prg_rom: Vec<u8> = (0..cfg.prg_rom_bytes()).map(|i| (i / 256) as u8).collect();
+        let chr_data = vec![0u8; cfg.chr_rom_bytes()];
+        let mut mapper = Mapper0::new(&cfg, prg_rom.clone(), chr_data);
+
+        assert_eq!(mapper.prg_rom_offset(0x0000), None);
+        assert_eq!(mapper.prg_rom_offset(0x6000), None);
+        for addr in [0x8000, 0x9234, 0xbfff, 0xc000, 0xd234, 0xffff] {
+            let offset = mapper.prg_rom_offset(addr).unwrap();
+            assert_eq!(mapper.system_bus_peek(addr).0, prg_rom[offset]);
+        }
+        let expected = if n_prg_rom_pages == 1 { 0x1234 } else { 0x5234 };
+        assert_eq!(mapper.prg_rom_offset(0xd234), Some(expected));
+    }
+}
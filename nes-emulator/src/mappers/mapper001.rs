@@ -101,6 +101,47 @@ impl Mapper1 {
         }
     }
 
+    #[inline]
+    fn prg_offset_from_address(&self, addr: u16) -> usize {
+        match addr {
+            0x8000..=0xbfff => {
+                // 16 KB PRG ROM bank, either switchable or fixed to the first bank
+                let prg_bank_offset = match self.prg_bank_mode {
+                    Mapper1PrgMode::Switch32KConsecutive => {
+                        // mask out (ignore) bit zero from the bank selector
+                        let page_no = (self.prg_bank & !1) as usize;
+                        page_no * PAGE_SIZE_16K
+                    }
+                    Mapper1PrgMode::Fixed16KFirstSwitch16K => 0,
+                    Mapper1PrgMode::Switch16KFixed16KLast => {
+                        let page_no = self.prg_bank as usize;
+                        page_no * PAGE_SIZE_16K
+                    }
+                };
+                prg_bank_offset + (addr - 0x8000) as usize
+            }
+            0xc000..=0xffff => {
+                // 16 KB PRG ROM bank, either fixed to the last bank or switchable
+                let prg_bank_offset = match self.prg_bank_mode {
+                    Mapper1PrgMode::Switch32KConsecutive => {
+                        // force odd page_no so it follows on from bank 0
+                        let page_no = (self.prg_bank | 1) as usize;
+                        page_no * PAGE_SIZE_16K
+                    }
+                    Mapper1PrgMode::Fixed16KFirstSwitch16K => {
+                        let page_no = self.prg_bank as usize;
+                        page_no * PAGE_SIZE_16K
+                    }
+                    Mapper1PrgMode::Switch16KFixed16KLast => {
+                        self.prg_rom_last_16k_page * PAGE_SIZE_16K
+                    }
+                };
+                prg_bank_offset + (addr - 0xc000) as usize
+            }
+            _ => unreachable!(),
+        }
+    }
+
     pub fn load_register_write(&mut self, addr: u16, data: u8) {
         trace!("MMC1: load register write {}", data);
 
@@ -215,6 +256,10 @@ impl Mapper for Mapper1 {
         Box::new(self.clone())
     }
 
+    fn observes_ppu_fetches(&self) -> bool {
+        false
+    }
+
     fn system_bus_read(&mut self, addr: u16) -> (u8, u8) {
         let value = match addr {
             0x6000..=0x7fff => {
@@ -222,41 +267,9 @@ impl Mapper for Mapper1 {
                 let ram_offset = (addr - 0x6000) as usize;
                 self.prg_ram[ram_offset]
             }
-            0x8000..=0xbfff => {
-                // 16 KB PRG ROM bank, either switchable or fixed to the first bank
-                let prg_bank_offset = match self.prg_bank_mode {
-                    Mapper1PrgMode::Switch32KConsecutive => {
-                        // mask out (ignore) bit zero from the bank selector
-                        let page_no = (self.prg_bank & !1) as usize;
-                        page_no * PAGE_SIZE_16K
-                    }
-                    Mapper1PrgMode::Fixed16KFirstSwitch16K => 0,
-                    Mapper1PrgMode::Switch16KFixed16KLast => {
-                        let page_no = self.prg_bank as usize;
-                        page_no * PAGE_SIZE_16K
-                    }
-                };
-                let bank_offset = (addr - 0x8000) as usize;
-                arr_read!(self.prg_rom, prg_bank_offset + bank_offset)
-            }
-            0xc000..=0xffff => {
-                // 16 KB PRG ROM bank, either fixed to the last bank or switchable
-                let prg_bank_offset = match self.prg_bank_mode {
-                    Mapper1PrgMode::Switch32KConsecutive => {
-                        // force odd page_no so it follows on from bank 0
-                        let page_no = (self.prg_bank | 1) as usize;
-                        page_no * PAGE_SIZE_16K
-                    }
-                    Mapper1PrgMode::Fixed16KFirstSwitch16K => {
-                        let page_no = self.prg_bank as usize;
-                        page_no * PAGE_SIZE_16K
-                    }
-                    Mapper1PrgMode::Switch16KFixed16KLast => {
-                        self.prg_rom_last_16k_page * PAGE_SIZE_16K
-                    }
-                };
-                let bank_offset = (addr - 0xc000) as usize;
-                arr_read!(self.prg_rom, prg_bank_offset + bank_offset)
+            0x8000..=0xffff => {
+                let off = self.prg_offset_from_address(addr);
+                arr_read!(self.prg_rom, off)
             }
             _ => {
                 error!("MMC1: invalid system bus read");
@@ -271,6 +284,13 @@ impl Mapper for Mapper1 {
         self.system_bus_read(addr)
     }
 
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xffff => Some(self.prg_offset_from_address(addr)),
+            _ => None,
+        }
+    }
+
     fn system_bus_write(&mut self, addr: u16, data: u8) {
         match addr {
             0x6000..=0x7fff => {
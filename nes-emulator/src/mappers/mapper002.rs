@@ -108,6 +108,10 @@ impl Mapper for Mapper2 {
         Box::new(self.clone())
     }
 
+    fn observes_ppu_fetches(&self) -> bool {
+        false
+    }
+
     fn system_bus_read(&mut self, addr: u16) -> (u8, u8) {
         (self.system_bus_read_direct(addr), 0) // no undefined bits
     }
@@ -116,6 +120,14 @@ impl Mapper for Mapper2 {
         self.system_bus_read(addr)
     }
 
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xbfff => Some(addr as usize - 0x8000 + self.bank0_offset),
+            0xc000..=0xffff => Some(addr as usize - 0xc000 + self.bank1_offset),
+            _ => None,
+        }
+    }
+
     fn system_bus_write(&mut self, addr: u16, mut data: u8) {
         if self.has_bus_conflicts {
             let conflicting_read = self.system_bus_read_direct(addr);
@@ -54,8 +54,9 @@ pub struct Mapper3 {
     // Ref: https://www.nesdev.org/wiki/NES_2.0_submappers
     has_bus_conflicts: bool,
 
-    prg_rom0: Vec<u8>, // first 16k bank
-    prg_rom1: Vec<u8>, // second 16k bank
+    prg_rom0: Vec<u8>,      // first 16k bank
+    prg_rom1: Vec<u8>,      // second 16k bank
+    prg_rom1_offset: usize, // where the second bank is in PRG ROM (zero if mirrored)
 
     chr_data: Vec<u8>,
 
@@ -73,10 +74,13 @@ impl Mapper3 {
     pub fn new(config: &INesConfig, prg_rom: Vec<u8>, chr_data: Vec<u8>) -> Self {
         let prg_rom0 = prg_rom[0..PAGE_SIZE_16K].to_vec();
 
-        let prg_rom1 = if prg_rom.len() >= (PAGE_SIZE_16K * 2) {
-            prg_rom[PAGE_SIZE_16K..(PAGE_SIZE_16K * 2)].to_vec()
+        let (prg_rom1, prg_rom1_offset) = if prg_rom.len() >= (PAGE_SIZE_16K * 2) {
+            (
+                prg_rom[PAGE_SIZE_16K..(PAGE_SIZE_16K * 2)].to_vec(),
+                PAGE_SIZE_16K,
+            )
         } else {
-            prg_rom0.clone()
+            (prg_rom0.clone(), 0)
         };
 
         let n_chr_pages = config.n_chr_rom_pages as u8;
@@ -92,6 +96,7 @@ impl Mapper3 {
 
             prg_rom0,
             prg_rom1,
+            prg_rom1_offset,
 
             chr_bank_select_mask,
             chr_data,
@@ -119,6 +124,10 @@ impl Mapper for Mapper3 {
         Box::new(self.clone())
     }
 
+    fn observes_ppu_fetches(&self) -> bool {
+        false
+    }
+
     fn system_bus_read(&mut self, addr: u16) -> (u8, u8) {
         (self.system_bus_read_direct(addr), 0) // no undefined bits
     }
@@ -127,6 +136,14 @@ impl Mapper for Mapper3 {
         self.system_bus_read(addr)
     }
 
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xbfff => Some((addr - 0x8000) as usize),
+            0xc000..=0xffff => Some((addr - 0xc000) as usize + self.prg_rom1_offset),
+            _ => None,
+        }
+    }
+
     fn system_bus_write(&mut self, addr: u16, mut data: u8) {
         if self.has_bus_conflicts {
             let conflicting_read = self.system_bus_read_direct(addr);
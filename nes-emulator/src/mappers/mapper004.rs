@@ -260,6 +260,13 @@ impl Mapper for Mapper4 {
         self.system_bus_read(addr)
     }
 
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xffff => Some(self.prg_offset_from_address(addr)),
+            _ => None,
+        }
+    }
+
     fn system_bus_write(&mut self, addr: u16, data: u8) {
         match addr {
             0x6000..=0x7fff => {
@@ -100,6 +100,10 @@ impl Mapper for Mapper7 {
         Box::new(self.clone())
     }
 
+    fn observes_ppu_fetches(&self) -> bool {
+        false
+    }
+
     fn system_bus_read(&mut self, addr: u16) -> (u8, u8) {
         (self.system_bus_read_direct(addr), 0) // no undefined bits
     }
@@ -108,6 +112,13 @@ impl Mapper for Mapper7 {
         self.system_bus_read(addr)
     }
 
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xffff => Some(addr as usize - 0x8000 + self.prg_bank),
+            _ => None,
+        }
+    }
+
     fn system_bus_write(&mut self, addr: u16, mut data: u8) {
         if self.has_bus_conflicts {
             let conflicting_read = self.system_bus_read_direct(addr);
@@ -59,6 +59,18 @@ impl Mapper31 {
             nsf_bios,
         }
     }
+
+    #[inline]
+    fn prg_offset_from_address(&self, addr: u16) -> usize {
+        // 8 x 4k bank switched rom
+        let addr = (addr - 0x8000) as usize;
+        let bank_index = (addr & 0b0111_0000_0000_0000) >> 12;
+
+        let bank_offset = self.prg_bank_offsets[bank_index];
+        let bank_offset = PAGE_SIZE_4K * bank_offset as usize;
+        let page_offset = addr & 0xfff;
+        bank_offset + page_offset
+    }
 }
 
 impl Mapper for Mapper31 {
@@ -66,6 +78,10 @@ impl Mapper for Mapper31 {
         Box::new(self.clone())
     }
 
+    fn observes_ppu_fetches(&self) -> bool {
+        false
+    }
+
     fn system_bus_read(&mut self, addr: u16) -> (u8, u8) {
         let value = match addr {
             // Unused memory region according to https://www.nesdev.org/wiki/NSF
@@ -81,15 +97,7 @@ impl Mapper for Mapper31 {
                 self.prg_ram[ram_offset]
             }
             0x8000..=0xffff => {
-                // 8 x 4k bank switched rom
-                let addr = (addr - 0x8000) as usize;
-                let bank_index = (addr & 0b0111_0000_0000_0000) >> 12;
-
-                let bank_offset = self.prg_bank_offsets[bank_index];
-                let bank_offset = PAGE_SIZE_4K * bank_offset as usize;
-                let page_offset = addr & 0xfff;
-                let rom_addr = bank_offset + page_offset;
-
+                let rom_addr = self.prg_offset_from_address(addr);
                 self.prg_rom[rom_addr]
             }
             _ => {
@@ -105,6 +113,13 @@ impl Mapper for Mapper31 {
         self.system_bus_read(addr)
     }
 
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xffff => Some(self.prg_offset_from_address(addr)),
+            _ => None,
+        }
+    }
+
     fn system_bus_write(&mut self, addr: u16, data: u8) {
         match addr {
             // Unused memory region according to https://www.nesdev.org/wiki/NSF
@@ -120,6 +120,10 @@ impl Mapper for Mapper66 {
         Box::new(self.clone())
     }
 
+    fn observes_ppu_fetches(&self) -> bool {
+        false
+    }
+
     fn system_bus_read(&mut self, addr: u16) -> (u8, u8) {
         (self.system_bus_read_direct(addr), 0) // no undefined bits
     }
@@ -128,6 +132,13 @@ impl Mapper for Mapper66 {
         self.system_bus_read(addr)
     }
 
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xffff => Some(addr as usize - 0x8000 + self.prg_bank_offset),
+            _ => None,
+        }
+    }
+
     fn system_bus_write(&mut self, addr: u16, mut data: u8) {
         // Apply bus conflicts
         let conflicting_read = self.system_bus_read_direct(addr);
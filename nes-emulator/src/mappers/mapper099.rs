@@ -0,0 +1,168 @@
+#[allow(unused_imports)]
+use log::{debug, error, trace};
+
+use crate::binary::INesConfig;
+use crate::cartridge::NameTableMirror;
+use crate::constants::*;
+use crate::mappers::Mapper;
+
+use super::mirror_vram_address;
+
+/// iNES Mapper 099: The Vs. System's built-in bank switching
+///
+/// # Properties
+/// |                     |                        |
+/// |---------------------|------------------------|
+/// | PRG ROM capacity | 32KiB (+ 8KiB for Vs. Gumshoe) |
+/// | PRG ROM window | 8KiB at $8000 |
+/// | PRG RAM capacity | 2KiB |
+/// | CHR capacity | 16KiB |
+/// | CHR window | 8KiB |
+/// | Nametable mirroring | 4KiB of VRAM for four screens |
+///
+/// # Example games:
+/// * Vs. Super Mario Bros.
+/// * Vs. Duck Hunt
+/// * Vs. Tennis
+/// * Vs. Gumshoe
+///
+/// # Banks
+/// - CPU $6000-$7FFF: 2KiB PRG RAM, mirrored
+/// - CPU $8000-$9FFF: 8KiB PRG ROM bank, switchable for 40KiB PRG ROM
+/// - CPU $A000-$FFFF: Fixed 24KiB PRG ROM
+/// - PPU $0000-$1FFF: 8KiB switchable CHR ROM bank
+///
+/// # Registers
+///
+/// There are no registers on the cartridge and instead bit 2 of $4016 writes
+/// (the same register used to strobe the controllers) selects the CHR bank
+/// (and also the PRG bank at $8000 for Vs. Gumshoe).
+///
+/// ref: https://www.nesdev.org/wiki/INES_Mapper_099
+#[derive(Clone)]
+pub struct Mapper99 {
+    vram: [u8; 4096], // Enough for 4 full screens
+    prg_ram: [u8; 2048],
+    prg_rom: Vec<u8>,
+    chr_data: Vec<u8>,
+    has_chr_ram: bool,
+
+    bank_select: usize,
+}
+
+impl Mapper99 {
+    pub fn new(config: &INesConfig, prg_rom: Vec<u8>, chr_data: Vec<u8>) -> Self {
+        Self {
+            vram: [0u8; 4096],
+            prg_ram: [0u8; 2048],
+            prg_rom,
+            chr_data,
+            has_chr_ram: config.has_chr_ram,
+            bank_select: 0,
+        }
+    }
+
+    fn system_bus_read_direct(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7fff => arr_read!(self.prg_ram, (addr & 0x7ff) as usize),
+            0x8000..=0x9fff if self.prg_rom.len() > PAGE_SIZE_32K => {
+                let off = self.bank_select * PAGE_SIZE_32K + (addr - 0x8000) as usize;
+                arr_read!(self.prg_rom, off % self.prg_rom.len())
+            }
+            0x8000..=0xffff => {
+                let off = (addr - 0x8000) as usize;
+                arr_read!(self.prg_rom, off % self.prg_rom.len())
+            }
+            _ => 0,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        (self.bank_select * PAGE_SIZE_8K + addr as usize) % self.chr_data.len()
+    }
+}
+
+impl Mapper for Mapper99 {
+    fn reset(&mut self) {
+        self.bank_select = 0;
+    }
+
+    fn clone_mapper(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+
+    fn observes_ppu_fetches(&self) -> bool {
+        false
+    }
+
+    fn system_bus_read(&mut self, addr: u16) -> (u8, u8) {
+        match addr {
+            0x6000..=0xffff => (self.system_bus_read_direct(addr), 0),
+            _ => (0, 0xff),
+        }
+    }
+
+    fn system_bus_peek(&mut self, addr: u16) -> (u8, u8) {
+        self.system_bus_read(addr)
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        let off = match addr {
+            0x8000..=0x9fff if self.prg_rom.len() > PAGE_SIZE_32K => {
+                self.bank_select * PAGE_SIZE_32K + (addr - 0x8000) as usize
+            }
+            0x8000..=0xffff => (addr - 0x8000) as usize,
+            _ => return None,
+        };
+        Some(off % self.prg_rom.len())
+    }
+
+    fn system_bus_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            // Forwarded by the system for Vs. System games
+            0x4016 => {
+                self.bank_select = ((data >> 2) & 1) as usize;
+            }
+            0x6000..=0x7fff => {
+                arr_write!(self.prg_ram, (addr & 0x7ff) as usize, data);
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_bus_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1fff => arr_read!(self.chr_data, self.chr_offset(addr)),
+            0x2000..=0x3fff => arr_read!(
+                self.vram,
+                mirror_vram_address(addr, NameTableMirror::FourScreen)
+            ),
+            _ => 0,
+        }
+    }
+
+    fn ppu_bus_peek(&mut self, addr: u16) -> u8 {
+        self.ppu_bus_read(addr)
+    }
+
+    fn ppu_bus_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x0000..=0x1fff if self.has_chr_ram => {
+                let off = self.chr_offset(addr);
+                arr_write!(self.chr_data, off, data);
+            }
+            0x2000..=0x3fff => {
+                arr_write!(
+                    self.vram,
+                    mirror_vram_address(addr, NameTableMirror::FourScreen),
+                    data
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn mirror_mode(&self) -> NameTableMirror {
+        NameTableMirror::FourScreen
+    }
+}
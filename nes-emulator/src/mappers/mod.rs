@@ -21,10 +21,203 @@ pub trait Mapper {
         NameTableMirror::Vertical
     }
 
+    /// The offset into PRG ROM that's currently mapped at the given CPU
+    /// address (or `None` if the address isn't mapped to PRG ROM), for
+    /// debugging tools like a disassembler to show which bank code is in
+    fn prg_rom_offset(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+
     fn step_m2_phi2(&mut self, _cpu_clock: u64) {}
     fn irq(&self) -> bool {
         false
     }
+
+    /// Whether the mapper depends on observing the PPU's bus activity as it
+    /// happens (e.g. to clock a scanline counter)
+    ///
+    /// Mappers that only respond to CPU writes can return `false` to let the
+    /// PPU render spans of a line in one batch.
+    fn observes_ppu_fetches(&self) -> bool {
+        true
+    }
+}
+
+/// The mapper for when no cartridge is inserted
+#[derive(Clone)]
+pub struct NoCartridge;
+impl Mapper for NoCartridge {
+    fn reset(&mut self) {}
+    fn clone_mapper(&self) -> Box<dyn Mapper> {
+        Box::new(NoCartridge)
+    }
+    fn observes_ppu_fetches(&self) -> bool {
+        false
+    }
+    fn system_bus_read(&mut self, _addr: u16) -> (u8, u8) {
+        (0, 0)
+    }
+    fn system_bus_peek(&mut self, _addr: u16) -> (u8, u8) {
+        (0, 0)
+    }
+    fn system_bus_write(&mut self, _addr: u16, _data: u8) {}
+    fn ppu_bus_read(&mut self, _addr: u16) -> u8 {
+        0
+    }
+    fn ppu_bus_peek(&mut self, _addr: u16) -> u8 {
+        0
+    }
+    fn ppu_bus_write(&mut self, _addr: u16, _data: u8) {}
+}
+
+/// Any of the built-in mappers, or an external [`Mapper`] implementation
+///
+/// The CPU and PPU access the cartridge for almost every clock cycle, so the
+/// built-in mappers are dispatched via a `match` instead of a vtable, which
+/// lets the compiler inline the hot paths like `ppu_bus_read` and
+/// `step_m2_phi2`. Mappers that are implemented outside of this crate can
+/// still be used via [`AnyMapper::External`].
+// There's only one mapper per cartridge, so the size of the largest variant
+// isn't a concern, and boxing it would add back an indirection
+#[allow(clippy::large_enum_variant)]
+pub enum AnyMapper {
+    NoCartridge(NoCartridge),
+    Mapper0(Mapper0),
+    Mapper1(Mapper1),
+    Mapper2(Mapper2),
+    Mapper3(Mapper3),
+    Mapper4(Mapper4),
+    Mapper7(Mapper7),
+    Mapper31(Mapper31),
+    Mapper66(Mapper66),
+    Mapper99(Mapper99),
+    External(Box<dyn Mapper>),
+}
+
+macro_rules! dispatch_mapper {
+    ($self:expr, $mapper:ident => $expr:expr) => {
+        match $self {
+            AnyMapper::NoCartridge($mapper) => $expr,
+            AnyMapper::Mapper0($mapper) => $expr,
+            AnyMapper::Mapper1($mapper) => $expr,
+            AnyMapper::Mapper2($mapper) => $expr,
+            AnyMapper::Mapper3($mapper) => $expr,
+            AnyMapper::Mapper4($mapper) => $expr,
+            AnyMapper::Mapper7($mapper) => $expr,
+            AnyMapper::Mapper31($mapper) => $expr,
+            AnyMapper::Mapper66($mapper) => $expr,
+            AnyMapper::Mapper99($mapper) => $expr,
+            AnyMapper::External($mapper) => $expr,
+        }
+    };
+}
+
+impl AnyMapper {
+    /// Moves the mapper into a `Box<dyn Mapper>`
+    ///
+    /// This is mainly useful for comparing the performance of enum and
+    /// vtable dispatch, by wrapping the result with [`AnyMapper::External`]
+    pub fn into_dyn(self) -> Box<dyn Mapper> {
+        match self {
+            AnyMapper::NoCartridge(mapper) => Box::new(mapper),
+            AnyMapper::Mapper0(mapper) => Box::new(mapper),
+            AnyMapper::Mapper1(mapper) => Box::new(mapper),
+            AnyMapper::Mapper2(mapper) => Box::new(mapper),
+            AnyMapper::Mapper3(mapper) => Box::new(mapper),
+            AnyMapper::Mapper4(mapper) => Box::new(mapper),
+            AnyMapper::Mapper7(mapper) => Box::new(mapper),
+            AnyMapper::Mapper31(mapper) => Box::new(mapper),
+            AnyMapper::Mapper66(mapper) => Box::new(mapper),
+            AnyMapper::Mapper99(mapper) => Box::new(mapper),
+            AnyMapper::External(mapper) => mapper,
+        }
+    }
+}
+
+impl Clone for AnyMapper {
+    fn clone(&self) -> Self {
+        match self {
+            AnyMapper::NoCartridge(mapper) => AnyMapper::NoCartridge(mapper.clone()),
+            AnyMapper::Mapper0(mapper) => AnyMapper::Mapper0(mapper.clone()),
+            AnyMapper::Mapper1(mapper) => AnyMapper::Mapper1(mapper.clone()),
+            AnyMapper::Mapper2(mapper) => AnyMapper::Mapper2(mapper.clone()),
+            AnyMapper::Mapper3(mapper) => AnyMapper::Mapper3(mapper.clone()),
+            AnyMapper::Mapper4(mapper) => AnyMapper::Mapper4(mapper.clone()),
+            AnyMapper::Mapper7(mapper) => AnyMapper::Mapper7(mapper.clone()),
+            AnyMapper::Mapper31(mapper) => AnyMapper::Mapper31(mapper.clone()),
+            AnyMapper::Mapper66(mapper) => AnyMapper::Mapper66(mapper.clone()),
+            AnyMapper::Mapper99(mapper) => AnyMapper::Mapper99(mapper.clone()),
+            AnyMapper::External(mapper) => AnyMapper::External(mapper.clone_mapper()),
+        }
+    }
+}
+
+impl Mapper for AnyMapper {
+    #[inline]
+    fn reset(&mut self) {
+        dispatch_mapper!(self, mapper => mapper.reset())
+    }
+    #[inline]
+    fn power_cycle(&mut self) {
+        dispatch_mapper!(self, mapper => mapper.power_cycle())
+    }
+    fn clone_mapper(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+
+    #[inline]
+    fn system_bus_read(&mut self, addr: u16) -> (u8, u8) {
+        dispatch_mapper!(self, mapper => mapper.system_bus_read(addr))
+    }
+    #[inline]
+    fn system_bus_peek(&mut self, addr: u16) -> (u8, u8) {
+        dispatch_mapper!(self, mapper => mapper.system_bus_peek(addr))
+    }
+    #[inline]
+    fn system_bus_write(&mut self, addr: u16, data: u8) {
+        dispatch_mapper!(self, mapper => mapper.system_bus_write(addr, data))
+    }
+
+    #[inline]
+    fn ppu_bus_read(&mut self, addr: u16) -> u8 {
+        dispatch_mapper!(self, mapper => mapper.ppu_bus_read(addr))
+    }
+    #[inline]
+    fn ppu_bus_peek(&mut self, addr: u16) -> u8 {
+        dispatch_mapper!(self, mapper => mapper.ppu_bus_peek(addr))
+    }
+    #[inline]
+    fn ppu_bus_write(&mut self, addr: u16, data: u8) {
+        dispatch_mapper!(self, mapper => mapper.ppu_bus_write(addr, data))
+    }
+    #[inline]
+    fn ppu_bus_nop_io(&mut self, addr: u16) {
+        dispatch_mapper!(self, mapper => mapper.ppu_bus_nop_io(addr))
+    }
+
+    #[inline]
+    fn mirror_mode(&self) -> NameTableMirror {
+        dispatch_mapper!(self, mapper => mapper.mirror_mode())
+    }
+
+    #[inline]
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        dispatch_mapper!(self, mapper => mapper.prg_rom_offset(addr))
+    }
+
+    #[inline]
+    fn step_m2_phi2(&mut self, cpu_clock: u64) {
+        dispatch_mapper!(self, mapper => mapper.step_m2_phi2(cpu_clock))
+    }
+    #[inline]
+    fn irq(&self) -> bool {
+        dispatch_mapper!(self, mapper => mapper.irq())
+    }
+
+    #[inline]
+    fn observes_ppu_fetches(&self) -> bool {
+        dispatch_mapper!(self, mapper => mapper.observes_ppu_fetches())
+    }
 }
 
 #[inline]
@@ -87,7 +280,7 @@ pub fn mirror_vram_address(mut addr: u16, mode: NameTableMirror) -> usize {
                 _ => unreachable!(),
             }
         }
-        NameTableMirror::FourScreen => addr - 0x2000,
+        NameTableMirror::FourScreen => addr,
         _ => panic!("Unknown mirror mode"),
     };
 
@@ -131,3 +324,6 @@ pub use mapper031::Mapper31;
 
 pub mod mapper066;
 pub use mapper066::Mapper66;
+
+pub mod mapper099;
+pub use mapper099::Mapper99;
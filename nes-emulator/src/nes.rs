@@ -96,6 +96,11 @@ impl NsfPlayer {
 }
 
 /// The top-level representation of a full NES console
+///
+/// Cloning a `Nes` takes a snapshot of the emulated hardware state that can
+/// be progressed independently (e.g. to speculatively run ahead) but any debug
+/// state, such as breakpoints, watch points and hooks, is not cloned.
+#[derive(Clone)]
 pub struct Nes {
     pub model: Model,
     cpu_clock_hz: u32,
@@ -107,6 +112,11 @@ pub struct Nes {
 
     system: System,
 
+    /// Whether the last completed frame was a 'lag' frame, where the game
+    /// didn't read either of the controller ports
+    frame_was_lag: bool,
+    lag_frame_count: u64,
+
     #[cfg(feature = "ppu-sim")]
     ppu_sim_visible: bool,
 
@@ -133,6 +143,7 @@ impl Nes {
         let (cpu_clock_hz, cpu_clocks_per_frame) = match model {
             Model::Ntsc => (NTSC_CPU_CLOCK_HZ, 29780.5),
             Model::Pal => (PAL_CPU_CLOCK_HZ, 33247.5),
+            Model::Dendy => (DENDY_CPU_CLOCK_HZ, 35464.0),
         };
         let mut nes = Nes {
             model,
@@ -145,6 +156,9 @@ impl Nes {
             cpu,
             system,
 
+            frame_was_lag: false,
+            lag_frame_count: 0,
+
             #[cfg(feature = "ppu-sim")]
             ppu_sim_visible: true,
 
@@ -166,6 +180,27 @@ impl Nes {
         nes
     }
 
+    /// Creates a new Nes console with the given cartridge inserted and powered on
+    ///
+    /// The model (NTSC or PAL) is automatically picked based on the cartridge's
+    /// TV system compatibility (see [`Model::from_tv_system`])
+    pub fn new_with_cartridge(
+        cartridge: Cartridge,
+        audio_sample_rate: u32,
+        start_timestamp: Instant,
+    ) -> Result<Nes> {
+        let model = Model::from_tv_system(cartridge.tv_system());
+        let mut nes = Nes::new(model, audio_sample_rate, start_timestamp);
+        nes.insert_cartridge(Some(cartridge))?;
+        nes.power_cycle(start_timestamp);
+        Ok(nes)
+    }
+
+    /// The model of Nes being emulated
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
     /// Loads the given `binary` as a `Cartridge` and inserts the loaded cartridge
     ///
     /// It's also necessary to explicitly power cycle or reset the Nes via [`Nes::power_cycle`]
@@ -224,10 +259,13 @@ impl Nes {
     fn nsf_init(&mut self) {
         #[cfg(feature = "nsf-player")]
         if let Some(ref nsf_config) = self.nsf_player.nsf_config {
-            // TODO: handle PAL...
-            self.nsf_player.nsf_step_period = ((nsf_config.ntsc_play_speed as u64
-                * NTSC_CPU_CLOCK_HZ as u64)
-                / 1_000_000u64) as u64;
+            let play_speed = match self.model {
+                Model::Ntsc => nsf_config.ntsc_play_speed,
+                // The Dendy runs at 50Hz, like PAL
+                Model::Pal | Model::Dendy => nsf_config.pal_play_speed,
+            };
+            self.nsf_player.nsf_step_period =
+                ((play_speed as u64 * self.cpu_clock_hz as u64) / 1_000_000u64) as u64;
             self.nsf_player.nsf_last_step_cycle = self.cpu.clock;
 
             // "1. Write $00 to all RAM at $0000-$07FF and $6000-$7FFF."
@@ -255,7 +293,10 @@ impl Nes {
             self.cpu.a = first_track;
 
             // 6. Set the X register for PAL or NTSC.
-            self.cpu.x = nsf_config.tv_system_byte;
+            self.cpu.x = match self.model {
+                Model::Ntsc => 0,
+                Model::Pal | Model::Dendy => 1,
+            };
 
             // 7. Call the music INIT routine.
             let init = nsf_config.init_address;
@@ -287,6 +328,9 @@ impl Nes {
         self.reference_cpu_clock = 0;
         self.reference_timestamp = start_timestamp;
 
+        self.frame_was_lag = false;
+        self.lag_frame_count = 0;
+
         #[cfg(feature = "nsf-player")]
         {
             self.nsf_player.restart();
@@ -315,7 +359,13 @@ impl Nes {
     }
 
     /// Get a mutable reference to the system bus, which also owns the PPU and APU
+    ///
+    /// This first catches up any rendering that the PPU has deferred (see
+    /// [`Ppu::set_fast_renderer_enabled`])
     pub fn system_mut(&mut self) -> &mut System {
+        self.system
+            .ppu
+            .render_deferred_dots(&mut self.system.cartridge);
         &mut self.system
     }
 
@@ -324,8 +374,22 @@ impl Nes {
         &mut self.cpu
     }
 
+    /// Get a reference to the PPU
+    ///
+    /// Note: unlike [`Self::ppu_mut`] this doesn't catch up any rendering that
+    /// the PPU has deferred, so the framebuffer may lag behind the current dot
+    pub fn ppu(&self) -> &Ppu {
+        &self.system.ppu
+    }
+
     /// Get a mutable reference to the PPU
+    ///
+    /// This first catches up any rendering that the PPU has deferred (see
+    /// [`Ppu::set_fast_renderer_enabled`])
     pub fn ppu_mut(&mut self) -> &mut Ppu {
+        self.system
+            .ppu
+            .render_deferred_dots(&mut self.system.cartridge);
         &mut self.system.ppu
     }
 
@@ -339,6 +403,13 @@ impl Nes {
         self.system.peek(addr)
     }
 
+    /// Write a value to the system bus, with the same side effects as a CPU write
+    ///
+    /// This doesn't trigger any watch points or write breakpoints.
+    pub fn write_system_bus(&mut self, addr: u16, data: u8) {
+        self.system.write(addr, data)
+    }
+
     /// Read a value from the PPU bus, without side effects
     pub fn peek_ppu_bus(&mut self, addr: u16) -> u8 {
         self.system
@@ -417,14 +488,12 @@ impl Nes {
     }
 
     /// Returns the number of CPU clocks that will cover the given `duration`
-    ///
-    /// Note: this currently assumes we're emulating an NTSC NES!
     pub fn cpu_clocks_for_duration(&self, duration: Duration) -> u64 {
         const NANOS_PER_SEC: f64 = 1_000_000_000.0;
 
-        let mut delta_clocks = duration.as_secs() * NTSC_CPU_CLOCK_HZ as u64;
+        let mut delta_clocks = duration.as_secs() * self.cpu_clock_hz as u64;
         delta_clocks +=
-            ((duration.subsec_nanos() as f64 / NANOS_PER_SEC) * NTSC_CPU_CLOCK_HZ as f64) as u64;
+            ((duration.subsec_nanos() as f64 / NANOS_PER_SEC) * self.cpu_clock_hz as f64) as u64;
 
         delta_clocks
     }
@@ -459,7 +528,7 @@ impl Nes {
     /// that the PPU will never progress into the future based on this function.
     pub fn cpu_to_ppu_clock(&self, cpu_clock: u64) -> u64 {
         match self.model {
-            Model::Ntsc => cpu_clock * 3,
+            Model::Ntsc | Model::Dendy => cpu_clock * 3,
 
             // Calculate clock * 3.2 in fixed point where decimals are divided
             // into 1000 units.  Do the calculation in integer arithmetic to be
@@ -491,7 +560,7 @@ impl Nes {
     /// See: [`Self::cpu_to_ppu_clock`] for more details.
     pub fn cpu_to_ppu_clock_mapper(&self) -> impl Fn(u64) -> u64 {
         match self.model {
-            Model::Ntsc => |clk: u64| clk * 3,
+            Model::Ntsc | Model::Dendy => |clk: u64| clk * 3,
             Model::Pal => |clk: u64| clk * 3200 / 1000,
         }
     }
@@ -523,7 +592,7 @@ impl Nes {
     /// debugging tools.
     pub fn ppu_to_cpu_clock(&self, ppu_clock: u64) -> u64 {
         match self.model {
-            Model::Ntsc => ppu_clock / 3,
+            Model::Ntsc | Model::Dendy => ppu_clock / 3,
 
             // Calculate clock / 3.2 in fixed point where decimals are divided into 1000 units.
             //
@@ -541,7 +610,7 @@ impl Nes {
     /// See: [`Self::ppu_to_cpu_clock`] for more details.
     pub fn ppu_to_cpu_clock_mapper(&self) -> impl Fn(u64) -> u64 {
         match self.model {
-            Model::Ntsc => |clk: u64| clk / 3,
+            Model::Ntsc | Model::Dendy => |clk: u64| clk / 3,
             Model::Pal => |clk: u64| Self::fixed_1000x_ceil(clk * 1000 * 1000 / 3200),
         }
     }
@@ -663,13 +732,20 @@ impl Nes {
             if self.system.take_frame_ready() {
                 self.system.port1.update_button_press_latches();
                 self.system.port2.update_button_press_latches();
+                if let Some(vs_system) = &mut self.system.vs_system {
+                    vs_system.start_frame();
+                }
+                self.frame_was_lag = !self.system.take_input_polled();
+                if self.frame_was_lag {
+                    self.lag_frame_count += 1;
+                }
                 return ProgressStatus::FrameReady;
             }
 
             #[cfg(feature = "trace")]
             {
-                self.cpu.trace.ppu_line = self.ppu_mut().line;
-                self.cpu.trace.ppu_dot = self.ppu_mut().dot;
+                self.cpu.trace.ppu_line = self.system.ppu.line;
+                self.cpu.trace.ppu_dot = self.system.ppu.dot;
             }
             self.cpu.step_instruction(&mut self.system);
             debug_assert_eq!(self.cpu.clock, self.system.apu_clock());
@@ -699,8 +775,8 @@ impl Nes {
     pub fn step_instruction_in(&mut self) {
         #[cfg(feature = "trace")]
         {
-            self.cpu.trace.ppu_line = self.ppu_mut().line;
-            self.cpu.trace.ppu_dot = self.ppu_mut().dot;
+            self.cpu.trace.ppu_line = self.system.ppu.line;
+            self.cpu.trace.ppu_dot = self.system.ppu.dot;
         }
         self.cpu.step_instruction(&mut self.system);
         debug_assert_eq!(self.cpu.clock, self.system.apu_clock());
@@ -729,10 +805,16 @@ impl Nes {
     pub fn add_tmp_step_over_breakpoint(&mut self) -> BreakpointHandle {
         let current_instruction = self.cpu.pc_peek_instruction(&mut self.system);
         let break_addr = self.cpu.pc.wrapping_add(current_instruction.len() as u16);
-        self.cpu.add_break(
-            break_addr,
-            Box::new(|_cpu, _addr| BreakpointCallbackAction::Remove),
-        )
+        self.cpu.add_tmp_break(break_addr)
+    }
+
+    /// Creates a temporary breakpoint for running until the given address
+    ///
+    /// NB: It's possible a different breakpoint will be hit and so this
+    /// should always be explicitly removed via [`Cpu::remove_break`]
+    #[cfg(feature = "debugger")]
+    pub fn add_tmp_run_to_breakpoint(&mut self, addr: u16) -> BreakpointHandle {
+        self.cpu.add_tmp_break(addr)
     }
 
     /// Creates a temporary breakpoint for stepping out of a function
@@ -750,10 +832,7 @@ impl Nes {
             .next()
             .map(|frame| frame.0);
         if let Some(out_addr) = out_addr {
-            Some(self.cpu.add_break(
-                out_addr,
-                Box::new(|_cpu, _addr| BreakpointCallbackAction::Remove),
-            ))
+            Some(self.cpu.add_tmp_break(out_addr))
         } else {
             None
         }
@@ -771,6 +850,20 @@ impl Nes {
         self.cpu.clock
     }
 
+    /// Returns `true` if the game didn't read either controller port ($4016 / $4017)
+    /// during the last completed frame
+    ///
+    /// Any input that's only held for the duration of a lag frame will be ignored
+    /// by the game.
+    pub fn frame_was_lag(&self) -> bool {
+        self.frame_was_lag
+    }
+
+    /// The number of lag frames since the last power cycle (see [`Self::frame_was_lag`])
+    pub fn lag_frame_count(&self) -> u64 {
+        self.lag_frame_count
+    }
+
     pub fn debug_sample_nametable(&mut self, x: usize, y: usize) -> [u8; 3] {
         self.system
             .ppu
@@ -798,6 +891,12 @@ impl Nes {
         (instruction, operand)
     }
 
+    /// The offset into PRG ROM that the cartridge currently maps at the given
+    /// CPU address, or `None` for RAM, I/O registers or unmapped addresses
+    pub fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        self.system.cartridge.prg_rom_offset(addr)
+    }
+
     pub fn backtrace(&mut self) -> Backtrace {
         self.cpu.backtrace(&mut self.system)
     }
@@ -810,3 +909,438 @@ impl Nes {
         self.system.set_game_genie_codes(codes);
     }
 }
+
+/// Creates a powered on `Nes` with the "hello" test ROM
+#[cfg(test)]
+fn hello_nes(model: Model) -> Nes {
+    let rom = include_bytes!("../../roms/other/hello.nes");
+    let start = Instant::now();
+    let mut nes = Nes::new(model, 48000, start);
+    nes.open_binary(rom).unwrap();
+    nes.power_cycle(start);
+    nes
+}
+
+/// Progresses until `n_frames` frames are ready, ignoring any breakpoints
+#[cfg(test)]
+fn run_frames(nes: &mut Nes, n_frames: usize) {
+    for _ in 0..n_frames {
+        while !matches!(
+            nes.progress(ProgressTarget::FrameReady),
+            ProgressStatus::FrameReady
+        ) {}
+    }
+}
+
+/// Progresses until a breakpoint stops the emulator, failing after 60 frames
+#[cfg(all(test, feature = "debugger"))]
+fn run_to_breakpoint(nes: &mut Nes) {
+    let mut frames = 0;
+    loop {
+        match nes.progress(ProgressTarget::FrameReady) {
+            ProgressStatus::Breakpoint => break,
+            ProgressStatus::FrameReady => frames += 1,
+            _ => {}
+        }
+        assert!(frames < 60, "Expected to hit a breakpoint");
+    }
+}
+
+#[test]
+fn test_clone_progresses_identically() {
+    let mut nes = hello_nes(Model::Ntsc);
+    run_frames(&mut nes, 10);
+
+    let mut clone = nes.clone();
+    run_frames(&mut nes, 5);
+    run_frames(&mut clone, 5);
+
+    assert_eq!(nes.cpu_clock(), clone.cpu_clock());
+    assert_eq!(nes.ppu_mut().frame, clone.ppu_mut().frame);
+    assert!(nes.ppu_mut().framebuffer.data == clone.ppu_mut().framebuffer.data);
+}
+
+#[test]
+fn test_raw_framebuffer_ignores_palette() {
+    use crate::ppu_palette;
+
+    let new_raw_nes = || {
+        let mut nes = hello_nes(Model::Ntsc);
+        nes.ppu_mut().set_raw_output_enabled(true);
+        nes.swap_framebuffer(Framebuffer::new(256, 240, PixelFormat::RAW9))
+            .unwrap();
+        nes
+    };
+
+    let mut nes = new_raw_nes();
+    let mut ntsc_nes = new_raw_nes();
+    ntsc_nes
+        .ppu_mut()
+        .set_rgb_palette(ppu_palette::generate_ntsc_palette(&Default::default()))
+        .unwrap();
+    run_frames(&mut nes, 10);
+    run_frames(&mut ntsc_nes, 10);
+
+    let raw_data = nes.ppu_mut().framebuffer.data.clone();
+    assert!(raw_data == ntsc_nes.ppu_mut().framebuffer.data);
+
+    let raw_frame = nes.ppu_mut().raw_frame().unwrap();
+    let raw_frame_data: Vec<u8> = raw_frame
+        .pixels
+        .iter()
+        .flat_map(|pixel| pixel.to_le_bytes())
+        .collect();
+    assert!(raw_data == raw_frame_data);
+    assert!(raw_frame.pixels.iter().all(|pixel| *pixel < 512));
+    assert!(raw_frame
+        .pixels
+        .iter()
+        .any(|pixel| *pixel != raw_frame.pixels[0]));
+
+    // The PPU can only render to RGBA8888 or RAW9 framebuffers
+    assert!(nes
+        .swap_framebuffer(Framebuffer::new(256, 240, PixelFormat::RGB888))
+        .is_err());
+}
+
+#[test]
+fn test_model_frame_timing() {
+    let frame_lengths = |model: Model| {
+        let mut nes = hello_nes(model);
+        let mut last_clock = 0;
+        let mut lengths = vec![];
+        for _ in 0..10 {
+            run_frames(&mut nes, 1);
+            // The frame is only polled between instructions so find the clock
+            // for the start of line 0 to measure whole frames
+            let ppu = nes.ppu_mut();
+            let clock = ppu.clock - ppu.line as u64 * 341 - ppu.dot as u64;
+            lengths.push(clock - last_clock);
+            last_clock = clock;
+        }
+        lengths.split_off(4)
+    };
+
+    // PAL frames are always 312 lines of 341 dots
+    let pal = frame_lengths(Model::Pal);
+    assert!(pal.iter().all(|len| *len == 312 * 341), "{pal:?}");
+
+    // NTSC skips a dot on odd frames while rendering is enabled
+    let ntsc = frame_lengths(Model::Ntsc);
+    assert!(ntsc.contains(&(262 * 341)), "{ntsc:?}");
+    assert!(ntsc.contains(&(262 * 341 - 1)), "{ntsc:?}");
+
+    // Dendy frames are the same length as PAL
+    let dendy = frame_lengths(Model::Dendy);
+    assert!(dendy.iter().all(|len| *len == 312 * 341), "{dendy:?}");
+}
+
+#[test]
+fn test_dendy_timing() {
+    use crate::ppu_registers::StatusFlags;
+
+    let mut nes = hello_nes(Model::Dendy);
+
+    // The Dendy keeps the NTSC 3:1 PPU:CPU clock ratio
+    assert_eq!(nes.cpu_to_ppu_clock(1000), 3000);
+    assert_eq!(nes.ppu_to_cpu_clock(3000), 1000);
+    assert_eq!(nes.cpu_clocks_per_frame(), (312.0 * 341.0) / 3.0);
+
+    // ...but delays vblank until 51 lines after the post-render line
+    let mut vblank_lines = vec![];
+    for _ in 0..3 {
+        run_frames(&mut nes, 1);
+        while !nes.ppu_mut().status.contains(StatusFlags::IN_VBLANK) {
+            nes.step_instruction_in();
+        }
+        vblank_lines.push(nes.ppu_mut().line);
+    }
+    assert!(
+        vblank_lines.iter().all(|line| *line == 291),
+        "{vblank_lines:?}"
+    );
+}
+
+#[test]
+fn test_ppu_display_options() {
+    use crate::ppu::Overscan;
+    use crate::ppu_registers::{Control1Flags, StatusFlags};
+
+    let render_frame = |sprite_limit: bool, overscan: Overscan| {
+        let mut nes = hello_nes(Model::Ntsc);
+        nes.ppu_mut().set_raw_output_enabled(true);
+        nes.ppu_mut().set_sprite_limit_enabled(sprite_limit);
+        nes.ppu_mut().set_overscan(overscan);
+        run_frames(&mut nes, 3);
+
+        // Put 16 sprites on the same lines, using the first tile that isn't
+        // blank (all of which are in the first pattern table)
+        let system = nes.system_mut();
+        let tile = (0..=255u16)
+            .find(|tile| (0..16).any(|i| system.cartridge.ppu_bus_peek(tile * 16 + i) != 0))
+            .unwrap();
+        let ppu = nes.ppu_mut();
+        ppu.control1
+            .remove(Control1Flags::SPRITES_IN_PATTERN_TABLE_1 | Control1Flags::SPRITE_HEIGHT_16);
+        for n in 0..64 {
+            ppu.oam[n * 4] = if n < 16 { 100 } else { 0xff };
+            ppu.oam[n * 4 + 1] = tile as u8;
+            ppu.oam[n * 4 + 2] = 0;
+            ppu.oam[n * 4 + 3] = (n * 16) as u8;
+        }
+        ppu.palette[0x11..0x14].copy_from_slice(&[0x16, 0x2a, 0x12]);
+        let system = nes.system_mut();
+        system
+            .ppu
+            .system_bus_write(&mut system.cartridge, 0x2001, 0x1e);
+
+        run_frames(&mut nes, 1);
+        let overflow = nes.ppu_mut().status.contains(StatusFlags::SPRITE_OVERFLOW);
+        (nes.ppu_mut().raw_frame().unwrap().pixels.clone(), overflow)
+    };
+    let sprite_pixels = |pixels: &[u16], x_range: std::ops::Range<usize>| {
+        (100..110)
+            .flat_map(|y| x_range.clone().map(move |x| y * 256 + x))
+            .filter(|i| matches!(pixels[*i] & 0x3f, 0x16 | 0x2a | 0x12))
+            .count()
+    };
+
+    let (limited, limited_overflow) = render_frame(true, Overscan::default());
+    let (unlimited, unlimited_overflow) = render_frame(false, Overscan::default());
+    assert!(sprite_pixels(&limited, 0..128) > 0);
+    assert_eq!(sprite_pixels(&limited, 128..256), 0);
+    assert_eq!(
+        sprite_pixels(&unlimited, 0..128),
+        sprite_pixels(&limited, 0..128)
+    );
+    assert!(sprite_pixels(&unlimited, 128..256) > 0);
+    assert!(limited_overflow && unlimited_overflow);
+
+    let overscan = Overscan {
+        top: 8,
+        bottom: 8,
+        left: 4,
+        right: 4,
+    };
+    let (cropped, _) = render_frame(false, overscan);
+    for y in 0..240 {
+        for x in 0..256 {
+            let i = y * 256 + x;
+            if (8..232).contains(&y) && (4..252).contains(&x) {
+                assert_eq!(cropped[i], unlimited[i]);
+            } else {
+                assert_eq!(cropped[i], 0x0f);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_fast_renderer_matches_dot_stepping() {
+    use crate::ppu_registers::Control1Flags;
+
+    let run = |fast: bool, catch_up_period: Option<u64>| {
+        let mut nes = hello_nes(Model::Ntsc);
+        nes.ppu_mut().set_raw_output_enabled(true);
+        nes.ppu_mut().set_fast_renderer_enabled(fast);
+
+        let mut frames = vec![];
+        for frame in 0..6 {
+            if frame == 3 {
+                // Add some sprites, including a sprite zero that overlaps the
+                // background, so we also cover sprite evaluation, overflow and
+                // sprite zero hits
+                let system = nes.system_mut();
+                let tile = (0..=255u16)
+                    .find(|tile| (0..16).any(|i| system.cartridge.ppu_bus_peek(tile * 16 + i) != 0))
+                    .unwrap();
+                let ppu = nes.ppu_mut();
+                ppu.control1.remove(
+                    Control1Flags::SPRITES_IN_PATTERN_TABLE_1 | Control1Flags::SPRITE_HEIGHT_16,
+                );
+                for n in 0..64 {
+                    ppu.oam[n * 4] = if n < 12 { 20 + n as u8 * 3 } else { 0xff };
+                    ppu.oam[n * 4 + 1] = tile as u8;
+                    ppu.oam[n * 4 + 2] = (n & 3) as u8;
+                    ppu.oam[n * 4 + 3] = (n * 20) as u8;
+                }
+                let system = nes.system_mut();
+                system
+                    .ppu
+                    .system_bus_write(&mut system.cartridge, 0x2001, 0x1e);
+            }
+
+            match catch_up_period {
+                Some(period) => loop {
+                    // Regularly observe the PPU part way through lines
+                    let target = ProgressTarget::Clock(nes.cpu_clock() + period);
+                    let status = nes.progress(target);
+                    nes.ppu_mut();
+                    if let ProgressStatus::FrameReady = status {
+                        break;
+                    }
+                },
+                None => run_frames(&mut nes, 1),
+            }
+
+            let ppu = nes.ppu_mut();
+            frames.push((
+                ppu.raw_frame().unwrap().pixels.clone(),
+                ppu.framebuffer.data.clone(),
+                ppu.status,
+                ppu.clock,
+                ppu.shared_v_register,
+            ));
+        }
+        (frames, nes.ppu_mut().fast_renderer_stats())
+    };
+
+    let (reference, stats) = run(false, None);
+    assert_eq!(stats, Default::default());
+
+    let (fast, stats) = run(true, None);
+    assert!(fast == reference);
+    assert!(stats.batched_lines > 0);
+
+    let (interrupted, stats) = run(true, Some(37));
+    assert!(interrupted == reference);
+    assert!(stats.interrupted_lines > 0);
+}
+
+#[test]
+fn test_external_mapper_matches_builtin() {
+    use crate::mappers::AnyMapper;
+
+    let rom = include_bytes!("../../roms/other/hello.nes");
+    let start = Instant::now();
+    let run = |cartridge: Cartridge| {
+        // new_with_cartridge() already power cycles the Nes
+        let mut nes = Nes::new_with_cartridge(cartridge, 48000, start).unwrap();
+        run_frames(&mut nes, 3);
+        nes.ppu_mut().framebuffer.data.clone()
+    };
+
+    let builtin = Cartridge::from_binary(rom).unwrap();
+    assert!(!matches!(builtin.mapper, AnyMapper::External(_)));
+    let external =
+        Cartridge::with_external_mapper(builtin.config.clone(), builtin.mapper.clone().into_dyn());
+    assert!(run(builtin) == run(external));
+}
+
+#[cfg(feature = "debugger")]
+#[test]
+fn test_write_breakpoint() {
+    let mut nes = hello_nes(Model::Ntsc);
+
+    // A breakpoint for a value that's never written shouldn't stop the emulator
+    let never = nes.system_mut().add_write_breakpoint(0x2001, Some(0xff));
+    let mask = nes.system_mut().add_write_breakpoint(0x2001, None);
+    run_to_breakpoint(&mut nes);
+    assert!(nes.system_mut().write_breakpoint_hit(mask));
+    assert!(!nes.system_mut().write_breakpoint_hit(never));
+
+    // Only the breakpoint for the value that was written should be hit
+    let written = nes.ppu_mut().control2.bits();
+    let value = nes.system_mut().add_write_breakpoint(0x2001, Some(written));
+    nes.system_mut().remove_write_breakpoint(mask);
+    assert!(!nes.system_mut().write_breakpoint_hit(mask));
+    assert!(!nes.system_mut().write_breakpoint_hit(value));
+
+    nes.write_system_bus(0x0300, 0x42);
+    assert_eq!(nes.peek_system_bus(0x0300), 0x42);
+    assert_eq!(nes.peek_system_bus(0x0b00), 0x42); // mirrored
+}
+
+#[cfg(feature = "debugger")]
+#[test]
+fn test_conditional_breakpoint() {
+    use crate::cpu::condition::Condition;
+
+    let mut nes = hello_nes(Model::Ntsc);
+
+    // A condition without an address is checked before every instruction
+    let condition = Condition::try_from("frame == 3 && scanline >= 200").unwrap();
+    let handle = nes.cpu_mut().add_conditional_break(None, condition);
+    run_to_breakpoint(&mut nes);
+    assert_eq!(nes.ppu_mut().frame, 3);
+    assert!(nes.ppu_mut().line >= 200);
+    assert_eq!(nes.cpu_mut().breakpoint_mut(handle).unwrap().hits(), 1);
+
+    // Resuming executes the instruction that the breakpoint stopped before,
+    // without checking (and counting) it again
+    let clock = nes.cpu_mut().clock;
+    run_to_breakpoint(&mut nes);
+    assert!(nes.cpu_mut().clock > clock);
+    let bp = nes.cpu_mut().breakpoint_mut(handle).unwrap();
+    assert_eq!(bp.hits(), 2);
+
+    // Only break after the condition has been true for a number of instructions
+    bp.set_break_after(10);
+    run_to_breakpoint(&mut nes);
+    assert_eq!(nes.cpu_mut().breakpoint_mut(handle).unwrap().hits(), 10);
+
+    // Disabled breakpoints are ignored
+    nes.cpu_mut()
+        .breakpoint_mut(handle)
+        .unwrap()
+        .set_enabled(false);
+    let pc = nes.cpu_mut().pc;
+    let condition = Condition::try_from("frame >= 5").unwrap();
+    let at_pc = nes.cpu_mut().add_conditional_break(Some(pc), condition);
+    run_to_breakpoint(&mut nes);
+    assert_eq!(nes.cpu_mut().pc, pc);
+    assert!(nes.ppu_mut().frame >= 5);
+    assert_eq!(nes.cpu_mut().breakpoint_mut(handle).unwrap().hits(), 10);
+    assert_eq!(nes.cpu_mut().breakpoint_mut(at_pc).unwrap().hits(), 1);
+
+    let clock = nes.cpu_mut().clock;
+    run_to_breakpoint(&mut nes);
+    assert_eq!(nes.cpu_mut().pc, pc);
+    assert!(nes.cpu_mut().clock > clock);
+    assert_eq!(nes.cpu_mut().breakpoint_mut(at_pc).unwrap().hits(), 2);
+
+    // Temporary breakpoints, like for "run to cursor", remove themselves
+    nes.cpu_mut().remove_breakpoint(at_pc);
+    let run_to = nes.add_tmp_run_to_breakpoint(pc);
+    assert!(nes.cpu_mut().breakpoint_mut(run_to).unwrap().temporary());
+    assert!(!nes.cpu_mut().breakpoint_mut(handle).unwrap().temporary());
+    run_to_breakpoint(&mut nes);
+    assert_eq!(nes.cpu_mut().pc, pc);
+    assert!(nes.cpu_mut().breakpoint_mut(run_to).is_none());
+}
+
+#[test]
+fn test_lag_frames() {
+    use crate::binary::test_nrom_binary;
+
+    // Only reads the controller while $00 is non-zero
+    let program = [
+        0xa5, 0x00, // LDA $00
+        0xf0, 0xfc, // BEQ $8000
+        0xad, 0x16, 0x40, // LDA $4016
+        0x4c, 0x00, 0x80, // JMP $8000
+    ];
+    let start = Instant::now();
+    let mut nes = Nes::new(Model::Ntsc, 48000, start);
+    nes.open_binary(&test_nrom_binary(&program, None)).unwrap();
+    nes.power_cycle(start);
+    nes.system_mut().wram[0] = 0;
+
+    for _ in 0..3 {
+        run_frames(&mut nes, 1);
+        assert!(nes.frame_was_lag());
+    }
+    assert_eq!(nes.lag_frame_count(), 3);
+
+    nes.system_mut().wram[0] = 1;
+    for _ in 0..3 {
+        run_frames(&mut nes, 1);
+        assert!(!nes.frame_was_lag());
+    }
+    assert_eq!(nes.lag_frame_count(), 3);
+
+    nes.system_mut().wram[0] = 0;
+    run_frames(&mut nes, 1);
+    assert!(nes.frame_was_lag());
+    assert_eq!(nes.lag_frame_count(), 4);
+}
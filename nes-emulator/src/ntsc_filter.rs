@@ -0,0 +1,204 @@
+//! A CPU based filter that re-creates the artifacts of the NES's composite
+//! video output on an NTSC TV
+//!
+//! The filter takes the raw 9-bit pixel values output by the PPU (see
+//! [`Ppu::set_raw_output_enabled`](crate::ppu::Ppu::set_raw_output_enabled)),
+//! generates the composite signal the PPU would output for each line, and then
+//! decodes that signal in the same way as a TV. Since the decoder has to look
+//! at a full cycle of the color subcarrier, which spans 1.5 pixels, colors will
+//! fringe and blend between neighbouring pixels. The phase of the subcarrier
+//! at the start of each line is taken from the PPU so that the artifacts move
+//! from frame to frame ("dot crawl") just like on real hardware.
+//!
+//! ref: https://www.nesdev.org/wiki/NTSC_video
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+use crate::color::Color32;
+use crate::constants::{FRAME_HEIGHT, FRAME_WIDTH};
+use crate::framebuffer::{FramebufferDataRental, FramebufferInfo};
+use crate::ppu::RawFrame;
+use crate::ppu_palette::{self, PaletteGeneratorParams};
+
+/// Each PPU dot lasts for 8 phases of the 12-phase color subcarrier
+const SAMPLES_PER_PIXEL: usize = 8;
+const SAMPLES_PER_LINE: usize = FRAME_WIDTH * SAMPLES_PER_PIXEL;
+
+/// The number of samples covering a full cycle of the color subcarrier, which
+/// is the window the decoder averages over
+const CARRIER_CYCLE: usize = 12;
+
+/// The number of signal samples that are decoded for each output pixel
+const SAMPLES_PER_OUTPUT_PIXEL: usize = 4;
+
+/// The width of the framebuffer the filter writes to
+pub const NTSC_FILTER_WIDTH: usize = SAMPLES_PER_LINE / SAMPLES_PER_OUTPUT_PIXEL;
+
+/// The height of the framebuffer the filter writes to
+pub const NTSC_FILTER_HEIGHT: usize = FRAME_HEIGHT;
+
+pub struct NtscFilter {
+    params: PaletteGeneratorParams,
+
+    /// The normalized signal level for every 9-bit pixel value at each of the
+    /// 12 phases of the color subcarrier
+    signal_lut: Vec<[f32; CARRIER_CYCLE]>,
+    carrier: [(f32, f32); CARRIER_CYCLE],
+
+    /// Running sums of the luma and (carrier modulated) chroma for the current
+    /// line, so that each output pixel can be decoded in constant time
+    y_sums: Vec<f32>,
+    i_sums: Vec<f32>,
+    q_sums: Vec<f32>,
+}
+
+impl NtscFilter {
+    pub fn new(params: PaletteGeneratorParams) -> Self {
+        let signal_lut = (0..ppu_palette::EMPHASIS_PALETTE_SIZE)
+            .map(|pixel| {
+                let nes_color = (pixel & 0x3f) as u8;
+                let emphasis = (pixel >> 6) as u8;
+                std::array::from_fn(|phase| {
+                    ppu_palette::ntsc_signal_level(nes_color, emphasis, phase as u32)
+                })
+            })
+            .collect();
+
+        Self {
+            carrier: ppu_palette::ntsc_carrier(&params),
+            params,
+            signal_lut,
+            y_sums: vec![0.0; SAMPLES_PER_LINE + 1],
+            i_sums: vec![0.0; SAMPLES_PER_LINE + 1],
+            q_sums: vec![0.0; SAMPLES_PER_LINE + 1],
+        }
+    }
+
+    pub fn params(&self) -> &PaletteGeneratorParams {
+        &self.params
+    }
+
+    /// Update the hue, saturation, contrast, brightness and gamma adjustments
+    /// that are applied when decoding
+    pub fn set_params(&mut self, params: PaletteGeneratorParams) {
+        self.carrier = ppu_palette::ntsc_carrier(&params);
+        self.params = params;
+    }
+
+    fn decode_line(&mut self, pixels: &[u16], line_phase: usize) {
+        let mut phase = line_phase % CARRIER_CYCLE;
+        let mut sample = 0;
+        for pixel in pixels {
+            let signal = &self.signal_lut[(*pixel & 0x1ff) as usize];
+            for _ in 0..SAMPLES_PER_PIXEL {
+                let level = signal[phase];
+                let (cos, sin) = self.carrier[phase];
+                self.y_sums[sample + 1] = self.y_sums[sample] + level;
+                self.i_sums[sample + 1] = self.i_sums[sample] + level * cos;
+                self.q_sums[sample + 1] = self.q_sums[sample] + level * sin;
+
+                sample += 1;
+                phase = (phase + 1) % CARRIER_CYCLE;
+            }
+        }
+    }
+
+    /// Decodes the color of an output pixel by averaging a full cycle of the
+    /// color subcarrier, centered on the pixel
+    fn decode_pixel(&self, x: usize) -> Color32 {
+        let center = x * SAMPLES_PER_OUTPUT_PIXEL + SAMPLES_PER_OUTPUT_PIXEL / 2;
+        let start = center
+            .saturating_sub(CARRIER_CYCLE / 2)
+            .min(SAMPLES_PER_LINE - CARRIER_CYCLE);
+        let end = start + CARRIER_CYCLE;
+
+        let scale = 1.0 / CARRIER_CYCLE as f32;
+        let yiq = [
+            (self.y_sums[end] - self.y_sums[start]) * scale,
+            (self.i_sums[end] - self.i_sums[start]) * scale,
+            (self.q_sums[end] - self.q_sums[start]) * scale,
+        ];
+        let rgb = ppu_palette::ntsc_yiq_to_rgb(&self.params, yiq);
+        Color32::from_rgb(rgb[0], rgb[1], rgb[2])
+    }
+
+    /// Filters a raw PPU frame into a framebuffer that must be
+    /// [`NTSC_FILTER_WIDTH`] x [`NTSC_FILTER_HEIGHT`] pixels
+    pub fn filter_frame(
+        &mut self,
+        raw: &RawFrame,
+        output: &mut FramebufferDataRental,
+    ) -> Result<()> {
+        if output.width() != NTSC_FILTER_WIDTH || output.height() != NTSC_FILTER_HEIGHT {
+            return Err(anyhow!(
+                "NTSC filter output should be {NTSC_FILTER_WIDTH}x{NTSC_FILTER_HEIGHT}, not {}x{}",
+                output.width(),
+                output.height()
+            ));
+        }
+
+        for (y, pixels) in raw.pixels.chunks_exact(FRAME_WIDTH).enumerate() {
+            self.decode_line(pixels, raw.line_phases[y] as usize);
+            for x in 0..NTSC_FILTER_WIDTH {
+                let color = self.decode_pixel(x);
+                output.plot(x, y, color);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for NtscFilter {
+    fn default() -> Self {
+        Self::new(PaletteGeneratorParams::default())
+    }
+}
+
+#[test]
+fn test_ntsc_filter() {
+    use crate::framebuffer::{Framebuffer, PixelFormat};
+
+    let params = PaletteGeneratorParams::default();
+    let palette = ppu_palette::generate_ntsc_palette(&params);
+    let mut filter = NtscFilter::new(params);
+    let framebuffer =
+        Framebuffer::new(NTSC_FILTER_WIDTH, NTSC_FILTER_HEIGHT, PixelFormat::RGBA8888);
+    let mut output = framebuffer.rent_data().unwrap();
+
+    let pixel_at = |output: &FramebufferDataRental, x: usize, y: usize| {
+        let off = (y * NTSC_FILTER_WIDTH + x) * 4;
+        Color32::from_rgb(output.data[off], output.data[off + 1], output.data[off + 2])
+    };
+
+    // A flat color decodes to the same color as the generated palette,
+    // regardless of the subcarrier phase
+    let mut raw = RawFrame {
+        pixels: vec![0x16; FRAME_WIDTH * FRAME_HEIGHT],
+        line_phases: (0..FRAME_HEIGHT).map(|y| ((y * 4) % 12) as u8).collect(),
+    };
+    filter.filter_frame(&raw, &mut output).unwrap();
+    for y in [0, 1, 2, 239] {
+        for x in [0, 100, NTSC_FILTER_WIDTH - 1] {
+            assert_eq!(pixel_at(&output, x, y), palette[0x16]);
+        }
+    }
+
+    // A vertical edge between black and white bleeds color into the
+    // neighbouring pixels and the fringes depend on the subcarrier phase
+    for (i, pixel) in raw.pixels.iter_mut().enumerate() {
+        *pixel = if (i % FRAME_WIDTH) < 128 { 0x0f } else { 0x30 };
+    }
+    filter.filter_frame(&raw, &mut output).unwrap();
+    assert_eq!(pixel_at(&output, 0, 0), palette[0x0f]);
+    assert_eq!(pixel_at(&output, NTSC_FILTER_WIDTH - 1, 0), palette[0x30]);
+    let edge = |output: &FramebufferDataRental, y: usize| {
+        (254..258)
+            .map(|x| pixel_at(output, x, y))
+            .collect::<Vec<_>>()
+    };
+    let is_grey = |c: &Color32| c.r() == c.g() && c.g() == c.b();
+    assert!(!edge(&output, 0).iter().all(is_grey));
+    assert_ne!(edge(&output, 0), edge(&output, 1));
+}
@@ -11,13 +11,15 @@ use crate::constants::FRAME_HEIGHT;
 use crate::constants::FRAME_WIDTH;
 use crate::framebuffer::Framebuffer;
 use crate::framebuffer::FramebufferDataRental;
+use crate::framebuffer::FramebufferInfo;
 use crate::framebuffer::PixelFormat;
 use crate::hook::{HookHandle, HooksList};
-use crate::ppu_palette::rgb_lut;
+use crate::ppu_palette::{self, rgb_lut};
 use crate::ppu_registers::Control1Flags;
 use crate::ppu_registers::Control2Flags;
 use crate::ppu_registers::StatusFlags;
 use crate::system::Model;
+use crate::vs_system::VsPpu;
 
 use crate::trace::TraceBuffer;
 #[cfg(feature = "trace-events")]
@@ -29,6 +31,7 @@ use crate::trace::TraceEvent;
 //pub const FRAME_HEIGHT: usize = 240;
 //pub const RENDER_SCREEN_WIDTH: u16 = FRAME_WIDTH as u16;
 pub const N_LINES: u16 = 262;
+pub const N_LINES_PAL: u16 = 312;
 pub const DOTS_PER_LINE: u16 = 341;
 pub const NAMETABLE_PIXELS_PER_TILE: u16 = 8; // 1tile=8*8
 pub const NAMETABLE_X_TILES_COUNT: u16 = (FRAME_WIDTH as u16) / NAMETABLE_PIXELS_PER_TILE; // 256/8=32
@@ -40,6 +43,8 @@ const VT_HORIZONTAL_SCROLL_BITS_MASK: u16 = 0b0000_0100_0001_1111;
 const VT_VERTICAL_SCROLL_BITS_MASK: u16 = 0b0111_1011_1110_0000;
 
 pub const OAM_SIZE: usize = 64 * 4;
+/// The number of sprites the hardware can show on a single line
+const SECONDARY_OAM_SPRITES: usize = 8;
 pub const PATTERN_TABLE_ENTRY_BYTE: u16 = 16;
 
 //pub const SPRITE_TEMP_SIZE: usize = 8;
@@ -91,6 +96,114 @@ pub(super) struct DotBreakpoint {
     pub(super) callback: Box<FnDotBreakpointCallback>,
 }
 
+/// A write to PPUCTRL ($2000) or PPUMASK ($2001) that's known to glitch
+/// rendering, or a PPUSCROLL ($2005) / PPUADDR ($2006) write that won't take
+/// effect in the way a raster effect might expect, based on when it lands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterWriteGlitch {
+    /// A PPUCTRL write on dot 257, racing the copy of the horizontal scroll
+    /// bits from t to v, which can glitch the horizontal nametable select
+    CtrlWriteAtDot257,
+
+    /// Rendering was disabled while sprites were being evaluated or fetched,
+    /// which will corrupt a row of OAM when rendering starts again
+    OamCorruption,
+
+    /// A horizontal scroll write during the visible part of a line, where the
+    /// fine X scroll changes immediately but the coarse X scroll won't take
+    /// effect until the next line
+    MidScanlineHorizontalScroll,
+
+    /// A vertical scroll write while rendering, which won't affect v until the
+    /// pre-render line unless followed by a PPUADDR write
+    VerticalScrollDeferred,
+
+    /// A second PPUADDR write during the visible part of a line, which moves
+    /// v while tiles for the line are still being fetched
+    MidScanlineAddress,
+
+    /// A second PPUADDR write on a dot where the PPU also increments v, so it's
+    /// unpredictable which update wins
+    AddressIncrementConflict,
+}
+
+impl RegisterWriteGlitch {
+    pub fn description(&self) -> &'static str {
+        match self {
+            RegisterWriteGlitch::CtrlWriteAtDot257 => {
+                "PPUCTRL write on dot 257 may glitch the horizontal nametable"
+            }
+            RegisterWriteGlitch::OamCorruption => {
+                "Rendering disabled during sprite evaluation/fetches will corrupt OAM"
+            }
+            RegisterWriteGlitch::MidScanlineHorizontalScroll => {
+                "Horizontal scroll change in the middle of a visible line"
+            }
+            RegisterWriteGlitch::VerticalScrollDeferred => {
+                "Vertical scroll change won't take effect until the next frame"
+            }
+            RegisterWriteGlitch::MidScanlineAddress => {
+                "PPUADDR change in the middle of a visible line"
+            }
+            RegisterWriteGlitch::AddressIncrementConflict => {
+                "PPUADDR change on the same dot that the PPU increments v"
+            }
+        }
+    }
+}
+
+/// A write to one of the PPU registers that affect rendering ($2000, $2001,
+/// $2005 or $2006), recorded when the register write log is enabled (see
+/// [`Ppu::set_register_write_log_enabled`])
+#[derive(Debug, Clone)]
+pub struct RegisterWrite {
+    pub frame: u32,
+    pub line: u16,
+    pub dot: u16,
+    pub addr: u16,
+    pub value: u8,
+
+    /// The state of v and fine x just before the write, which determine
+    /// where the PPU is within the nametables
+    pub shared_v_register: u16,
+    pub fine_x_scroll: u8,
+
+    pub glitch: Option<RegisterWriteGlitch>,
+}
+
+impl RegisterWrite {
+    /// The approximate pixel position within all four (512x480) nametables
+    /// that the PPU was rendering when the write landed
+    ///
+    /// While rendering, v runs two tiles ahead of the pixel being output, due
+    /// to the tiles that are prefetched at the end of the previous line.
+    /// Returns `None` for writes that land outside of the visible lines.
+    pub fn nametable_position(&self) -> Option<(usize, usize)> {
+        if self.line >= FRAME_HEIGHT as u16 {
+            return None;
+        }
+        let v = self.shared_v_register as usize;
+        let coarse_x = v & 0x1f;
+        let coarse_y = (v >> 5) & 0x1f;
+        let nametable_x = (v >> 10) & 1;
+        let nametable_y = (v >> 11) & 1;
+        let fine_y = (v >> 12) & 0b111;
+
+        let x = nametable_x * FRAME_WIDTH + coarse_x * 8 + self.fine_x_scroll as usize;
+        let x = (x + FRAME_WIDTH * 2 - 16) % (FRAME_WIDTH * 2);
+        let y = nametable_y * FRAME_HEIGHT + (coarse_y * 8 + fine_y).min(FRAME_HEIGHT - 1);
+        Some((x, y))
+    }
+}
+
+#[cfg(feature = "debugger")]
+#[derive(Default)]
+pub(super) struct RegisterWriteLog {
+    pub(super) enabled: bool,
+    pub(super) current: Vec<RegisterWrite>,
+    pub(super) prev: Vec<RegisterWrite>,
+}
+
 /// Debugger state attached to a PPU instance that won't be
 /// cloned if the PPU is cloned but will be preserved through
 /// a power cycle
@@ -105,6 +218,8 @@ pub struct NoCloneDebugState {
     pub(super) breakpoints: Vec<DotBreakpoint>,
     #[cfg(feature = "debugger")]
     pub breakpoint_hit: bool,
+    #[cfg(feature = "debugger")]
+    pub(super) register_writes: RegisterWriteLog,
 
     #[cfg(feature = "trace-events")]
     pub trace_events_current: TraceBuffer,
@@ -116,10 +231,23 @@ pub struct NoCloneDebugState {
 
     #[cfg(feature = "ppu-hooks")]
     dot_hooks: Vec<[HooksList<FnDotHook>; 341]>,
+    /// The total number of hooks in `dot_hooks`, so we can quickly tell if
+    /// the fast renderer needs to step every dot
+    #[cfg(feature = "ppu-hooks")]
+    n_dot_hooks: usize,
+}
+impl NoCloneDebugState {
+    fn new() -> Self {
+        Self {
+            #[cfg(feature = "ppu-hooks")]
+            dot_hooks: vec![[(); 341].map(|_| HooksList::default()); N_LINES_PAL as usize], // awkward because HooksList isn't Copy
+            ..Default::default()
+        }
+    }
 }
 impl Clone for NoCloneDebugState {
     fn clone(&self) -> Self {
-        Self::default()
+        Self::new()
     }
 }
 
@@ -200,6 +328,13 @@ pub struct MuxHookState {
     pub monochrome: bool,
 }
 
+impl MuxHookState {
+    /// The pixel as it would be stored in a [`PixelFormat::RAW9`] framebuffer
+    pub fn raw_pixel(&self) -> u16 {
+        ((self.emphasis as u16) << 6) | (self.palette_value & 0x3f) as u16
+    }
+}
+
 //type LineHookMask = BitArr!(for 262);
 //type DotHookMask = BitArr!(for 341);
 
@@ -214,22 +349,22 @@ pub enum LineStatus {
     /// Lines 0..=239
     #[default]
     Visible,
-    /// Line 240
+    /// Line 240 (240..=290 for Dendy)
     PostRender,
-    /// Lines 241..=260
+    /// Lines 241..=260 (241..=310 for PAL, 291..=310 for Dendy)
     VerticalBlanking,
-    /// Line 261
+    /// Line 261 (311 for PAL and Dendy)
     PreRender,
 }
 impl LineStatus {
-    fn from(line: u16) -> LineStatus {
+    fn from(line: u16, vblank_line: u16, pre_render_line: u16) -> LineStatus {
         if line < 240 {
             LineStatus::Visible
-        } else if line == 240 {
+        } else if line < vblank_line {
             LineStatus::PostRender
-        } else if line < 261 {
+        } else if line < pre_render_line {
             LineStatus::VerticalBlanking
-        } else if line == 261 {
+        } else if line == pre_render_line {
             LineStatus::PreRender
         } else {
             panic!("invalid line status");
@@ -277,10 +412,67 @@ impl Arr256 {
     }
 }
 
+/// The pixels output by the PPU before they are mapped to RGB colors
+#[derive(Clone)]
+pub struct RawFrame {
+    /// [`FRAME_WIDTH`] x [`FRAME_HEIGHT`] pixels, each with the emphasis bits
+    /// from PPUMASK (in the same order as the register, so red is bit 6 for
+    /// NTSC and green is bit 6 for PAL and Dendy) above the 6-bit palette value
+    pub pixels: Vec<u16>,
+
+    /// The phase (0..12) of the color subcarrier at the start of each line
+    pub line_phases: Vec<u8>,
+}
+
+impl RawFrame {
+    fn new() -> Self {
+        Self {
+            pixels: vec![0; FRAME_WIDTH * FRAME_HEIGHT],
+            line_phases: vec![0; FRAME_HEIGHT],
+        }
+    }
+}
+
+/// The number of pixels to crop from each edge of the framebuffer
+///
+/// Many TVs wouldn't show the edges of the picture and games often left
+/// glitches there (such as from scrolling) that weren't expected to be seen.
+/// Cropped pixels are output as black.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Overscan {
+    pub top: u8,
+    pub bottom: u8,
+    pub left: u8,
+    pub right: u8,
+}
+
+impl Overscan {
+    #[inline(always)]
+    fn crops(&self, screen_x: usize, screen_y: usize) -> bool {
+        screen_y < self.top as usize
+            || screen_y >= FRAME_HEIGHT.saturating_sub(self.bottom as usize)
+            || screen_x < self.left as usize
+            || screen_x >= FRAME_WIDTH.saturating_sub(self.right as usize)
+    }
+}
+
+/// Counts of visible lines rendered by the fast renderer (see [`Ppu::set_fast_renderer_enabled`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FastRendererStats {
+    /// Lines whose dots 1-256 were all rendered in one batch
+    pub batched_lines: u64,
+    /// Lines where batching started but something needed to observe the PPU
+    /// mid-line, so the rest of the line was stepped normally
+    pub interrupted_lines: u64,
+}
+
 #[derive(Clone, Default)]
 pub struct Ppu {
     pub nes_model: Model,
 
+    /// The RGB PPU variant, for Vs. System games
+    vs_ppu: Option<VsPpu>,
+
     pub clock: u64,
     pub frame: u32,
 
@@ -306,6 +498,29 @@ pub struct Ppu {
     pub emphasize_green: bool,
     pub emphasize_blue: bool,
 
+    /// RGB colors for all 64 NES colors, repeated for all combinations of emphasis bits
+    /// (see [`ppu_palette::generate_emphasis_palette`])
+    rgb_palette: Vec<Color32>,
+
+    /// Offset into `rgb_palette` for the current emphasis bits
+    emphasis_palette_offset: usize,
+
+    /// Optional copy of the frame's raw 9-bit pixel values (see [`Self::set_raw_output_enabled`])
+    raw_frame: Option<Box<RawFrame>>,
+
+    /// Show all sprites on a line, instead of just the first eight (see [`Self::set_sprite_limit_enabled`])
+    sprite_limit_disabled: bool,
+
+    /// Edges cropped from the framebuffer (see [`Self::set_overscan`])
+    overscan: Overscan,
+
+    /// Batch the visible dots of a line when nothing can observe them (see
+    /// [`Self::set_fast_renderer_enabled`])
+    fast_renderer_enabled: bool,
+    /// The number of dots that have been clocked but not yet rendered
+    deferred_dots: u16,
+    fast_renderer_stats: FastRendererStats,
+
     pub status: StatusFlags,
 
     pub dot: u16, // wraps every 341 clock cycles
@@ -316,17 +531,29 @@ pub struct Ppu {
 
     pub io_latch_value: u8,
 
-    /// To (crudely) model the decay of the IO latch value then:
-    /// - Each time a bit is updated it is added to the first keep-alive mask.
-    /// - We periodically move the first mask to a second keep-alive mask before
-    ///   clearing the first one.
-    /// - Whenever a bit is not found in either of these masks before the first
-    ///   mask is cleared then we know that bit hasn't been written recently and
-    ///   it decays to zero.
-    io_latch_keep_alive_masks: [u8; 2],
-    io_latch_last_update_clock: u64,
+    /// The clock at which each bit of the IO latch was last refreshed
+    ///
+    /// Each bit of the latch decays to zero independently if it isn't refreshed
+    /// by a write (which refreshes all bits) or a read that drives that bit.
+    io_latch_refresh_clocks: [u64; 8],
+    /// The next clock at which a set bit in the IO latch will decay
+    io_latch_next_decay_clock: u64,
     io_latch_decay_clock_period: u32,
 
+    /// The clock at which each 8-byte row of OAM was last refreshed by an
+    /// OAMDATA read or write
+    ///
+    /// OAM is dynamic RAM that's refreshed while rendering, and rows will decay
+    /// if rendering is disabled for too long without them being accessed.
+    oam_row_refresh_clocks: [u64; 32],
+    /// The clock at which rendering last refreshed all of OAM
+    oam_render_refresh_clock: u64,
+    oam_decay_clock_period: u32,
+
+    /// A bitmask of OAM rows that will be overwritten by row zero the next time
+    /// rendering starts, after rendering was disabled mid-line
+    oam_corrupt_rows: u32,
+
     pub read_buffer: u8,
 
     pub shared_w_toggle: bool, // Latch for PPU_SCROLL and PPU_ADDR
@@ -436,13 +663,17 @@ impl Ppu {
     pub fn new(nes_model: Model) -> Self {
         let clock_hz = match nes_model {
             Model::Ntsc => 5369318, // +- 10Hz
-            Model::Pal => 4295454,  // more like 4295454.4 +- 10Hz
+            Model::Pal => 5320342,  // 26.601712MHz / 5
+            Model::Dendy => 5320342,
         };
 
-        // For now we have a conservative decay rate for the IO bus latch which will
-        // decay bits to zero if they haven't been written for at _least_ half
-        // a second
-        let io_latch_decay_clock_period = clock_hz / 2;
+        // Bits of the IO bus latch decay to zero if they haven't been refreshed
+        // for around 600ms
+        let io_latch_decay_clock_period = clock_hz * 3 / 5;
+
+        // OAM decay times vary between consoles (and with temperature) but
+        // rows can start to decay within a couple of milliseconds
+        let oam_decay_clock_period = clock_hz / 500;
 
         let framebuffer = Framebuffer::new(FRAME_WIDTH, FRAME_HEIGHT, PixelFormat::RGBA8888);
         let framebuffer = framebuffer.rent_data().unwrap();
@@ -451,27 +682,161 @@ impl Ppu {
         Self {
             nes_model,
             io_latch_decay_clock_period,
+            oam_decay_clock_period,
             framebuffer,
             line: start_line,
-            line_status: LineStatus::from(start_line),
-            debug: NoCloneDebugState {
-                dot_hooks: vec![[(); 341].map(|_| HooksList::default()); 262], // awkward because HooksList isn't Copy
-                ..Default::default()
-            },
+            line_status: LineStatus::from(
+                start_line,
+                Self::vblank_line_for_model(nes_model),
+                Self::n_lines_for_model(nes_model) - 1,
+            ),
+            rgb_palette: ppu_palette::default_emphasis_palette(),
+            debug: NoCloneDebugState::new(),
             ..Default::default()
         }
     }
 
     pub fn power_cycle(&mut self) {
-        // Note we preserve any debugger state / hooks
+        // Note we preserve any debugger state / hooks, the RGB palette, the
+        // Vs. System PPU variant, whether raw output is enabled and the
+        // display options
         let debug = std::mem::take(&mut self.debug);
+        let rgb_palette = std::mem::take(&mut self.rgb_palette);
+        let raw_frame = self.raw_frame.is_some().then(|| Box::new(RawFrame::new()));
 
         *self = Self {
             debug,
+            rgb_palette,
+            vs_ppu: self.vs_ppu,
+            raw_frame,
+            sprite_limit_disabled: self.sprite_limit_disabled,
+            overscan: self.overscan,
+            fast_renderer_enabled: self.fast_renderer_enabled,
             ..Ppu::new(self.nes_model)
         }
     }
 
+    /// Emulate one of the RGB PPUs used in Vs. System cabinets, or the standard
+    /// PPU if `None`
+    ///
+    /// This also replaces the RGB palette with the palette of the given PPU
+    /// (or the default palette).
+    pub fn set_vs_ppu(&mut self, vs_ppu: Option<VsPpu>) {
+        self.vs_ppu = vs_ppu;
+        self.rgb_palette = match vs_ppu {
+            Some(vs_ppu) => ppu_palette::generate_vs_palette(vs_ppu),
+            None => ppu_palette::default_emphasis_palette(),
+        };
+    }
+
+    pub fn vs_ppu(&self) -> Option<VsPpu> {
+        self.vs_ppu
+    }
+
+    /// Reads PPUSTATUS, with the fixed ID bits of a Vs. System 2C05 PPU
+    ///
+    /// Returns (value, undefined bits mask)
+    #[inline(always)]
+    fn status_with_id_bits(&self) -> (u8, u8) {
+        let data = self.status.bits();
+        let undefined_bits = StatusFlags::UNDEFINED_BITS.bits();
+        match self.vs_ppu.and_then(|vs_ppu| vs_ppu.status_id_bits()) {
+            Some((mask, id)) => ((data & !mask) | id, undefined_bits & !mask),
+            None => (data, undefined_bits),
+        }
+    }
+
+    /// Replace the palette used to map NES colors to RGB for the framebuffer
+    ///
+    /// The palette must have 512 entries that cover all combinations of emphasis
+    /// bits (see [`ppu_palette::generate_emphasis_palette`] and [`ppu_palette::parse_pal_file`])
+    pub fn set_rgb_palette(&mut self, palette: Vec<Color32>) -> Result<()> {
+        if palette.len() != ppu_palette::EMPHASIS_PALETTE_SIZE {
+            return Err(anyhow!(
+                "Palette should have {} entries, not {}",
+                ppu_palette::EMPHASIS_PALETTE_SIZE,
+                palette.len()
+            ));
+        }
+        self.rgb_palette = palette;
+        Ok(())
+    }
+
+    /// The palette used to map NES colors to RGB, including all emphasis combinations
+    pub fn rgb_palette(&self) -> &[Color32] {
+        &self.rgb_palette
+    }
+
+    /// Enables writing the raw 9-bit value of each pixel into a [`RawFrame`], in
+    /// addition to the RGB framebuffer
+    ///
+    /// This is useful for filters that need to know more than the final RGB color,
+    /// such as [`crate::ntsc_filter::NtscFilter`]
+    pub fn set_raw_output_enabled(&mut self, enabled: bool) {
+        if enabled != self.raw_frame.is_some() {
+            self.raw_frame = enabled.then(|| Box::new(RawFrame::new()));
+        }
+    }
+
+    /// The raw pixel values for the most recent frame, if raw output is enabled
+    ///
+    /// Note: this is written to as the frame is rendered, so it's only complete
+    /// once the PPU reports that the frame is ready
+    pub fn raw_frame(&self) -> Option<&RawFrame> {
+        self.raw_frame.as_deref()
+    }
+
+    /// Enables or disables the hardware limit of showing eight sprites per line
+    ///
+    /// With the limit disabled, sprites that would normally be dropped are
+    /// shown too, which avoids flickering in many games. This is a display
+    /// option only and doesn't affect the sprite overflow flag or sprite zero
+    /// hits, which games may depend on.
+    pub fn set_sprite_limit_enabled(&mut self, enabled: bool) {
+        self.sprite_limit_disabled = !enabled;
+    }
+
+    pub fn sprite_limit_enabled(&self) -> bool {
+        !self.sprite_limit_disabled
+    }
+
+    /// Crop the edges of the framebuffer (and raw frame), replacing them with black
+    pub fn set_overscan(&mut self, overscan: Overscan) {
+        self.overscan = overscan;
+    }
+
+    pub fn overscan(&self) -> Overscan {
+        self.overscan
+    }
+
+    /// Enables rendering the visible dots of a line in one batch, when nothing
+    /// could observe the PPU part way through the line
+    ///
+    /// Once the first dot of a visible line is reached with rendering enabled,
+    /// and with no mapper that watches PPU fetches (such as the MMC3's A12
+    /// scanline counter), no breakpoints and no dot hooks, the PPU only counts
+    /// clock cycles until the end of dot 256 and then renders the whole span
+    /// at once. Anything that could observe the PPU mid-line, such as a CPU
+    /// read or write of a PPU register (including polling for a sprite zero
+    /// hit), a write to the cartridge or [`crate::Nes::ppu_mut`], first
+    /// catches up the deferred dots so the output is identical to stepping
+    /// one dot at a time.
+    ///
+    /// While dots are deferred, an immutable view of the PPU will see
+    /// `line`, `dot` and `clock` values that are ahead of the framebuffer and
+    /// internal rendering state.
+    pub fn set_fast_renderer_enabled(&mut self, enabled: bool) {
+        self.fast_renderer_enabled = enabled;
+    }
+
+    pub fn fast_renderer_enabled(&self) -> bool {
+        self.fast_renderer_enabled
+    }
+
+    pub fn fast_renderer_stats(&self) -> FastRendererStats {
+        self.fast_renderer_stats
+    }
+
     pub fn reset(&mut self) {
         /* Actually - no reason why we can't trace across a reset
         #[cfg(feature="trace-events")]
@@ -495,7 +860,26 @@ impl Ppu {
     /// While the framebuffer is associated with the PPU the PPU will rent access to the underlying data
     /// and so you must swap with a new framebuffer before being able to rent access to the data
     /// for presenting
+    ///
+    /// The framebuffer must be 256x240 and either [`PixelFormat::RGBA8888`] or
+    /// [`PixelFormat::RAW9`] (to capture the PPU's output before any palette lookup)
     pub fn swap_framebuffer(&mut self, framebuffer: Framebuffer) -> Result<Framebuffer> {
+        if framebuffer.width() != FRAME_WIDTH || framebuffer.height() != FRAME_HEIGHT {
+            return Err(anyhow!(
+                "PPU framebuffer should be {FRAME_WIDTH}x{FRAME_HEIGHT}, not {}x{}",
+                framebuffer.width(),
+                framebuffer.height()
+            ));
+        }
+        if !matches!(
+            framebuffer.format(),
+            PixelFormat::RGBA8888 | PixelFormat::RAW9
+        ) {
+            return Err(anyhow!(
+                "Unsupported PPU framebuffer format {:?}",
+                framebuffer.format()
+            ));
+        }
         if let Some(rental) = framebuffer.rent_data() {
             let old = self.framebuffer.owner();
             self.framebuffer = rental;
@@ -536,6 +920,56 @@ impl Ppu {
     }
 
     #[inline(always)]
+    fn n_lines_for_model(nes_model: Model) -> u16 {
+        match nes_model {
+            Model::Ntsc => N_LINES,
+            Model::Pal | Model::Dendy => N_LINES_PAL,
+        }
+    }
+
+    /// The Dendy has the same number of lines as PAL but, to stay compatible
+    /// with NTSC games that expect ~20 lines of vblank, it only starts vblank
+    /// (and raises the NMI) 51 lines after the post-render line
+    ///
+    /// ref: https://www.nesdev.org/wiki/Cycle_reference_chart
+    #[inline(always)]
+    fn vblank_line_for_model(nes_model: Model) -> u16 {
+        match nes_model {
+            Model::Ntsc | Model::Pal => 241,
+            Model::Dendy => 291,
+        }
+    }
+
+    /// The number of lines per frame, including vertical blanking
+    pub fn n_lines(&self) -> u16 {
+        Self::n_lines_for_model(self.nes_model)
+    }
+
+    /// The line on which the vblank flag is set (and an NMI may be raised)
+    pub fn vblank_line(&self) -> u16 {
+        Self::vblank_line_for_model(self.nes_model)
+    }
+
+    #[inline(always)]
+    fn pre_render_line(&self) -> u16 {
+        self.n_lines() - 1
+    }
+
+    /// To stop its OAM from decaying during the longer vblank period the PAL
+    /// PPU (2C07) always refreshes OAM from 24 lines after the start of vblank
+    /// until the pre-render line, regardless of whether rendering is enabled.
+    ///
+    /// Writes to OAMDATA are treated the same as during rendering while OAM is
+    /// being refreshed, so games must upload OAM early in vblank.
+    ///
+    /// ref: https://www.nesdev.org/wiki/PPU_OAM
+    #[inline(always)]
+    fn is_pal_oam_refresh(&self) -> bool {
+        self.nes_model == Model::Pal
+            && self.line_status == LineStatus::VerticalBlanking
+            && self.line >= 241 + 24
+    }
+
     fn is_rendering(&self) -> bool {
         matches!(
             self.line_status,
@@ -553,6 +987,37 @@ impl Ppu {
         (self.control2 & Control2Flags::EMPHASIS).bits() >> 5
     }
 
+    /// Updates the offset into the emphasis palette after the emphasis bits change
+    ///
+    /// The PAL and Dendy PPUs swap the meaning of the red and green emphasis bits
+    /// compared to NTSC
+    fn update_emphasis_palette_offset(&mut self) {
+        let (red, green) = match self.nes_model {
+            Model::Pal | Model::Dendy => (self.emphasize_green, self.emphasize_red),
+            _ => (self.emphasize_red, self.emphasize_green),
+        };
+        let mut emphasis = 0;
+        if red {
+            emphasis |= ppu_palette::EMPHASIS_RED;
+        }
+        if green {
+            emphasis |= ppu_palette::EMPHASIS_GREEN;
+        }
+        if self.emphasize_blue {
+            emphasis |= ppu_palette::EMPHASIS_BLUE;
+        }
+        self.emphasis_palette_offset = (emphasis as usize) << 6;
+    }
+
+    /// Maps a palette value to an RGB color, taking into account the current emphasis bits
+    #[inline(always)]
+    fn emphasized_rgb(&self, palette_value: u8) -> Color32 {
+        arr_read!(
+            self.rgb_palette,
+            self.emphasis_palette_offset + (palette_value & 0x3f) as usize
+        )
+    }
+
     fn update_nmi(&mut self) {
         if self.nmi_enable && self.status.contains(StatusFlags::IN_VBLANK) {
             self.nmi_interrupt_raised = true;
@@ -706,10 +1171,10 @@ impl Ppu {
         //println!("inc data address: scroll_tile_x = {}, scroll_tile_y = {}", self.scroll_tile_x(), self.scroll_tile_y());
     }
 
-    // TODO: decay the latch value over time
     pub fn finish_read_with_latch(&mut self, value: u8, undefined_bits: u8) -> u8 {
         let read = (value & !undefined_bits) | (self.io_latch_value & undefined_bits);
         self.io_latch_value = (self.io_latch_value & undefined_bits) | (value & !undefined_bits);
+        self.refresh_io_latch_bits(!undefined_bits);
         read
     }
 
@@ -742,6 +1207,76 @@ impl Ppu {
         }
     }
 
+    /// Has the OAM row containing `offset` decayed, due to rendering being
+    /// disabled and the row not being accessed for too long
+    #[inline]
+    fn is_oam_row_decayed(&self, offset: u8) -> bool {
+        let row_refresh_clock = self.oam_row_refresh_clocks[(offset >> 3) as usize];
+        let last_refresh_clock = row_refresh_clock.max(self.oam_render_refresh_clock);
+        self.clock - last_refresh_clock >= self.oam_decay_clock_period as u64
+    }
+
+    /// Decays the contents of an OAM row that has gone too long without
+    /// being refreshed and then marks it as refreshed
+    ///
+    /// The contents of decayed DRAM isn't predictable so for the sake of
+    /// determinism we decay all bits to one, which notably moves any affected
+    /// sprites off screen.
+    fn refresh_oam_row(&mut self, offset: u8) {
+        if self.is_oam_row_decayed(offset) {
+            let row = offset & 0xf8;
+            for i in 0..8 {
+                self.write_oam_data(row + i, 0xff);
+            }
+        }
+        self.oam_row_refresh_clocks[(offset >> 3) as usize] = self.clock;
+    }
+
+    /// Called at the start of each line where rendering (or PAL's forced OAM
+    /// refresh) keeps OAM refreshed
+    fn refresh_oam(&mut self) {
+        if self.clock - self.oam_render_refresh_clock >= self.oam_decay_clock_period as u64 {
+            for row in 0..32 {
+                self.refresh_oam_row(row * 8);
+            }
+        }
+        self.oam_render_refresh_clock = self.clock;
+    }
+
+    /// Records which OAM row will be corrupted when rendering is disabled mid-line
+    ///
+    /// Disabling rendering while the PPU is clearing secondary OAM or fetching
+    /// sprite patterns leaves the OAM row that was being accessed to be
+    /// overwritten with the first row the next time that rendering starts.
+    ///
+    /// ref: https://www.nesdev.org/wiki/PPU_OAM#Corruption
+    fn set_oam_corruption_row(&mut self) {
+        let row = match self.dot {
+            0..=63 => self.dot >> 1,
+            256..=319 => {
+                // The first three dots of each 8-dot sprite fetch step through
+                // rows and the remaining dots access the next row
+                let base = (self.dot - 256) >> 3;
+                let offset = ((self.dot - 256) & 0b111).min(3);
+                base * 4 + offset
+            }
+            _ => return,
+        };
+        self.oam_corrupt_rows |= 1 << row;
+    }
+
+    /// Copies the first row of OAM over any rows corrupted by disabling rendering mid-line
+    fn process_oam_corruption(&mut self) {
+        for row in 1..32 {
+            if self.oam_corrupt_rows & (1 << row) != 0 {
+                for i in 0..8 {
+                    self.write_oam_data(row * 8 + i, self.read_oam_data(i));
+                }
+            }
+        }
+        self.oam_corrupt_rows = 0;
+    }
+
     /// Read OAM data via $2004 register
     fn read_oam_data_register(&self) -> u8 {
         // "Cycles 1-64: Secondary OAM (32-byte buffer for current
@@ -750,7 +1285,7 @@ impl Ppu {
         // is implemented by reading from the OAM and writing into the
         // secondary OAM as usual, only a signal is active that makes
         // the read always return $FF."
-        if self.secondary_oam_being_cleared {
+        if self.secondary_oam_being_cleared || self.is_oam_row_decayed(self.oam_offset) {
             0xff
         } else {
             self.read_oam_data(self.oam_offset)
@@ -779,12 +1314,12 @@ impl Ppu {
             // PPU_STATUS (read-only) Resets double-write register status, clears VBLANK flag
             0x2002 => {
                 // Status (Read-only)
-                let data = self.status.bits();
+                let status = self.status_with_id_bits();
                 self.shared_w_toggle = false;
                 //self.ppu_is_second_write = false;
                 self.status.set(StatusFlags::IN_VBLANK, false);
                 //println!("Clear IN_VBLANK flag (status read)");
-                (data, StatusFlags::UNDEFINED_BITS.bits())
+                status
             }
             0x2003 => {
                 // OAMADDR (Write-only)
@@ -792,7 +1327,11 @@ impl Ppu {
             }
             0x2004 => {
                 // OAMDATA (Read/Write)
-                (self.read_oam_data_register(), 0x0)
+                let data = self.read_oam_data_register();
+                if !self.is_rendering() {
+                    self.refresh_oam_row(self.oam_offset);
+                }
+                (data, 0x0)
             }
             0x2005 => {
                 // PPU_SCROLL (Write-only)
@@ -814,6 +1353,7 @@ impl Ppu {
     }
 
     pub fn system_bus_read(&mut self, cartridge: &mut Cartridge, addr: u16) -> (u8, u8) {
+        self.render_deferred_dots(cartridge);
         //self.read_without_openbus(cartridge, addr)
         let (value, undefined_bits) = self.read_without_openbus(cartridge, addr);
         let value = self.finish_read_with_latch(value, undefined_bits);
@@ -823,13 +1363,13 @@ impl Ppu {
     }
 
     pub fn system_bus_peek(&mut self, cartridge: &mut Cartridge, addr: u16) -> (u8, u8) {
+        self.render_deferred_dots(cartridge);
         // mirror
         let addr = ((addr - 0x2000) % 8) + 0x2000;
         let (value, undefined_bits) = match addr {
             0x2002 => {
                 // Status (Read-only)
-                let data = self.status.bits();
-                (data, StatusFlags::UNDEFINED_BITS.bits())
+                self.status_with_id_bits()
             }
             0x2004 => {
                 // OamData
@@ -847,10 +1387,30 @@ impl Ppu {
     }
 
     pub fn system_bus_write(&mut self, cartridge: &mut Cartridge, addr: u16, data: u8) {
+        self.render_deferred_dots(cartridge);
         //println!("CPU->PPU write 0x{:04x} = 0x{:02x}", addr, data);
         // mirror
         let addr = ((addr - 0x2000) % 8) + 0x2000;
+
+        // The Vs. System 2C05 PPUs swap the PPUCTRL and PPUMASK registers
+        let addr = match addr {
+            0x2000 | 0x2001
+                if self
+                    .vs_ppu
+                    .is_some_and(|vs_ppu| vs_ppu.swaps_control_registers()) =>
+            {
+                addr ^ 1
+            }
+            _ => addr,
+        };
+
+        #[cfg(feature = "debugger")]
+        if self.debug.register_writes.enabled && matches!(addr, 0x2000 | 0x2001 | 0x2005 | 0x2006) {
+            self.log_register_write(addr, data);
+        }
+
         self.io_latch_value = data;
+        self.refresh_io_latch_bits(0xff);
         match addr {
             0x2000 => {
                 // Control 1
@@ -866,6 +1426,7 @@ impl Ppu {
             0x2001 => {
                 // Control 2
 
+                let was_rendering = self.is_rendering();
                 self.control2 = Control2Flags::from_bits_truncate(data);
                 self.show_background = self.control2.contains(Control2Flags::SHOW_BG);
                 self.show_sprites = self.control2.contains(Control2Flags::SHOW_SPRITES);
@@ -879,6 +1440,10 @@ impl Ppu {
                 self.emphasize_red = self.control2.contains(Control2Flags::EMPHASIZE_RED);
                 self.emphasize_green = self.control2.contains(Control2Flags::EMPHASIZE_GREEN);
                 self.emphasize_blue = self.control2.contains(Control2Flags::EMPHASIZE_BLUE);
+                self.update_emphasis_palette_offset();
+                if was_rendering && !self.rendering_enabled {
+                    self.set_oam_corruption_row();
+                }
                 //println!("PPU Control2 write = {:08b}: rendering_enabled = {:?}", data, self.rendering_enabled);
             }
             0x2002 => { // Status
@@ -889,6 +1454,10 @@ impl Ppu {
                 /* TODO: also corrupts OAM data...
                   https://forums.nesdev.org/viewtopic.php?t=10189
 
+                   (Not to be confused with the corruption when rendering starts
+                   with OAMADDR >= 8, which is emulated at the start of the
+                   pre-render line)
+
                    * Take old value from $2003 and AND it with $F8
                    * Read 8 bytes from OAM starting at this masked value
                    * Write them starting at $XX in OAM, where $XX is the high byte of the PPU register written to ($20-$3F) masked with $F8
@@ -923,7 +1492,8 @@ impl Ppu {
                 //
                 // "For emulation purposes, it is probably best to completely ignore
                 //  writes during rendering."
-                if !self.is_rendering() {
+                if !self.is_rendering() && !self.is_pal_oam_refresh() {
+                    self.refresh_oam_row(self.oam_offset);
                     self.write_oam_data(self.oam_offset, data);
                     self.oam_offset = self.oam_offset.wrapping_add(1);
                 } else {
@@ -1189,6 +1759,26 @@ impl Ppu {
             };
 
         let sprite_attr = self.secondary_oam[self.sprite_fetch_index * 4 + 2];
+        let sprite_x = self.secondary_oam[self.sprite_fetch_index * 4 + 3] as usize;
+        self.compose_sprite_row(
+            sprite_x,
+            sprite_attr,
+            self.pattern_table0_latch,
+            self.pattern_table1_latch,
+            sprite_zero_bit,
+        );
+    }
+
+    /// Composes one row of a sprite into `sprite_line_back`, behind any
+    /// sprite pixels that have already been composed
+    fn compose_sprite_row(
+        &mut self,
+        sprite_x: usize,
+        sprite_attr: u8,
+        sprite_row_pattern_low: u8,
+        sprite_row_pattern_hi: u8,
+        sprite_zero_bit: u8,
+    ) {
         let sprite_palette_bits = (sprite_attr & 0b11) << 2;
         let sprite_priority_bit = if sprite_attr & 0b0010_0000 != 0 {
             0b0001_0000
@@ -1197,9 +1787,6 @@ impl Ppu {
         }; // we store the priority state in bit 4
         let x_flip = sprite_attr & 0b0100_0000 != 0;
         //let y_flip = sprite_attr & 0b1000_0000 != 0;
-        let sprite_x = self.secondary_oam[self.sprite_fetch_index * 4 + 3] as usize;
-        let sprite_row_pattern_low = self.pattern_table0_latch;
-        let sprite_row_pattern_hi = self.pattern_table1_latch;
 
         // Don't handle clipping here in case it gets changed by the time we render the final pixels
         //let clip_left_margin = !self.control2.contains(Control2Flags::SPRITES_LEFT_COL_SHOW);
@@ -1395,14 +1982,15 @@ impl Ppu {
         }*/
     }
 
-    /// Calculate the pattern table row address (lower plane) for the 8x16 sprite currently being fetched
-    fn current_sprite8x16_pattern_table_row_address(&self) -> u16 {
-        // XXX: if the 'current' sprite state was stored in the Ppu struct we could avoid
-        // a bunch of bounds checks here
-        let sprite_index_byte = self.secondary_oam[self.sprite_fetch_index * 4 + 1];
+    /// Calculate the pattern table row address (lower plane) for an 8x16 sprite
+    fn sprite8x16_pattern_table_row_address(
+        &self,
+        sprite_index_byte: u8,
+        sprite_y: u8,
+        sprite_attr: u8,
+    ) -> u16 {
         let sprite_tile_index = sprite_index_byte & 0xfe;
-        let sprite_y = self.secondary_oam[self.sprite_fetch_index * 4];
-        let y_flip = self.secondary_oam[self.sprite_fetch_index * 4 + 2] & 0x80 != 0;
+        let y_flip = sprite_attr & 0x80 != 0;
 
         let line = self.line as u8;
 
@@ -1426,13 +2014,14 @@ impl Ppu {
         pattern_table_base | ((sprite_tile_index as u16) << 4) | (row_offset as u16)
     }
 
-    /// Calculate the pattern table row address (lower plane) for the 8x8 sprite currently being fetched
-    fn current_sprite8x8_pattern_table_row_address(&self) -> u16 {
-        // XXX: if the 'current' sprite state was store in the Ppu struct we could avoid
-        // a bunch of bounds checks here
-        let sprite_tile_index = self.secondary_oam[self.sprite_fetch_index * 4 + 1];
-        let sprite_y = self.secondary_oam[self.sprite_fetch_index * 4];
-        let y_flip = self.secondary_oam[self.sprite_fetch_index * 4 + 2] & 0x80 != 0;
+    /// Calculate the pattern table row address (lower plane) for an 8x8 sprite
+    fn sprite8x8_pattern_table_row_address(
+        &self,
+        sprite_tile_index: u8,
+        sprite_y: u8,
+        sprite_attr: u8,
+    ) -> u16 {
+        let y_flip = sprite_attr & 0x80 != 0;
 
         let line = self.line as u8;
 
@@ -1446,12 +2035,62 @@ impl Ppu {
         pattern_table_base | ((sprite_tile_index as u16) << 4) | (row_offset as u16)
     }
 
-    fn calculate_sprite_pattern_table_row_address(&self) -> u16 {
-        //println!("sprite fetch index = {}, sprite count = {}", self.sprite_fetch_index, self.oam_evaluate_n_sprites);
+    fn sprite_pattern_table_row_address(
+        &self,
+        sprite_index_byte: u8,
+        sprite_y: u8,
+        sprite_attr: u8,
+    ) -> u16 {
         if self.sprite_height() == 8 {
-            self.current_sprite8x8_pattern_table_row_address()
+            self.sprite8x8_pattern_table_row_address(sprite_index_byte, sprite_y, sprite_attr)
         } else {
-            self.current_sprite8x16_pattern_table_row_address()
+            self.sprite8x16_pattern_table_row_address(sprite_index_byte, sprite_y, sprite_attr)
+        }
+    }
+
+    /// Calculate the pattern table row address (lower plane) for the sprite currently being fetched
+    fn calculate_sprite_pattern_table_row_address(&self) -> u16 {
+        //println!("sprite fetch index = {}, sprite count = {}", self.sprite_fetch_index, self.oam_evaluate_n_sprites);
+        // XXX: if the 'current' sprite state was stored in the Ppu struct we could avoid
+        // a bunch of bounds checks here
+        let sprite = &self.secondary_oam[self.sprite_fetch_index * 4..];
+        self.sprite_pattern_table_row_address(sprite[1], sprite[0], sprite[2])
+    }
+
+    /// Composes any sprites beyond the eight per line that the hardware can
+    /// show, when the sprite limit is disabled
+    ///
+    /// This is done after the real sprite fetches for the line and only peeks
+    /// at OAM and the pattern tables, so the sprite overflow flag, sprite zero
+    /// hits and the timing of PPU bus reads (that mappers may observe) aren't
+    /// affected. The extra sprites are shown behind the first eight.
+    fn compose_extra_sprites(&mut self, cartridge: &mut Cartridge) {
+        if (self.oam_evaluate_n_sprites as usize) < SECONDARY_OAM_SPRITES {
+            return;
+        }
+
+        let line = self.line;
+        let sprite_height = self.sprite_height() as u16;
+        let mut n_in_range = 0;
+        for n in 0..64u8 {
+            let sprite_y = self.read_oam_data(n * 4);
+            if line < sprite_y as u16 || line >= sprite_y as u16 + sprite_height {
+                continue;
+            }
+            // The first eight sprites in range were found by sprite evaluation
+            n_in_range += 1;
+            if n_in_range <= SECONDARY_OAM_SPRITES {
+                continue;
+            }
+
+            let sprite_index_byte = self.read_oam_data(n * 4 + 1);
+            let sprite_attr = self.read_oam_data(n * 4 + 2);
+            let sprite_x = self.read_oam_data(n * 4 + 3) as usize;
+            let addr =
+                self.sprite_pattern_table_row_address(sprite_index_byte, sprite_y, sprite_attr);
+            let pattern_low = cartridge.vram_peek(addr);
+            let pattern_hi = cartridge.vram_peek(addr + 8);
+            self.compose_sprite_row(sprite_x, sprite_attr, pattern_low, pattern_hi, 0);
         }
     }
 
@@ -1720,7 +2359,7 @@ impl Ppu {
         sprite_pix: u8,
         screen_x: usize,
         cartridge: &mut Cartridge,
-    ) -> Color32 {
+    ) -> u8 {
         //let is_sprite_zero_debug = sprite_pix & 0b10_0000 != 0;
         //if is_sprite_zero_debug {
         //    println!("PX: line = {}, screen_x = {screen_x}, sprite pix = {sprite_pix:02x}m bg_pattern = {bg_pattern:02x}", self.line);
@@ -1793,8 +2432,6 @@ impl Ppu {
 
         let palette_value = self.palette_read(palette_addr + pattern as u16);
 
-        let color = rgb_lut(palette_value);
-
         #[cfg(feature = "ppu-hooks")]
         if !self.debug.mux_hooks.hooks.is_empty() {
             // Copied code from above
@@ -1836,7 +2473,7 @@ impl Ppu {
             self.call_mux_hooks(cartridge, &state);
         }
 
-        color
+        palette_value
     }
 
     fn compose_enabled_pixel(
@@ -1844,7 +2481,7 @@ impl Ppu {
         screen_x: usize,
         _screen_y: usize,
         cartridge: &mut Cartridge,
-    ) -> Color32 {
+    ) -> u8 {
         let bg_palette = self.select_bg_palette_from_shift_registers(self.scroll_x_fine3 as usize);
         let bg_pattern = self.select_bg_pattern_from_shift_registers(self.scroll_x_fine3 as usize);
 
@@ -1864,7 +2501,7 @@ impl Ppu {
         screen_x: usize,
         _screen_y: usize,
         cartridge: &mut Cartridge,
-    ) -> Color32 {
+    ) -> u8 {
         // "During forced blanking, when neither background nor sprites are
         // enabled in PPUMASK ($2001), the picture will show the backdrop color"
         //
@@ -1912,15 +2549,29 @@ impl Ppu {
             self.call_mux_hooks(cartridge, &state);
         }
 
-        rgb_lut(palette_value)
+        palette_value
     }
 
     fn render_pixel(&mut self, screen_x: usize, screen_y: usize, cartridge: &mut Cartridge) {
-        let color = if self.rendering_enabled {
+        let palette_value = if self.rendering_enabled {
             self.compose_enabled_pixel(screen_x, screen_y, cartridge)
         } else {
             self.compose_disabled_pixel(screen_x, screen_y, cartridge)
         };
+        let cropped = self.overscan.crops(screen_x, screen_y);
+        let raw_pixel = if cropped {
+            0x0f
+        } else {
+            ((self.emphasis() as u16) << 6) | (palette_value & 0x3f) as u16
+        };
+
+        if let Some(raw_frame) = &mut self.raw_frame {
+            if screen_x == 0 {
+                // Each dot lasts for 8 of the 12 phases of the color subcarrier
+                raw_frame.line_phases[screen_y] = ((self.clock * 8) % 12) as u8;
+            }
+            raw_frame.pixels[screen_y * FRAME_WIDTH + screen_x] = raw_pixel;
+        }
 
         /*
         if screen_x < 50 || screen_x > 200 {
@@ -1937,14 +2588,23 @@ impl Ppu {
         }
         */
 
-        let fb = self.framebuffer.data.as_mut_ptr();
-        let fb_off = self.framebuffer_offset;
-        debug_assert!(fb_off >= 0 && fb_off < FRAMEBUFFER_STRIDE * FRAME_HEIGHT as isize);
-        unsafe {
-            *fb.offset(fb_off) = color.r();
-            *fb.offset(fb_off + 1) = color.g();
-            *fb.offset(fb_off + 2) = color.b();
-            *fb.offset(fb_off + 3) = 0xff;
+        if self.framebuffer.format() == PixelFormat::RAW9 {
+            self.framebuffer.plot_raw(screen_x, screen_y, raw_pixel);
+        } else {
+            let color = if cropped {
+                Color32::BLACK
+            } else {
+                self.emphasized_rgb(palette_value)
+            };
+            let fb = self.framebuffer.data.as_mut_ptr();
+            let fb_off = self.framebuffer_offset;
+            debug_assert!(fb_off >= 0 && fb_off < FRAMEBUFFER_STRIDE * FRAME_HEIGHT as isize);
+            unsafe {
+                *fb.offset(fb_off) = color.r();
+                *fb.offset(fb_off + 1) = color.g();
+                *fb.offset(fb_off + 2) = color.b();
+                *fb.offset(fb_off + 3) = 0xff;
+            }
         }
 
         self.framebuffer_offset += 4;
@@ -2034,6 +2694,113 @@ impl Ppu {
             self.print_shift_register_state();
         }
         */
+        // OAM is only refreshed by rendering on the visible and pre-render
+        // lines, so its rows can decay during vblank (unless they are accessed
+        // or PAL's forced refresh is active)
+        if self.dot == 0 && (self.is_rendering() || self.is_pal_oam_refresh()) {
+            self.refresh_oam();
+            if self.oam_corrupt_rows != 0 && self.is_rendering() {
+                self.process_oam_corruption();
+            }
+        }
+
+        if self.line_status == LineStatus::Visible
+            && self.rendering_enabled
+            && matches!(self.dot, 1..=256)
+        {
+            self.step_visible_rendering_dot(cartridge);
+        } else {
+            self.step_other_dot(cartridge);
+        }
+
+        #[cfg(feature = "ppu-hooks")]
+        {
+            if !self.debug.dot_hooks[self.line as usize][self.dot as usize]
+                .hooks
+                .is_empty()
+            {
+                let mut hooks = std::mem::take(
+                    &mut self.debug.dot_hooks[self.line as usize][self.dot as usize],
+                );
+                for hook in hooks.hooks.iter_mut() {
+                    (hook.func)(self, cartridge);
+                }
+                std::mem::swap(
+                    &mut self.debug.dot_hooks[self.line as usize][self.dot as usize],
+                    &mut hooks,
+                );
+            }
+        }
+    }
+
+    /// The background tile fetches for dots 1-256 and 321-336 of the visible
+    /// and pre-render lines while rendering is enabled
+    ///
+    /// The PPU reads one byte every two clocks
+    #[inline(always)]
+    fn fetch_background_tile_dot(&mut self, cartridge: &mut Cartridge) {
+        match self.dot % 8 {
+            0 => {
+                self.increment_coarse_x_scroll();
+                if self.dot == 256 {
+                    self.increment_fine_y_scroll();
+                }
+            }
+            1 => self.read_nametable_byte(cartridge),
+            3 => self.read_attribute_table_byte(cartridge),
+            5 => self.read_pattern_table_low_byte(cartridge),
+            7 => self.read_pattern_table_high_byte(cartridge),
+            _ => {}
+        }
+    }
+
+    /// Steps one of dots 1-256 of a visible line while rendering is enabled
+    ///
+    /// This is the bulk of the PPU's work and is shared by [`Self::step_line`]
+    /// and [`Self::render_deferred_dots`]
+    #[inline(always)]
+    fn step_visible_rendering_dot(&mut self, cartridge: &mut Cartridge) {
+        let dot = self.dot;
+        if dot >= 9 && dot % 8 == 1 {
+            // "The shifters are reloaded during ticks 9, 17, 25, ..., 257."
+            self.reload_shift_registers();
+        }
+
+        self.fetch_background_tile_dot(cartridge);
+
+        // Attempts to read OAM data via $2004 will return 0xff while secondary OAM is being cleared
+        match dot {
+            1 => {
+                self.secondary_oam_being_cleared = true;
+                self.clear_secondary_oam();
+
+                // also clear the intermediate buffer we use for composing sprite pixels while fetching
+                // sprite data later
+                std::mem::swap(&mut self.sprite_line_front, &mut self.sprite_line_back);
+                self.sprite_line_back = Default::default();
+            }
+            64 => {
+                self.secondary_oam_being_cleared = false;
+                self.start_sprite_evaluation();
+            }
+            // "Cycles 65-256: Sprite evaluation"
+            //
+            // "Sprite evaluation occurs if either the sprite layer or
+            // background layer is enabled via $2001. Unless both layers
+            // are disabled, it merely hides sprite rendering"
+            65..=256 => self.step_sprite_evaluation(),
+            _ => {}
+        }
+
+        self.render_pixel(dot as usize - 1, self.line as usize, cartridge);
+
+        if dot >= 2 {
+            self.shift_registers();
+        }
+    }
+
+    /// Steps any dot that isn't handled by [`Self::step_visible_rendering_dot`]
+    fn step_other_dot(&mut self, cartridge: &mut Cartridge) {
         if let LineStatus::Visible | LineStatus::PreRender = self.line_status {
             if let 2..=257 | 322..=337 = self.dot {
                 if self.dot % 8 == 1 {
@@ -2042,34 +2809,9 @@ impl Ppu {
                 }
             }
 
-            // PPU Reads one byte every two clocks
             if let 1..=256 | 321..=336 = self.dot {
                 if self.rendering_enabled {
-                    match self.dot % 8 {
-                        0 => {
-                            //self.draw_tile_span(cartridge, fb);
-                            self.increment_coarse_x_scroll();
-                            if self.dot == 256 {
-                                self.increment_fine_y_scroll();
-                            }
-                        }
-                        1 => {
-                            self.read_nametable_byte(cartridge);
-                        }
-                        2 => {}
-                        3 => {
-                            self.read_attribute_table_byte(cartridge);
-                        }
-                        4 => {}
-                        5 => {
-                            self.read_pattern_table_low_byte(cartridge);
-                        }
-                        6 => {}
-                        7 => {
-                            self.read_pattern_table_high_byte(cartridge);
-                        }
-                        _ => unreachable!(),
-                    }
+                    self.fetch_background_tile_dot(cartridge);
                 }
             } else if let 257..=320 = self.dot {
                 if self.rendering_enabled {
@@ -2092,7 +2834,15 @@ impl Ppu {
 
                     self.sprite_fetch_index = (self.dot as usize - 257) / 8;
                     match self.dot % 8 {
-                        0 => {}
+                        0 => {
+                            if self.dot == 320
+                                && self.sprite_limit_disabled
+                                && self.line != 239
+                                && self.line_status != LineStatus::PreRender
+                            {
+                                self.compose_extra_sprites(cartridge);
+                            }
+                        }
                         1 => {
                             // These are a continuation of the nametable reads that happen between 1..=256 | 321..=336 that
                             // are redundant except that mappers may depend on observing them for synchronization
@@ -2127,7 +2877,7 @@ impl Ppu {
                             // Note: we currently rely on this optimization to leave the sprite_line_back buffer
                             // clear on lin 239, so that the next time the buffers are swapped at the start of the
                             // next frame then line zero will have a clear sprite_line_front buffer.
-                            if self.line != 239 && self.line != 261 {
+                            if self.line != 239 && self.line_status != LineStatus::PreRender {
                                 self.compose_sprite();
                             }
                         }
@@ -2149,7 +2899,13 @@ impl Ppu {
                     // We skip from 339 to 340 so we don't need special case logic
                     // to progress the line counter (this is still after the
                     // nametable read)
-                    if self.frame & 1 != 0 && self.line == 261 && self.dot == 339 {
+                    //
+                    // The PAL and Dendy PPUs don't skip any dots
+                    if self.nes_model == Model::Ntsc
+                        && self.frame & 1 != 0
+                        && self.line_status == LineStatus::PreRender
+                        && self.dot == 339
+                    {
                         self.dot = 340;
                     }
                 }
@@ -2158,33 +2914,7 @@ impl Ppu {
 
         match self.line_status {
             LineStatus::Visible => {
-                if self.rendering_enabled {
-                    // Attempts to read OAM data via $2004 will return 0xff while secondary OAM is being cleared
-                    if self.dot == 1 {
-                        self.secondary_oam_being_cleared = true;
-                        self.clear_secondary_oam();
-
-                        // also clear the intermediate buffer we use for composing sprite pixels while fetching
-                        // sprite data later
-                        //println!("Swapping sprite line buffers");
-                        std::mem::swap(&mut self.sprite_line_front, &mut self.sprite_line_back);
-                        //println!("Clearing sprite line back buffer");
-                        self.sprite_line_back = Default::default();
-                    } else if self.dot == 64 {
-                        self.secondary_oam_being_cleared = false;
-                        self.start_sprite_evaluation();
-                    } else if let 65..=256 = self.dot {
-                        // "Cycles 65-256: Sprite evaluation"
-
-                        // "Sprite evaluation occurs if either the sprite layer or
-                        // background layer is enabled via $2001. Unless both layers
-                        // are disabled, it merely hides sprite rendering"
-                        if self.rendering_enabled {
-                            self.step_sprite_evaluation();
-                        }
-                    }
-                }
-
+                // (with rendering enabled these dots are handled by step_visible_rendering_dot)
                 if let 1..=256 = self.dot {
                     let screen_x = self.dot as usize - 1;
                     let screen_y = self.line as usize;
@@ -2198,13 +2928,13 @@ impl Ppu {
             }
             LineStatus::PostRender => {
                 // TODO: remove redundant enum value
-                if self.dot == 340 {
+                if self.line == 240 && self.dot == 340 {
                     //println!("PPU: Finished Frame");
                     self.frame_ready = true;
                 }
             }
             LineStatus::VerticalBlanking => {
-                if self.line == 241 && self.dot == 1 {
+                if self.line == self.vblank_line() && self.dot == 1 {
                     //println!("IN VBLANK {}", self.clock);
                     self.status.set(StatusFlags::IN_VBLANK, true);
                     //println!("Set IN_VBLANK flag");
@@ -2265,25 +2995,6 @@ impl Ppu {
                 self.shift_registers();
             }
         }
-
-        #[cfg(feature = "ppu-hooks")]
-        {
-            if !self.debug.dot_hooks[self.line as usize][self.dot as usize]
-                .hooks
-                .is_empty()
-            {
-                let mut hooks = std::mem::take(
-                    &mut self.debug.dot_hooks[self.line as usize][self.dot as usize],
-                );
-                for hook in hooks.hooks.iter_mut() {
-                    (hook.func)(self, cartridge);
-                }
-                std::mem::swap(
-                    &mut self.debug.dot_hooks[self.line as usize][self.dot as usize],
-                    &mut hooks,
-                );
-            }
-        }
     }
 
     pub fn step(&mut self, cartridge: &mut Cartridge) -> bool {
@@ -2333,6 +3044,17 @@ impl Ppu {
             }
         }
 
+        if self.deferred_dots > 0 || (self.dot == 1 && self.can_defer_visible_dots(cartridge)) {
+            self.deferred_dots += 1;
+            self.clock += 1;
+            self.dot += 1;
+            if self.dot == 257 {
+                self.render_deferred_dots(cartridge);
+                self.fast_renderer_stats.batched_lines += 1;
+            }
+            return true;
+        }
+
         self.step_line(cartridge);
 
         self.clock += 1;
@@ -2342,40 +3064,125 @@ impl Ppu {
         self.dot = (self.dot + 1) % 341;
 
         if self.dot == 0 {
-            self.line = (self.line + 1) % N_LINES;
-            self.line_status = LineStatus::from(self.line);
+            self.line = (self.line + 1) % self.n_lines();
+            self.line_status =
+                LineStatus::from(self.line, self.vblank_line(), self.pre_render_line());
             //println!("Next line = {}: {:?}", self.line, self.line_status);
 
             if self.line == 0 {
                 self.frame += 1;
+
+                #[cfg(feature = "debugger")]
+                if self.debug.register_writes.enabled {
+                    let log = &mut self.debug.register_writes;
+                    std::mem::swap(&mut log.current, &mut log.prev);
+                    log.current.clear();
+                }
             }
         }
 
-        self.decay_io_latch();
+        if self.clock >= self.io_latch_next_decay_clock {
+            self.decay_io_latch();
+        }
 
         true
     }
 
-    #[inline(always)]
-    fn decay_io_latch(&mut self) {
-        if self.clock - self.io_latch_last_update_clock >= (self.io_latch_decay_clock_period as u64)
+    /// Whether dots 1-256 of the current line can be deferred and rendered in
+    /// one batch, without anything being able to observe the difference
+    fn can_defer_visible_dots(&self, cartridge: &Cartridge) -> bool {
+        if !self.fast_renderer_enabled
+            || self.line_status != LineStatus::Visible
+            || !self.rendering_enabled
+            || cartridge.observes_ppu_fetches()
         {
-            let keep = self.io_latch_keep_alive_masks[0] | self.io_latch_keep_alive_masks[1];
-            self.io_latch_value &= keep;
-            self.io_latch_keep_alive_masks[1] = self.io_latch_keep_alive_masks[0];
-            self.io_latch_keep_alive_masks[0] = 0;
-            self.io_latch_last_update_clock = self.clock;
+            return false;
         }
+
+        #[cfg(feature = "debugger")]
+        if !self.debug.breakpoints.is_empty() {
+            return false;
+        }
+        #[cfg(feature = "ppu-hooks")]
+        if self.debug.n_dot_hooks > 0 {
+            return false;
+        }
+
+        // The PPU simulator is compared against us for every dot
+        !cfg!(feature = "ppu-sim")
+    }
+
+    /// Catch up with any dots that were deferred by the fast renderer
+    ///
+    /// This must be called before anything can observe or modify the PPU's
+    /// rendering state part way through a line (see
+    /// [`Self::set_fast_renderer_enabled`])
+    pub(crate) fn render_deferred_dots(&mut self, cartridge: &mut Cartridge) {
+        if self.deferred_dots == 0 {
+            return;
+        }
+
+        let end_dot = self.dot;
+        self.dot -= self.deferred_dots;
+        self.clock -= self.deferred_dots as u64;
+        self.deferred_dots = 0;
+        if end_dot != 257 {
+            self.fast_renderer_stats.interrupted_lines += 1;
+        }
+
+        while self.dot < end_dot {
+            self.step_visible_rendering_dot(cartridge);
+
+            self.clock += 1;
+            self.dot += 1;
+
+            if self.clock >= self.io_latch_next_decay_clock {
+                self.decay_io_latch();
+            }
+        }
+    }
+
+    fn refresh_io_latch_bits(&mut self, mask: u8) {
+        for bit in 0..8 {
+            if mask & (1 << bit) != 0 {
+                self.io_latch_refresh_clocks[bit] = self.clock;
+            }
+        }
+        self.update_io_latch_next_decay_clock();
+    }
+
+    fn update_io_latch_next_decay_clock(&mut self) {
+        let period = self.io_latch_decay_clock_period as u64;
+        self.io_latch_next_decay_clock = (0..8)
+            .filter(|bit| self.io_latch_value & (1 << bit) != 0)
+            .map(|bit| self.io_latch_refresh_clocks[bit] + period)
+            .min()
+            .unwrap_or(u64::MAX);
+    }
+
+    /// Decays any bits of the IO latch that haven't been refreshed recently enough
+    fn decay_io_latch(&mut self) {
+        let period = self.io_latch_decay_clock_period as u64;
+        for bit in 0..8 {
+            if self.clock - self.io_latch_refresh_clocks[bit] >= period {
+                self.io_latch_value &= !(1 << bit);
+            }
+        }
+        self.update_io_latch_next_decay_clock();
     }
 
     #[cfg(feature = "ppu-hooks")]
     pub fn add_dot_hook(&mut self, line: usize, dot: usize, func: Box<FnDotHook>) -> HookHandle {
+        self.debug.n_dot_hooks += 1;
         self.debug.dot_hooks[line][dot].add_hook(func)
     }
 
     #[cfg(feature = "ppu-hooks")]
     pub fn remove_dot_hook(&mut self, line: usize, dot: usize, handle: HookHandle) {
-        self.debug.dot_hooks[line][dot].remove_hook(handle);
+        let hooks = &mut self.debug.dot_hooks[line][dot];
+        let len = hooks.hooks.len();
+        hooks.remove_hook(handle);
+        self.debug.n_dot_hooks -= len - hooks.hooks.len();
     }
 
     /// Add a hook function into the background priority MUX operation
@@ -2416,6 +3223,93 @@ impl Ppu {
         handle
     }
 
+    /// Enables recording all writes to $2000, $2001, $2005 and $2006 with the
+    /// line and dot they land on, and flagging any that are known to glitch
+    ///
+    /// Writes are collected per frame, starting from line 0 (see
+    /// [`Self::register_writes`])
+    #[cfg(feature = "debugger")]
+    pub fn set_register_write_log_enabled(&mut self, enabled: bool) {
+        let log = &mut self.debug.register_writes;
+        log.enabled = enabled;
+        if !enabled {
+            log.current.clear();
+            log.prev.clear();
+        }
+    }
+
+    #[cfg(feature = "debugger")]
+    pub fn register_write_log_enabled(&self) -> bool {
+        self.debug.register_writes.enabled
+    }
+
+    /// The register writes logged for the last complete frame
+    #[cfg(feature = "debugger")]
+    pub fn register_writes(&self) -> &[RegisterWrite] {
+        &self.debug.register_writes.prev
+    }
+
+    /// The register writes logged so far for the current frame
+    #[cfg(feature = "debugger")]
+    pub fn register_writes_in_progress(&self) -> &[RegisterWrite] {
+        &self.debug.register_writes.current
+    }
+
+    #[cfg(feature = "debugger")]
+    fn log_register_write(&mut self, addr: u16, data: u8) {
+        let write = RegisterWrite {
+            frame: self.frame,
+            line: self.line,
+            dot: self.dot,
+            addr,
+            value: data,
+            shared_v_register: self.shared_v_register,
+            fine_x_scroll: self.scroll_x_fine3,
+            glitch: self.register_write_glitch(addr, data),
+        };
+        self.debug.register_writes.current.push(write);
+    }
+
+    /// Determines whether a write that's about to be made will glitch, based
+    /// on the current line and dot
+    #[cfg(feature = "debugger")]
+    fn register_write_glitch(&self, addr: u16, data: u8) -> Option<RegisterWriteGlitch> {
+        if !self.is_rendering() {
+            return None;
+        }
+        let visible = self.line_status == LineStatus::Visible;
+        let visible_dot = matches!(self.dot, 1..=256);
+
+        match addr {
+            0x2000 if self.dot == 257 => Some(RegisterWriteGlitch::CtrlWriteAtDot257),
+            0x2001 => {
+                let disables_rendering =
+                    data & (Control2Flags::SHOW_BG | Control2Flags::SHOW_SPRITES).bits() == 0;
+                (disables_rendering && matches!(self.dot, 0..=63 | 256..=319))
+                    .then_some(RegisterWriteGlitch::OamCorruption)
+            }
+            0x2005 if !self.shared_w_toggle => {
+                (visible && visible_dot).then_some(RegisterWriteGlitch::MidScanlineHorizontalScroll)
+            }
+            0x2005 => {
+                // v's vertical bits are only copied from t during dots 280-304
+                // of the pre-render line
+                (visible || self.dot > 304).then_some(RegisterWriteGlitch::VerticalScrollDeferred)
+            }
+            0x2006 if self.shared_w_toggle => {
+                let fetch_dot = matches!(self.dot, 1..=256 | 321..=336);
+                if (fetch_dot && self.dot & 0b111 == 0) || self.dot == 257 {
+                    Some(RegisterWriteGlitch::AddressIncrementConflict)
+                } else if visible && visible_dot {
+                    Some(RegisterWriteGlitch::MidScanlineAddress)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
     #[cfg(feature = "debugger")]
     pub fn remove_dot_breakpoint(&mut self, handle: DotBreakpointHandle) {
         if let Some(i) = self
@@ -2476,3 +3370,211 @@ fn ppu_scroll_y_increment() {
     }
 }
 */
+
+#[cfg(test)]
+fn step_ppu_clocks(ppu: &mut Ppu, cartridge: &mut Cartridge, clocks: u64) {
+    for _ in 0..clocks {
+        ppu.step(cartridge);
+    }
+}
+
+#[cfg(test)]
+fn step_ppu_to(ppu: &mut Ppu, cartridge: &mut Cartridge, line: u16, dot: u16) {
+    while ppu.line != line || ppu.dot != dot {
+        ppu.step(cartridge);
+    }
+}
+
+#[test]
+fn test_io_latch_decay() {
+    let mut cartridge = Cartridge::none();
+    let mut ppu = Ppu::new(Model::Ntsc);
+    let ms = |ms: u64| 5369318 * ms / 1000;
+
+    // OAMADDR is write-only so reads see the whole latch
+    ppu.system_bus_write(&mut cartridge, 0x2003, 0xff);
+    assert_eq!(ppu.system_bus_peek(&mut cartridge, 0x2003).0, 0xff);
+
+    // Reading PPUSTATUS only refreshes the top three bits, which it drives
+    step_ppu_clocks(&mut ppu, &mut cartridge, ms(300));
+    assert_eq!(ppu.system_bus_peek(&mut cartridge, 0x2003).0, 0xff);
+    let status = ppu.system_bus_read(&mut cartridge, 0x2002).0;
+    step_ppu_clocks(&mut ppu, &mut cartridge, ms(400));
+    assert_eq!(
+        ppu.system_bus_peek(&mut cartridge, 0x2003).0,
+        status & 0b1110_0000
+    );
+
+    step_ppu_clocks(&mut ppu, &mut cartridge, ms(400));
+    assert_eq!(ppu.system_bus_peek(&mut cartridge, 0x2003).0, 0);
+}
+
+#[test]
+fn test_oam_decay_and_corruption() {
+    let mut cartridge = Cartridge::none();
+    let mut ppu = Ppu::new(Model::Ntsc);
+    let ms = |ms: u64| 5369318 * ms / 1000;
+    let write_oam = |ppu: &mut Ppu, cartridge: &mut Cartridge, offset: u8, data: &[u8]| {
+        ppu.system_bus_write(cartridge, 0x2003, offset);
+        for byte in data {
+            ppu.system_bus_write(cartridge, 0x2004, *byte);
+        }
+    };
+    let read_oam = |ppu: &mut Ppu, cartridge: &mut Cartridge, offset: u8| {
+        ppu.system_bus_write(cartridge, 0x2003, offset);
+        ppu.system_bus_read(cartridge, 0x2004).0
+    };
+    let row0 = [0x10, 0x11, 0x02, 0x13, 0x14, 0x15, 0x02, 0x17];
+
+    // With rendering disabled, rows decay unless they are accessed
+    write_oam(&mut ppu, &mut cartridge, 0, &row0);
+    step_ppu_clocks(&mut ppu, &mut cartridge, ms(1));
+    assert_eq!(read_oam(&mut ppu, &mut cartridge, 0), 0x10);
+    step_ppu_clocks(&mut ppu, &mut cartridge, ms(1) + ms(1) / 2);
+    assert_eq!(read_oam(&mut ppu, &mut cartridge, 1), 0x11);
+    assert_eq!(read_oam(&mut ppu, &mut cartridge, 8), 0xff);
+    step_ppu_clocks(&mut ppu, &mut cartridge, ms(3));
+    assert_eq!(read_oam(&mut ppu, &mut cartridge, 1), 0xff);
+
+    // Rendering keeps all of OAM refreshed
+    step_ppu_to(&mut ppu, &mut cartridge, 241, 10);
+    write_oam(&mut ppu, &mut cartridge, 0, &row0);
+    write_oam(&mut ppu, &mut cartridge, 40, &[0x50; 8]);
+    // (Avoiding OAM corruption from starting rendering with OAMADDR >= 8)
+    ppu.system_bus_write(&mut cartridge, 0x2003, 0);
+    ppu.system_bus_write(&mut cartridge, 0x2001, 0x18);
+    step_ppu_clocks(&mut ppu, &mut cartridge, ms(10));
+    step_ppu_to(&mut ppu, &mut cartridge, 241, 10);
+    ppu.system_bus_write(&mut cartridge, 0x2001, 0);
+    assert_eq!(read_oam(&mut ppu, &mut cartridge, 0), 0x10);
+    assert_eq!(read_oam(&mut ppu, &mut cartridge, 40), 0x50);
+
+    // Disabling rendering while secondary OAM is being cleared corrupts the
+    // row being accessed once rendering starts again
+    ppu.system_bus_write(&mut cartridge, 0x2003, 0);
+    ppu.system_bus_write(&mut cartridge, 0x2001, 0x18);
+    step_ppu_to(&mut ppu, &mut cartridge, 10, 10);
+    ppu.system_bus_write(&mut cartridge, 0x2001, 0);
+    step_ppu_to(&mut ppu, &mut cartridge, 11, 100);
+    assert_eq!(ppu.oam[40], 0x50);
+    ppu.system_bus_write(&mut cartridge, 0x2001, 0x18);
+    step_ppu_to(&mut ppu, &mut cartridge, 12, 1);
+    assert_eq!(&ppu.oam.0[40..48], &row0);
+}
+
+#[test]
+fn test_oam_decay_in_long_vblank() {
+    let upload_oam = |model: Model| {
+        let mut cartridge = Cartridge::none();
+        let mut ppu = Ppu::new(model);
+
+        step_ppu_to(&mut ppu, &mut cartridge, 241, 10);
+        ppu.system_bus_write(&mut cartridge, 0x2003, 0);
+        for i in 0..=255u8 {
+            ppu.system_bus_write(&mut cartridge, 0x2004, i & 0xe3);
+        }
+        ppu.system_bus_write(&mut cartridge, 0x2003, 0);
+        (ppu, cartridge)
+    };
+
+    // PAL's vblank is longer than the OAM decay period but its forced OAM
+    // refresh means that OAM survives across frames while rendering is enabled
+    let (mut ppu, mut cartridge) = upload_oam(Model::Pal);
+    let oam = ppu.oam.0;
+    ppu.system_bus_write(&mut cartridge, 0x2001, 0x18);
+    for _ in 0..5 {
+        step_ppu_clocks(&mut ppu, &mut cartridge, 312 * 341);
+        assert_eq!(ppu.oam.0, oam);
+    }
+
+    // Dendy has no forced refresh, so OAM decays over its long post-render
+    // period, even with rendering enabled
+    let (mut ppu, mut cartridge) = upload_oam(Model::Dendy);
+    ppu.system_bus_write(&mut cartridge, 0x2001, 0x18);
+    step_ppu_clocks(&mut ppu, &mut cartridge, 312 * 341);
+    ppu.system_bus_write(&mut cartridge, 0x2003, 0);
+    assert_eq!(ppu.system_bus_read(&mut cartridge, 0x2004).0, 0xff);
+
+    // OAM always decays if rendering stays disabled for longer than the
+    // forced refresh can cover
+    for model in [Model::Ntsc, Model::Pal, Model::Dendy] {
+        let (mut ppu, mut cartridge) = upload_oam(model);
+        step_ppu_clocks(&mut ppu, &mut cartridge, 312 * 341);
+        ppu.system_bus_write(&mut cartridge, 0x2003, 0);
+        assert_eq!(
+            ppu.system_bus_read(&mut cartridge, 0x2004).0,
+            0xff,
+            "{model:?}"
+        );
+    }
+}
+
+#[cfg(feature = "debugger")]
+#[test]
+fn test_register_write_log() {
+    let mut cartridge = Cartridge::none();
+    let mut ppu = Ppu::new(Model::Ntsc);
+    ppu.set_register_write_log_enabled(true);
+
+    step_ppu_to(&mut ppu, &mut cartridge, 241, 10);
+    ppu.system_bus_write(&mut cartridge, 0x2001, 0x18);
+
+    step_ppu_to(&mut ppu, &mut cartridge, 20, 100);
+    ppu.system_bus_write(&mut cartridge, 0x2005, 0x10);
+    ppu.system_bus_write(&mut cartridge, 0x2005, 0x20);
+    step_ppu_to(&mut ppu, &mut cartridge, 21, 260);
+    ppu.system_bus_write(&mut cartridge, 0x2006, 0x00);
+    ppu.system_bus_write(&mut cartridge, 0x2006, 0x40);
+    step_ppu_to(&mut ppu, &mut cartridge, 22, 257);
+    ppu.system_bus_write(&mut cartridge, 0x2000, 0x01);
+    step_ppu_to(&mut ppu, &mut cartridge, 23, 24);
+    ppu.system_bus_write(&mut cartridge, 0x2006, 0x00);
+    ppu.system_bus_write(&mut cartridge, 0x2006, 0x40);
+    // Not a register that affects rendering
+    ppu.system_bus_write(&mut cartridge, 0x2003, 0x00);
+    assert_eq!(ppu.register_writes_in_progress().len(), 7);
+    // Writes are logged per frame, starting from line 0
+    assert_eq!(ppu.register_writes().len(), 1);
+    assert_eq!(ppu.register_writes()[0].addr, 0x2001);
+
+    step_ppu_to(&mut ppu, &mut cartridge, 0, 1);
+    let writes: Vec<_> = ppu
+        .register_writes()
+        .iter()
+        .map(|write| (write.line, write.dot, write.addr, write.glitch))
+        .collect();
+    assert_eq!(
+        writes,
+        [
+            (
+                20,
+                100,
+                0x2005,
+                Some(RegisterWriteGlitch::MidScanlineHorizontalScroll)
+            ),
+            (
+                20,
+                100,
+                0x2005,
+                Some(RegisterWriteGlitch::VerticalScrollDeferred)
+            ),
+            (21, 260, 0x2006, None),
+            (21, 260, 0x2006, None),
+            (
+                22,
+                257,
+                0x2000,
+                Some(RegisterWriteGlitch::CtrlWriteAtDot257)
+            ),
+            (23, 24, 0x2006, None),
+            (
+                23,
+                24,
+                0x2006,
+                Some(RegisterWriteGlitch::AddressIncrementConflict)
+            ),
+        ]
+    );
+    assert!(ppu.register_writes_in_progress().is_empty());
+    assert!(ppu.register_writes()[0].nametable_position().is_some());
+}
@@ -1,4 +1,8 @@
-use crate::color::Color32;
+use anyhow::anyhow;
+use anyhow::Result;
+
+use crate::color::{rgb_from_yiq, Color32};
+use crate::vs_system::VsPpu;
 
 const RGB_PALETTE: [Color32; 64] = [
     Color32::from_rgb(84, 84, 84),
@@ -67,7 +71,366 @@ const RGB_PALETTE: [Color32; 64] = [
     Color32::from_rgb(0, 0, 0),
 ];
 
+/// The number of entries in a palette that has a copy of the 64 base colors
+/// for each combination of the three color emphasis bits
+pub const EMPHASIS_PALETTE_SIZE: usize = 512;
+
+/// Emphasis bits, as used to index into a palette generated by
+/// [`generate_emphasis_palette`]
+///
+/// Note: these are in terms of the RGB output and don't necessarily match the
+/// bit order of the Control2 register, since the red and green bits are swapped
+/// for PAL.
+pub const EMPHASIS_RED: u8 = 0b001;
+pub const EMPHASIS_GREEN: u8 = 0b010;
+pub const EMPHASIS_BLUE: u8 = 0b100;
+
+/// The attenuation applied to the color channels that aren't emphasized
+///
+/// This approximates the measured voltage drop of the composite signal while
+/// an emphasis bit is active.
+const EMPHASIS_ATTENUATION: f32 = 0.816328;
+
 pub fn rgb_lut(nes_color: u8) -> Color32 {
     let index = nes_color & 0x3f;
     RGB_PALETTE[index as usize]
 }
+
+/// Generates a 512-entry palette from a 64-entry `base` palette with a copy of the
+/// base colors for each combination of emphasis bits, indexed by
+/// `(emphasis << 6) | nes_color` (see [`EMPHASIS_RED`], [`EMPHASIS_GREEN`] and [`EMPHASIS_BLUE`])
+pub fn generate_emphasis_palette(base: &[Color32; 64]) -> Vec<Color32> {
+    let mut palette = Vec::with_capacity(EMPHASIS_PALETTE_SIZE);
+    for emphasis in 0..8u8 {
+        for color in base.iter() {
+            let mut rgb = [color.r() as f32, color.g() as f32, color.b() as f32];
+            for (channel, bit) in [EMPHASIS_RED, EMPHASIS_GREEN, EMPHASIS_BLUE]
+                .iter()
+                .enumerate()
+            {
+                if emphasis & bit != 0 {
+                    for (other, value) in rgb.iter_mut().enumerate() {
+                        if other != channel {
+                            *value *= EMPHASIS_ATTENUATION;
+                        }
+                    }
+                }
+            }
+            palette.push(Color32::from_rgb(
+                rgb[0].round() as u8,
+                rgb[1].round() as u8,
+                rgb[2].round() as u8,
+            ));
+        }
+    }
+    palette
+}
+
+/// The default palette, including all emphasis combinations
+pub fn default_emphasis_palette() -> Vec<Color32> {
+    generate_emphasis_palette(&RGB_PALETTE)
+}
+
+/// The palette of the RGB PPUs (2C03, 2C04 and 2C05) used in Vs. System and
+/// PlayChoice-10 arcade machines, with 3 bits per channel (as octal RGB triplets)
+///
+/// ref: https://www.nesdev.org/wiki/PPU_palettes#2C03_and_2C05
+#[rustfmt::skip]
+const RGB_PPU_PALETTE: [u16; 64] = [
+    0o333, 0o014, 0o006, 0o326, 0o403, 0o503, 0o510, 0o420, 0o320, 0o120, 0o031, 0o040, 0o022, 0o000, 0o000, 0o000,
+    0o555, 0o036, 0o027, 0o407, 0o507, 0o704, 0o700, 0o630, 0o430, 0o140, 0o040, 0o053, 0o044, 0o000, 0o000, 0o000,
+    0o777, 0o357, 0o447, 0o637, 0o707, 0o737, 0o740, 0o750, 0o660, 0o360, 0o070, 0o276, 0o077, 0o000, 0o000, 0o000,
+    0o777, 0o567, 0o657, 0o757, 0o747, 0o755, 0o764, 0o772, 0o773, 0o572, 0o473, 0o276, 0o467, 0o000, 0o000, 0o000,
+];
+
+#[rustfmt::skip]
+const RP2C04_0001_LUT: [u8; 64] = [
+    0x35, 0x23, 0x16, 0x22, 0x1c, 0x09, 0x1d, 0x15, 0x20, 0x00, 0x27, 0x05, 0x04, 0x28, 0x08, 0x20,
+    0x21, 0x3e, 0x1f, 0x29, 0x3c, 0x32, 0x36, 0x12, 0x3f, 0x2b, 0x2e, 0x1e, 0x3d, 0x2d, 0x24, 0x01,
+    0x0e, 0x31, 0x33, 0x2a, 0x2c, 0x0c, 0x1b, 0x14, 0x2e, 0x07, 0x34, 0x06, 0x13, 0x02, 0x26, 0x2e,
+    0x2e, 0x19, 0x10, 0x0a, 0x39, 0x03, 0x37, 0x17, 0x0f, 0x11, 0x0b, 0x0d, 0x38, 0x25, 0x18, 0x3a,
+];
+
+#[rustfmt::skip]
+const RP2C04_0002_LUT: [u8; 64] = [
+    0x2e, 0x27, 0x18, 0x39, 0x3a, 0x25, 0x1c, 0x31, 0x16, 0x13, 0x38, 0x34, 0x20, 0x23, 0x3c, 0x0b,
+    0x0f, 0x21, 0x06, 0x3d, 0x1b, 0x29, 0x1e, 0x22, 0x1d, 0x24, 0x0e, 0x2b, 0x32, 0x08, 0x2e, 0x03,
+    0x04, 0x36, 0x26, 0x33, 0x11, 0x1f, 0x10, 0x02, 0x14, 0x3f, 0x00, 0x09, 0x12, 0x2e, 0x28, 0x20,
+    0x3e, 0x0d, 0x2a, 0x17, 0x0c, 0x01, 0x15, 0x19, 0x2e, 0x2c, 0x07, 0x37, 0x35, 0x05, 0x0a, 0x2d,
+];
+
+#[rustfmt::skip]
+const RP2C04_0003_LUT: [u8; 64] = [
+    0x14, 0x25, 0x3a, 0x10, 0x0b, 0x20, 0x31, 0x09, 0x01, 0x2e, 0x36, 0x08, 0x15, 0x3d, 0x3e, 0x3c,
+    0x22, 0x1c, 0x05, 0x12, 0x19, 0x18, 0x17, 0x1b, 0x00, 0x03, 0x2e, 0x02, 0x16, 0x06, 0x34, 0x35,
+    0x23, 0x0f, 0x0e, 0x37, 0x0d, 0x27, 0x26, 0x20, 0x29, 0x04, 0x21, 0x24, 0x11, 0x2d, 0x2e, 0x1f,
+    0x2c, 0x1e, 0x39, 0x33, 0x07, 0x2a, 0x28, 0x1d, 0x0a, 0x2e, 0x32, 0x38, 0x13, 0x2b, 0x3f, 0x0c,
+];
+
+#[rustfmt::skip]
+const RP2C04_0004_LUT: [u8; 64] = [
+    0x18, 0x03, 0x1c, 0x28, 0x2e, 0x35, 0x01, 0x17, 0x10, 0x1f, 0x2a, 0x0e, 0x36, 0x37, 0x0b, 0x39,
+    0x25, 0x1e, 0x12, 0x34, 0x2e, 0x1d, 0x06, 0x26, 0x3e, 0x1b, 0x22, 0x19, 0x04, 0x2e, 0x3a, 0x21,
+    0x05, 0x0a, 0x07, 0x02, 0x13, 0x14, 0x00, 0x15, 0x0c, 0x3d, 0x11, 0x0f, 0x0d, 0x38, 0x2d, 0x24,
+    0x33, 0x20, 0x08, 0x16, 0x3f, 0x2b, 0x20, 0x3c, 0x2e, 0x27, 0x23, 0x31, 0x29, 0x32, 0x2c, 0x09,
+];
+
+/// Generates the palette for a Vs. System PPU, including all emphasis combinations
+///
+/// The 2C04 PPUs have the same set of colors as the 2C03 but each revision
+/// scrambles their order, so games only look right with the PPU they were
+/// made for.
+///
+/// Instead of darkening the other channels, the emphasis bits of the RGB PPUs
+/// drive their corresponding channel at full intensity.
+pub fn generate_vs_palette(ppu: VsPpu) -> Vec<Color32> {
+    let lut = match ppu {
+        VsPpu::RP2C04_0001 => Some(&RP2C04_0001_LUT),
+        VsPpu::RP2C04_0002 => Some(&RP2C04_0002_LUT),
+        VsPpu::RP2C04_0003 => Some(&RP2C04_0003_LUT),
+        VsPpu::RP2C04_0004 => Some(&RP2C04_0004_LUT),
+        _ => None,
+    };
+    let channel = |rgb: u16, shift: u16| -> u8 {
+        let level = (rgb >> shift) & 0o7;
+        ((level * 255 + 3) / 7) as u8
+    };
+
+    let mut palette = Vec::with_capacity(EMPHASIS_PALETTE_SIZE);
+    for emphasis in 0..8u8 {
+        for index in 0..64 {
+            let color = match lut {
+                Some(lut) => lut[index],
+                None => index as u8,
+            };
+            let rgb = RGB_PPU_PALETTE[color as usize];
+            let mut rgb = [channel(rgb, 6), channel(rgb, 3), channel(rgb, 0)];
+            for (channel, bit) in [EMPHASIS_RED, EMPHASIS_GREEN, EMPHASIS_BLUE]
+                .iter()
+                .enumerate()
+            {
+                if emphasis & bit != 0 {
+                    rgb[channel] = 255;
+                }
+            }
+            palette.push(Color32::from_rgb(rgb[0], rgb[1], rgb[2]));
+        }
+    }
+    palette
+}
+
+/// Parses a `.pal` palette file, either with 64 RGB colors (192 bytes) or with
+/// 512 RGB colors (1536 bytes) that include all emphasis combinations
+///
+/// Returns a palette with all emphasis combinations (see [`generate_emphasis_palette`])
+pub fn parse_pal_file(data: &[u8]) -> Result<Vec<Color32>> {
+    let colors: Vec<Color32> = data
+        .chunks_exact(3)
+        .map(|rgb| Color32::from_rgb(rgb[0], rgb[1], rgb[2]))
+        .collect();
+    match data.len() {
+        192 => {
+            let base: [Color32; 64] = colors.try_into().unwrap();
+            Ok(generate_emphasis_palette(&base))
+        }
+        1536 => Ok(colors),
+        len => Err(anyhow!(
+            "Unsupported .pal file size of {len} bytes (expected 192 or 1536 bytes)"
+        )),
+    }
+}
+
+/// Serializes a palette in the `.pal` file format, with three bytes per color
+///
+/// Saving a palette with all emphasis combinations will result in a 1536 byte file.
+pub fn pal_file_from_palette(palette: &[Color32]) -> Vec<u8> {
+    palette
+        .iter()
+        .flat_map(|color| [color.r(), color.g(), color.b()])
+        .collect()
+}
+
+/// Parameters for [`generate_ntsc_palette`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PaletteGeneratorParams {
+    /// Hue rotation, in degrees
+    pub hue: f32,
+    pub saturation: f32,
+    pub contrast: f32,
+    pub brightness: f32,
+
+    /// The gamma of the generated signal that will be corrected for an sRGB display
+    /// (2.2 means that no correction is applied)
+    pub gamma: f32,
+}
+
+impl Default for PaletteGeneratorParams {
+    fn default() -> Self {
+        Self {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            gamma: 2.2,
+        }
+    }
+}
+
+/// Composite signal voltages for the low and high levels of each luma value
+/// ref: https://www.nesdev.org/wiki/NTSC_video
+const SIGNAL_LOW: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+const SIGNAL_HIGH: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+const SIGNAL_BLACK: f32 = 0.518;
+const SIGNAL_WHITE: f32 = 1.962;
+const SIGNAL_EMPHASIS_ATTENUATION: f32 = 0.746;
+
+/// The phase offset (in units of the 12 phases of the color subcarrier) between
+/// the PPU's hue and the I axis of the YIQ color space
+const SIGNAL_HUE_PHASE: f32 = 3.9;
+
+/// Samples the composite signal the PPU generates for a given palette entry at
+/// one of the 12 phases of the color subcarrier
+fn ntsc_signal(nes_color: u8, emphasis: u8, phase: u32) -> f32 {
+    let hue = (nes_color & 0x0f) as u32;
+    let luma = ((nes_color >> 4) & 0x3) as usize;
+
+    let in_color_phase = |hue: u32| (hue + phase) % 12 < 6;
+
+    let (low, high) = match hue {
+        0 => (SIGNAL_HIGH[luma], SIGNAL_HIGH[luma]),
+        // $xE and $xF are forced to black
+        0xe | 0xf => (SIGNAL_LOW[1], SIGNAL_LOW[1]),
+        0xd => (SIGNAL_LOW[luma], SIGNAL_LOW[luma]),
+        _ => (SIGNAL_LOW[luma], SIGNAL_HIGH[luma]),
+    };
+    let mut signal = if in_color_phase(hue) { high } else { low };
+
+    if (emphasis & EMPHASIS_RED != 0 && in_color_phase(0))
+        || (emphasis & EMPHASIS_GREEN != 0 && in_color_phase(4))
+        || (emphasis & EMPHASIS_BLUE != 0 && in_color_phase(8))
+    {
+        signal *= SIGNAL_EMPHASIS_ATTENUATION;
+    }
+
+    signal
+}
+
+/// The composite signal level for a palette entry, normalized so that black is
+/// 0.0 and white is 1.0
+pub(crate) fn ntsc_signal_level(nes_color: u8, emphasis: u8, phase: u32) -> f32 {
+    (ntsc_signal(nes_color, emphasis, phase) - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK)
+}
+
+/// The (cos, sin) of the color subcarrier at each of its 12 phases, used to
+/// demodulate the I and Q components of the signal
+pub(crate) fn ntsc_carrier(params: &PaletteGeneratorParams) -> [(f32, f32); 12] {
+    let hue_offset = SIGNAL_HUE_PHASE + params.hue / 30.0; // 30 degrees per phase
+    std::array::from_fn(|phase| {
+        let angle = std::f32::consts::PI * (phase as f32 + hue_offset) / 6.0;
+        (angle.cos(), angle.sin())
+    })
+}
+
+/// Converts a demodulated YIQ color into an RGB color, applying the contrast,
+/// brightness, saturation and gamma adjustments
+pub(crate) fn ntsc_yiq_to_rgb(params: &PaletteGeneratorParams, yiq: [f32; 3]) -> [u8; 3] {
+    let gamma_exponent = 2.2 / params.gamma.max(0.01);
+    let y = yiq[0] * params.contrast + params.brightness;
+    let i = yiq[1] * params.saturation * params.contrast;
+    let q = yiq[2] * params.saturation * params.contrast;
+
+    rgb_from_yiq([y, i, q]).map(|c| {
+        let c = c.clamp(0.0, 1.0).powf(gamma_exponent);
+        (c * 255.0).round() as u8
+    })
+}
+
+/// Generates a palette with all emphasis combinations by decoding the YIQ
+/// color that an NTSC TV would see for each of the PPU's composite signals
+pub fn generate_ntsc_palette(params: &PaletteGeneratorParams) -> Vec<Color32> {
+    let carrier = ntsc_carrier(params);
+
+    let mut palette = Vec::with_capacity(EMPHASIS_PALETTE_SIZE);
+    for emphasis in 0..8u8 {
+        for nes_color in 0..64u8 {
+            let mut yiq = [0.0f32; 3];
+            for (phase, (cos, sin)) in carrier.iter().enumerate() {
+                let level = ntsc_signal_level(nes_color, emphasis, phase as u32);
+                yiq[0] += level;
+                yiq[1] += level * cos;
+                yiq[2] += level * sin;
+            }
+            let rgb = ntsc_yiq_to_rgb(params, yiq.map(|c| c / 12.0));
+            palette.push(Color32::from_rgb(rgb[0], rgb[1], rgb[2]));
+        }
+    }
+    palette
+}
+
+#[test]
+fn test_emphasis_palette() {
+    let palette = default_emphasis_palette();
+    assert_eq!(palette.len(), EMPHASIS_PALETTE_SIZE);
+    assert_eq!(&palette[0..64], &RGB_PALETTE[..]);
+
+    // Emphasizing red should attenuate green and blue
+    let white = RGB_PALETTE[0x30];
+    let red_white = palette[((EMPHASIS_RED as usize) << 6) | 0x30];
+    assert_eq!(red_white.r(), white.r());
+    assert!(red_white.g() < white.g());
+    assert!(red_white.b() < white.b());
+
+    // Black stays black
+    let all = (EMPHASIS_RED | EMPHASIS_GREEN | EMPHASIS_BLUE) as usize;
+    assert_eq!(palette[(all << 6) | 0x0f], Color32::from_rgb(0, 0, 0));
+}
+
+#[test]
+fn test_ntsc_palette() {
+    let palette = generate_ntsc_palette(&PaletteGeneratorParams::default());
+    assert_eq!(palette.len(), EMPHASIS_PALETTE_SIZE);
+
+    let dominant = |color: Color32| {
+        let rgb = [color.r(), color.g(), color.b()];
+        (0..3).max_by_key(|i| rgb[*i]).unwrap()
+    };
+    assert_eq!(dominant(palette[0x16]), 0); // red
+    assert_eq!(dominant(palette[0x1a]), 1); // green
+    assert_eq!(dominant(palette[0x12]), 2); // blue
+    assert_eq!(palette[0x0f], Color32::from_rgb(0, 0, 0));
+    assert_eq!(palette[0x30], Color32::from_rgb(255, 255, 255));
+
+    let pal = pal_file_from_palette(&palette);
+    assert_eq!(pal.len(), 1536);
+    assert_eq!(parse_pal_file(&pal).unwrap(), palette);
+    assert_eq!(parse_pal_file(&pal[0..192]).unwrap()[0..64], palette[0..64]);
+    assert!(parse_pal_file(&pal[0..100]).is_err());
+}
+
+#[test]
+fn test_vs_palette() {
+    let rgb = generate_vs_palette(VsPpu::RC2C05_01);
+    assert_eq!(rgb.len(), EMPHASIS_PALETTE_SIZE);
+    assert_eq!(rgb[0x0f], Color32::from_rgb(0, 0, 0));
+    assert_eq!(rgb[0x20], Color32::from_rgb(255, 255, 255));
+    assert_eq!(rgb[0x06], Color32::from_rgb(182, 36, 0));
+
+    // The 2C04 palettes are a permutation of the 2C03 colors
+    for ppu in [
+        VsPpu::RP2C04_0001,
+        VsPpu::RP2C04_0002,
+        VsPpu::RP2C04_0003,
+        VsPpu::RP2C04_0004,
+    ] {
+        let scrambled = generate_vs_palette(ppu);
+        assert_ne!(scrambled[0..64], rgb[0..64]);
+        assert!(scrambled[0..64]
+            .iter()
+            .all(|color| rgb[0..64].contains(color)));
+    }
+    assert_eq!(generate_vs_palette(VsPpu::RP2C04_0001)[0x09], rgb[0x00]);
+
+    // Emphasis drives a channel at full intensity
+    let blue_black = rgb[((EMPHASIS_BLUE as usize) << 6) | 0x0f];
+    assert_eq!(blue_black, Color32::from_rgb(0, 0, 255));
+}
@@ -236,6 +236,7 @@ impl PpuSim {
         let revision = match nes_model {
             Model::Ntsc => Revision::RP2C02G,
             Model::Pal => Revision::RP2C07_0,
+            Model::Dendy => Revision::UMC_UA6538,
         };
 
         let framebuffer = Framebuffer::new(256, 240, PixelFormat::RGBA8888);
@@ -0,0 +1,491 @@
+//! A GGPO-style rollback API for keeping the input of two Nes instances in sync
+//!
+//! Each [`RollbackSession`] owns a [`Nes`] and is advanced one frame at a time
+//! with the input of the local player. Local input is immediately sent to the
+//! remote session via a [`Transport`] and, instead of waiting for the remote
+//! player's input to arrive, the session predicts the remote input (by repeating
+//! the last input that was received) so it can keep running without any added
+//! latency.
+//!
+//! The session saves a snapshot of the Nes at the start of each frame that is
+//! emulated with predicted input and if the real input turns out to be different
+//! then the session rolls back to the snapshot for the first mispredicted frame
+//! and re-simulates up to the current frame.
+//!
+//! Note: snapshots are taken by cloning the [`Nes`] which means that any debug
+//! state (such as breakpoints or hooks) will be lost after a rollback.
+
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use crate::{
+    nes::{Nes, ProgressStatus, ProgressTarget},
+    port::{ControllerButton, Port},
+};
+
+/// The state of all the buttons of a standard controller, where bit N represents
+/// the [`ControllerButton`] with the value N
+pub type PadInput = u8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputMessage {
+    pub frame: u32,
+    pub input: PadInput,
+}
+
+/// Exchanges input with a remote [`RollbackSession`]
+///
+/// Messages may be delayed and may arrive out of order but they must not be lost.
+pub trait Transport {
+    fn send(&mut self, message: InputMessage);
+
+    /// Returns the next message that has been received, if any
+    fn receive(&mut self) -> Option<InputMessage>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdvanceStatus {
+    /// The session progressed by one frame
+    Advanced,
+
+    /// The session is too far ahead of the remote input that has been received and
+    /// the local input was not consumed. The same input should be given again later.
+    WaitingForRemote,
+}
+
+pub struct RollbackSession<T: Transport> {
+    nes: Nes,
+    transport: T,
+
+    /// 0 or 1 depending on whether local input is connected to port1 or port2
+    local_port: usize,
+
+    /// The maximum number of frames that can be emulated ahead of the last confirmed
+    /// remote input
+    max_prediction_frames: u32,
+
+    /// The next frame to be emulated
+    frame: u32,
+
+    /// The number of frames (from the start of the session) for which all remote
+    /// input has been received
+    confirmed_frames: u32,
+
+    /// Indexed by frame
+    local_inputs: Vec<PadInput>,
+    remote_inputs: Vec<Option<PadInput>>,
+
+    /// The remote input (either confirmed or predicted) that was last used to emulate each frame
+    simulated_remote_inputs: Vec<PadInput>,
+
+    /// Snapshots of the Nes from the start of each frame emulated with predicted input
+    snapshots: VecDeque<(u32, Nes)>,
+
+    rollback_count: u32,
+    resimulated_frame_count: u64,
+}
+
+impl<T: Transport> RollbackSession<T> {
+    /// Creates a new session that takes ownership of an `nes` that has been
+    /// powered on with a cartridge inserted
+    ///
+    /// The remote session must be created with a Nes that's in the same state
+    /// and with the opposite `local_port`.
+    pub fn new(nes: Nes, transport: T, local_port: usize, max_prediction_frames: u32) -> Self {
+        assert!(
+            local_port < 2,
+            "Local input must be connected to port 0 or 1"
+        );
+        Self {
+            nes,
+            transport,
+            local_port,
+            max_prediction_frames,
+            frame: 0,
+            confirmed_frames: 0,
+            local_inputs: vec![],
+            remote_inputs: vec![],
+            simulated_remote_inputs: vec![],
+            snapshots: VecDeque::new(),
+            rollback_count: 0,
+            resimulated_frame_count: 0,
+        }
+    }
+
+    pub fn nes(&self) -> &Nes {
+        &self.nes
+    }
+
+    pub fn nes_mut(&mut self) -> &mut Nes {
+        &mut self.nes
+    }
+
+    /// The number of frames that have been emulated
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// The number of frames for which all remote input has been received
+    pub fn confirmed_frames(&self) -> u32 {
+        self.confirmed_frames
+    }
+
+    /// The number of times the session had to roll back after a misprediction
+    pub fn rollback_count(&self) -> u32 {
+        self.rollback_count
+    }
+
+    /// The total number of frames that had to be emulated again after rolling back
+    pub fn resimulated_frame_count(&self) -> u64 {
+        self.resimulated_frame_count
+    }
+
+    fn predict_remote_input(&self) -> PadInput {
+        if self.confirmed_frames > 0 {
+            self.remote_inputs[self.confirmed_frames as usize - 1].unwrap_or(0)
+        } else {
+            0
+        }
+    }
+
+    fn set_port_input(port: &mut Port, input: PadInput) {
+        for i in 0..8u8 {
+            let button = ControllerButton::try_from(i).unwrap();
+            if input & (1 << i) != 0 {
+                port.press_button(button);
+            } else {
+                port.release_button(button);
+            }
+        }
+    }
+
+    fn simulate_frame(&mut self) {
+        let frame = self.frame as usize;
+
+        let remote_input = match self.remote_inputs.get(frame).copied().flatten() {
+            Some(input) => input,
+            None => {
+                self.snapshots.push_back((self.frame, self.nes.clone()));
+                self.predict_remote_input()
+            }
+        };
+        if frame < self.simulated_remote_inputs.len() {
+            self.simulated_remote_inputs[frame] = remote_input;
+        } else {
+            self.simulated_remote_inputs.push(remote_input);
+        }
+
+        let local_input = self.local_inputs[frame];
+        let (pad1, pad2) = if self.local_port == 0 {
+            (local_input, remote_input)
+        } else {
+            (remote_input, local_input)
+        };
+        Self::set_port_input(&mut self.nes.system_mut().port1, pad1);
+        Self::set_port_input(&mut self.nes.system_mut().port2, pad2);
+
+        // Any breakpoints are ignored, we only stop at the end of the frame
+        while !matches!(
+            self.nes.progress(ProgressTarget::FrameReady),
+            ProgressStatus::FrameReady
+        ) {}
+
+        self.frame += 1;
+    }
+
+    fn rollback(&mut self, frame: u32) {
+        log::debug!("Rollback from frame {} to {}", self.frame, frame);
+
+        let index = self
+            .snapshots
+            .iter()
+            .position(|(snapshot_frame, _)| *snapshot_frame == frame)
+            .expect("Missing snapshot for mispredicted frame");
+        let (_, nes) = self.snapshots.drain(index..).next().unwrap();
+        self.nes = nes;
+
+        let end = self.frame;
+        self.frame = frame;
+        while self.frame < end {
+            self.simulate_frame();
+            self.resimulated_frame_count += 1;
+        }
+        self.rollback_count += 1;
+    }
+
+    /// Handles any remote input that has been received and rolls back if
+    /// it's found that any input was mispredicted
+    ///
+    /// This is called automatically by [`Self::advance_frame`]
+    pub fn poll_remote(&mut self) {
+        let mut rollback_frame: Option<u32> = None;
+
+        while let Some(message) = self.transport.receive() {
+            let frame = message.frame as usize;
+            if frame >= self.remote_inputs.len() {
+                self.remote_inputs.resize(frame + 1, None);
+            }
+            self.remote_inputs[frame] = Some(message.input);
+
+            if message.frame < self.frame && self.simulated_remote_inputs[frame] != message.input {
+                rollback_frame =
+                    Some(rollback_frame.map_or(message.frame, |f| f.min(message.frame)));
+            }
+        }
+
+        while let Some(Some(_)) = self.remote_inputs.get(self.confirmed_frames as usize) {
+            self.confirmed_frames += 1;
+        }
+
+        if let Some(frame) = rollback_frame {
+            self.rollback(frame);
+        }
+
+        // We will never need to roll back to a frame whose input is confirmed
+        while let Some((frame, _)) = self.snapshots.front() {
+            if *frame < self.confirmed_frames {
+                self.snapshots.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Emulates the next frame with the given local input
+    ///
+    /// If the session has already predicted `max_prediction_frames` beyond the last
+    /// confirmed remote input then the emulator won't progress and
+    /// [`AdvanceStatus::WaitingForRemote`] is returned.
+    pub fn advance_frame(&mut self, local_input: PadInput) -> AdvanceStatus {
+        self.poll_remote();
+
+        if self.frame >= self.confirmed_frames + self.max_prediction_frames {
+            return AdvanceStatus::WaitingForRemote;
+        }
+
+        debug_assert_eq!(self.local_inputs.len(), self.frame as usize);
+        self.local_inputs.push(local_input);
+        self.transport.send(InputMessage {
+            frame: self.frame,
+            input: local_input,
+        });
+
+        self.simulate_frame();
+
+        AdvanceStatus::Advanced
+    }
+}
+
+struct MemoryChannelState {
+    now: u64,
+    latency: u64,
+    jitter: u64,
+    rng_state: u64,
+
+    /// Messages in flight to each endpoint along with the time they are delivered
+    in_flight: [Vec<(u64, InputMessage)>; 2],
+}
+
+impl MemoryChannelState {
+    // xorshift64
+    fn next_random(&mut self) -> u64 {
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng_state = x;
+        x
+    }
+}
+
+/// An in-memory channel for connecting two [`RollbackSession`]s within a single
+/// process, with a simulated latency and jitter
+///
+/// Time is measured in abstract ticks that are progressed via [`Self::advance`]
+/// (e.g. once per frame).
+pub struct MemoryChannel {
+    state: Rc<RefCell<MemoryChannelState>>,
+}
+
+impl MemoryChannel {
+    /// Creates a channel where each message is delivered after `latency` ticks plus a
+    /// random delay of up to `jitter` ticks (which may result in messages being
+    /// delivered out of order)
+    ///
+    /// The jitter is deterministic for any given `seed`
+    pub fn new(latency: u64, jitter: u64, seed: u64) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(MemoryChannelState {
+                now: 0,
+                latency,
+                jitter,
+                rng_state: seed.max(1),
+                in_flight: [vec![], vec![]],
+            })),
+        }
+    }
+
+    /// Returns the transports for either end of the channel
+    pub fn endpoints(&self) -> (MemoryTransport, MemoryTransport) {
+        (
+            MemoryTransport {
+                state: self.state.clone(),
+                endpoint: 0,
+            },
+            MemoryTransport {
+                state: self.state.clone(),
+                endpoint: 1,
+            },
+        )
+    }
+
+    pub fn advance(&self, ticks: u64) {
+        self.state.borrow_mut().now += ticks;
+    }
+}
+
+pub struct MemoryTransport {
+    state: Rc<RefCell<MemoryChannelState>>,
+    endpoint: usize,
+}
+
+impl Transport for MemoryTransport {
+    fn send(&mut self, message: InputMessage) {
+        let mut state = self.state.borrow_mut();
+        let jitter = if state.jitter > 0 {
+            state.next_random() % (state.jitter + 1)
+        } else {
+            0
+        };
+        let deliver_time = state.now + state.latency + jitter;
+        state.in_flight[1 - self.endpoint].push((deliver_time, message));
+    }
+
+    fn receive(&mut self) -> Option<InputMessage> {
+        let mut state = self.state.borrow_mut();
+        let now = state.now;
+        let in_flight = &mut state.in_flight[self.endpoint];
+        let index = in_flight
+            .iter()
+            .position(|(deliver_time, _)| *deliver_time <= now)?;
+        Some(in_flight.remove(index).1)
+    }
+}
+
+#[test]
+fn test_rollback_sessions_sync() {
+    use crate::binary::test_nrom_binary;
+    use crate::system::Model;
+    use instant::Instant;
+
+    // Continuously reads both controllers and adds their state to running
+    // totals at $01 (port 1) and $04 (port 2), so that the state of the
+    // system depends on exactly which input was seen for how long
+    let program = [
+        0xa9, 0x01, // LDA #1
+        0x8d, 0x16, 0x40, // STA $4016
+        0xa9, 0x00, // LDA #0
+        0x8d, 0x16, 0x40, // STA $4016
+        0xa2, 0x08, // LDX #8
+        0xad, 0x16, 0x40, // read: LDA $4016
+        0x4a, // LSR A
+        0x26, 0x02, // ROL $02
+        0xad, 0x17, 0x40, // LDA $4017
+        0x4a, // LSR A
+        0x26, 0x03, // ROL $03
+        0xca, // DEX
+        0xd0, 0xf1, // BNE read
+        0xa5, 0x01, // LDA $01
+        0x18, // CLC
+        0x65, 0x02, // ADC $02
+        0x85, 0x01, // STA $01
+        0xa5, 0x04, // LDA $04
+        0x18, // CLC
+        0x65, 0x03, // ADC $03
+        0x85, 0x04, // STA $04
+        0x4c, 0x00, 0x80, // JMP $8000
+    ];
+    let rom = test_nrom_binary(&program, None);
+    let new_nes = || {
+        let start = Instant::now();
+        let mut nes = Nes::new(Model::Ntsc, 48000, start);
+        nes.open_binary(&rom).unwrap();
+        nes.power_cycle(start);
+        nes
+    };
+
+    // Input that changes every few frames so that predictions will often be wrong
+    let input_for_frame = |port: u32, frame: u32| -> PadInput {
+        let period = 5 + port * 2;
+        ((frame / period + port) * 37) as u8
+    };
+
+    const LATENCY: u64 = 3;
+    const JITTER: u64 = 4;
+    const N_FRAMES: u32 = 60;
+
+    let run_reference = |remote_input: &dyn Fn(u32) -> PadInput| {
+        let mut reference = new_nes();
+        for frame in 0..N_FRAMES {
+            RollbackSession::<MemoryTransport>::set_port_input(
+                &mut reference.system_mut().port1,
+                input_for_frame(0, frame),
+            );
+            RollbackSession::<MemoryTransport>::set_port_input(
+                &mut reference.system_mut().port2,
+                remote_input(frame),
+            );
+            while !matches!(
+                reference.progress(ProgressTarget::FrameReady),
+                ProgressStatus::FrameReady
+            ) {}
+        }
+        reference
+    };
+
+    // A Nes that's given the correct input for every frame
+    let mut reference = run_reference(&|frame| input_for_frame(1, frame));
+
+    // Sanity check that the end state really depends on port 2's input, so
+    // a session that failed to correct a misprediction couldn't still match
+    let mut mispredicted = run_reference(&|_| 0);
+    assert!(mispredicted.system_mut().wram != reference.system_mut().wram);
+
+    let channel = MemoryChannel::new(LATENCY, JITTER, 0x1234);
+    let (transport_a, transport_b) = channel.endpoints();
+    let mut session_a = RollbackSession::new(new_nes(), transport_a, 0, 12);
+    let mut session_b = RollbackSession::new(new_nes(), transport_b, 1, 12);
+
+    // The first frames are always emulated with predicted (zero) remote input
+    // before any input arrives, which is wrong for port 2's first input
+    assert_ne!(input_for_frame(1, 0), 0);
+
+    for frame in 0..N_FRAMES {
+        let status = session_a.advance_frame(input_for_frame(0, frame));
+        assert_eq!(status, AdvanceStatus::Advanced);
+        let status = session_b.advance_frame(input_for_frame(1, frame));
+        assert_eq!(status, AdvanceStatus::Advanced);
+        channel.advance(1);
+    }
+
+    // Let all remaining input arrive
+    channel.advance(LATENCY + JITTER + 1);
+    session_a.poll_remote();
+    session_b.poll_remote();
+
+    assert_eq!(session_a.confirmed_frames(), N_FRAMES);
+    assert_eq!(session_b.confirmed_frames(), N_FRAMES);
+    assert!(session_a.rollback_count() > 0);
+    assert!(session_b.rollback_count() > 0);
+    assert!(session_a.resimulated_frame_count() > 0);
+
+    let totals = |nes: &mut Nes| {
+        let wram = &nes.system_mut().wram;
+        (wram[0x01], wram[0x04])
+    };
+    assert_eq!(totals(session_a.nes_mut()), totals(&mut reference));
+    assert_eq!(totals(session_b.nes_mut()), totals(&mut reference));
+    assert_eq!(session_a.nes().cpu_clock(), reference.cpu_clock());
+    assert_eq!(session_b.nes().cpu_clock(), reference.cpu_clock());
+    assert!(session_a.nes_mut().system_mut().wram == reference.system_mut().wram);
+    assert!(session_b.nes_mut().system_mut().wram == reference.system_mut().wram);
+}
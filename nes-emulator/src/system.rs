@@ -1,10 +1,12 @@
 use crate::apu::core::Apu;
+use crate::cpu::bus::CpuBus;
 use crate::genie::GameGenieCode;
 use crate::ppu::Ppu;
 
 #[cfg(feature = "ppu-sim")]
 use crate::ppusim::PpuSim;
 use crate::trace::TraceEvent;
+use crate::vs_system::VsSystem;
 
 use super::cartridge::*;
 use super::constants::*;
@@ -24,12 +26,27 @@ pub enum Model {
     #[default]
     Ntsc,
     Pal,
+    /// The UMC UA6538 based "Dendy" famiclone, which has the same number of
+    /// lines and frame rate as PAL but keeps the NTSC 3:1 PPU:CPU clock ratio
+    Dendy,
 }
 impl Model {
+    /// Picks the model to emulate for a cartridge with the given TV system compatibility
+    ///
+    /// Cartridges that are compatible with both (or unknown) systems will run as NTSC
+    pub fn from_tv_system(tv_system: TVSystemCompatibility) -> Model {
+        match tv_system {
+            TVSystemCompatibility::Pal => Model::Pal,
+            TVSystemCompatibility::Dendy => Model::Dendy,
+            _ => Model::Ntsc,
+        }
+    }
+
     pub fn cpu_clock_hz(&self) -> u32 {
         match self {
             Model::Ntsc => NTSC_CPU_CLOCK_HZ,
             Model::Pal => PAL_CPU_CLOCK_HZ,
+            Model::Dendy => DENDY_CPU_CLOCK_HZ,
         }
     }
 }
@@ -51,6 +68,21 @@ pub struct WatchPoint {
     pub ops: WatchOps,
 }
 
+/// A unique handle for a registered write breakpoint that can be used to check
+/// if it was hit and to remove it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteBreakpointHandle(u32);
+
+/// Stops the emulator after the CPU writes to an address (optionally only when
+/// writing a specific value)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteBreakpoint {
+    pub handle: WriteBreakpointHandle,
+    pub address: u16,
+    pub value: Option<u8>,
+    pub hit: bool,
+}
+
 /// The DMC doesn't have direct access to the system bus within
 /// apu.step() so any DMA needs to requested, and the result
 /// will be passed back
@@ -82,13 +114,21 @@ pub struct NoCloneDebugState {
     pub watch_points: Vec<WatchPoint>,
     #[cfg(feature = "debugger")]
     pub watch_hit: bool,
+    #[cfg(feature = "debugger")]
+    pub write_breakpoints: Vec<WriteBreakpoint>,
+    #[cfg(feature = "debugger")]
+    pub next_write_breakpoint_handle: u32,
 
     #[cfg(feature = "io-stats")]
     pub io_stats: Vec<IoStatsRecord>,
 }
 impl Clone for NoCloneDebugState {
     fn clone(&self) -> Self {
-        Self::default()
+        Self {
+            #[cfg(feature = "io-stats")]
+            io_stats: vec![IoStatsRecord::default(); (u16::MAX as usize) + 1],
+            ..Default::default()
+        }
     }
 }
 
@@ -114,6 +154,14 @@ pub struct System {
     pub port1: Port,
     pub port2: Port,
 
+    /// Set whenever either of the controller ports are read via $4016 or $4017
+    /// so we can recognise 'lag' frames where the game didn't poll for input
+    input_polled: bool,
+
+    /// The coin slots, service button and DIP switches of a Vs. System cabinet,
+    /// if a Vs. System game is inserted
+    pub vs_system: Option<VsSystem>,
+
     genie_codes: Vec<GameGenieCode>,
     genie_codes_mask: BitArr!(for 0x10000-0x8000, in usize),
 
@@ -140,6 +188,8 @@ impl System {
             wram: [0; WRAM_SIZE],
             port1: Default::default(),
             port2: Default::default(),
+            input_polled: false,
+            vs_system: None,
             open_bus_value: 0,
 
             genie_codes: vec![],
@@ -155,6 +205,10 @@ impl System {
 
                 watch_points: vec![],
                 watch_hit: false,
+                #[cfg(feature = "debugger")]
+                write_breakpoints: vec![],
+                #[cfg(feature = "debugger")]
+                next_write_breakpoint_handle: 0,
 
                 #[cfg(feature = "io-stats")]
                 io_stats: vec![IoStatsRecord::default(); (u16::MAX as usize) + 1],
@@ -168,6 +222,15 @@ impl System {
     }
 
     pub(crate) fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        self.vs_system = cartridge.vs_system().map(VsSystem::new);
+        let vs_ppu = self
+            .vs_system
+            .as_ref()
+            .map(|vs_system| vs_system.config.ppu);
+        if vs_ppu != self.ppu.vs_ppu() {
+            self.ppu.set_vs_ppu(vs_ppu);
+        }
+
         self.cartridge = cartridge;
 
         #[cfg(feature = "ppu-sim")]
@@ -207,11 +270,16 @@ impl System {
         let ppu_sim_cartridge = std::mem::take(&mut self.debug.ppu_sim_cartridge);
         let pad1 = std::mem::take(&mut self.port1);
         let pad2 = std::mem::take(&mut self.port2);
+        let vs_system = std::mem::take(&mut self.vs_system);
         let genie_codes = std::mem::take(&mut self.genie_codes);
         let genie_codes_mask = std::mem::take(&mut self.genie_codes_mask);
 
         #[cfg(feature = "debugger")]
         let watch_points = std::mem::take(&mut self.debug.watch_points);
+        #[cfg(feature = "debugger")]
+        let write_breakpoints = std::mem::take(&mut self.debug.write_breakpoints);
+        #[cfg(feature = "debugger")]
+        let next_write_breakpoint_handle = self.debug.next_write_breakpoint_handle;
 
         *self = Self {
             ppu,
@@ -221,6 +289,8 @@ impl System {
             wram: [0; WRAM_SIZE],
             port1: pad1,
             port2: pad2,
+            input_polled: false,
+            vs_system,
             open_bus_value: 0,
             genie_codes,
             genie_codes_mask,
@@ -237,6 +307,10 @@ impl System {
                 watch_points,
                 #[cfg(feature = "debugger")]
                 watch_hit: false,
+                #[cfg(feature = "debugger")]
+                write_breakpoints,
+                #[cfg(feature = "debugger")]
+                next_write_breakpoint_handle,
 
                 #[cfg(feature = "io-stats")]
                 io_stats: vec![IoStatsRecord::default(); (u16::MAX as usize) + 1],
@@ -248,6 +322,7 @@ impl System {
     }
 
     pub(crate) fn reset(&mut self) {
+        self.ppu.render_deferred_dots(&mut self.cartridge);
         self.ppu.reset();
         #[cfg(feature = "ppu-sim")]
         {
@@ -279,6 +354,12 @@ impl System {
         }
     }
 
+    /// Returns whether either controller port has been read since the last call,
+    /// and clears the status
+    pub fn take_input_polled(&mut self) -> bool {
+        std::mem::take(&mut self.input_polled)
+    }
+
     pub fn take_frame_ready(&mut self) -> bool {
         #[cfg(feature = "ppu-sim")]
         {
@@ -301,7 +382,7 @@ impl System {
     }
 
     pub fn irq_line(&self) -> bool {
-        self.apu.irq() || self.cartridge.mapper.irq()
+        self.apu.irq() || self.cartridge.irq()
     }
 
     /// Apply the open bus bits and update the open bus value for future reads
@@ -332,6 +413,19 @@ impl System {
         }
     }
 
+    #[inline(always)]
+    fn check_write_breakpoints(&mut self, addr: u16, data: u8) {
+        #[cfg(feature = "debugger")]
+        if !self.debug.write_breakpoints.is_empty() {
+            for bp in self.debug.write_breakpoints.iter_mut() {
+                if bp.address == addr && bp.value.is_none_or(|value| value == data) {
+                    bp.hit = true;
+                    self.debug.watch_hit = true;
+                }
+            }
+        }
+    }
+
     /// Perform a system bus read from the CPU
     ///
     /// Considering that all CPU IO takes one CPU clock cycle this will
@@ -372,8 +466,24 @@ impl System {
                         // Write-only OAMDMA
                         (0, 0xff)
                     }
-                    0x16 => (self.port1.read(), 0b1110_0000), // pad1
-                    0x17 => (self.port2.read(), 0b1110_0000), // pad2
+                    0x16 => {
+                        self.input_polled = true;
+                        match &self.vs_system {
+                            Some(vs_system) => {
+                                (self.port1.read() | vs_system.read_4016_bits(), 0b1000_0010)
+                            }
+                            None => (self.port1.read(), 0b1110_0000), // pad1
+                        }
+                    }
+                    0x17 => {
+                        self.input_polled = true;
+                        match &self.vs_system {
+                            Some(vs_system) => {
+                                (self.port2.read() | vs_system.read_4017_bits(), 0b0000_0010)
+                            }
+                            None => (self.port2.read(), 0b1110_0000), // pad2
+                        }
+                    }
                     _ => self.apu.read(addr),
                 }
             }
@@ -536,8 +646,18 @@ impl System {
                         // Write-only OAMDMA
                         (0, 0xff)
                     }
-                    0x16 => (self.port1.peek(), 0b1110_0000), // pad1
-                    0x17 => (self.port2.peek(), 0b1110_0000), // pad2
+                    0x16 => match &self.vs_system {
+                        Some(vs_system) => {
+                            (self.port1.peek() | vs_system.read_4016_bits(), 0b1000_0010)
+                        }
+                        None => (self.port1.peek(), 0b1110_0000), // pad1
+                    },
+                    0x17 => match &self.vs_system {
+                        Some(vs_system) => {
+                            (self.port2.peek() | vs_system.read_4017_bits(), 0b0000_0010)
+                        }
+                        None => (self.port2.peek(), 0b1110_0000), // pad2
+                    },
                     _ => self.apu.peek(addr),
                 }
             }
@@ -551,7 +671,7 @@ impl System {
     }
 
     /// Perform a system bus write from the CPU
-    fn write(&mut self, addr: u16, data: u8) {
+    pub(crate) fn write(&mut self, addr: u16, data: u8) {
         match addr {
             0x0000..=0x1fff => {
                 // RAM
@@ -597,6 +717,12 @@ impl System {
                         self.port1.write_register(data);
                         self.port2.write_register(data);
                         self.apu.write(addr, data);
+
+                        // Vs. System bank switching (mapper 99) is driven by bit 2 of $4016
+                        if self.vs_system.is_some() {
+                            self.ppu.render_deferred_dots(&mut self.cartridge);
+                            self.cartridge.system_bus_write(addr, data);
+                        }
                     }
                     0x17 => {
                         // This register is split between being an APU register and a controller register
@@ -607,8 +733,18 @@ impl System {
                     }
                 }
             }
+            0x4020 if self.vs_system.is_some() => {
+                // Vs. System coin counter
+                if let Some(vs_system) = &mut self.vs_system {
+                    vs_system.write_coin_counter(data);
+                }
+            }
             _ => {
                 // Cartridge
+                //
+                // Bank switching may change what the PPU fetches, so the PPU
+                // has to catch up with any deferred rendering first
+                self.ppu.render_deferred_dots(&mut self.cartridge);
                 self.cartridge.system_bus_write(addr, data);
                 #[cfg(feature = "ppu-sim")]
                 self.debug.ppu_sim_cartridge.system_bus_write(addr, data);
@@ -651,6 +787,7 @@ impl System {
     /// Perform a system bus write from the CPU
     pub fn cpu_write(&mut self, addr: u16, data: u8) {
         self.check_watch_points(addr, WatchOps::WRITE);
+        self.check_write_breakpoints(addr, data);
 
         #[cfg(feature = "io-stats")]
         {
@@ -876,6 +1013,47 @@ impl System {
             .push(WatchPoint { address: addr, ops })
     }
 
+    /// Request that the emulator should stop after the CPU writes to the given
+    /// address, or only when it writes `value` (if given)
+    ///
+    /// Dummy writes are ignored. Use [`Self::write_breakpoint_hit`] to check which
+    /// breakpoint stopped the emulator.
+    #[cfg(feature = "debugger")]
+    pub fn add_write_breakpoint(&mut self, addr: u16, value: Option<u8>) -> WriteBreakpointHandle {
+        let handle = WriteBreakpointHandle(self.debug.next_write_breakpoint_handle);
+        self.debug.next_write_breakpoint_handle += 1;
+
+        self.debug.write_breakpoints.push(WriteBreakpoint {
+            handle,
+            address: addr,
+            value,
+            hit: false,
+        });
+
+        handle
+    }
+
+    #[cfg(feature = "debugger")]
+    pub fn remove_write_breakpoint(&mut self, handle: WriteBreakpointHandle) {
+        if let Some(i) = self
+            .debug
+            .write_breakpoints
+            .iter()
+            .position(|bp| bp.handle == handle)
+        {
+            self.debug.write_breakpoints.swap_remove(i);
+        }
+    }
+
+    /// Returns `true` if the given write breakpoint has been hit since it was added
+    #[cfg(feature = "debugger")]
+    pub fn write_breakpoint_hit(&self, handle: WriteBreakpointHandle) -> bool {
+        self.debug
+            .write_breakpoints
+            .iter()
+            .any(|bp| bp.handle == handle && bp.hit)
+    }
+
     pub fn game_genie_codes(&self) -> &Vec<GameGenieCode> {
         &self.genie_codes
     }
@@ -904,3 +1082,75 @@ impl System {
         self.ppu.trace(event)
     }
 }
+
+impl CpuBus for System {
+    #[inline(always)]
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        System::cpu_read(self, addr)
+    }
+
+    #[inline(always)]
+    fn cpu_fetch(&mut self, addr: u16) -> u8 {
+        System::cpu_fetch(self, addr)
+    }
+
+    #[inline(always)]
+    fn dummy_cpu_read(&mut self, addr: u16) {
+        System::dummy_cpu_read(self, addr)
+    }
+
+    #[inline(always)]
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        System::cpu_write(self, addr, data)
+    }
+
+    #[inline(always)]
+    fn dummy_cpu_write(&mut self, addr: u16, data: u8) {
+        System::dummy_cpu_write(self, addr, data)
+    }
+
+    #[inline(always)]
+    fn peek(&mut self, addr: u16) -> u8 {
+        System::peek(self, addr)
+    }
+
+    #[inline(always)]
+    fn step_for_cpu_cycle(&mut self) {
+        System::step_for_cpu_cycle(self)
+    }
+
+    #[inline(always)]
+    fn nmi_line(&self) -> bool {
+        System::nmi_line(self)
+    }
+
+    #[inline(always)]
+    fn irq_line(&self) -> bool {
+        System::irq_line(self)
+    }
+
+    #[inline(always)]
+    fn take_dmc_dma_request(&mut self) -> Option<u16> {
+        std::mem::take(&mut self.dmc_dma_request).map(|req| req.address)
+    }
+
+    #[inline(always)]
+    fn completed_dmc_dma(&mut self, addr: u16, sample: u8) {
+        self.apu.dmc_channel.completed_dma(addr, sample);
+    }
+
+    #[inline(always)]
+    fn step_m2_phi2(&mut self, cpu_clock: u64) {
+        self.cartridge.step_m2_phi2(cpu_clock);
+    }
+
+    fn ppu_position(&self) -> (u32, u16, u16) {
+        (self.ppu.frame, self.ppu.line, self.ppu.dot)
+    }
+
+    #[cfg(feature = "trace-events")]
+    #[inline(always)]
+    fn trace(&mut self, event: TraceEvent) {
+        System::trace(self, event)
+    }
+}
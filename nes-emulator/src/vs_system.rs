@@ -0,0 +1,289 @@
+//! Support for Nintendo's Vs. UniSystem arcade hardware
+//!
+//! The Vs. System is based on the same CPU and PPU architecture as the NES
+//! but uses RGB PPUs (some of which scramble their palette or swap registers
+//! as a form of copy protection), has 4KB of nametable VRAM and adds coin
+//! slots, a service button and DIP switches that are read via $4016/$4017.
+//!
+//! ref: https://www.nesdev.org/wiki/Vs._System
+
+use log::warn;
+
+/// The RGB PPU variants found in Vs. System cabinets
+///
+/// The numbering of variants matches the NES 2.0 Vs. PPU type.
+///
+/// ref: https://www.nesdev.org/wiki/NES_2.0#Vs._System_Type
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum VsPpu {
+    #[default]
+    RP2C03B,
+    RP2C03G,
+    RP2C04_0001,
+    RP2C04_0002,
+    RP2C04_0003,
+    RP2C04_0004,
+    RC2C03B,
+    RC2C03C,
+    RC2C05_01,
+    RC2C05_02,
+    RC2C05_03,
+    RC2C05_04,
+    RC2C05_05,
+}
+
+impl VsPpu {
+    /// Maps the NES 2.0 Vs. PPU type (the low nibble of header byte 13)
+    pub fn from_nes2_ppu_type(ppu_type: u8) -> Option<VsPpu> {
+        match ppu_type {
+            0x0 => Some(VsPpu::RP2C03B),
+            0x1 => Some(VsPpu::RP2C03G),
+            0x2 => Some(VsPpu::RP2C04_0001),
+            0x3 => Some(VsPpu::RP2C04_0002),
+            0x4 => Some(VsPpu::RP2C04_0003),
+            0x5 => Some(VsPpu::RP2C04_0004),
+            0x6 => Some(VsPpu::RC2C03B),
+            0x7 => Some(VsPpu::RC2C03C),
+            0x8 => Some(VsPpu::RC2C05_01),
+            0x9 => Some(VsPpu::RC2C05_02),
+            0xa => Some(VsPpu::RC2C05_03),
+            0xb => Some(VsPpu::RC2C05_04),
+            0xc => Some(VsPpu::RC2C05_05),
+            _ => None,
+        }
+    }
+
+    /// The 2C05 PPUs swap the addresses of PPUCTRL ($2000) and PPUMASK ($2001)
+    pub fn swaps_control_registers(&self) -> bool {
+        matches!(
+            self,
+            VsPpu::RC2C05_01
+                | VsPpu::RC2C05_02
+                | VsPpu::RC2C05_03
+                | VsPpu::RC2C05_04
+                | VsPpu::RC2C05_05
+        )
+    }
+
+    /// Returns a `(mask, value)` pair for the bits of PPUSTATUS ($2002) that
+    /// read back as a fixed ID, instead of open bus, which some games check
+    /// to confirm they are running with the expected PPU
+    ///
+    /// The ID of the RC2C05-05 isn't known.
+    pub fn status_id_bits(&self) -> Option<(u8, u8)> {
+        match self {
+            VsPpu::RC2C05_01 => Some((0x1f, 0x1b)),
+            VsPpu::RC2C05_02 => Some((0x3f, 0x3d)),
+            VsPpu::RC2C05_03 => Some((0x1f, 0x1c)),
+            VsPpu::RC2C05_04 => Some((0x1f, 0x1b)),
+            _ => None,
+        }
+    }
+}
+
+/// The kind of Vs. System (and any protection hardware) a game expects
+///
+/// Only [`VsHardware::UniSystem`] games are currently supported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum VsHardware {
+    #[default]
+    UniSystem,
+    RbiBaseballProtection,
+    TkoBoxingProtection,
+    SuperXeviousProtection,
+    IceClimberJapanProtection,
+    DualSystem,
+    DualSystemRaidOnBungelingBay,
+}
+
+impl VsHardware {
+    /// Maps the NES 2.0 Vs. hardware type (the high nibble of header byte 13)
+    pub fn from_nes2_hardware_type(hardware_type: u8) -> Option<VsHardware> {
+        match hardware_type {
+            0x0 => Some(VsHardware::UniSystem),
+            0x1 => Some(VsHardware::RbiBaseballProtection),
+            0x2 => Some(VsHardware::TkoBoxingProtection),
+            0x3 => Some(VsHardware::SuperXeviousProtection),
+            0x4 => Some(VsHardware::IceClimberJapanProtection),
+            0x5 => Some(VsHardware::DualSystem),
+            0x6 => Some(VsHardware::DualSystemRaidOnBungelingBay),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct VsSystemConfig {
+    pub ppu: VsPpu,
+    pub hardware: VsHardware,
+}
+
+impl VsSystemConfig {
+    /// Parses the NES 2.0 Vs. System type (header byte 13)
+    pub fn from_nes2_system_type(system_type: u8) -> VsSystemConfig {
+        let ppu_type = system_type & 0xf;
+        let ppu = VsPpu::from_nes2_ppu_type(ppu_type).unwrap_or_else(|| {
+            warn!("Unknown Vs. PPU type {ppu_type}, assuming RP2C03B");
+            VsPpu::RP2C03B
+        });
+        let hardware_type = system_type >> 4;
+        let hardware = VsHardware::from_nes2_hardware_type(hardware_type).unwrap_or_else(|| {
+            warn!("Unknown Vs. hardware type {hardware_type}, assuming a UniSystem");
+            VsHardware::UniSystem
+        });
+        if hardware != VsHardware::UniSystem {
+            warn!("Vs. hardware {hardware:?} isn't supported, will run as a UniSystem");
+        }
+        VsSystemConfig { ppu, hardware }
+    }
+}
+
+/// The number of frames that a coin will be registered as inserted
+///
+/// Games poll the coin slots each frame and may miss a coin that's only
+/// seen for a single frame.
+pub const COIN_INSERT_FRAMES: u8 = 3;
+
+/// The state of the Vs. System cabinet I/O (coin slots, service button and DIP switches)
+#[derive(Clone, Debug, Default)]
+pub struct VsSystem {
+    pub config: VsSystemConfig,
+
+    /// DIP switches 1 to 8 (where switch 1 is bit 0)
+    ///
+    /// The meaning of each switch is game specific.
+    pub dip_switches: u8,
+
+    pub service_button: bool,
+
+    /// The number of frames left for which each coin slot will register a coin
+    coin_frames: [u8; 2],
+
+    coin_counter: bool,
+    coins_counted: u32,
+}
+
+impl VsSystem {
+    pub fn new(config: VsSystemConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Registers a coin inserted into the given slot (0 or 1) for the next
+    /// [`COIN_INSERT_FRAMES`] frames
+    pub fn insert_coin(&mut self, slot: usize) {
+        self.coin_frames[slot] = COIN_INSERT_FRAMES;
+    }
+
+    /// The number of coins counted by the cabinet's mechanical coin counter,
+    /// which is driven by writes to $4020
+    pub fn coins_counted(&self) -> u32 {
+        self.coins_counted
+    }
+
+    pub(crate) fn start_frame(&mut self) {
+        for frames in self.coin_frames.iter_mut() {
+            *frames = frames.saturating_sub(1);
+        }
+    }
+
+    /// The cabinet bits that are combined with the controller bit for $4016 reads
+    ///
+    /// ```text
+    /// 7  bit  0
+    /// ---- ----
+    /// xCCD DSxB
+    ///  ||| ||
+    ///  ||| |+--- Service button
+    ///  ||+-+---- DIP switches 1 and 2
+    ///  |+------- Coin inserted in slot 1
+    ///  +-------- Coin inserted in slot 2
+    /// ```
+    pub(crate) fn read_4016_bits(&self) -> u8 {
+        let mut bits = (self.dip_switches & 0b11) << 3;
+        if self.service_button {
+            bits |= 0b100;
+        }
+        if self.coin_frames[0] > 0 {
+            bits |= 0b0010_0000;
+        }
+        if self.coin_frames[1] > 0 {
+            bits |= 0b0100_0000;
+        }
+        bits
+    }
+
+    /// The cabinet bits that are combined with the controller bit for $4017
+    /// reads, for DIP switches 3 to 8 (bits 2 to 7)
+    pub(crate) fn read_4017_bits(&self) -> u8 {
+        self.dip_switches & 0b1111_1100
+    }
+
+    /// Handles $4020 writes, where bit 0 drives the coin counter
+    pub(crate) fn write_coin_counter(&mut self, data: u8) {
+        let coin_counter = data & 1 != 0;
+        if coin_counter && !self.coin_counter {
+            self.coins_counted += 1;
+        }
+        self.coin_counter = coin_counter;
+    }
+}
+
+#[test]
+fn test_vs_system_io() {
+    use crate::cartridge::Cartridge;
+    use crate::ppu_registers::Control1Flags;
+    use crate::system::{Model, System};
+
+    // A NES 2.0 mapper 99 Vs. System game with an RC2C05-03 PPU, 32K PRG ROM
+    // and two 8K CHR ROM banks
+    let mut rom = vec![0u8; 16 + 32 * 1024 + 16 * 1024];
+    rom[0..4].copy_from_slice(b"NES\x1a");
+    rom[4] = 2;
+    rom[5] = 2;
+    rom[6] = 0x30;
+    rom[7] = 0x60 | 0x08 | 0x01;
+    rom[13] = 0x0a;
+    let chr = 16 + 32 * 1024;
+    rom[chr] = 0x11;
+    rom[chr + 8 * 1024] = 0x22;
+
+    let mut system = System::new(Model::Ntsc, 48000, Cartridge::none());
+    system.insert_cartridge(Cartridge::from_binary(&rom).unwrap());
+    system.power_cycle();
+    let config = system.vs_system.as_ref().unwrap().config;
+    assert_eq!(config.ppu, VsPpu::RC2C05_03);
+    assert_eq!(config.hardware, VsHardware::UniSystem);
+
+    // CHR bank switching via $4016
+    assert_eq!(system.cartridge.ppu_bus_peek(0), 0x11);
+    system.cpu_write(0x4016, 0b100);
+    assert_eq!(system.cartridge.ppu_bus_peek(0), 0x22);
+    system.cpu_write(0x4016, 0);
+    assert_eq!(system.cartridge.ppu_bus_peek(0), 0x11);
+
+    // Coins, service button and DIP switches
+    let vs_system = system.vs_system.as_mut().unwrap();
+    vs_system.dip_switches = 0b1010_0101;
+    vs_system.service_button = true;
+    vs_system.insert_coin(1);
+    assert_eq!(system.peek(0x4016) & 0b0111_1100, 0b0100_1100);
+    assert_eq!(system.peek(0x4017) & 0b1111_1100, 0b1010_0100);
+    for _ in 0..COIN_INSERT_FRAMES {
+        system.vs_system.as_mut().unwrap().start_frame();
+    }
+    assert_eq!(system.peek(0x4016) & 0b0110_0000, 0);
+
+    system.cpu_write(0x4020, 1);
+    system.cpu_write(0x4020, 0);
+    system.cpu_write(0x4020, 1);
+    assert_eq!(system.vs_system.as_ref().unwrap().coins_counted(), 2);
+
+    // The 2C05 PPU ID and swapped PPUCTRL/PPUMASK registers
+    assert_eq!(system.peek(0x2002) & 0x1f, 0x1c);
+    system.cpu_write(0x2001, 0x80);
+    assert!(system.ppu.control1.contains(Control1Flags::NMI_ENABLE));
+}
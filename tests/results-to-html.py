@@ -26,7 +26,9 @@ result_labels = {
     "PASSED": { "label": "Pass", "fg": "black", "bg": "lightgreen" },
     "FAILED": { "label": "Failed", "fg": "black", "bg": "pink" },
     "EXPECTED_FAILURE": { "label": "Failed (expected)", "fg": "black", "bg": "pink" },
-    "UNKNOWN": { "label": "Unknown", "fg": "black", "bg": "yellow" }
+    "UNKNOWN": { "label": "Unknown", "fg": "black", "bg": "yellow" },
+    "CRASHED": { "label": "Crashed", "fg": "black", "bg": "red" },
+    "TIMEOUT": { "label": "Timeout", "fg": "black", "bg": "orange" }
 }
 
 with open(args.tests) as f: